@@ -1,4 +1,5 @@
 pub mod http;
+pub mod pake;
 
 use base64::{engine::general_purpose::STANDARD_NO_PAD as BASE64, Engine};
 use rand::RngCore;
@@ -35,6 +36,17 @@ impl From<&[u8]> for Nonce {
     }
 }
 
+/// Stable identity of a peer, derived from its public key
+pub fn peer_identity(pubkey: &PublicKey) -> String {
+    BASE64.encode(pubkey.as_bytes())
+}
+
+/// Public key of the peer with the given identity
+pub fn parse_peer_identity(identity: &str) -> Option<PublicKey> {
+    let key: [u8; PUBLIC_KEY_LENGTH] = BASE64.decode(identity).ok()?.try_into().ok()?;
+    Some(PublicKey::from(key))
+}
+
 pub fn authorize(
     server_nonce: &Nonce,
     server_secret: &SecretKey,
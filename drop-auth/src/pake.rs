@@ -0,0 +1,160 @@
+//! Pairing of the peers which do not know each other's public keys yet. Both
+//! sides run SPAKE2 over the Ed25519 group with a short code entered by the
+//! users and end up with the same key only if the codes match. The key is then
+//! used to confirm the public keys exchanged in the clear.
+
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use sha2::Sha256;
+use spake2::{Ed25519Group, Identity, Password, Spake2};
+
+use crate::PublicKey;
+
+pub const CODE_LEN: usize = 6;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Role {
+    // Connects to the peer and enters the code
+    Client,
+    // Generated the code and waits for the peer
+    Server,
+}
+
+impl Role {
+    fn label(self) -> &'static str {
+        match self {
+            Role::Client => "client",
+            Role::Server => "server",
+        }
+    }
+}
+
+/// Random numeric code to be shown to the user and entered on the other device
+pub fn generate_code() -> String {
+    let code = rand::thread_rng().gen_range(0..10u32.pow(CODE_LEN as u32));
+    format!("{code:0CODE_LEN$}")
+}
+
+pub struct Pake {
+    spake: Spake2<Ed25519Group>,
+    message: Vec<u8>,
+}
+
+impl Pake {
+    pub fn new(role: Role, code: &str) -> Self {
+        let password = Password::new(code.trim());
+        let client = Identity::new(b"libdrop-pairing-client");
+        let server = Identity::new(b"libdrop-pairing-server");
+
+        let (spake, message) = match role {
+            Role::Client => Spake2::start_a(&password, &client, &server),
+            Role::Server => Spake2::start_b(&password, &client, &server),
+        };
+
+        Self { spake, message }
+    }
+
+    /// The message to be sent to the other side
+    pub fn message(&self) -> Vec<u8> {
+        self.message.clone()
+    }
+
+    /// Derive the shared key from the message of the other side. Fails only on
+    /// a malformed message, a wrong code is detected by the key confirmation
+    pub fn finish(self, peer_message: &[u8]) -> Option<SharedKey> {
+        self.spake.finish(peer_message).ok().map(SharedKey)
+    }
+}
+
+pub struct SharedKey(Vec<u8>);
+
+impl SharedKey {
+    /// Tag proving that the given side knows the code. It binds the public
+    /// keys of both sides so they cannot be swapped by a third party
+    pub fn confirmation(&self, role: Role, client: &PublicKey, server: &PublicKey) -> Vec<u8> {
+        self.mac(role, client, server)
+            .finalize()
+            .into_bytes()
+            .to_vec()
+    }
+
+    pub fn verify(&self, role: Role, client: &PublicKey, server: &PublicKey, tag: &[u8]) -> bool {
+        self.mac(role, client, server).verify_slice(tag).is_ok()
+    }
+
+    fn mac(&self, role: Role, client: &PublicKey, server: &PublicKey) -> HmacSha256 {
+        let mut hmac =
+            HmacSha256::new_from_slice(&self.0).expect("HMAC should accept keys of any size");
+        hmac.update(role.label().as_bytes());
+        hmac.update(client.as_bytes());
+        hmac.update(server.as_bytes());
+        hmac
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exchange(client_code: &str, server_code: &str) -> (SharedKey, SharedKey) {
+        let client = Pake::new(Role::Client, client_code);
+        let server = Pake::new(Role::Server, server_code);
+
+        let (client_msg, server_msg) = (client.message(), server.message());
+
+        (
+            client.finish(&server_msg).unwrap(),
+            server.finish(&client_msg).unwrap(),
+        )
+    }
+
+    #[test]
+    fn matching_codes() {
+        let client_key = PublicKey::from([1; 32]);
+        let server_key = PublicKey::from([2; 32]);
+
+        let (client, server) = exchange("123456", "123456");
+
+        let tag = server.confirmation(Role::Server, &client_key, &server_key);
+        assert!(client.verify(Role::Server, &client_key, &server_key, &tag));
+
+        let tag = client.confirmation(Role::Client, &client_key, &server_key);
+        assert!(server.verify(Role::Client, &client_key, &server_key, &tag));
+
+        // The tags are not interchangeable between the sides and the keys
+        assert!(!server.verify(Role::Server, &client_key, &server_key, &tag));
+        assert!(!server.verify(Role::Client, &server_key, &client_key, &tag));
+    }
+
+    #[test]
+    fn mismatched_codes() {
+        let client_key = PublicKey::from([1; 32]);
+        let server_key = PublicKey::from([2; 32]);
+
+        let (client, server) = exchange("123456", "123457");
+
+        let tag = server.confirmation(Role::Server, &client_key, &server_key);
+        assert!(!client.verify(Role::Server, &client_key, &server_key, &tag));
+    }
+
+    #[test]
+    fn malformed_message() {
+        // The first byte tells the side the message comes from
+        let pake = Pake::new(Role::Client, "123456");
+        assert!(pake
+            .finish(&Pake::new(Role::Client, "123456").message())
+            .is_none());
+
+        let pake = Pake::new(Role::Client, "123456");
+        assert!(pake.finish(&[0; 4]).is_none());
+    }
+
+    #[test]
+    fn code_format() {
+        let code = generate_code();
+        assert_eq!(code.len(), CODE_LEN);
+        assert!(code.chars().all(|c| c.is_ascii_digit()));
+    }
+}
@@ -1,4 +1,4 @@
-use std::time::Duration;
+use std::{net::IpAddr, time::Duration};
 
 #[derive(Debug, Clone, Default)]
 pub struct Config {
@@ -13,7 +13,15 @@ pub struct DropConfig {
     pub req_connection_timeout: Duration,
     pub connection_max_retry_interval: Duration,
     pub transfer_idle_lifetime: Duration,
+    // Incoming transfers expire when there is no action taken by the user
+    // within this time. Disabled when `None`
+    pub transfer_accept_timeout: Option<Duration>,
     pub storage_path: String,
+    // Source address of the outgoing connections, chosen by the OS when `None`
+    pub source_addr: Option<IpAddr>,
+    // Network interface the outgoing connections are bound to, Linux and
+    // Android only
+    pub bind_device: Option<String>,
 }
 
 impl Default for DropConfig {
@@ -24,7 +32,10 @@ impl Default for DropConfig {
             req_connection_timeout: Duration::from_secs(5),
             connection_max_retry_interval: Duration::from_secs(10),
             transfer_idle_lifetime: Duration::from_secs(60),
+            transfer_accept_timeout: None,
             storage_path: "libdrop.sqlite".to_string(),
+            source_addr: None,
+            bind_device: None,
         }
     }
 }
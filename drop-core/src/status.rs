@@ -25,6 +25,8 @@ pub enum Status {
     DbLost = 32,
     FileChecksumMismatch = 33,
     FileRejected = 34,
+    ShareUnavailable = 35,
+    PairingUnavailable = 36,
 }
 
 impl serde::Serialize for Status {
@@ -0,0 +1,4 @@
+-- Add migration script here
+
+ALTER TABLE incoming_paths ADD COLUMN kind INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE outgoing_paths ADD COLUMN kind INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,3 @@
+-- Add migration script here
+
+ALTER TABLE transfers ADD COLUMN group_id TEXT;
@@ -0,0 +1,13 @@
+-- Add migration script here
+
+ALTER TABLE outgoing_path_cancel_states ADD COLUMN reason_code INTEGER;
+ALTER TABLE outgoing_path_cancel_states ADD COLUMN reason TEXT;
+
+ALTER TABLE incoming_path_cancel_states ADD COLUMN reason_code INTEGER;
+ALTER TABLE incoming_path_cancel_states ADD COLUMN reason TEXT;
+
+ALTER TABLE outgoing_path_reject_states ADD COLUMN reason_code INTEGER;
+ALTER TABLE outgoing_path_reject_states ADD COLUMN reason TEXT;
+
+ALTER TABLE incoming_path_reject_states ADD COLUMN reason_code INTEGER;
+ALTER TABLE incoming_path_reject_states ADD COLUMN reason TEXT;
@@ -0,0 +1,13 @@
+-- Add migration script here
+
+-- addresses the peers were seen on, the identity is derived from the public key
+CREATE TABLE IF NOT EXISTS peers (
+  identity TEXT NOT NULL,
+  address TEXT NOT NULL,
+  last_seen TIMESTAMP NOT NULL DEFAULT(STRFTIME('%Y-%m-%d %H:%M:%f', 'NOW')),
+  PRIMARY KEY(identity, address)
+);
+
+CREATE INDEX IF NOT EXISTS peers_address ON peers(address);
+
+ALTER TABLE transfers ADD COLUMN peer_identity TEXT;
@@ -0,0 +1,8 @@
+-- Add migration script here
+
+-- peers which exchanged their public keys in the pairing, the addresses they
+-- were seen on are in the peers table
+CREATE TABLE IF NOT EXISTS paired_peers (
+  identity TEXT PRIMARY KEY NOT NULL,
+  paired_at TIMESTAMP NOT NULL DEFAULT(STRFTIME('%Y-%m-%d %H:%M:%f', 'NOW'))
+);
@@ -11,13 +11,13 @@ use rusqlite_migration::Migrations;
 use slog::{trace, warn, Logger};
 use types::{
     DbTransferType, IncomingPath, IncomingPathStateEvent, IncomingPathStateEventData, OutgoingPath,
-    OutgoingPathStateEvent, OutgoingPathStateEventData, Transfer, TransferFiles,
+    OutgoingPathStateEvent, OutgoingPathStateEventData, PathKind, Reason, Transfer, TransferFiles,
     TransferIncomingPath, TransferOutgoingPath, TransferStateEvent,
 };
 use uuid::Uuid;
 
 use crate::error::Error;
-pub use crate::types::{Event, FileChecksum, TransferInfo, TransferType};
+pub use crate::types::{Event, FileChecksum, PairedPeer, TransferInfo, TransferType};
 
 type Result<T> = std::result::Result<T, Error>;
 type QueryResult<T> = std::result::Result<T, rusqlite::Error>;
@@ -66,10 +66,179 @@ impl Storage {
         let conn = conn.transaction()?;
 
         conn.execute(
-            "INSERT INTO transfers (id, peer, is_outgoing) VALUES (?1, ?2, ?3)",
-            params![tid, transfer.peer, transfer_type_int],
+            "INSERT INTO transfers (id, peer, is_outgoing, group_id, peer_identity) VALUES (?1, \
+             ?2, ?3, ?4, ?5)",
+            params![
+                tid,
+                transfer.peer,
+                transfer_type_int,
+                transfer.group_id.map(|id| id.to_string()),
+                transfer.identity,
+            ],
+        )?;
+
+        if let Some(identity) = &transfer.identity {
+            Self::upsert_peer(&conn, identity, &transfer.peer)?;
+        }
+
+        self.insert_paths(&conn, transfer)?;
+
+        conn.commit()?;
+
+        Ok(())
+    }
+
+    /// Record that the peer with the given identity was seen on the address
+    pub fn insert_peer(&self, identity: &str, address: &str) -> Result<()> {
+        trace!(
+            self.logger,
+            "Inserting peer";
+            "identity" => identity,
+            "address" => address,
+        );
+
+        let mut conn = self.pool.get()?;
+        let conn = conn.transaction()?;
+
+        Self::upsert_peer(&conn, identity, address)?;
+
+        conn.commit()?;
+
+        Ok(())
+    }
+
+    /// Record the identity of the transfer peer once it is proven by the
+    /// connection
+    pub fn update_transfer_identity(
+        &self,
+        transfer_id: Uuid,
+        identity: &str,
+        address: &str,
+    ) -> Result<()> {
+        let tid = transfer_id.to_string();
+
+        trace!(
+            self.logger,
+            "Updating transfer peer identity";
+            "transfer_id" => &tid,
+            "identity" => identity,
+        );
+
+        let mut conn = self.pool.get()?;
+        let conn = conn.transaction()?;
+
+        conn.execute(
+            "UPDATE transfers SET peer_identity = ?2 WHERE id = ?1",
+            params![tid, identity],
+        )?;
+        Self::upsert_peer(&conn, identity, address)?;
+
+        conn.commit()?;
+
+        Ok(())
+    }
+
+    fn upsert_peer(conn: &Transaction<'_>, identity: &str, address: &str) -> Result<()> {
+        conn.execute(
+            "INSERT OR REPLACE INTO peers (identity, address) VALUES (?1, ?2)",
+            params![identity, address],
+        )?;
+
+        Ok(())
+    }
+
+    /// Identity of the peer most recently seen on the address
+    pub fn peer_identity(&self, address: &str) -> Result<Option<String>> {
+        let conn = self.pool.get()?;
+
+        let identity = conn
+            .prepare(
+                "SELECT identity FROM peers WHERE address = ?1 ORDER BY last_seen DESC LIMIT 1",
+            )?
+            .query_map(params![address], |row| row.get("identity"))?
+            .next()
+            .transpose()?;
+
+        Ok(identity)
+    }
+
+    /// Addresses the peer was seen on, the most recent first
+    pub fn peer_addresses(&self, identity: &str) -> Result<Vec<String>> {
+        let conn = self.pool.get()?;
+
+        let addresses = conn
+            .prepare("SELECT address FROM peers WHERE identity = ?1 ORDER BY last_seen DESC")?
+            .query_map(params![identity], |row| row.get("address"))?
+            .collect::<QueryResult<Vec<String>>>()?;
+
+        Ok(addresses)
+    }
+
+    /// Record the peer whose public key was exchanged in the pairing
+    pub fn insert_paired_peer(&self, identity: &str, address: &str) -> Result<()> {
+        trace!(
+            self.logger,
+            "Inserting paired peer";
+            "identity" => identity,
+            "address" => address,
+        );
+
+        let mut conn = self.pool.get()?;
+        let conn = conn.transaction()?;
+
+        conn.execute(
+            "INSERT OR REPLACE INTO paired_peers (identity) VALUES (?1)",
+            params![identity],
         )?;
+        Self::upsert_peer(&conn, identity, address)?;
+
+        conn.commit()?;
+
+        Ok(())
+    }
+
+    /// Addresses of the paired peers, the most recently seen last
+    pub fn paired_peers(&self) -> Result<Vec<PairedPeer>> {
+        let conn = self.pool.get()?;
+
+        let peers = conn
+            .prepare(
+                r#"
+                SELECT p.identity, p.address FROM peers p
+                INNER JOIN paired_peers pp ON pp.identity = p.identity
+                ORDER BY p.last_seen ASC
+                "#,
+            )?
+            .query_map([], |row| {
+                Ok(PairedPeer {
+                    identity: row.get("identity")?,
+                    address: row.get("address")?,
+                })
+            })?
+            .collect::<QueryResult<Vec<_>>>()?;
+
+        Ok(peers)
+    }
 
+    /// Insert the paths appended to an already existing transfer
+    pub fn insert_transfer_paths(&self, transfer: &TransferInfo) -> Result<()> {
+        trace!(
+            self.logger,
+            "Appending transfer paths";
+            "transfer_id" => transfer.id.to_string(),
+        );
+
+        let mut conn = self.pool.get()?;
+        let conn = conn.transaction()?;
+
+        self.insert_paths(&conn, transfer)?;
+
+        conn.commit()?;
+
+        Ok(())
+    }
+
+    fn insert_paths(&self, conn: &Transaction<'_>, transfer: &TransferInfo) -> Result<()> {
         match &transfer.files {
             TransferFiles::Incoming(files) => {
                 trace!(
@@ -79,7 +248,7 @@ impl Storage {
                 );
 
                 for file in files {
-                    Self::insert_incoming_path(&conn, transfer.id, file)?;
+                    Self::insert_incoming_path(conn, transfer.id, file)?;
                 }
             }
             TransferFiles::Outgoing(files) => {
@@ -90,13 +259,11 @@ impl Storage {
                 );
 
                 for file in files {
-                    Self::insert_outgoing_path(&conn, transfer.id, file)?;
+                    Self::insert_outgoing_path(conn, transfer.id, file)?;
                 }
             }
         }
 
-        conn.commit()?;
-
         Ok(())
     }
 
@@ -108,9 +275,15 @@ impl Storage {
         let tid = transfer_id.to_string();
 
         conn.execute(
-            "INSERT INTO incoming_paths (transfer_id, relative_path, path_hash, bytes)
-            VALUES (?1, ?2, ?3, ?4) ON CONFLICT DO NOTHING",
-            params![tid, path.relative_path, path.file_id, path.size],
+            "INSERT INTO incoming_paths (transfer_id, relative_path, path_hash, bytes, kind)
+            VALUES (?1, ?2, ?3, ?4, ?5) ON CONFLICT DO NOTHING",
+            params![
+                tid,
+                path.relative_path,
+                path.file_id,
+                path.size,
+                path.kind as u32
+            ],
         )?;
 
         Ok(())
@@ -124,14 +297,16 @@ impl Storage {
         let tid = transfer_id.to_string();
 
         conn.execute(
-            "INSERT INTO outgoing_paths (transfer_id, relative_path, path_hash, bytes, base_path)
-            VALUES (?1, ?2, ?3, ?4, ?5)",
+            "INSERT INTO outgoing_paths (transfer_id, relative_path, path_hash, bytes, base_path, \
+             kind)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
             params![
                 tid,
                 path.relative_path,
                 path.file_id,
                 path.size,
-                path.base_path
+                path.base_path,
+                path.kind as u32
             ],
         )?;
 
@@ -233,6 +408,23 @@ impl Storage {
         Ok(())
     }
 
+    pub fn insert_transfer_expired_state(&self, transfer_id: Uuid) -> Result<()> {
+        let tid = transfer_id.to_string();
+
+        trace!(
+            self.logger,
+            "Inserting transfer expired state";
+            "transfer_id" => &tid);
+
+        let conn = self.pool.get()?;
+        conn.execute(
+            "INSERT INTO transfer_expired_states (transfer_id) VALUES (?1)",
+            params![tid],
+        )?;
+
+        Ok(())
+    }
+
     pub fn insert_outgoing_path_pending_state(
         &self,
         transfer_id: Uuid,
@@ -333,6 +525,7 @@ impl Storage {
         path_id: &str,
         by_peer: bool,
         bytes_sent: i64,
+        reason: Option<&Reason>,
     ) -> Result<()> {
         let tid = transfer_id.to_string();
 
@@ -346,9 +539,17 @@ impl Storage {
 
         let conn = self.pool.get()?;
         conn.execute(
-            "INSERT INTO outgoing_path_cancel_states (path_id, by_peer, bytes_sent) VALUES \
-             ((SELECT id FROM outgoing_paths WHERE transfer_id = ?1 AND path_hash = ?2), ?3, ?4)",
-            params![tid, path_id, by_peer, bytes_sent],
+            "INSERT INTO outgoing_path_cancel_states (path_id, by_peer, bytes_sent, reason_code, \
+             reason) VALUES ((SELECT id FROM outgoing_paths WHERE transfer_id = ?1 AND path_hash \
+             = ?2), ?3, ?4, ?5, ?6)",
+            params![
+                tid,
+                path_id,
+                by_peer,
+                bytes_sent,
+                reason.map(|r| r.code),
+                reason.map(|r| &r.message)
+            ],
         )?;
 
         Ok(())
@@ -360,6 +561,7 @@ impl Storage {
         path_id: &str,
         by_peer: bool,
         bytes_received: i64,
+        reason: Option<&Reason>,
     ) -> Result<()> {
         let tid = transfer_id.to_string();
 
@@ -373,9 +575,17 @@ impl Storage {
 
         let conn = self.pool.get()?;
         conn.execute(
-            "INSERT INTO incoming_path_cancel_states (path_id, by_peer, bytes_received) VALUES \
-             ((SELECT id FROM incoming_paths WHERE transfer_id = ?1 AND path_hash = ?2), ?3, ?4)",
-            params![tid, path_id, by_peer, bytes_received],
+            "INSERT INTO incoming_path_cancel_states (path_id, by_peer, bytes_received, \
+             reason_code, reason) VALUES ((SELECT id FROM incoming_paths WHERE transfer_id = ?1 \
+             AND path_hash = ?2), ?3, ?4, ?5, ?6)",
+            params![
+                tid,
+                path_id,
+                by_peer,
+                bytes_received,
+                reason.map(|r| r.code),
+                reason.map(|r| &r.message)
+            ],
         )?;
 
         Ok(())
@@ -486,14 +696,22 @@ impl Storage {
         transfer_id: Uuid,
         path_id: &str,
         by_peer: bool,
+        reason: Option<&Reason>,
     ) -> Result<()> {
         let tid = transfer_id.to_string();
 
         let conn = self.pool.get()?;
         conn.execute(
-            "INSERT INTO outgoing_path_reject_states (path_id, by_peer) VALUES ((SELECT id FROM \
-             outgoing_paths WHERE transfer_id = ?1 AND path_hash = ?2), ?3)",
-            params![tid, path_id, by_peer],
+            "INSERT INTO outgoing_path_reject_states (path_id, by_peer, reason_code, reason) \
+             VALUES ((SELECT id FROM outgoing_paths WHERE transfer_id = ?1 AND path_hash = ?2), \
+             ?3, ?4, ?5)",
+            params![
+                tid,
+                path_id,
+                by_peer,
+                reason.map(|r| r.code),
+                reason.map(|r| &r.message)
+            ],
         )?;
 
         Ok(())
@@ -504,14 +722,22 @@ impl Storage {
         transfer_id: Uuid,
         path_id: &str,
         by_peer: bool,
+        reason: Option<&Reason>,
     ) -> Result<()> {
         let tid = transfer_id.to_string();
 
         let conn = self.pool.get()?;
         conn.execute(
-            "INSERT INTO incoming_path_reject_states (path_id, by_peer) VALUES ((SELECT id FROM \
-             incoming_paths WHERE transfer_id = ?1 AND path_hash = ?2), ?3)",
-            params![tid, path_id, by_peer],
+            "INSERT INTO incoming_path_reject_states (path_id, by_peer, reason_code, reason) \
+             VALUES ((SELECT id FROM incoming_paths WHERE transfer_id = ?1 AND path_hash = ?2), \
+             ?3, ?4, ?5)",
+            params![
+                tid,
+                path_id,
+                by_peer,
+                reason.map(|r| r.code),
+                reason.map(|r| &r.message)
+            ],
         )?;
 
         Ok(())
@@ -570,7 +796,7 @@ impl Storage {
         let mut transfers = conn
             .prepare(
                 r#"
-                SELECT id, peer, created_at, is_outgoing FROM transfers
+                SELECT id, peer, peer_identity, created_at, is_outgoing, group_id FROM transfers
                 WHERE created_at >= datetime(?1, 'unixepoch')
                 "#,
             )?
@@ -582,10 +808,16 @@ impl Storage {
                 };
 
                 let id: String = row.get("id")?;
+                let group_id = row
+                    .get::<_, Option<String>>("group_id")?
+                    .map(|id| Uuid::parse_str(&id).map_err(|_| rusqlite::Error::InvalidQuery))
+                    .transpose()?;
 
                 Ok(Transfer {
                     id: Uuid::parse_str(&id).map_err(|_| rusqlite::Error::InvalidQuery)?,
                     peer_id: row.get("peer")?,
+                    identity: row.get("peer_identity")?,
+                    group_id,
                     transfer_type,
                     created_at: row.get("created_at")?,
                     states: vec![],
@@ -659,6 +891,22 @@ impl Storage {
                 .collect::<QueryResult<Vec<TransferStateEvent>>>()?,
             );
 
+            transfer.states.extend(
+                conn.prepare(
+                    r#"
+                    SELECT created_at FROM transfer_expired_states WHERE transfer_id = ?1
+                    "#,
+                )?
+                .query_map(params![tid], |row| {
+                    Ok(TransferStateEvent {
+                        transfer_id: transfer.id,
+                        created_at: row.get("created_at")?,
+                        data: types::TransferStateEventData::Expired,
+                    })
+                })?
+                .collect::<QueryResult<Vec<TransferStateEvent>>>()?,
+            );
+
             transfer
                 .states
                 .sort_by(|a, b| a.created_at.cmp(&b.created_at));
@@ -738,6 +986,7 @@ impl Storage {
                     relative_path: row.get("relative_path")?,
                     file_id: row.get("path_hash")?,
                     bytes: row.get("bytes")?,
+                    kind: path_kind(row.get("kind")?),
                     created_at: row.get("created_at")?,
                     states: vec![],
                 })
@@ -792,6 +1041,7 @@ impl Storage {
                         data: OutgoingPathStateEventData::Cancel {
                             by_peer: row.get("by_peer")?,
                             bytes_sent: row.get("bytes_sent")?,
+                            reason: reason_from_row(row)?,
                         },
                     })
                 })?
@@ -845,6 +1095,7 @@ impl Storage {
                         created_at: row.get("created_at")?,
                         data: OutgoingPathStateEventData::Rejected {
                             by_peer: row.get("by_peer")?,
+                            reason: reason_from_row(row)?,
                         },
                     })
                 })?
@@ -879,6 +1130,7 @@ impl Storage {
                     relative_path: row.get("relative_path")?,
                     file_id: row.get("path_hash")?,
                     bytes: row.get("bytes")?,
+                    kind: path_kind(row.get("kind")?),
                     created_at: row.get("created_at")?,
                     states: vec![],
                 })
@@ -934,6 +1186,7 @@ impl Storage {
                         data: IncomingPathStateEventData::Cancel {
                             by_peer: row.get("by_peer")?,
                             bytes_received: row.get("bytes_received")?,
+                            reason: reason_from_row(row)?,
                         },
                     })
                 })?
@@ -989,6 +1242,7 @@ impl Storage {
                         created_at: row.get("created_at")?,
                         data: IncomingPathStateEventData::Rejected {
                             by_peer: row.get("by_peer")?,
+                            reason: reason_from_row(row)?,
                         },
                     })
                 })?
@@ -1002,6 +1256,25 @@ impl Storage {
     }
 }
 
+fn reason_from_row(row: &rusqlite::Row) -> QueryResult<Option<Reason>> {
+    let code: Option<u32> = row.get("reason_code")?;
+    let message: Option<String> = row.get("reason")?;
+
+    Ok(code.map(|code| Reason {
+        code,
+        message: message.unwrap_or_default(),
+    }))
+}
+
+fn path_kind(kind: u32) -> PathKind {
+    match kind {
+        1 => PathKind::Dir,
+        2 => PathKind::Symlink,
+        3 => PathKind::Archive,
+        _ => PathKind::File,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1018,16 +1291,20 @@ mod tests {
             let transfer = TransferInfo {
                 id: transfer_id_1,
                 peer: "1.2.3.4".to_string(),
+                identity: None,
+                group_id: None,
                 files: TransferFiles::Incoming(vec![
                     TransferIncomingPath {
                         file_id: "id1".to_string(),
                         relative_path: "1".to_string(),
                         size: 1024,
+                        kind: PathKind::File,
                     },
                     TransferIncomingPath {
                         file_id: "id2".to_string(),
                         relative_path: "2".to_string(),
                         size: 2048,
+                        kind: PathKind::File,
                     },
                 ]),
             };
@@ -1039,10 +1316,13 @@ mod tests {
             let transfer = TransferInfo {
                 id: transfer_id_2,
                 peer: "5.6.7.8".to_string(),
+                identity: None,
+                group_id: None,
                 files: TransferFiles::Outgoing(vec![
                     TransferOutgoingPath {
                         file_id: "id3".to_string(),
                         size: 1024,
+                        kind: PathKind::File,
                         base_path: "/dir".to_string(),
                         relative_path: "3".to_string(),
                     },
@@ -1051,6 +1331,7 @@ mod tests {
                         relative_path: "4".to_string(),
                         base_path: "/dir".to_string(),
                         size: 2048,
+                        kind: PathKind::File,
                     },
                 ]),
             };
@@ -1091,10 +1372,13 @@ mod tests {
         let transfer = TransferInfo {
             id: transfer_id,
             peer: "5.6.7.8".to_string(),
+            identity: None,
+            group_id: None,
             files: TransferFiles::Outgoing(vec![
                 TransferOutgoingPath {
                     file_id: "id3".to_string(),
                     size: 1024,
+                    kind: PathKind::File,
                     base_path: "/dir".to_string(),
                     relative_path: "3".to_string(),
                 },
@@ -1103,13 +1387,14 @@ mod tests {
                     relative_path: "4".to_string(),
                     base_path: "/dir".to_string(),
                     size: 2048,
+                    kind: PathKind::File,
                 },
             ]),
         };
 
         storage.insert_transfer(&transfer).unwrap();
         storage
-            .insert_outgoing_path_reject_state(transfer_id, "id3", false)
+            .insert_outgoing_path_reject_state(transfer_id, "id3", false, None)
             .unwrap();
 
         let transfers = storage.transfers_since(0).unwrap();
@@ -1151,23 +1436,27 @@ mod tests {
         let transfer = TransferInfo {
             id: transfer_id,
             peer: "5.6.7.8".to_string(),
+            identity: None,
+            group_id: None,
             files: TransferFiles::Incoming(vec![
                 TransferIncomingPath {
                     file_id: "id3".to_string(),
                     size: 1024,
+                    kind: PathKind::File,
                     relative_path: "3".to_string(),
                 },
                 TransferIncomingPath {
                     file_id: "id4".to_string(),
                     relative_path: "4".to_string(),
                     size: 2048,
+                    kind: PathKind::File,
                 },
             ]),
         };
 
         storage.insert_transfer(&transfer).unwrap();
         storage
-            .insert_incoming_path_reject_state(transfer_id, "id3", false)
+            .insert_incoming_path_reject_state(transfer_id, "id3", false, None)
             .unwrap();
 
         let transfers = storage.transfers_since(0).unwrap();
@@ -1199,4 +1488,261 @@ mod tests {
         assert_eq!(paths.len(), 1); // 1 since we removed one of them
         assert_eq!(paths[0].file_id, "id4");
     }
+
+    #[test]
+    fn append_transfer_paths() {
+        let logger = slog::Logger::root(slog::Discard, slog::o!());
+        let storage = Storage::new(logger, ":memory:").unwrap();
+
+        let transfer_id: Uuid = "23e488a4-0521-11ee-be56-0242ac120002".parse().unwrap();
+
+        let path = |id: &str| TransferOutgoingPath {
+            file_id: id.to_string(),
+            relative_path: id.to_string(),
+            base_path: "/dir".to_string(),
+            size: 1024,
+            kind: PathKind::File,
+        };
+
+        storage
+            .insert_transfer(&TransferInfo {
+                id: transfer_id,
+                peer: "5.6.7.8".to_string(),
+                identity: None,
+                group_id: None,
+                files: TransferFiles::Outgoing(vec![path("id1")]),
+            })
+            .unwrap();
+
+        storage
+            .insert_transfer_paths(&TransferInfo {
+                id: transfer_id,
+                peer: "5.6.7.8".to_string(),
+                identity: None,
+                group_id: None,
+                files: TransferFiles::Outgoing(vec![path("id2"), path("id3")]),
+            })
+            .unwrap();
+
+        let transfers = storage.transfers_since(0).unwrap();
+        assert_eq!(transfers.len(), 1);
+
+        let paths = match &transfers[0].transfer_type {
+            DbTransferType::Outgoing(out) => out,
+            _ => panic!("Unexpected transfer type"),
+        };
+
+        let mut ids: Vec<_> = paths.iter().map(|p| p.file_id.as_str()).collect();
+        ids.sort();
+        assert_eq!(ids, ["id1", "id2", "id3"]);
+    }
+
+    #[test]
+    fn transfer_group() {
+        let logger = slog::Logger::root(slog::Discard, slog::o!());
+        let storage = Storage::new(logger, ":memory:").unwrap();
+
+        let group_id: Uuid = "5a9b2b4e-0521-11ee-be56-0242ac120002".parse().unwrap();
+        let transfer_id_1: Uuid = "23e488a4-0521-11ee-be56-0242ac120002".parse().unwrap();
+        let transfer_id_2: Uuid = "23e48d7c-0521-11ee-be56-0242ac120002".parse().unwrap();
+
+        for (id, peer) in [(transfer_id_1, "1.2.3.4"), (transfer_id_2, "5.6.7.8")] {
+            storage
+                .insert_transfer(&TransferInfo {
+                    id,
+                    peer: peer.to_string(),
+                    identity: None,
+                    group_id: Some(group_id),
+                    files: TransferFiles::Outgoing(vec![]),
+                })
+                .unwrap();
+        }
+
+        let transfers = storage.transfers_since(0).unwrap();
+        assert_eq!(transfers.len(), 2);
+        assert!(transfers.iter().all(|t| t.group_id == Some(group_id)));
+    }
+
+    #[test]
+    fn transfer_expired_state() {
+        let logger = slog::Logger::root(slog::Discard, slog::o!());
+        let storage = Storage::new(logger, ":memory:").unwrap();
+
+        let transfer_id: Uuid = "23e488a4-0521-11ee-be56-0242ac120002".parse().unwrap();
+
+        storage
+            .insert_transfer(&TransferInfo {
+                id: transfer_id,
+                peer: "1.2.3.4".to_string(),
+                identity: None,
+                group_id: None,
+                files: TransferFiles::Incoming(vec![]),
+            })
+            .unwrap();
+        storage.insert_transfer_expired_state(transfer_id).unwrap();
+
+        let transfers = storage.transfers_since(0).unwrap();
+        assert_eq!(transfers.len(), 1);
+        assert!(matches!(
+            transfers[0].states.as_slice(),
+            [TransferStateEvent {
+                data: types::TransferStateEventData::Expired,
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn path_reasons() {
+        let logger = slog::Logger::root(slog::Discard, slog::o!());
+        let storage = Storage::new(logger, ":memory:").unwrap();
+
+        let transfer_id: Uuid = "23e488a4-0521-11ee-be56-0242ac120002".parse().unwrap();
+
+        storage
+            .insert_transfer(&TransferInfo {
+                id: transfer_id,
+                peer: "1.2.3.4".to_string(),
+                identity: None,
+                group_id: None,
+                files: TransferFiles::Incoming(vec![
+                    TransferIncomingPath {
+                        file_id: "id1".to_string(),
+                        relative_path: "1".to_string(),
+                        size: 1024,
+                        kind: PathKind::File,
+                    },
+                    TransferIncomingPath {
+                        file_id: "id2".to_string(),
+                        relative_path: "2".to_string(),
+                        size: 2048,
+                        kind: PathKind::File,
+                    },
+                ]),
+            })
+            .unwrap();
+
+        let reason = Reason {
+            code: 3,
+            message: "Disk full".to_string(),
+        };
+        storage
+            .insert_incoming_path_cancel_state(transfer_id, "id1", true, 10, Some(&reason))
+            .unwrap();
+        storage
+            .insert_incoming_path_reject_state(transfer_id, "id2", false, None)
+            .unwrap();
+
+        let transfers = storage.transfers_since(0).unwrap();
+        let mut paths = match &transfers[0].transfer_type {
+            DbTransferType::Incoming(paths) => paths.iter().collect::<Vec<_>>(),
+            _ => panic!("Unexpected transfer type"),
+        };
+        paths.sort_by(|a, b| a.file_id.cmp(&b.file_id));
+
+        assert!(matches!(
+            paths[0].states.as_slice(),
+            [IncomingPathStateEvent {
+                data: IncomingPathStateEventData::Cancel {
+                    by_peer: true,
+                    reason: Some(Reason { code: 3, message }),
+                    ..
+                },
+                ..
+            }] if message == "Disk full"
+        ));
+        assert!(matches!(
+            paths[1].states.as_slice(),
+            [IncomingPathStateEvent {
+                data: IncomingPathStateEventData::Rejected {
+                    by_peer: false,
+                    reason: None,
+                },
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn peer_lookup() {
+        let logger = slog::Logger::root(slog::Discard, slog::o!());
+        let storage = Storage::new(logger, ":memory:").unwrap();
+
+        let transfer_id: Uuid = "23e488a4-0521-11ee-be56-0242ac120002".parse().unwrap();
+
+        storage
+            .insert_transfer(&TransferInfo {
+                id: transfer_id,
+                peer: "1.2.3.4".to_string(),
+                identity: Some("peer-a".to_string()),
+                group_id: None,
+                files: TransferFiles::Incoming(vec![]),
+            })
+            .unwrap();
+        storage.insert_peer("peer-a", "5.6.7.8").unwrap();
+
+        assert_eq!(
+            storage.peer_identity("5.6.7.8").unwrap().as_deref(),
+            Some("peer-a")
+        );
+        assert_eq!(storage.peer_identity("9.9.9.9").unwrap(), None);
+
+        let mut addresses = storage.peer_addresses("peer-a").unwrap();
+        addresses.sort();
+        assert_eq!(addresses, ["1.2.3.4", "5.6.7.8"]);
+
+        let transfers = storage.transfers_since(0).unwrap();
+        assert_eq!(transfers[0].identity.as_deref(), Some("peer-a"));
+    }
+
+    #[test]
+    fn transfer_identity_update() {
+        let logger = slog::Logger::root(slog::Discard, slog::o!());
+        let storage = Storage::new(logger, ":memory:").unwrap();
+
+        let transfer_id: Uuid = "23e488a4-0521-11ee-be56-0242ac120002".parse().unwrap();
+
+        storage
+            .insert_transfer(&TransferInfo {
+                id: transfer_id,
+                peer: "1.2.3.4".to_string(),
+                identity: None,
+                group_id: None,
+                files: TransferFiles::Outgoing(vec![]),
+            })
+            .unwrap();
+        storage
+            .update_transfer_identity(transfer_id, "peer-a", "1.2.3.4")
+            .unwrap();
+
+        let transfers = storage.transfers_since(0).unwrap();
+        assert_eq!(transfers[0].identity.as_deref(), Some("peer-a"));
+        assert_eq!(
+            storage.peer_identity("1.2.3.4").unwrap().as_deref(),
+            Some("peer-a")
+        );
+    }
+
+    #[test]
+    fn paired_peers() {
+        let logger = slog::Logger::root(slog::Discard, slog::o!());
+        let storage = Storage::new(logger, ":memory:").unwrap();
+
+        storage.insert_peer("peer-a", "1.2.3.4").unwrap();
+        storage.insert_paired_peer("peer-b", "5.6.7.8").unwrap();
+        // Pairing again must not duplicate the peer
+        storage.insert_paired_peer("peer-b", "5.6.7.8").unwrap();
+
+        assert_eq!(
+            storage.paired_peers().unwrap(),
+            [PairedPeer {
+                identity: "peer-b".to_string(),
+                address: "5.6.7.8".to_string(),
+            }]
+        );
+        assert_eq!(
+            storage.peer_identity("5.6.7.8").unwrap().as_deref(),
+            Some("peer-b")
+        );
+    }
 }
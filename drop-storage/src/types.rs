@@ -1,4 +1,5 @@
 use chrono::NaiveDateTime;
+use schemars::JsonSchema;
 use serde::Serialize;
 
 type TransferId = uuid::Uuid;
@@ -11,7 +12,16 @@ where
     serializer.serialize_i64(timestamp.timestamp_millis())
 }
 
-#[derive(Debug, Serialize)]
+/// Application defined reason of a cancel or reject
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct Reason {
+    #[serde(rename = "reason_code")]
+    pub code: u32,
+    #[serde(rename = "reason")]
+    pub message: String,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
 #[serde(tag = "state")]
 pub enum OutgoingPathStateEventData {
     #[serde(rename = "pending")]
@@ -19,16 +29,25 @@ pub enum OutgoingPathStateEventData {
     #[serde(rename = "started")]
     Started { bytes_sent: i64 },
     #[serde(rename = "cancel")]
-    Cancel { by_peer: bool, bytes_sent: i64 },
+    Cancel {
+        by_peer: bool,
+        bytes_sent: i64,
+        #[serde(flatten)]
+        reason: Option<Reason>,
+    },
     #[serde(rename = "failed")]
     Failed { status_code: i64, bytes_sent: i64 },
     #[serde(rename = "completed")]
     Completed,
     #[serde(rename = "rejected")]
-    Rejected { by_peer: bool },
+    Rejected {
+        by_peer: bool,
+        #[serde(flatten)]
+        reason: Option<Reason>,
+    },
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 #[serde(tag = "state")]
 pub enum IncomingPathStateEventData {
     #[serde(rename = "pending")]
@@ -39,7 +58,12 @@ pub enum IncomingPathStateEventData {
         bytes_received: i64,
     },
     #[serde(rename = "cancel")]
-    Cancel { by_peer: bool, bytes_received: i64 },
+    Cancel {
+        by_peer: bool,
+        bytes_received: i64,
+        #[serde(flatten)]
+        reason: Option<Reason>,
+    },
     #[serde(rename = "failed")]
     Failed {
         status_code: i64,
@@ -48,30 +72,36 @@ pub enum IncomingPathStateEventData {
     #[serde(rename = "completed")]
     Completed { final_path: String },
     #[serde(rename = "rejected")]
-    Rejected { by_peer: bool },
+    Rejected {
+        by_peer: bool,
+        #[serde(flatten)]
+        reason: Option<Reason>,
+    },
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct OutgoingPathStateEvent {
     #[serde(skip_serializing)]
     pub path_id: i64,
     #[serde(serialize_with = "serialize_datetime")]
+    #[schemars(with = "i64")]
     pub created_at: NaiveDateTime,
     #[serde(flatten)]
     pub data: OutgoingPathStateEventData,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct IncomingPathStateEvent {
     #[serde(skip_serializing)]
     pub path_id: i64,
     #[serde(serialize_with = "serialize_datetime")]
+    #[schemars(with = "i64")]
     pub created_at: NaiveDateTime,
     #[serde(flatten)]
     pub data: IncomingPathStateEventData,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 #[serde(tag = "state")]
 pub enum TransferStateEventData {
     #[serde(rename = "active")]
@@ -80,13 +110,16 @@ pub enum TransferStateEventData {
     Cancel { by_peer: bool },
     #[serde(rename = "failed")]
     Failed { status_code: i64 },
+    #[serde(rename = "expired")]
+    Expired,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct TransferStateEvent {
     #[serde(skip_serializing)]
     pub transfer_id: TransferId,
     #[serde(serialize_with = "serialize_datetime")]
+    #[schemars(with = "i64")]
     pub created_at: NaiveDateTime,
     #[serde(flatten)]
     pub data: TransferStateEventData,
@@ -99,11 +132,29 @@ pub enum TransferType {
     Outgoing = 1,
 }
 
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+#[repr(u32)]
+pub enum PathKind {
+    #[default]
+    File = 0,
+    Dir = 1,
+    Symlink = 2,
+    Archive = 3,
+}
+
+impl PathKind {
+    pub fn is_file(&self) -> bool {
+        matches!(self, Self::File)
+    }
+}
+
 #[derive(Debug)]
 pub struct TransferIncomingPath {
     pub file_id: FileId,
     pub relative_path: String,
     pub size: i64,
+    pub kind: PathKind,
 }
 
 #[derive(Debug)]
@@ -112,6 +163,7 @@ pub struct TransferOutgoingPath {
     pub relative_path: String,
     pub base_path: String,
     pub size: i64,
+    pub kind: PathKind,
 }
 
 #[derive(Debug)]
@@ -124,9 +176,18 @@ pub enum TransferFiles {
 pub struct TransferInfo {
     pub id: TransferId,
     pub peer: String,
+    // Identity derived from the public key of the peer, if known
+    pub identity: Option<String>,
+    pub group_id: Option<TransferId>,
     pub files: TransferFiles,
 }
 
+#[derive(Debug, PartialEq, Eq)]
+pub struct PairedPeer {
+    pub identity: String,
+    pub address: String,
+}
+
 #[derive(Debug)]
 pub struct FileChecksum {
     pub file_id: FileId,
@@ -152,6 +213,7 @@ pub enum Event {
         transfer_id: TransferId,
         file_id: FileId,
         by_peer: bool,
+        reason: Option<Reason>,
     },
     TransferCanceled {
         transfer_type: TransferType,
@@ -169,6 +231,9 @@ pub enum Event {
         transfer_info: TransferInfo,
         error_code: u32,
     },
+    TransferExpired {
+        transfer_id: TransferId,
+    },
     FileUploadComplete {
         transfer_id: TransferId,
         file_id: FileId,
@@ -188,10 +253,11 @@ pub enum Event {
         transfer_id: TransferId,
         file_id: FileId,
         by_peer: bool,
+        reason: Option<Reason>,
     },
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 #[serde(tag = "type", content = "paths")]
 pub enum DbTransferType {
     #[serde(rename = "incoming")]
@@ -200,47 +266,59 @@ pub enum DbTransferType {
     Outgoing(Vec<OutgoingPath>),
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct Peer {
     pub id: Option<String>,
     #[serde(serialize_with = "serialize_datetime")]
+    #[schemars(with = "i64")]
     pub created_at: NaiveDateTime,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct Transfer {
     pub id: TransferId,
     #[serde(serialize_with = "serialize_datetime")]
+    #[schemars(with = "i64")]
     pub created_at: NaiveDateTime,
     pub peer_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub identity: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group_id: Option<TransferId>,
     pub states: Vec<TransferStateEvent>,
     #[serde(flatten)]
     pub transfer_type: DbTransferType,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct OutgoingPath {
     #[serde(skip_serializing)]
     pub id: i64,
     #[serde(serialize_with = "serialize_datetime")]
+    #[schemars(with = "i64")]
     pub created_at: NaiveDateTime,
     pub transfer_id: TransferId,
     pub base_path: String,
     pub relative_path: String,
     pub file_id: String,
     pub bytes: i64,
+    #[serde(skip_serializing_if = "PathKind::is_file")]
+    pub kind: PathKind,
     pub states: Vec<OutgoingPathStateEvent>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct IncomingPath {
     #[serde(skip_serializing)]
     pub id: i64,
     #[serde(serialize_with = "serialize_datetime")]
+    #[schemars(with = "i64")]
     pub created_at: NaiveDateTime,
     pub transfer_id: TransferId,
     pub relative_path: String,
     pub file_id: String,
     pub bytes: i64,
+    #[serde(skip_serializing_if = "PathKind::is_file")]
+    pub kind: PathKind,
     pub states: Vec<IncomingPathStateEvent>,
 }
@@ -61,7 +61,7 @@ async fn listen(
             error!("Failed to handle storage event: {e}");
         }
         match ev {
-            Event::RequestReceived(xfer) => {
+            Event::RequestReceived(xfer, _) => {
                 let xfid = xfer.id();
                 let files = xfer.files();
 
@@ -100,6 +100,24 @@ async fn listen(
                     });
                 }
             }
+            Event::RequestAppendReceived(xfer, files) => {
+                let xfid = xfer.id();
+
+                info!("[EVENT] RequestAppendReceived {}: {:?}", xfid, files);
+
+                if let Some(file_set) = active_file_downloads.get_mut(&xfid) {
+                    for file in files {
+                        service
+                            .lock()
+                            .await
+                            .download(xfid, &file, out_dir)
+                            .await
+                            .context("Cannot issue download call")?;
+
+                        file_set.insert(file);
+                    }
+                }
+            }
             Event::FileDownloadStarted(xfer, file, base_dir) => {
                 info!(
                     "[EVENT] [{}] FileDownloadStarted {:?} transfer started, to {:?}",
@@ -117,6 +135,15 @@ async fn listen(
                     byte_count,
                 );
             }
+            Event::FileUploadArchiveEntry(xfer, file, entry)
+            | Event::FileDownloadArchiveEntry(xfer, file, entry) => {
+                info!(
+                    "[EVENT] [{}] ArchiveEntry {:?} entry: {:?}",
+                    xfer.id(),
+                    file,
+                    entry,
+                );
+            }
             Event::FileDownloadSuccess(xfer, info) => {
                 let xfid = xfer.id();
 
@@ -143,6 +170,9 @@ async fn listen(
                     xfers.insert(xfer.id());
                 });
             }
+            Event::RequestAppendQueued(xfer, files) => {
+                info!("[EVENT] RequestAppendQueued {}: {:?}", xfer.id(), files);
+            }
             Event::FileUploadStarted(xfer, file) => {
                 info!("[EVENT] FileUploadStarted {}: {:?}", xfer.id(), file,);
             }
@@ -154,18 +184,19 @@ async fn listen(
                     progress
                 );
             }
-            Event::FileUploadCancelled(xfer, file, by_peer) => {
+            Event::FileUploadCancelled(xfer, file, by_peer, reason) => {
                 info!(
-                    "[EVENT] FileUploadCancelled {}: {:?}, by_peer: {by_peer}",
+                    "[EVENT] FileUploadCancelled {}: {:?}, by_peer: {by_peer}, reason: {reason:?}",
                     xfer.id(),
                     file,
                 );
             }
-            Event::FileDownloadCancelled(xfer, file, by_peer) => {
+            Event::FileDownloadCancelled(xfer, file, by_peer, reason) => {
                 let xfid = xfer.id();
 
                 info!(
-                    "[EVENT] FileDownloadCancelled {}: {:?}, by_peer: {by_peer}",
+                    "[EVENT] FileDownloadCancelled {}: {:?}, by_peer: {by_peer}, reason: \
+                     {reason:?}",
                     xfid, file
                 );
 
@@ -230,17 +261,70 @@ async fn listen(
                 transfer_id,
                 file_id,
                 by_peer,
+                reason,
             } => {
-                info!("[EVENT] FileDownloadRejected {transfer_id}: {file_id}, by_peer?: {by_peer}")
+                info!(
+                    "[EVENT] FileDownloadRejected {transfer_id}: {file_id}, by_peer?: {by_peer}, \
+                     reason: {reason:?}"
+                )
             }
 
             Event::FileUploadRejected {
                 transfer_id,
                 file_id,
                 by_peer,
+                reason,
+            } => {
+                info!(
+                    "[EVENT] FileUploadRejected {transfer_id}: {file_id}, by_peer?: {by_peer}, \
+                     reason: {reason:?}"
+                )
+            }
+
+            Event::TransferRejected(xfer, by_peer, reason) => {
+                info!(
+                    "[EVENT] TransferRejected {}, by peer? {by_peer}, reason: {reason:?}",
+                    xfer.id(),
+                );
+
+                active_file_downloads.remove(&xfer.id());
+                xfers.send_modify(|xfers| {
+                    xfers.remove(&xfer.id());
+                });
+            }
+
+            Event::TransferExpired(xfer) => {
+                info!("[EVENT] TransferExpired {}", xfer.id());
+
+                xfers.send_modify(|xfers| {
+                    xfers.remove(&xfer.id());
+                });
+            }
+
+            Event::FileRequestReceived {
+                request_id,
+                peer,
+                export,
+                paths,
             } => {
-                info!("[EVENT] FileUploadRejected {transfer_id}: {file_id}, by_peer?: {by_peer}")
+                info!("[EVENT] FileRequestReceived {request_id} from {peer}: {export} {paths:?}");
+
+                // There are no exports in this example, so nothing to approve
+                if let Err(err) = service.lock().await.deny_file_request(request_id).await {
+                    warn!("Failed to deny file request {request_id}: {err:?}");
+                }
+            }
+
+            Event::FileRequestDenied { request_id, peer } => {
+                info!("[EVENT] FileRequestDenied {request_id} by {peer}")
+            }
+
+            Event::PairingStarted(peer) => info!("[EVENT] PairingStarted with {peer}"),
+            Event::PairingRequested(peer) => info!("[EVENT] PairingRequested by {peer}"),
+            Event::PairingCompleted { peer, identity } => {
+                info!("[EVENT] PairingCompleted with {peer} as {identity}")
             }
+            Event::PairingFailed(peer, err) => info!("[EVENT] PairingFailed with {peer}: {err}"),
         }
     }
 
@@ -384,7 +468,7 @@ async fn main() -> anyhow::Result<()> {
     let storage = Arc::new(Storage::new(logger.clone(), storage_file).unwrap());
 
     let mut service = Service::start(
-        addr,
+        &[addr],
         storage.clone(),
         tx,
         logger,
@@ -1,10 +1,13 @@
-use std::net::IpAddr;
+use std::{collections::HashMap, net::IpAddr, sync::RwLock};
 
 use drop_auth::{PublicKey, SecretKey};
 
 pub struct Context {
     secret: SecretKey,
     public: Box<dyn Fn(IpAddr) -> Option<PublicKey> + Send + Sync>,
+    // Keys exchanged in the pairing by the peer identity, used when the
+    // callback does not know the peer
+    paired: RwLock<HashMap<String, PublicKey>>,
 }
 
 impl Context {
@@ -15,40 +18,82 @@ impl Context {
         Self {
             secret,
             public: Box::new(public),
+            paired: RwLock::default(),
         }
     }
 
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey::from(&self.secret)
+    }
+
+    pub fn add_paired(&self, pubkey: PublicKey) {
+        self.paired
+            .write()
+            .expect("Paired keys lock poisoned")
+            .insert(drop_auth::peer_identity(&pubkey), pubkey);
+    }
+
+    fn paired_key(&self, identity: &str) -> Option<PublicKey> {
+        self.paired
+            .read()
+            .expect("Paired keys lock poisoned")
+            .get(identity)
+            .copied()
+    }
+
+    /// Returns the key the peer proved to own, `None` if the authorization
+    /// failed
     pub fn authorize(
         &self,
         peer_ip: IpAddr,
         auth_header_value: &str,
         nonce: &drop_auth::Nonce,
-    ) -> bool {
+    ) -> Option<PublicKey> {
         tokio::task::block_in_place(|| {
             let auth_req = drop_auth::http::Authorization::parse(auth_header_value)?;
-            let pubkey = (self.public)(peer_ip)?;
-            drop_auth::authorize(nonce, &self.secret, &pubkey, &auth_req)
+
+            // The ticket does not name the peer, it is the paired key it
+            // verifies against which proves the identity
+            let paired: Vec<_> = self
+                .paired
+                .read()
+                .expect("Paired keys lock poisoned")
+                .values()
+                .copied()
+                .collect();
+
+            (self.public)(peer_ip)
+                .into_iter()
+                .chain(paired)
+                .find(|pubkey| {
+                    drop_auth::authorize(nonce, &self.secret, pubkey, &auth_req).is_some()
+                })
         })
-        .is_some()
     }
 
+    /// Returns the header value together with the key of the peer the ticket
+    /// is made for. The identity the peer was last seen with on the address
+    /// selects the paired key when the callback does not know the peer
     pub fn create_ticket_header_val(
         &self,
         peer_ip: IpAddr,
+        peer_identity: Option<&str>,
         www_auth_header_value: &str,
-    ) -> anyhow::Result<String> {
+    ) -> anyhow::Result<(String, PublicKey)> {
         use anyhow::Context;
 
         tokio::task::block_in_place(|| {
             let resp = drop_auth::http::WWWAuthenticate::parse(www_auth_header_value)
                 .context("Failed to parse 'www-authenticate' header")?;
 
-            let public = (self.public)(peer_ip).context("Failed to fetch peer's public key")?;
+            let public = (self.public)(peer_ip)
+                .or_else(|| peer_identity.and_then(|identity| self.paired_key(identity)))
+                .context("Failed to fetch peer's public key")?;
 
             let ticket = drop_auth::create_ticket(&self.secret, &public, resp)
                 .context("Failed to create auth ticket")?;
 
-            anyhow::Ok(ticket.to_string())
+            anyhow::Ok((ticket.to_string(), public))
         })
     }
 }
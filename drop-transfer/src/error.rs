@@ -50,6 +50,12 @@ pub enum Error {
     ChecksumMismatch,
     #[error("File is rejected")]
     Rejected,
+    #[error("Share is unknown, expired or exhausted")]
+    ShareUnavailable,
+    #[error("No pairing code is pending or it has expired")]
+    PairingUnavailable,
+    #[error("Setting cannot be changed at runtime: {0}")]
+    ConfigNotUpdatable(String),
 }
 
 impl Error {
@@ -102,6 +108,9 @@ impl From<&Error> for u32 {
             Error::StorageError => Status::StorageError as _,
             Error::ChecksumMismatch => Status::FileChecksumMismatch as _,
             Error::Rejected => Status::FileRejected as _,
+            Error::ShareUnavailable => Status::ShareUnavailable as _,
+            Error::PairingUnavailable => Status::PairingUnavailable as _,
+            Error::ConfigNotUpdatable(_) => Status::InvalidArgument as _,
         }
     }
 }
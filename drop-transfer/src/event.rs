@@ -1,8 +1,12 @@
-use std::path::Path;
+use std::{net::IpAddr, path::Path};
 
 use uuid::Uuid;
 
-use crate::{file::FileId, utils::Hidden, Error, Transfer};
+use crate::{
+    file::{ArchiveEntry, FileId},
+    utils::Hidden,
+    Error, Transfer,
+};
 
 #[derive(Debug)]
 pub struct DownloadSuccess {
@@ -10,22 +14,47 @@ pub struct DownloadSuccess {
     pub final_path: Hidden<Box<Path>>,
 }
 
+/// Optional explanation of a cancel or reject, passed on to the peer. The
+/// meaning of the code is up to the application
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reason {
+    pub code: u32,
+    pub message: String,
+}
+
+impl From<Reason> for drop_storage::types::Reason {
+    fn from(value: Reason) -> Self {
+        Self {
+            code: value.code,
+            message: value.message,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum Event {
-    RequestReceived(Transfer),
+    // The transfer together with the local address it arrived on
+    RequestReceived(Transfer, IpAddr),
     RequestQueued(Transfer),
 
+    // Files appended to an already running transfer
+    RequestAppendReceived(Transfer, Vec<FileId>),
+    RequestAppendQueued(Transfer, Vec<FileId>),
+
     FileUploadStarted(Transfer, FileId),
     FileDownloadStarted(Transfer, FileId, String),
 
     FileUploadProgress(Transfer, FileId, u64),
     FileDownloadProgress(Transfer, FileId, u64),
 
+    FileUploadArchiveEntry(Transfer, FileId, ArchiveEntry),
+    FileDownloadArchiveEntry(Transfer, FileId, ArchiveEntry),
+
     FileUploadSuccess(Transfer, FileId),
     FileDownloadSuccess(Transfer, DownloadSuccess),
 
-    FileUploadCancelled(Transfer, FileId, bool),
-    FileDownloadCancelled(Transfer, FileId, bool),
+    FileUploadCancelled(Transfer, FileId, bool, Option<Reason>),
+    FileDownloadCancelled(Transfer, FileId, bool, Option<Reason>),
 
     FileUploadFailed(Transfer, FileId, Error),
     FileDownloadFailed(Transfer, FileId, Error),
@@ -34,14 +63,46 @@ pub enum Event {
         transfer_id: Uuid,
         file_id: FileId,
         by_peer: bool,
+        reason: Option<Reason>,
     },
     FileDownloadRejected {
         transfer_id: Uuid,
         file_id: FileId,
         by_peer: bool,
+        reason: Option<Reason>,
     },
 
     TransferCanceled(Transfer, bool, bool),
 
+    // All of the files were rejected at once and the connection closed
+    TransferRejected(Transfer, bool, Option<Reason>),
+
     TransferFailed(Transfer, Error, bool),
+
+    // The incoming transfer was not acted upon within the accept timeout
+    TransferExpired(Transfer),
+
+    // The peer asks for the files from the exported directory, awaits the
+    // approval
+    FileRequestReceived {
+        request_id: Uuid,
+        peer: IpAddr,
+        export: String,
+        paths: Vec<String>,
+    },
+    // The peer denied the files we requested from it
+    FileRequestDenied {
+        request_id: Uuid,
+        peer: IpAddr,
+    },
+
+    // Pairing with the peer using the short code. Started when we connect to
+    // the peer, requested when the peer connects to us with our code
+    PairingStarted(IpAddr),
+    PairingRequested(IpAddr),
+    PairingCompleted {
+        peer: IpAddr,
+        identity: String,
+    },
+    PairingFailed(IpAddr, Error),
 }
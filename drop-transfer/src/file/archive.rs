@@ -0,0 +1,854 @@
+//! Directories can be sent as a single tar stream instead of separate files.
+//! This saves the per file round trips for trees with lots of small files.
+//! The stream is generated lazily out of the walked entries, so the exact size
+//! is known upfront and the stream can be resumed at any offset
+
+use std::{
+    fs,
+    io::{self, Read, Seek, Write},
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+    time::UNIX_EPOCH,
+};
+
+use tar::{EntryType, Header};
+
+use super::{reader, File, FileKind, FileSubPath};
+use crate::{quarantine::PathExt, utils::Hidden, Error};
+
+const BLOCK_SIZE: u64 = 512;
+// The archive is terminated with two empty blocks
+const TRAILER_SIZE: u64 = 2 * BLOCK_SIZE;
+const NAME_FIELD_LEN: usize = 100;
+const LONG_LINK_NAME: &[u8] = b"././@LongLink";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    Deflate,
+}
+
+/// An entry of the archive that was fully transferred
+#[derive(Clone, Debug)]
+pub struct ArchiveEntry {
+    pub path: FileSubPath,
+    pub size: u64,
+}
+
+#[derive(Debug)]
+pub struct Archive {
+    root: Hidden<PathBuf>,
+    members: Vec<Member>,
+    size: u64,
+}
+
+#[derive(Debug)]
+struct Member {
+    file: File,
+    // Path relative to the archive root, with `/` separators
+    name: String,
+    start: u64,
+    header_len: u64,
+}
+
+impl Archive {
+    pub(super) fn new(root: PathBuf, files: Vec<File>) -> Self {
+        let mut members = Vec::with_capacity(files.len());
+        let mut start = 0;
+
+        for file in files {
+            // The root itself is created by the receiver
+            let name = file.subpath().iter().skip(1).cloned().collect::<Vec<_>>();
+            if name.is_empty() {
+                continue;
+            }
+
+            let mut member = Member {
+                file,
+                name: name.join("/"),
+                start,
+                header_len: 0,
+            };
+            member.header_len = member.header().len() as u64;
+
+            start = member.end();
+            members.push(member);
+        }
+
+        Self {
+            root: Hidden(root),
+            members,
+            size: start + TRAILER_SIZE,
+        }
+    }
+
+    pub(crate) fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub(crate) fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The entries which content ends within the given range of the stream
+    pub(crate) fn entries_done(
+        &self,
+        from: u64,
+        to: u64,
+    ) -> impl Iterator<Item = ArchiveEntry> + '_ {
+        let first = self.members.partition_point(|m| m.content_end() <= from);
+
+        self.members[first..]
+            .iter()
+            .take_while(move |m| m.content_end() <= to)
+            .map(|m| ArchiveEntry {
+                path: m.file.subpath().clone(),
+                size: m.file.size(),
+            })
+    }
+}
+
+impl Member {
+    fn content_end(&self) -> u64 {
+        self.start + self.header_len + self.file.size()
+    }
+
+    fn end(&self) -> u64 {
+        self.start + self.header_len + padded(self.file.size())
+    }
+
+    fn header(&self) -> Vec<u8> {
+        let mut blocks = Vec::new();
+
+        let mut name = self.name.clone();
+        let (kind, mode) = match &self.file.kind {
+            FileKind::DirToSend { .. } => {
+                name.push('/');
+                (EntryType::Directory, 0o755)
+            }
+            FileKind::SymlinkToSend { .. } => (EntryType::Symlink, 0o777),
+            _ => (EntryType::Regular, 0o644),
+        };
+
+        let target = self
+            .file
+            .symlink_target()
+            .map(|target| target.to_string_lossy().replace('\\', "/"));
+
+        if name.len() > NAME_FIELD_LEN {
+            push_long_entry(&mut blocks, EntryType::GNULongName, name.as_bytes());
+        }
+        if let Some(target) = target.as_ref().filter(|t| t.len() > NAME_FIELD_LEN) {
+            push_long_entry(&mut blocks, EntryType::GNULongLink, target.as_bytes());
+        }
+
+        let mut header = Header::new_gnu();
+        copy_truncated(&mut header.as_old_mut().name, name.as_bytes());
+        if let Some(target) = &target {
+            copy_truncated(&mut header.as_old_mut().linkname, target.as_bytes());
+        }
+        header.set_entry_type(kind);
+        header.set_mode(mode);
+        header.set_size(self.file.size());
+        header.set_mtime(self.mtime());
+        header.set_cksum();
+
+        blocks.extend_from_slice(header.as_bytes());
+        blocks
+    }
+
+    fn mtime(&self) -> u64 {
+        match &self.file.kind {
+            FileKind::FileToSend { meta, .. } => meta
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |time| time.as_secs()),
+            _ => 0,
+        }
+    }
+}
+
+fn push_long_entry(blocks: &mut Vec<u8>, kind: EntryType, data: &[u8]) {
+    let mut header = Header::new_gnu();
+    copy_truncated(&mut header.as_old_mut().name, LONG_LINK_NAME);
+    header.set_entry_type(kind);
+    header.set_mode(0o644);
+    header.set_size(data.len() as u64 + 1);
+    header.set_cksum();
+
+    blocks.extend_from_slice(header.as_bytes());
+    blocks.extend_from_slice(data);
+    blocks.push(0);
+    blocks.resize(padded(blocks.len() as u64) as usize, 0);
+}
+
+fn copy_truncated(field: &mut [u8], value: &[u8]) {
+    let len = value.len().min(field.len());
+    field[..len].copy_from_slice(&value[..len]);
+}
+
+fn padded(size: u64) -> u64 {
+    size.div_ceil(BLOCK_SIZE) * BLOCK_SIZE
+}
+
+// Generates the tar stream on the fly
+pub(super) struct ArchiveReader {
+    archive: Arc<Archive>,
+    pos: u64,
+    // The member the current position falls into
+    index: usize,
+    header: Option<(usize, Vec<u8>)>,
+    content: Option<(usize, Box<dyn reader::Reader>)>,
+}
+
+impl ArchiveReader {
+    pub(super) fn new(archive: Arc<Archive>) -> Self {
+        Self {
+            archive,
+            pos: 0,
+            index: 0,
+            header: None,
+            content: None,
+        }
+    }
+
+    fn read_header(&mut self, offset: u64, buf: &mut [u8]) -> usize {
+        let index = self.index;
+        let member = &self.archive.members[index];
+
+        let header = match &mut self.header {
+            Some((i, header)) if *i == index => header,
+            cache => &mut cache.insert((index, member.header())).1,
+        };
+
+        let header = &header[offset as usize..];
+        let n = header.len().min(buf.len());
+        buf[..n].copy_from_slice(&header[..n]);
+        n
+    }
+
+    fn read_content(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let index = self.index;
+        let member = &self.archive.members[index];
+
+        let content = match &mut self.content {
+            Some((i, content)) if *i == index => content,
+            cache => {
+                let (source, meta) = match &member.file.kind {
+                    FileKind::FileToSend { source, meta, .. } => (source, meta),
+                    _ => return Err(io::Error::other("Unexpected entry")),
+                };
+
+                let mut content = reader::open(source).map_err(into_io)?;
+
+                let mtime = content.meta().map_err(into_io)?.modified().ok();
+                if mtime != meta.modified().ok() {
+                    return Err(into_io(Error::FileModified));
+                }
+
+                &mut cache.insert((index, content)).1
+            }
+        };
+
+        if content.bytes_read() != offset {
+            content.seek(io::SeekFrom::Start(offset))?;
+        }
+
+        let limit = (member.file.size() - offset).min(buf.len() as u64) as usize;
+        let n = content.read(&mut buf[..limit])?;
+
+        if n == 0 {
+            return Err(into_io(Error::MismatchedSize));
+        }
+
+        Ok(n)
+    }
+}
+
+impl io::Read for ArchiveReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.pos >= self.archive.size {
+            return Ok(0);
+        }
+
+        let members = &self.archive.members;
+        while self.index < members.len() && members[self.index].end() <= self.pos {
+            self.index += 1;
+        }
+
+        let n = match members.get(self.index) {
+            Some(member) => {
+                let offset = self.pos - member.start;
+                let content_end = member.content_end();
+                let end = member.end();
+
+                if offset < member.header_len {
+                    self.read_header(offset, buf)
+                } else if self.pos < content_end {
+                    let header_len = member.header_len;
+                    self.read_content(offset - header_len, buf)?
+                } else {
+                    fill_zeros(buf, end - self.pos)
+                }
+            }
+            None => fill_zeros(buf, self.archive.size - self.pos),
+        };
+
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl io::Seek for ArchiveReader {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        self.pos = match pos {
+            io::SeekFrom::Start(off) => off,
+            io::SeekFrom::End(off) => self.archive.size.wrapping_add(off as _),
+            io::SeekFrom::Current(off) => self.pos.wrapping_add(off as _),
+        };
+
+        self.index = self
+            .archive
+            .members
+            .partition_point(|m| m.end() <= self.pos);
+
+        Ok(self.pos)
+    }
+}
+
+impl reader::Reader for ArchiveReader {
+    fn bytes_read(&self) -> u64 {
+        self.pos
+    }
+
+    fn meta(&mut self) -> crate::Result<fs::Metadata> {
+        let meta = fs::metadata(&self.archive.root.0)?;
+        Ok(meta)
+    }
+}
+
+fn fill_zeros(buf: &mut [u8], remaining: u64) -> usize {
+    let n = remaining.min(buf.len() as u64) as usize;
+    buf[..n].fill(0);
+    n
+}
+
+fn into_io(err: Error) -> io::Error {
+    match err {
+        Error::Io(err) => err,
+        err => io::Error::other(err.to_string()),
+    }
+}
+
+/// Compresses the stream chunk by chunk. Each chunk is flushed so that the
+/// receiver is able to decompress it right away
+pub(crate) struct Encoder(flate2::write::DeflateEncoder<Vec<u8>>);
+
+impl Encoder {
+    pub(crate) fn new() -> Self {
+        Self(flate2::write::DeflateEncoder::new(
+            Vec::new(),
+            flate2::Compression::default(),
+        ))
+    }
+
+    pub(crate) fn compress(&mut self, chunk: &[u8]) -> io::Result<Vec<u8>> {
+        self.0.write_all(chunk)?;
+        self.0.flush()?;
+        Ok(std::mem::take(self.0.get_mut()))
+    }
+}
+
+pub(crate) struct Decoder(flate2::Decompress);
+
+impl Decoder {
+    pub(crate) fn new() -> Self {
+        Self(flate2::Decompress::new(false))
+    }
+
+    /// Decompress the chunk, failing as soon as the output grows over `limit`.
+    /// The output buffer never grows much past the limit, so a small chunk
+    /// cannot inflate into a huge allocation
+    pub(crate) fn decompress(&mut self, chunk: &[u8], limit: u64) -> crate::Result<Vec<u8>> {
+        let cap = usize::try_from(limit.saturating_add(1)).unwrap_or(usize::MAX);
+
+        let mut out = Vec::with_capacity(chunk.len().saturating_mul(4).clamp(1, cap));
+        let mut input = chunk;
+
+        loop {
+            let (total_in, total_out) = (self.0.total_in(), self.0.total_out());
+            let status = self
+                .0
+                .decompress_vec(input, &mut out, flate2::FlushDecompress::Sync)
+                .map_err(io::Error::from)?;
+
+            if out.len() as u64 > limit {
+                return Err(Error::MismatchedSize);
+            }
+
+            input = &input[(self.0.total_in() - total_in) as usize..];
+            let progress = self.0.total_in() != total_in || self.0.total_out() != total_out;
+
+            // Everything is flushed once there is room left in the buffer
+            if status == flate2::Status::StreamEnd
+                || (input.is_empty() && out.len() < out.capacity())
+            {
+                break;
+            }
+
+            if out.len() < out.capacity() && !progress {
+                return Err(Error::UnexpectedData);
+            }
+
+            if out.len() == out.capacity() {
+                out.reserve_exact(out.capacity().min(cap - out.len()).max(1));
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+/// Follows the tar stream on the receiver side to report the entries as soon
+/// as they are received. The tracker stops at the first malformed header, the
+/// stream is validated on extraction anyway
+pub(crate) struct Tracker {
+    root: FileSubPath,
+    block: Vec<u8>,
+    long_name: Option<Vec<u8>>,
+    state: TrackerState,
+}
+
+enum TrackerState {
+    Header,
+    LongName { remaining: u64, data: Vec<u8> },
+    LongLink { remaining: u64 },
+    Content { entry: ArchiveEntry, remaining: u64 },
+    Skip(u64),
+    End,
+}
+
+impl Tracker {
+    pub(crate) fn new(root: FileSubPath) -> Self {
+        Self {
+            root,
+            block: Vec::with_capacity(BLOCK_SIZE as usize),
+            long_name: None,
+            state: TrackerState::Header,
+        }
+    }
+
+    pub(crate) fn feed(&mut self, mut data: &[u8]) -> Vec<ArchiveEntry> {
+        let mut done = Vec::new();
+
+        while !data.is_empty() {
+            match &mut self.state {
+                TrackerState::Header => {
+                    let n = (BLOCK_SIZE as usize - self.block.len()).min(data.len());
+                    self.block.extend_from_slice(&data[..n]);
+                    data = &data[n..];
+
+                    if self.block.len() == BLOCK_SIZE as usize {
+                        self.state = self.parse_header(&mut done);
+                        self.block.clear();
+                    }
+                }
+                TrackerState::LongName {
+                    remaining,
+                    data: name,
+                } => {
+                    name.extend_from_slice(consume(remaining, &mut data));
+
+                    if *remaining == 0 {
+                        let size = name.len() as u64;
+                        let mut name = std::mem::take(name);
+                        if let Some(end) = name.iter().position(|&b| b == 0) {
+                            name.truncate(end);
+                        }
+
+                        self.long_name = Some(name);
+                        self.state = TrackerState::Skip(padded(size) - size);
+                    }
+                }
+                TrackerState::LongLink { remaining } => {
+                    let total = *remaining;
+                    consume(remaining, &mut data);
+
+                    if *remaining == 0 {
+                        self.state = TrackerState::Skip(padded(total) - total);
+                    }
+                }
+                TrackerState::Content { entry, remaining } => {
+                    consume(remaining, &mut data);
+
+                    if *remaining == 0 {
+                        let size = entry.size;
+                        done.push(entry.clone());
+                        self.state = TrackerState::Skip(padded(size) - size);
+                    }
+                }
+                TrackerState::Skip(remaining) => {
+                    consume(remaining, &mut data);
+
+                    if *remaining == 0 {
+                        self.state = TrackerState::Header;
+                    }
+                }
+                TrackerState::End => break,
+            }
+        }
+
+        done
+    }
+
+    fn parse_header(&mut self, done: &mut Vec<ArchiveEntry>) -> TrackerState {
+        if self.block.iter().all(|&b| b == 0) {
+            return TrackerState::End;
+        }
+
+        let header = Header::from_byte_slice(&self.block);
+        let size = match header.entry_size() {
+            Ok(size) => size,
+            Err(_) => return TrackerState::End,
+        };
+
+        let kind = header.entry_type();
+        if kind.is_gnu_longname() {
+            return TrackerState::LongName {
+                remaining: size,
+                data: Vec::new(),
+            };
+        }
+        if kind.is_gnu_longlink() {
+            return TrackerState::LongLink { remaining: size };
+        }
+        if kind.is_pax_global_extensions() || kind.is_pax_local_extensions() {
+            return TrackerState::Skip(padded(size));
+        }
+
+        let name = self
+            .long_name
+            .take()
+            .unwrap_or_else(|| header.path_bytes().into_owned());
+
+        let mut path = self.root.clone();
+        String::from_utf8_lossy(&name)
+            .split('/')
+            .filter(|part| !part.is_empty())
+            .for_each(|part| path.append(part.to_string()));
+
+        let entry = ArchiveEntry {
+            path,
+            size: if kind.is_file() { size } else { 0 },
+        };
+
+        if entry.size == 0 {
+            done.push(entry);
+            TrackerState::Skip(padded(size))
+        } else {
+            TrackerState::Content {
+                remaining: entry.size,
+                entry,
+            }
+        }
+    }
+}
+
+// Takes at most `remaining` bytes from the front of `data`
+fn consume<'a>(remaining: &mut u64, data: &mut &'a [u8]) -> &'a [u8] {
+    let n = (*remaining).min(data.len() as u64) as usize;
+    let (head, tail) = data.split_at(n);
+    *remaining -= n as u64;
+    *data = tail;
+    head
+}
+
+/// Unpack the received archive into `dst`. Entries escaping the destination
+/// directory are rejected, symlinks are created only when the target resolves
+/// inside of it. Already existing files are not overwritten, a number is
+/// appended to the file name instead. This is a blocking operation
+pub(crate) fn extract(archive: &Path, dst: &Path) -> crate::Result<()> {
+    fs::create_dir_all(dst)?;
+
+    let mut archive = tar::Archive::new(fs::File::open(archive)?);
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+
+        let subpath = entry.path()?.into_owned();
+        if subpath.as_os_str().is_empty()
+            || !subpath
+                .components()
+                .all(|comp| matches!(comp, Component::Normal(_)))
+        {
+            return Err(Error::BadPath(
+                "Archive entry points outside of the destination directory".into(),
+            ));
+        }
+
+        let path = dst.join(&subpath);
+        ensure_no_links(dst, &path)?;
+
+        let kind = entry.header().entry_type();
+        match kind {
+            EntryType::Directory => fs::create_dir_all(&path)?,
+            EntryType::Regular | EntryType::Continuous => {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+
+                let (mut file, path) = create_unique(&path)?;
+                io::copy(&mut entry, &mut file)?;
+                drop(file);
+
+                path.quarantine()?;
+            }
+            EntryType::Symlink => {
+                let target = entry
+                    .link_name()?
+                    .ok_or_else(|| Error::BadPath("Missing symlink target".into()))?
+                    .into_owned();
+
+                crate::utils::check_link_target(&path, &target, dst)?;
+
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+
+                #[cfg(unix)]
+                std::os::unix::fs::symlink(&target, &path)?;
+
+                #[cfg(not(unix))]
+                return Err(Error::Io(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "Symlinks are not supported on this platform",
+                )));
+            }
+            _ => {
+                return Err(Error::BadPath(format!(
+                    "Unsupported archive entry type: {kind:?}"
+                )))
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Make sure we do not write through the symlinks created by the archive itself
+fn ensure_no_links(root: &Path, path: &Path) -> crate::Result<()> {
+    let mut ancestors = path
+        .ancestors()
+        .skip(1)
+        .take_while(|ancestor| *ancestor != root);
+
+    let is_link = ancestors.any(
+        |ancestor| matches!(ancestor.symlink_metadata(), Ok(meta) if meta.file_type().is_symlink()),
+    );
+
+    if is_link {
+        return Err(Error::BadPath(
+            "Archive entry points outside of the destination directory".into(),
+        ));
+    }
+
+    Ok(())
+}
+
+fn create_unique(path: &Path) -> crate::Result<(fs::File, PathBuf)> {
+    let mut opts = fs::OpenOptions::new();
+    opts.write(true).create_new(true);
+
+    for path in crate::utils::filepath_variants(path)? {
+        match opts.open(&path) {
+            Ok(file) => return Ok((file, path)),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    unreachable!("The filepath variants iterator should never end")
+}
+
+#[cfg(test)]
+mod tests {
+    use drop_config::DropConfig;
+
+    use super::*;
+    use crate::file::GatherOpts;
+
+    fn make_archive(root: &Path) -> Arc<Archive> {
+        let long = "a".repeat(120);
+
+        fs::create_dir_all(root.join("dir/empty")).unwrap();
+        fs::create_dir_all(root.join(&long)).unwrap();
+        fs::write(root.join("top.txt"), b"top").unwrap();
+        fs::write(root.join("dir/nested.txt"), vec![7u8; 1500]).unwrap();
+        fs::write(root.join(&long).join(&long), b"long").unwrap();
+
+        let gathered = File::archive_from_path(
+            root,
+            &DropConfig::default(),
+            &GatherOpts::default(),
+            Compression::None,
+            &mut |_| (),
+        )
+        .unwrap();
+
+        match &gathered.files[0].kind {
+            FileKind::ArchiveToSend { archive, .. } => archive.clone(),
+            _ => panic!("Expected an archive"),
+        }
+    }
+
+    fn read_all(archive: &Arc<Archive>, from: u64) -> Vec<u8> {
+        let mut reader = ArchiveReader::new(archive.clone());
+        reader.seek(io::SeekFrom::Start(from)).unwrap();
+
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn roundtrip() {
+        let tmp = tempfile::tempdir().unwrap();
+        let archive = make_archive(&tmp.path().join("root"));
+
+        let stream = read_all(&archive, 0);
+        assert_eq!(stream.len() as u64, archive.size());
+
+        // Resuming at any offset yields the rest of the same stream
+        for from in [1, 511, 512, 2000, archive.size() - 1] {
+            assert_eq!(read_all(&archive, from), &stream[from as usize..]);
+        }
+
+        let tar_path = tmp.path().join("root.tar");
+        fs::write(&tar_path, &stream).unwrap();
+
+        let dst = tmp.path().join("dst");
+        extract(&tar_path, &dst).unwrap();
+
+        let long = "a".repeat(120);
+        assert_eq!(fs::read(dst.join("top.txt")).unwrap(), b"top");
+        assert_eq!(
+            fs::read(dst.join("dir/nested.txt")).unwrap(),
+            vec![7u8; 1500]
+        );
+        assert_eq!(fs::read(dst.join(&long).join(&long)).unwrap(), b"long");
+        assert!(dst.join("dir/empty").is_dir());
+    }
+
+    #[test]
+    fn tracker_follows_stream() {
+        let tmp = tempfile::tempdir().unwrap();
+        let archive = make_archive(&tmp.path().join("root"));
+        let stream = read_all(&archive, 0);
+
+        let mut tracker = Tracker::new(FileSubPath::from("root"));
+        let tracked: Vec<_> = stream
+            .chunks(100)
+            .flat_map(|chunk| tracker.feed(chunk))
+            .map(|entry| (entry.path.to_string(), entry.size))
+            .collect();
+
+        let expected: Vec<_> = archive
+            .entries_done(0, archive.size())
+            .map(|entry| (entry.path.to_string(), entry.size))
+            .collect();
+
+        assert_eq!(tracked.len(), 4);
+        assert_eq!(tracked, expected);
+    }
+
+    #[test]
+    fn compression_roundtrip() {
+        let data = vec![42u8; 10_000];
+
+        let mut encoder = Encoder::new();
+        let mut decoder = Decoder::new();
+
+        let mut out = Vec::new();
+        for chunk in data.chunks(1024) {
+            let compressed = encoder.compress(chunk).unwrap();
+
+            // Each chunk must be decodable on its own
+            assert_eq!(decoder.decompress(&compressed, 1024).unwrap(), chunk);
+            out.extend_from_slice(chunk);
+        }
+
+        assert_eq!(out, data);
+    }
+
+    #[test]
+    fn decompression_limit() {
+        let compressed = Encoder::new().compress(&vec![0u8; 1 << 20]).unwrap();
+
+        assert!(matches!(
+            Decoder::new().decompress(&compressed, 1000),
+            Err(Error::MismatchedSize)
+        ));
+        assert_eq!(
+            Decoder::new()
+                .decompress(&compressed, 1 << 20)
+                .unwrap()
+                .len(),
+            1 << 20
+        );
+    }
+
+    #[test]
+    fn reject_escaping_entries() {
+        let tmp = tempfile::tempdir().unwrap();
+        let tar_path = tmp.path().join("evil.tar");
+
+        let mut header = Header::new_gnu();
+        let name = b"../evil.txt";
+        header.as_gnu_mut().unwrap().name[..name.len()].copy_from_slice(name);
+        header.set_size(4);
+        header.set_entry_type(EntryType::Regular);
+        header.set_cksum();
+
+        let mut builder = tar::Builder::new(fs::File::create(&tar_path).unwrap());
+        builder.append(&header, &b"evil"[..]).unwrap();
+        builder.finish().unwrap();
+        drop(builder);
+
+        let dst = tmp.path().join("dst");
+        assert!(extract(&tar_path, &dst).is_err());
+        assert!(!tmp.path().join("evil.txt").exists());
+    }
+    #[cfg(unix)]
+    #[test]
+    fn reject_chained_links() {
+        let tmp = tempfile::tempdir().unwrap();
+        let tar_path = tmp.path().join("evil.tar");
+
+        let mut builder = tar::Builder::new(fs::File::create(&tar_path).unwrap());
+
+        let mut header = Header::new_gnu();
+        header.set_entry_type(EntryType::Directory);
+        header.set_size(0);
+        builder.append_data(&mut header, "a", io::empty()).unwrap();
+
+        // Each link looks harmless when resolved lexically, but the OS follows
+        // `a/x` to the destination root first
+        for (path, target) in [("a/x", ".."), ("y", "a/x/../..")] {
+            let mut header = Header::new_gnu();
+            header.set_entry_type(EntryType::Symlink);
+            header.set_size(0);
+            header.set_link_name(target).unwrap();
+            builder.append_data(&mut header, path, io::empty()).unwrap();
+        }
+
+        builder.finish().unwrap();
+        drop(builder);
+
+        let dst = tmp.path().join("dst");
+        assert!(extract(&tar_path, &dst).is_err());
+        assert!(dst.join("a/x").symlink_metadata().is_ok());
+        assert!(dst.join("y").symlink_metadata().is_err());
+    }
+}
@@ -0,0 +1,135 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::{
+    gitignore::{Gitignore, GitignoreBuilder},
+    Match,
+};
+
+use super::GatherOpts;
+use crate::Error;
+
+// Decides which of the entries found while walking the directory tree make it
+// into the transfer
+pub(super) struct Filter<'a> {
+    root: &'a Path,
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+    skip_hidden: bool,
+    ignore_file: Option<&'a str>,
+    ignores: HashMap<PathBuf, Gitignore>,
+}
+
+impl<'a> Filter<'a> {
+    pub(super) fn new(root: &'a Path, opts: &'a GatherOpts) -> crate::Result<Self> {
+        let include = if opts.include.is_empty() {
+            None
+        } else {
+            Some(build_globset(&opts.include)?)
+        };
+
+        Ok(Self {
+            root,
+            include,
+            exclude: build_globset(&opts.exclude)?,
+            skip_hidden: opts.skip_hidden,
+            ignore_file: opts.ignore_file.as_deref(),
+            ignores: HashMap::new(),
+        })
+    }
+
+    // Needs to be called for each directory before its content is checked
+    pub(super) fn enter_dir(&mut self, dir: &Path) -> crate::Result<()> {
+        let name = match self.ignore_file {
+            Some(name) => name,
+            None => return Ok(()),
+        };
+
+        let path = dir.join(name);
+        if !path.is_file() {
+            return Ok(());
+        }
+
+        let mut builder = GitignoreBuilder::new(dir);
+        if let Some(err) = builder.add(&path) {
+            return Err(Error::BadPath(format!("Invalid ignore file: {err}")));
+        }
+
+        let gitignore = builder
+            .build()
+            .map_err(|err| Error::BadPath(format!("Invalid ignore file: {err}")))?;
+
+        self.ignores.insert(dir.to_path_buf(), gitignore);
+        Ok(())
+    }
+
+    // Excluded directories are skipped together with the whole content
+    pub(super) fn is_excluded(&self, path: &Path, is_dir: bool) -> bool {
+        let relative = match path.strip_prefix(self.root) {
+            Ok(relative) if relative.as_os_str().is_empty() => return false,
+            Ok(relative) => relative,
+            Err(_) => return false,
+        };
+
+        if self.skip_hidden
+            && matches!(path.file_name(), Some(name) if name.to_string_lossy().starts_with('.'))
+        {
+            return true;
+        }
+
+        if self.exclude.is_match(relative) {
+            return true;
+        }
+
+        // The rules from the deeper ignore files take precedence
+        for dir in path.ancestors().skip(1) {
+            if let Some(gitignore) = self.ignores.get(dir) {
+                match gitignore.matched(path, is_dir) {
+                    Match::Ignore(_) => return true,
+                    Match::Whitelist(_) => return false,
+                    Match::None => (),
+                }
+            }
+
+            if dir == self.root {
+                break;
+            }
+        }
+
+        false
+    }
+
+    // Include patterns apply only to the entries being sent, directories are
+    // always traversed
+    pub(super) fn is_included(&self, path: &Path) -> bool {
+        match (&self.include, path.strip_prefix(self.root)) {
+            (Some(include), Ok(relative)) => include.is_match(relative),
+            _ => true,
+        }
+    }
+}
+
+fn build_globset(patterns: &[String]) -> crate::Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+
+    for pattern in patterns {
+        let glob = |pattern: &str| {
+            Glob::new(pattern)
+                .map_err(|err| Error::BadPath(format!("Invalid pattern {pattern:?}: {err}")))
+        };
+
+        builder.add(glob(pattern)?);
+
+        // Like in `.gitignore`, the patterns without a slash are not anchored
+        if !pattern.contains('/') {
+            builder.add(glob(&format!("**/{pattern}"))?);
+        }
+    }
+
+    builder
+        .build()
+        .map_err(|err| Error::BadPath(format!("Invalid patterns: {err}")))
+}
@@ -1,14 +1,18 @@
+pub(crate) mod archive;
+mod filter;
 mod id;
 mod reader;
 
 #[cfg(unix)]
 use std::os::unix::prelude::*;
 use std::{
+    collections::HashSet,
     fs::{
         OpenOptions, {self},
     },
     io::{self, Read},
     path::{Path, PathBuf},
+    sync::Arc,
 };
 
 use drop_analytics::FileInfo;
@@ -18,6 +22,8 @@ pub use reader::FileReader;
 use sha2::Digest;
 use walkdir::WalkDir;
 
+pub use self::archive::{Archive, ArchiveEntry, Compression};
+use self::{archive::ArchiveReader, filter::Filter};
 use crate::{utils::Hidden, Error};
 
 const HEADER_SIZE: usize = 1024;
@@ -33,6 +39,80 @@ pub enum FileKind {
     FileToRecv {
         size: u64,
     },
+    DirToSend {
+        path: Hidden<PathBuf>,
+    },
+    DirToRecv,
+    SymlinkToSend {
+        path: Hidden<PathBuf>,
+        target: Hidden<PathBuf>,
+    },
+    SymlinkToRecv {
+        target: Hidden<PathBuf>,
+    },
+    ArchiveToSend {
+        archive: Arc<Archive>,
+        compression: Compression,
+    },
+    ArchiveToRecv {
+        size: u64,
+        compression: Compression,
+    },
+}
+
+/// Describes how symbolic links found inside of the directories are handled
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    /// Leave the links out of the transfer and report them as skipped
+    #[default]
+    Skip,
+    /// Send the content the links point to as if it was there
+    Follow,
+    /// Send relative links as link entries, the receiver recreates them
+    Preserve,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct GatherOpts {
+    pub symlinks: SymlinkPolicy,
+    /// Glob patterns matched against the path relative to the directory being
+    /// sent, the ones without a slash match at any depth like in `.gitignore`.
+    /// When not empty, only the matching entries are included
+    pub include: Vec<String>,
+    /// Glob patterns for the entries to leave out. Excluded directories are
+    /// not traversed
+    pub exclude: Vec<String>,
+    /// Leave out the entries which names start with a dot
+    pub skip_hidden: bool,
+    /// Name of the `.gitignore` style files to respect, e.g. ".gitignore".
+    /// The rules apply to the directory containing the file
+    pub ignore_file: Option<String>,
+    /// Leave out the entries that cannot be read instead of failing. The
+    /// errors are reported along with the skipped paths
+    pub skip_unreadable: bool,
+    /// Do not open the files while gathering. The mime type is detected when
+    /// the upload starts and the unreadable files fail only then
+    pub defer_mime: bool,
+}
+
+#[derive(Debug)]
+pub enum SkipReason {
+    Symlink,
+    SymlinkLoop,
+    AbsoluteSymlink,
+    Unreadable(Error),
+}
+
+#[derive(Debug)]
+pub struct Skipped {
+    pub path: Hidden<PathBuf>,
+    pub reason: SkipReason,
+}
+
+#[derive(Debug, Default)]
+pub struct Gathered {
+    pub files: Vec<File>,
+    pub skipped: Vec<Skipped>,
 }
 
 #[derive(Clone, Debug)]
@@ -50,75 +130,323 @@ pub struct File {
 }
 
 impl File {
-    fn walk(path: &Path, config: &DropConfig) -> Result<Vec<Self>, Error> {
+    fn walk(
+        path: &Path,
+        config: &DropConfig,
+        opts: &GatherOpts,
+        skipped: &mut Vec<Skipped>,
+        sink: &mut dyn FnMut(File) -> Result<(), Error>,
+    ) -> Result<(), Error> {
         let parent = path
             .parent()
             .ok_or_else(|| crate::Error::BadPath("Missing parent directory".into()))?;
 
-        let mut files = Vec::new();
-        let mut breadth = 0;
+        let make_subpath = |path: &Path| {
+            let subpath = path
+                .strip_prefix(parent)
+                .map_err(|err| crate::Error::BadPath(err.to_string()))?;
 
-        for entry in WalkDir::new(path).min_depth(1).into_iter() {
-            let entry = entry?;
-            let meta = entry.metadata()?;
+            FileSubPath::from_path(subpath)
+        };
 
-            if !meta.is_file() {
+        let mut dirs = Vec::new();
+        // Directories that have something to transfer inside
+        let mut populated = HashSet::new();
+        // Directories with some of the content left out by the filter
+        let mut filtered = HashSet::new();
+
+        let check_depth = |depth: usize| {
+            if depth > config.dir_depth_limit {
+                return Err(Error::TransferLimitsExceeded);
+            }
+
+            Ok(())
+        };
+
+        let follow_links = opts.symlinks == SymlinkPolicy::Follow;
+        let mut filter = Filter::new(path, opts)?;
+        let mut iter = WalkDir::new(path).follow_links(follow_links).into_iter();
+
+        while let Some(entry) = iter.next() {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) if err.loop_ancestor().is_some() => {
+                    skipped.push(Skipped {
+                        path: Hidden(err.path().unwrap_or(path).to_path_buf()),
+                        reason: SkipReason::SymlinkLoop,
+                    });
+                    continue;
+                }
+                Err(err) => {
+                    let path = err.path().unwrap_or(path).to_path_buf();
+                    tolerate::<()>(Err(err.into()), &path, opts, skipped)?;
+
+                    // Do not report the directory as empty if we failed to read it
+                    populated.insert(path);
+                    continue;
+                }
+            };
+
+            let is_dir = entry.file_type().is_dir();
+
+            if filter.is_excluded(entry.path(), is_dir) {
+                if is_dir {
+                    iter.skip_current_dir();
+                }
+                if let Some(parent) = entry.path().parent() {
+                    filtered.insert(parent.to_path_buf());
+                }
                 continue;
             }
 
-            if entry.depth() > config.dir_depth_limit {
-                return Err(Error::TransferLimitsExceeded);
+            if is_dir {
+                filter.enter_dir(entry.path())?;
+            } else if !filter.is_included(entry.path()) {
+                if let Some(parent) = entry.path().parent() {
+                    filtered.insert(parent.to_path_buf());
+                }
+                continue;
+            }
+
+            if entry.path_is_symlink() && !follow_links {
+                let target = match tolerate(
+                    fs::read_link(entry.path()).map_err(Into::into),
+                    entry.path(),
+                    opts,
+                    skipped,
+                )? {
+                    Some(target) => target,
+                    None => continue,
+                };
+
+                let reason = match opts.symlinks {
+                    SymlinkPolicy::Preserve if target.is_relative() => None,
+                    SymlinkPolicy::Preserve => Some(SkipReason::AbsoluteSymlink),
+                    _ => Some(SkipReason::Symlink),
+                };
+
+                if let Some(reason) = reason {
+                    skipped.push(Skipped {
+                        path: Hidden(entry.into_path()),
+                        reason,
+                    });
+                    continue;
+                }
+
+                check_depth(entry.depth())?;
+
+                if let Some(parent) = entry.path().parent() {
+                    populated.insert(parent.to_path_buf());
+                }
+
+                let subpath = make_subpath(entry.path())?;
+                sink(File::new_symlink(subpath, entry.into_path(), target)?)?;
+                continue;
             }
 
-            breadth += 1;
+            let meta = match tolerate(
+                entry.metadata().map_err(Into::into),
+                entry.path(),
+                opts,
+                skipped,
+            )? {
+                Some(meta) => meta,
+                None => continue,
+            };
 
-            if breadth > config.transfer_file_limit {
-                return Err(Error::TransferLimitsExceeded);
+            if meta.is_dir() {
+                dirs.push(entry);
+                continue;
             }
 
-            let subpath = entry
-                .path()
-                .strip_prefix(parent)
-                .map_err(|err| crate::Error::BadPath(err.to_string()))?;
+            if !meta.is_file() {
+                continue;
+            }
 
-            let subpath = FileSubPath::from_path(subpath)?;
+            let subpath = make_subpath(entry.path())?;
+            let file = match tolerate(
+                File::new(
+                    subpath,
+                    entry.path().to_path_buf(),
+                    meta,
+                    follow_links,
+                    opts,
+                ),
+                entry.path(),
+                opts,
+                skipped,
+            )? {
+                Some(file) => file,
+                None => continue,
+            };
+
+            check_depth(entry.depth())?;
+
+            if let Some(parent) = entry.path().parent() {
+                populated.insert(parent.to_path_buf());
+            }
 
-            let file = File::new(subpath, entry.into_path(), meta)?;
-            files.push(file);
+            sink(file)?;
         }
 
-        Ok(files)
+        // Walk the directories bottom-up so that the children are handled
+        // before their parents. Only the directories with no content are sent,
+        // the rest are recreated implicitly by the files inside. The ones left
+        // empty by the filter are dropped together with the content
+        for entry in dirs.into_iter().rev() {
+            if !populated.contains(entry.path()) {
+                if filtered.contains(entry.path()) || !filter.is_included(entry.path()) {
+                    if let Some(parent) = entry.path().parent() {
+                        filtered.insert(parent.to_path_buf());
+                    }
+                    continue;
+                }
+
+                check_depth(entry.depth())?;
+
+                let subpath = make_subpath(entry.path())?;
+                sink(File::new_dir(subpath, entry.path().to_path_buf())?)?;
+            }
+
+            if entry.depth() > 0 {
+                if let Some(parent) = entry.path().parent() {
+                    populated.insert(parent.to_path_buf());
+                }
+            }
+        }
+
+        Ok(())
     }
 
     pub fn from_path(path: impl Into<PathBuf>, config: &DropConfig) -> Result<Vec<Self>, Error> {
+        let Gathered { files, .. } = File::gather(path, config, &GatherOpts::default())?;
+        Ok(files)
+    }
+
+    /// Same as `from_path()` but allows to configure how the directory tree is
+    /// walked. Returns the entries that were left out along with the files
+    pub fn gather(
+        path: impl Into<PathBuf>,
+        config: &DropConfig,
+        opts: &GatherOpts,
+    ) -> Result<Gathered, Error> {
+        let mut files = Vec::new();
+        let skipped = File::gather_each(path, config, opts, &mut |file| {
+            push_limited(&mut files, file, config)
+        })?;
+
+        Ok(Gathered { files, skipped })
+    }
+
+    /// Walk the directory and pack its content into a single archive entry
+    /// streamed as tar. Progress is reported for each entry packed
+    pub fn archive_from_path(
+        path: impl Into<PathBuf>,
+        config: &DropConfig,
+        opts: &GatherOpts,
+        compression: Compression,
+        progress: &mut dyn FnMut(&File),
+    ) -> Result<Gathered, Error> {
         let path = path.into();
-        let meta = fs::symlink_metadata(&path)?;
 
-        let files = if meta.is_dir() {
-            File::walk(&path, config)?
+        let meta = fs::metadata(&path)?;
+        if !meta.is_dir() {
+            return Err(Error::BadPath("Only directories can be archived".into()));
+        }
+
+        let mut gathered = Gathered::default();
+        let mut files = Vec::new();
+        File::walk(&path, config, opts, &mut gathered.skipped, &mut |file| {
+            progress(&file);
+            push_limited(&mut files, file, config)
+        })?;
+
+        let abs = crate::utils::make_path_absolute(&path)?;
+        let mut hash = sha2::Sha256::new();
+        hash.update(abs.to_string_lossy().as_bytes());
+
+        gathered.files.push(Self {
+            file_id: FileId::from(hash),
+            subpath: FileSubPath::from_file_name(&path)?,
+            kind: FileKind::ArchiveToSend {
+                archive: Arc::new(Archive::new(path, files)),
+                compression,
+            },
+        });
+
+        Ok(gathered)
+    }
+
+    /// Same as `gather()` but hands each entry to `sink` as soon as it is
+    /// found instead of collecting them. Meant for streaming huge directory
+    /// trees, the number of the entries is not limited here. Returns the
+    /// entries that were left out
+    pub fn gather_each(
+        path: impl Into<PathBuf>,
+        config: &DropConfig,
+        opts: &GatherOpts,
+        sink: &mut dyn FnMut(File) -> Result<(), Error>,
+    ) -> Result<Vec<Skipped>, Error> {
+        let path = path.into();
+        let follow_links = opts.symlinks == SymlinkPolicy::Follow;
+
+        let mut skipped = Vec::new();
+
+        let meta = if follow_links {
+            fs::metadata(&path)
         } else {
-            let file = File::new(FileSubPath::from_file_name(&path)?, path, meta)?;
-            vec![file]
+            fs::symlink_metadata(&path)
         };
 
-        Ok(files)
+        let meta = match tolerate(meta.map_err(Into::into), &path, opts, &mut skipped)? {
+            Some(meta) => meta,
+            None => return Ok(skipped),
+        };
+
+        if meta.is_dir() {
+            File::walk(&path, config, opts, &mut skipped, sink)?;
+        } else {
+            let file = File::new(
+                FileSubPath::from_file_name(&path)?,
+                path.clone(),
+                meta,
+                follow_links,
+                opts,
+            );
+
+            if let Some(file) = tolerate(file, &path, opts, &mut skipped)? {
+                sink(file)?;
+            }
+        }
+
+        Ok(skipped)
     }
 
-    fn new(subpath: FileSubPath, path: PathBuf, meta: fs::Metadata) -> Result<Self, Error> {
+    fn new(
+        subpath: FileSubPath,
+        path: PathBuf,
+        meta: fs::Metadata,
+        follow_links: bool,
+        opts: &GatherOpts,
+    ) -> Result<Self, Error> {
         assert!(!meta.is_dir(), "Did not expect directory metadata");
 
-        let mut options = OpenOptions::new();
-        options.read(true);
-        #[cfg(unix)]
-        options.custom_flags(libc::O_NOFOLLOW);
+        let mime_type = if opts.defer_mime {
+            None
+        } else {
+            let mut options = OpenOptions::new();
+            options.read(true);
+            #[cfg(unix)]
+            if !follow_links {
+                options.custom_flags(libc::O_NOFOLLOW);
+            }
 
-        // Check if we are allowed to read the file
-        let mut file = options.open(&path)?;
-        let mut buf = vec![0u8; HEADER_SIZE];
-        let header_len = file.read(&mut buf)?;
-        let mime_type = infer::get(&buf[0..header_len])
-            .map_or("unknown", |t| t.mime_type())
-            .to_string();
+            // Check if we are allowed to read the file
+            let mut file = options.open(&path)?;
+            let mut buf = vec![0u8; HEADER_SIZE];
+            let header_len = file.read(&mut buf)?;
+            Some(Hidden(mime_type(&buf[0..header_len])))
+        };
 
         let abs = crate::utils::make_path_absolute(&path)?;
         let mut hash = sha2::Sha256::new();
@@ -130,7 +458,34 @@ impl File {
             kind: FileKind::FileToSend {
                 meta: Hidden(meta),
                 source: FileSource::Path(Hidden(path)),
-                mime_type: Some(Hidden(mime_type)),
+                mime_type,
+            },
+        })
+    }
+
+    fn new_dir(subpath: FileSubPath, path: PathBuf) -> Result<Self, Error> {
+        let abs = crate::utils::make_path_absolute(&path)?;
+        let mut hash = sha2::Sha256::new();
+        hash.update(abs.to_string_lossy().as_bytes());
+
+        Ok(Self {
+            file_id: FileId::from(hash),
+            subpath,
+            kind: FileKind::DirToSend { path: Hidden(path) },
+        })
+    }
+
+    fn new_symlink(subpath: FileSubPath, path: PathBuf, target: PathBuf) -> Result<Self, Error> {
+        let abs = crate::utils::make_path_absolute(&path)?;
+        let mut hash = sha2::Sha256::new();
+        hash.update(abs.to_string_lossy().as_bytes());
+
+        Ok(Self {
+            file_id: FileId::from(hash),
+            subpath,
+            kind: FileKind::SymlinkToSend {
+                path: Hidden(path),
+                target: Hidden(target),
             },
         })
     }
@@ -149,9 +504,7 @@ impl File {
 
             let mut buf = vec![0u8; HEADER_SIZE];
             let header_len = f.read_at(&mut buf, 0)?;
-            let mime_type = infer::get(&buf[0..header_len])
-                .map_or("unknown", |t| t.mime_type())
-                .to_string();
+            let mime_type = mime_type(&buf[0..header_len]);
 
             let mut hash = sha2::Sha256::new();
             hash.update(path.as_ref().as_os_str().as_bytes());
@@ -179,9 +532,64 @@ impl File {
         match &self.kind {
             FileKind::FileToSend { meta, .. } => meta.len(),
             FileKind::FileToRecv { size } => *size,
+            FileKind::DirToSend { .. }
+            | FileKind::DirToRecv
+            | FileKind::SymlinkToSend { .. }
+            | FileKind::SymlinkToRecv { .. } => 0,
+            FileKind::ArchiveToSend { archive, .. } => archive.size(),
+            FileKind::ArchiveToRecv { size, .. } => *size,
         }
     }
 
+    pub fn is_dir(&self) -> bool {
+        matches!(self.kind, FileKind::DirToSend { .. } | FileKind::DirToRecv)
+    }
+
+    pub fn symlink_target(&self) -> Option<&Path> {
+        match &self.kind {
+            FileKind::SymlinkToSend { target, .. } | FileKind::SymlinkToRecv { target } => {
+                Some(target)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn is_archive(&self) -> bool {
+        matches!(
+            self.kind,
+            FileKind::ArchiveToSend { .. } | FileKind::ArchiveToRecv { .. }
+        )
+    }
+
+    pub fn compression(&self) -> Compression {
+        match &self.kind {
+            FileKind::ArchiveToSend { compression, .. }
+            | FileKind::ArchiveToRecv { compression, .. } => *compression,
+            _ => Compression::None,
+        }
+    }
+
+    pub(crate) fn archive(&self) -> Option<&Archive> {
+        match &self.kind {
+            FileKind::ArchiveToSend { archive, .. } => Some(archive),
+            _ => None,
+        }
+    }
+
+    /// Whether the entry carries any content which needs to be streamed.
+    /// Directories and symlinks are recreated by the receiver instead
+    pub(crate) fn has_content(&self) -> bool {
+        self.is_regular() || self.is_archive()
+    }
+
+    /// Plain files are the only entries the legacy protocols can handle
+    pub(crate) fn is_regular(&self) -> bool {
+        matches!(
+            self.kind,
+            FileKind::FileToSend { .. } | FileKind::FileToRecv { .. }
+        )
+    }
+
     pub fn id(&self) -> &FileId {
         &self.file_id
     }
@@ -203,10 +611,33 @@ impl File {
                     .unwrap_or_else(|| "unknown".to_string()),
                 size_kb: (self.size() as f64 / 1024.0).ceil() as i32,
             }),
+            FileKind::ArchiveToSend { .. } => Some(FileInfo {
+                mime_type: "application/x-tar".to_string(),
+                extension: "tar".to_string(),
+                size_kb: (self.size() as f64 / 1024.0).ceil() as i32,
+            }),
             _ => None,
         }
     }
 
+    /// Detect the mime type if it was deferred when gathering the files. This
+    /// is a blocking operation
+    pub(crate) fn sniff_mime(&mut self) -> crate::Result<()> {
+        if let FileKind::FileToSend {
+            source,
+            mime_type: mime_type @ None,
+            ..
+        } = &mut self.kind
+        {
+            let mut reader = reader::open(source)?;
+            let mut buf = vec![0u8; HEADER_SIZE];
+            let header_len = reader.read(&mut buf)?;
+            *mime_type = Some(Hidden(self::mime_type(&buf[0..header_len])));
+        }
+
+        Ok(())
+    }
+
     // Open the file if it wasn't already opened and return the std::fs::File
     // instance
     pub(crate) fn open(&self, offset: u64) -> crate::Result<FileReader> {
@@ -214,7 +645,12 @@ impl File {
             FileKind::FileToSend { meta, source, .. } => {
                 let mut reader = reader::open(source)?;
                 reader.seek(io::SeekFrom::Start(offset))?;
-                FileReader::new(reader, meta.0.clone())
+                FileReader::new(reader, meta.len(), meta.modified().ok())
+            }
+            FileKind::ArchiveToSend { archive, .. } => {
+                let mut reader = ArchiveReader::new(archive.clone());
+                io::Seek::seek(&mut reader, io::SeekFrom::Start(offset))?;
+                FileReader::new(Box::new(reader), archive.size(), None)
             }
             _ => Err(Error::BadFile),
         }
@@ -224,6 +660,9 @@ impl File {
     pub(crate) fn checksum(&self, limit: u64) -> crate::Result<[u8; 32]> {
         let reader = match &self.kind {
             FileKind::FileToSend { source, .. } => reader::open(source)?,
+            FileKind::ArchiveToSend { archive, .. } => {
+                Box::new(ArchiveReader::new(archive.clone()))
+            }
             _ => return Err(Error::BadFile),
         };
 
@@ -233,6 +672,42 @@ impl File {
     }
 }
 
+fn mime_type(header: &[u8]) -> String {
+    infer::get(header)
+        .map_or("unknown", |t| t.mime_type())
+        .to_string()
+}
+
+// Collect the entries up to the transfer file limit
+fn push_limited(files: &mut Vec<File>, file: File, config: &DropConfig) -> Result<(), Error> {
+    if files.len() >= config.transfer_file_limit {
+        return Err(Error::TransferLimitsExceeded);
+    }
+
+    files.push(file);
+    Ok(())
+}
+
+// In the tolerant mode the error is recorded and the entry is left out
+fn tolerate<T>(
+    res: Result<T, Error>,
+    path: &Path,
+    opts: &GatherOpts,
+    skipped: &mut Vec<Skipped>,
+) -> Result<Option<T>, Error> {
+    match res {
+        Ok(val) => Ok(Some(val)),
+        Err(err) if opts.skip_unreadable => {
+            skipped.push(Skipped {
+                path: Hidden(path.to_path_buf()),
+                reason: SkipReason::Unreadable(err),
+            });
+            Ok(None)
+        }
+        Err(err) => Err(err),
+    }
+}
+
 pub fn checksum(reader: &mut impl io::Read) -> io::Result<[u8; 32]> {
     let mut csum = sha2::Sha256::new();
     io::copy(reader, &mut csum)?;
@@ -270,4 +745,221 @@ mod tests {
 
         assert_eq!(csum.as_slice(), EXPECTED);
     }
+
+    #[test]
+    fn walk_empty_dirs() {
+        use drop_config::DropConfig;
+
+        let tmp = tempfile::tempdir().expect("Failed to create tmp dir");
+        let root = tmp.path().join("root");
+
+        std::fs::create_dir_all(root.join("full")).unwrap();
+        std::fs::create_dir_all(root.join("nested/empty")).unwrap();
+        std::fs::create_dir_all(root.join("empty")).unwrap();
+        std::fs::write(root.join("full/a.txt"), TEST).unwrap();
+
+        let mut files: Vec<_> = super::File::from_path(&root, &DropConfig::default())
+            .unwrap()
+            .into_iter()
+            .map(|f| (f.subpath().to_string(), f.is_dir()))
+            .collect();
+        files.sort();
+
+        assert_eq!(
+            files,
+            [
+                ("root/empty".to_string(), true),
+                ("root/full/a.txt".to_string(), false),
+                ("root/nested/empty".to_string(), true),
+            ]
+        );
+
+        let empty = tmp.path().join("empty");
+        std::fs::create_dir(&empty).unwrap();
+
+        let files = super::File::from_path(&empty, &DropConfig::default()).unwrap();
+        assert_eq!(files.len(), 1);
+        assert!(files[0].is_dir());
+        assert_eq!(files[0].subpath().to_string(), "empty");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn walk_symlinks() {
+        use drop_config::DropConfig;
+
+        use super::{GatherOpts, SkipReason, SymlinkPolicy};
+
+        let tmp = tempfile::tempdir().expect("Failed to create tmp dir");
+        let root = tmp.path().join("root");
+
+        std::fs::create_dir(&root).unwrap();
+        std::fs::write(root.join("a.txt"), TEST).unwrap();
+        std::os::unix::fs::symlink("a.txt", root.join("rel")).unwrap();
+        std::os::unix::fs::symlink(root.join("a.txt"), root.join("abs")).unwrap();
+
+        let gather = |symlinks| {
+            super::File::gather(
+                &root,
+                &DropConfig::default(),
+                &GatherOpts {
+                    symlinks,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+        };
+
+        let skipped = gather(SymlinkPolicy::Skip);
+        assert_eq!(skipped.files.len(), 1);
+        assert_eq!(skipped.skipped.len(), 2);
+        assert!(skipped
+            .skipped
+            .iter()
+            .all(|s| matches!(s.reason, SkipReason::Symlink)));
+
+        let preserved = gather(SymlinkPolicy::Preserve);
+        assert_eq!(preserved.files.len(), 2);
+        let link = preserved
+            .files
+            .iter()
+            .find(|f| f.subpath().to_string() == "root/rel")
+            .expect("Missing link entry");
+        assert_eq!(link.symlink_target(), Some(std::path::Path::new("a.txt")));
+        assert_eq!(preserved.skipped.len(), 1);
+        assert!(matches!(
+            preserved.skipped[0].reason,
+            SkipReason::AbsoluteSymlink
+        ));
+
+        let followed = gather(SymlinkPolicy::Follow);
+        assert_eq!(followed.files.len(), 3);
+        assert!(followed.files.iter().all(|f| f.size() == TEST.len() as u64));
+        assert!(followed.skipped.is_empty());
+    }
+
+    #[test]
+    fn walk_filters() {
+        use drop_config::DropConfig;
+
+        use super::GatherOpts;
+
+        let tmp = tempfile::tempdir().expect("Failed to create tmp dir");
+        let root = tmp.path().join("root");
+
+        for dir in ["src", "node_modules/dep", ".git", "target", "docs", "empty"] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in [
+            "src/main.rs",
+            "src/notes.txt",
+            "node_modules/dep/index.js",
+            ".git/HEAD",
+            "target/out.bin",
+            "docs/notes.txt",
+            "readme.md",
+        ] {
+            std::fs::write(root.join(file), TEST).unwrap();
+        }
+        std::fs::write(root.join(".gitignore"), "target/\n*.txt\n").unwrap();
+
+        let gather = |opts: GatherOpts| {
+            let mut files: Vec<_> = super::File::gather(&root, &DropConfig::default(), &opts)
+                .unwrap()
+                .files
+                .into_iter()
+                .map(|f| f.subpath().to_string())
+                .collect();
+            files.sort();
+            files
+        };
+
+        assert_eq!(
+            gather(GatherOpts {
+                exclude: vec!["node_modules".into()],
+                skip_hidden: true,
+                ignore_file: Some(".gitignore".into()),
+                ..Default::default()
+            }),
+            // The directory emptied by the filter is not sent, unlike the
+            // empty one
+            ["root/empty", "root/readme.md", "root/src/main.rs"]
+        );
+
+        assert_eq!(
+            gather(GatherOpts {
+                include: vec!["**/*.rs".into(), "*.md".into()],
+                ..Default::default()
+            }),
+            ["root/readme.md", "root/src/main.rs"]
+        );
+
+        assert_eq!(gather(GatherOpts::default()).len(), 9);
+    }
+
+    #[test]
+    fn gather_tolerant() {
+        use drop_config::DropConfig;
+
+        use super::{GatherOpts, SkipReason};
+
+        let tmp = tempfile::tempdir().expect("Failed to create tmp dir");
+        let missing = tmp.path().join("missing");
+
+        assert!(
+            super::File::gather(&missing, &DropConfig::default(), &GatherOpts::default()).is_err()
+        );
+
+        let opts = GatherOpts {
+            skip_unreadable: true,
+            ..Default::default()
+        };
+        let gathered = super::File::gather(&missing, &DropConfig::default(), &opts).unwrap();
+
+        assert!(gathered.files.is_empty());
+        assert_eq!(gathered.skipped.len(), 1);
+        assert_eq!(gathered.skipped[0].path.0, missing);
+        assert!(matches!(
+            gathered.skipped[0].reason,
+            SkipReason::Unreadable(super::Error::Io(_))
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn gather_tolerant_walk() {
+        use drop_config::DropConfig;
+
+        use super::{GatherOpts, SkipReason};
+
+        // An entry that cannot be read inside the walked directory, the
+        // permissions are not enough as the tests may run as root
+        let tmp = tempfile::tempdir().expect("Failed to create tmp dir");
+        let missing = tmp.path().join("missing");
+        let root = tmp.path().join("root");
+        std::fs::create_dir(&root).unwrap();
+        std::fs::write(root.join("file.txt"), TEST).unwrap();
+        std::os::unix::fs::symlink(&missing, root.join("dangling")).unwrap();
+
+        let opts = GatherOpts {
+            symlinks: super::SymlinkPolicy::Follow,
+            ..Default::default()
+        };
+        assert!(super::File::gather(&root, &DropConfig::default(), &opts).is_err());
+
+        let opts = GatherOpts {
+            skip_unreadable: true,
+            ..opts
+        };
+        let gathered = super::File::gather(&root, &DropConfig::default(), &opts).unwrap();
+
+        assert_eq!(gathered.files.len(), 1);
+        assert_eq!(gathered.files[0].subpath().to_string(), "root/file.txt");
+        assert_eq!(gathered.skipped.len(), 1);
+        assert_eq!(gathered.skipped[0].path.0, root.join("dangling"));
+        assert!(matches!(
+            gathered.skipped[0].reason,
+            SkipReason::Unreadable(_)
+        ));
+    }
 }
@@ -3,7 +3,7 @@ mod fd;
 
 mod path;
 
-use std::{fs, io};
+use std::{fs, io, time::SystemTime};
 
 use crate::Error;
 
@@ -13,7 +13,8 @@ const CHUNK_SIZE: usize = 1024 * 1024;
 pub struct FileReader {
     inner: Box<dyn Reader>,
     buffer: Box<[u8]>,
-    meta: fs::Metadata,
+    size: u64,
+    mtime: Option<SystemTime>,
 }
 
 pub(super) fn open(source: &super::FileSource) -> crate::Result<Box<dyn Reader>> {
@@ -27,11 +28,16 @@ pub(super) fn open(source: &super::FileSource) -> crate::Result<Box<dyn Reader>>
 }
 
 impl FileReader {
-    pub(super) fn new(reader: Box<dyn Reader>, meta: fs::Metadata) -> crate::Result<Self> {
+    pub(super) fn new(
+        reader: Box<dyn Reader>,
+        size: u64,
+        mtime: Option<SystemTime>,
+    ) -> crate::Result<Self> {
         Ok(Self {
             inner: reader,
             buffer: vec![0u8; CHUNK_SIZE].into_boxed_slice(),
-            meta,
+            size,
+            mtime,
         })
     }
 
@@ -47,14 +53,14 @@ impl FileReader {
         if n == 0 {
             // File size might have been reduced while in the loop which
             // will result in an error
-            if total_read != self.meta.len() {
+            if total_read != self.size {
                 return Err(Error::MismatchedSize);
             } else {
                 return Ok(None);
             }
         }
 
-        if total_read > self.meta.len() {
+        if total_read > self.size {
             return Err(Error::MismatchedSize);
         }
 
@@ -63,7 +69,10 @@ impl FileReader {
     }
 
     fn is_mtime_ok(&mut self) -> crate::Result<bool> {
-        let mtime_orig = self.meta.modified()?;
+        let mtime_orig = match self.mtime {
+            Some(mtime) => mtime,
+            None => return Ok(true),
+        };
         let mtime_act = self.inner.meta()?.modified()?;
 
         Ok(mtime_orig == mtime_act)
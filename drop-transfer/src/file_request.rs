@@ -0,0 +1,282 @@
+use std::{
+    collections::HashMap,
+    fs,
+    net::IpAddr,
+    path::{Component, Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::Error;
+
+/// The requests not answered within this time are dropped
+const PENDING_LIFETIME: Duration = Duration::from_secs(60 * 60);
+/// Maximum number of requests of a single peer awaiting the approval
+const MAX_PENDING_PER_PEER: usize = 16;
+
+/// Sent by the requesting peer, lists paths relative to the exported
+/// directory
+#[derive(Serialize, Deserialize)]
+pub(crate) struct FileRequest {
+    pub export: String,
+    pub paths: Vec<String>,
+}
+
+/// Response of the exporting peer. A queued request waits for the approval,
+/// once approved the files are sent in a transfer with the request ID
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub(crate) enum FileRequestResponse {
+    Queued { request: Uuid },
+    Refused { reason: RefuseReason },
+}
+
+/// The details of the refusal are logged only by the exporting peer
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum RefuseReason {
+    // Unknown export or paths
+    Invalid,
+    TooManyPending,
+}
+
+/// Sent by the exporting peer when the user denies the request
+#[derive(Serialize, Deserialize)]
+pub(crate) struct FileRequestDenied {
+    pub request: Uuid,
+}
+
+pub(crate) struct PendingRequest {
+    pub peer: IpAddr,
+    pub paths: Vec<PathBuf>,
+    expires_at: Instant,
+}
+
+/// Directories the peers are allowed to request files from together with the
+/// requests waiting for the approval. Also keeps the requests sent to the peers
+/// so that only the asked peer can deny them
+#[derive(Default)]
+pub(crate) struct ExportStore {
+    exports: HashMap<String, PathBuf>,
+    pending: HashMap<Uuid, PendingRequest>,
+    sent: HashMap<Uuid, (IpAddr, Instant)>,
+}
+
+impl From<&Error> for RefuseReason {
+    fn from(err: &Error) -> Self {
+        match err {
+            Error::TransferLimitsExceeded => Self::TooManyPending,
+            _ => Self::Invalid,
+        }
+    }
+}
+
+impl From<RefuseReason> for Error {
+    fn from(reason: RefuseReason) -> Self {
+        match reason {
+            RefuseReason::Invalid => Error::BadPath("File request refused by the peer".into()),
+            RefuseReason::TooManyPending => Error::TransferLimitsExceeded,
+        }
+    }
+}
+
+impl ExportStore {
+    pub(crate) fn export(&mut self, name: String, dir: &Path) -> crate::Result<()> {
+        let dir = fs::canonicalize(dir)?;
+        if !dir.is_dir() {
+            return Err(Error::BadPath("Only directories can be exported".into()));
+        }
+
+        self.exports.insert(name, dir);
+        Ok(())
+    }
+
+    pub(crate) fn unexport(&mut self, name: &str) -> crate::Result<()> {
+        self.exports
+            .remove(name)
+            .map(|_| ())
+            .ok_or(Error::InvalidArgument)
+    }
+
+    /// Validate the request and queue it for the approval. Returns the
+    /// request ID
+    pub(crate) fn queue(&mut self, peer: IpAddr, req: &FileRequest) -> crate::Result<Uuid> {
+        self.remove_expired();
+
+        if self.pending.values().filter(|req| req.peer == peer).count() >= MAX_PENDING_PER_PEER {
+            return Err(Error::TransferLimitsExceeded);
+        }
+
+        let root = self
+            .exports
+            .get(&req.export)
+            .ok_or_else(|| Error::BadPath(format!("Unknown export {:?}", req.export)))?;
+
+        if req.paths.is_empty() {
+            return Err(Error::InvalidArgument);
+        }
+
+        let paths = req
+            .paths
+            .iter()
+            .map(|path| resolve(root, path))
+            .collect::<crate::Result<_>>()?;
+
+        let id = Uuid::new_v4();
+        self.pending.insert(
+            id,
+            PendingRequest {
+                peer,
+                paths,
+                expires_at: Instant::now() + PENDING_LIFETIME,
+            },
+        );
+
+        Ok(id)
+    }
+
+    pub(crate) fn take(&mut self, id: Uuid) -> crate::Result<PendingRequest> {
+        self.remove_expired();
+        self.pending.remove(&id).ok_or(Error::InvalidArgument)
+    }
+
+    /// Remember the request sent to the peer until it is denied or expires
+    pub(crate) fn sent(&mut self, peer: IpAddr, id: Uuid) {
+        self.remove_expired();
+        self.sent
+            .insert(id, (peer, Instant::now() + PENDING_LIFETIME));
+    }
+
+    /// Returns `true` if the request was sent to the peer denying it
+    pub(crate) fn denied(&mut self, peer: IpAddr, id: Uuid) -> bool {
+        self.remove_expired();
+
+        match self.sent.get(&id) {
+            Some((asked, _)) if *asked == peer => self.sent.remove(&id).is_some(),
+            _ => false,
+        }
+    }
+
+    fn remove_expired(&mut self) {
+        let now = Instant::now();
+        self.pending.retain(|_, req| req.expires_at > now);
+        self.sent.retain(|_, (_, expires_at)| *expires_at > now);
+    }
+}
+
+// The requested paths must stay inside of the exported directory, also after
+// resolving the symlinks
+fn resolve(root: &Path, path: &str) -> crate::Result<PathBuf> {
+    let relative = Path::new(path);
+
+    if relative.as_os_str().is_empty()
+        || !relative
+            .components()
+            .all(|comp| matches!(comp, Component::Normal(_)))
+    {
+        return Err(Error::BadPath(format!("Invalid path {path:?}")));
+    }
+
+    let full = fs::canonicalize(root.join(relative))?;
+    if !full.starts_with(root) {
+        return Err(Error::BadPath(format!("Path {path:?} escapes the export")));
+    }
+
+    Ok(full)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requested_paths() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().join("export");
+        fs::create_dir_all(root.join("dir")).unwrap();
+        fs::write(root.join("dir/a.txt"), b"a").unwrap();
+        fs::write(tmp.path().join("secret.txt"), b"s").unwrap();
+
+        let mut store = ExportStore::default();
+        store.export("docs".into(), &root).unwrap();
+
+        let peer = "172.17.0.2".parse().unwrap();
+        let request = |export: &str, paths: &[&str]| FileRequest {
+            export: export.into(),
+            paths: paths.iter().map(|p| p.to_string()).collect(),
+        };
+
+        let id = store.queue(peer, &request("docs", &["dir/a.txt"])).unwrap();
+        let pending = store.take(id).unwrap();
+        assert_eq!(pending.peer, peer);
+        assert_eq!(
+            pending.paths,
+            [fs::canonicalize(root.join("dir/a.txt")).unwrap()]
+        );
+        assert!(store.take(id).is_err());
+
+        assert!(store
+            .queue(peer, &request("docs", &["../secret.txt"]))
+            .is_err());
+        assert!(store
+            .queue(peer, &request("docs", &["/etc/passwd"]))
+            .is_err());
+        assert!(store
+            .queue(peer, &request("docs", &["missing.txt"]))
+            .is_err());
+        assert!(store.queue(peer, &request("other", &["dir"])).is_err());
+        assert!(store.queue(peer, &request("docs", &[])).is_err());
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(tmp.path().join("secret.txt"), root.join("link")).unwrap();
+            assert!(store.queue(peer, &request("docs", &["link"])).is_err());
+        }
+
+        store.unexport("docs").unwrap();
+        assert!(store.queue(peer, &request("docs", &["dir"])).is_err());
+    }
+
+    #[test]
+    fn pending_limit() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::write(tmp.path().join("a.txt"), b"a").unwrap();
+
+        let mut store = ExportStore::default();
+        store.export("docs".into(), tmp.path()).unwrap();
+
+        let req = FileRequest {
+            export: "docs".into(),
+            paths: vec!["a.txt".into()],
+        };
+
+        let peer = "172.17.0.2".parse().unwrap();
+        for _ in 0..MAX_PENDING_PER_PEER {
+            store.queue(peer, &req).unwrap();
+        }
+        assert!(matches!(
+            store.queue(peer, &req),
+            Err(Error::TransferLimitsExceeded)
+        ));
+
+        // The other peers are not affected
+        let other = "172.17.0.3".parse().unwrap();
+        assert!(store.queue(other, &req).is_ok());
+    }
+
+    #[test]
+    fn denial_from_asked_peer() {
+        let mut store = ExportStore::default();
+
+        let peer = "172.17.0.2".parse().unwrap();
+        let other = "172.17.0.3".parse().unwrap();
+        let id = Uuid::new_v4();
+        store.sent(peer, id);
+
+        assert!(!store.denied(other, id));
+        assert!(store.denied(peer, id));
+        assert!(!store.denied(peer, id));
+    }
+}
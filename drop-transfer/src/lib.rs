@@ -2,10 +2,14 @@ pub mod auth;
 mod error;
 pub mod event;
 pub mod file;
+mod file_request;
 mod manager;
+mod pairing;
+pub mod progress;
 mod protocol;
 mod quarantine;
 pub mod service;
+mod share;
 mod storage_dispatch;
 pub mod transfer;
 pub mod utils;
@@ -14,7 +18,7 @@ mod ws;
 pub(crate) use crate::manager::TransferManager;
 pub use crate::{
     error::Error,
-    event::Event,
+    event::{Event, Reason},
     file::{File, FileId},
     service::Service,
     storage_dispatch::StorageDispatch,
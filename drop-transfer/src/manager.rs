@@ -3,15 +3,19 @@ use std::{
     mem::ManuallyDrop,
     path::{Path, PathBuf},
     sync::Arc,
+    time::Duration,
 };
 
-use tokio::sync::mpsc::UnboundedSender;
+use drop_config::DropConfig;
+use tokio::{sync::mpsc::UnboundedSender, time::Instant};
 use uuid::Uuid;
 
 use crate::{
+    progress::{ActiveTransfer, ConnectionState, ProgressTracker},
+    protocol,
     service::State,
     ws::{client::ClientReq, server::ServerReq},
-    Error, FileId, Transfer,
+    Error, File, FileId, Transfer,
 };
 
 #[derive(Clone)]
@@ -23,6 +27,9 @@ pub enum TransferConnection {
 pub struct TransferState {
     pub(crate) xfer: Transfer,
     pub(crate) connection: TransferConnection,
+    version: protocol::Version,
+    // Whether the connection supports appending files
+    appendable: bool,
     // Used for mapping directories inside the destination
     dir_mappings: HashMap<PathBuf, String>,
 
@@ -34,13 +41,22 @@ pub struct TransferState {
 #[derive(Default)]
 pub(crate) struct TransferManager {
     transfers: HashMap<Uuid, TransferState>,
+    // Outgoing transfers for which the connection is not established yet
+    connecting: HashMap<Uuid, Transfer>,
+    // Incoming transfers the user already acted upon. Kept outside of the
+    // transfer state so that it outlives the connection
+    answered: HashSet<Uuid>,
+    // Incoming transfers expire at these times unless answered
+    accept_deadlines: HashMap<Uuid, Instant>,
 }
 
 impl TransferState {
-    fn new(xfer: Transfer, connection: TransferConnection) -> Self {
+    fn new(xfer: Transfer, connection: TransferConnection, version: protocol::Version) -> Self {
         Self {
             xfer,
             connection,
+            version,
+            appendable: false,
             dir_mappings: HashMap::new(),
             rejected: HashSet::new(),
         }
@@ -57,20 +73,132 @@ impl TransferManager {
         Ok(())
     }
 
+    /// Record that the user acted upon the incoming transfer so that it no
+    /// longer expires, even after reconnecting
+    pub(crate) fn mark_answered(&mut self, transfer_id: Uuid) {
+        self.answered.insert(transfer_id);
+        self.accept_deadlines.remove(&transfer_id);
+    }
+
+    pub(crate) fn is_answered(&self, transfer_id: Uuid) -> bool {
+        self.answered.contains(&transfer_id)
+    }
+
+    /// The time the incoming transfer expires at. Set on the first connection
+    /// so that reconnecting does not extend it
+    pub(crate) fn accept_deadline(&mut self, transfer_id: Uuid, timeout: Duration) -> Instant {
+        *self
+            .accept_deadlines
+            .entry(transfer_id)
+            .or_insert_with(|| Instant::now() + timeout)
+    }
+
+    /// Forget the transfer for good, as opposed to the connection being closed
+    pub(crate) fn forget_transfer(&mut self, transfer_id: Uuid) {
+        self.answered.remove(&transfer_id);
+        self.accept_deadlines.remove(&transfer_id);
+    }
+
     pub(crate) fn insert_transfer(
         &mut self,
         xfer: Transfer,
         connection: TransferConnection,
+        version: protocol::Version,
     ) -> crate::Result<()> {
         match self.transfers.entry(xfer.id()) {
             Entry::Occupied(_) => Err(Error::BadTransferState("Transfer already exists".into())),
             Entry::Vacant(entry) => {
-                entry.insert(TransferState::new(xfer, connection));
+                self.connecting.remove(&xfer.id());
+                entry.insert(TransferState::new(xfer, connection, version));
                 Ok(())
             }
         }
     }
 
+    /// Register the outgoing transfer while the connection to the peer is
+    /// being established
+    pub(crate) fn insert_connecting(&mut self, xfer: Transfer) {
+        self.connecting.insert(xfer.id(), xfer);
+    }
+
+    /// Snapshot of all of the connecting and connected transfers
+    pub(crate) fn active_transfers(&self, progress: &ProgressTracker) -> Vec<ActiveTransfer> {
+        let connecting = self.connecting.values().map(|xfer| ActiveTransfer {
+            transfer: xfer.clone(),
+            outgoing: true,
+            connection: ConnectionState::Connecting,
+            version: None,
+            files: progress.files(xfer),
+            rejected: Vec::new(),
+        });
+
+        let connected = self.transfers.values().map(|state| ActiveTransfer {
+            transfer: state.xfer.clone(),
+            outgoing: matches!(state.connection, TransferConnection::Client(_)),
+            connection: ConnectionState::Connected,
+            version: Some(state.version.to_string()),
+            files: progress.files(&state.xfer),
+            rejected: state.rejected.iter().cloned().collect(),
+        });
+
+        connecting.chain(connected).collect()
+    }
+
+    pub(crate) fn allow_append(&mut self, id: Uuid) {
+        if let Some(state) = self.transfers.get_mut(&id) {
+            state.appendable = true;
+        }
+    }
+
+    /// Extend the outgoing transfer with more files. Returns the updated
+    /// transfer together with the appended file IDs
+    pub(crate) fn append_outgoing_files(
+        &mut self,
+        id: Uuid,
+        files: Vec<File>,
+        config: &DropConfig,
+    ) -> crate::Result<(Transfer, Vec<FileId>, UnboundedSender<ClientReq>)> {
+        let state = self.transfers.get_mut(&id).ok_or(Error::BadTransfer)?;
+
+        let conn = match &state.connection {
+            TransferConnection::Client(conn) => conn.clone(),
+            TransferConnection::Server(_) => {
+                return Err(Error::BadTransferState(
+                    "Files can be appended only to outgoing transfers".into(),
+                ))
+            }
+        };
+
+        if !state.appendable {
+            return Err(Error::BadTransferState(
+                "Peer does not support appending files".into(),
+            ));
+        }
+
+        let ids = state.xfer.append_files(files, config)?;
+        Ok((state.xfer.clone(), ids, conn))
+    }
+
+    /// Take back the appended files which could not be offered to the peer
+    pub(crate) fn remove_outgoing_files(&mut self, id: Uuid, files: &[FileId]) {
+        if let Some(state) = self.transfers.get_mut(&id) {
+            state.xfer.remove_files(files);
+        }
+    }
+
+    /// Extend the incoming transfer with the files offered by the peer
+    pub(crate) fn append_incoming_files(
+        &mut self,
+        id: Uuid,
+        files: Vec<File>,
+        config: &DropConfig,
+    ) -> crate::Result<(Transfer, Vec<FileId>)> {
+        let state = self.transfers.get_mut(&id).ok_or(Error::BadTransfer)?;
+
+        let ids = state.xfer.append_files(files, config)?;
+        Ok((state.xfer.clone(), ids))
+    }
+
     /// Returns `true` if file was sucesfully marked as rejected and `false` if
     /// it was already marked as such
     pub(crate) fn reject_file(&mut self, id: Uuid, file: FileId) -> crate::Result<bool> {
@@ -85,6 +213,25 @@ impl TransferManager {
         Ok(xstate.rejected.insert(file))
     }
 
+    /// Mark all of the files which are not rejected yet as rejected. Returns
+    /// the transfer together with the newly rejected files
+    pub(crate) fn reject_all_files(&mut self, id: Uuid) -> crate::Result<(Transfer, Vec<FileId>)> {
+        let xstate = self
+            .transfers
+            .get_mut(&id)
+            .ok_or(crate::Error::BadTransfer)?;
+
+        let files = xstate
+            .xfer
+            .files()
+            .keys()
+            .filter(|&file| xstate.rejected.insert(file.clone()))
+            .cloned()
+            .collect();
+
+        Ok((xstate.xfer.clone(), files))
+    }
+
     pub(crate) fn ensure_file_not_rejected(
         &self,
         transfer_id: Uuid,
@@ -131,6 +278,7 @@ impl TransferManager {
             .get(file_id)
             .ok_or(crate::Error::BadFileId)?;
 
+        let is_dir = file.is_dir() || file.is_archive();
         let mut iter = file.subpath().iter().map(crate::utils::normalize_filename);
 
         let probe = iter.next().ok_or_else(|| {
@@ -138,34 +286,31 @@ impl TransferManager {
         })?;
         let next = iter.next();
 
-        let mapped = match next {
-            Some(next) => {
-                // Check if dir exists and is known to us
-                let name = match state.dir_mappings.entry(dest_dir.join(probe)) {
-                    // Dir is known, reuse
-                    Entry::Occupied(occ) => occ.get().clone(),
-                    // Dir in new, check if there is name conflict and add to known
-                    Entry::Vacant(vacc) => {
-                        let mapped = crate::utils::map_path_if_exists(vacc.key())?;
-                        vacc.insert(
-                            mapped
-                                .file_name()
-                                .ok_or_else(|| crate::Error::BadPath("Missing file name".into()))?
-                                .to_string_lossy()
-                                .to_string(),
-                        )
-                        .clone()
-                    }
-                };
-
-                [name, next].into_iter().chain(iter).collect()
-            }
-            None => {
-                // Ordinary file
-                probe.into()
+        if next.is_none() && !is_dir {
+            // Ordinary file
+            return Ok(probe.into());
+        }
+
+        // Check if dir exists and is known to us
+        let name = match state.dir_mappings.entry(dest_dir.join(probe)) {
+            // Dir is known, reuse
+            Entry::Occupied(occ) => occ.get().clone(),
+            // Dir in new, check if there is name conflict and add to known
+            Entry::Vacant(vacc) => {
+                let mapped = crate::utils::map_path_if_exists(vacc.key())?;
+                vacc.insert(
+                    mapped
+                        .file_name()
+                        .ok_or_else(|| crate::Error::BadPath("Missing file name".into()))?
+                        .to_string_lossy()
+                        .to_string(),
+                )
+                .clone()
             }
         };
 
+        let mapped = [name].into_iter().chain(next).chain(iter).collect();
+
         Ok(mapped)
     }
 }
@@ -191,7 +336,29 @@ impl Drop for TransferGuard {
 
         tokio::spawn(async move {
             let mut lock = state.transfer_manager.lock().await;
+            lock.connecting.remove(&id);
             let _ = lock.cancel_transfer(id);
+            state.event_tx.tracker().remove(id);
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accept_deadline_kept_on_reconnect() {
+        let mut manager = TransferManager::default();
+        let id = Uuid::new_v4();
+
+        let deadline = manager.accept_deadline(id, Duration::from_secs(60));
+        assert_eq!(
+            manager.accept_deadline(id, Duration::from_secs(120)),
+            deadline
+        );
+
+        manager.forget_transfer(id);
+        assert!(manager.accept_deadline(id, Duration::from_secs(120)) > deadline);
+    }
+}
@@ -0,0 +1,161 @@
+use std::{
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+use base64::prelude::*;
+use drop_auth::PublicKey;
+use drop_storage::Storage;
+use serde::{Deserialize, Serialize};
+use slog::{error, info, warn, Logger};
+
+use crate::{auth, service::State, Error, Event};
+
+/// Messages exchanged on the pairing endpoint. The SPAKE2 messages and the
+/// confirmation tags are base64 encoded, the public keys are sent as the peer
+/// identities
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub(crate) enum PairingMsg {
+    // Sent by the peer entering the code
+    Start {
+        message: String,
+        key: String,
+    },
+    // Sent by the peer which generated the code
+    Response {
+        message: String,
+        key: String,
+        confirm: String,
+    },
+    Confirm {
+        confirm: String,
+    },
+    // The keys are confirmed on both sides
+    Done,
+    // Sent instead of the response when there is no valid code
+    Unavailable,
+}
+
+pub(crate) fn encode(bytes: &[u8]) -> String {
+    BASE64_STANDARD_NO_PAD.encode(bytes)
+}
+
+pub(crate) fn decode(value: &str) -> crate::Result<Vec<u8>> {
+    BASE64_STANDARD_NO_PAD
+        .decode(value)
+        .map_err(|_| Error::UnexpectedData)
+}
+
+pub(crate) fn decode_key(value: &str) -> crate::Result<PublicKey> {
+    drop_auth::parse_peer_identity(value).ok_or(Error::UnexpectedData)
+}
+
+struct PendingCode {
+    code: String,
+    expires_at: Instant,
+}
+
+/// The code waiting for the peer to pair with us. Only a single attempt is
+/// allowed per code so it cannot be guessed by trying all of them
+#[derive(Default)]
+pub(crate) struct PairingStore {
+    pending: Option<PendingCode>,
+}
+
+impl PairingStore {
+    /// Generate a new code, replacing the pending one
+    pub(crate) fn start(&mut self, lifetime: Duration) -> String {
+        let code = drop_auth::pake::generate_code();
+
+        self.pending = Some(PendingCode {
+            code: code.clone(),
+            expires_at: Instant::now() + lifetime,
+        });
+
+        code
+    }
+
+    pub(crate) fn cancel(&mut self) -> crate::Result<()> {
+        self.pending
+            .take()
+            .map(|_| ())
+            .ok_or(Error::PairingUnavailable)
+    }
+
+    /// Consume the code for the incoming attempt
+    pub(crate) fn take(&mut self) -> crate::Result<String> {
+        self.pending
+            .take()
+            .filter(|pending| pending.expires_at > Instant::now())
+            .map(|pending| pending.code)
+            .ok_or(Error::PairingUnavailable)
+    }
+}
+
+/// Make the keys of the peers paired in the previous runs known to the
+/// authentication
+pub(crate) fn load_paired(auth: &auth::Context, storage: &Storage, logger: &Logger) {
+    let peers = match storage.paired_peers() {
+        Ok(peers) => peers,
+        Err(err) => {
+            error!(logger, "Failed to load the paired peers: {err}");
+            return;
+        }
+    };
+
+    for peer in peers {
+        match drop_auth::parse_peer_identity(&peer.identity) {
+            Some(key) => auth.add_paired(key),
+            None => warn!(logger, "Skipping invalid paired peer {peer:?}"),
+        }
+    }
+}
+
+/// Persist the key of the peer and use it for the authentication from now on
+pub(crate) async fn complete(state: &State, peer: IpAddr, key: PublicKey, logger: &Logger) {
+    let identity = drop_auth::peer_identity(&key);
+
+    if let Err(err) = state
+        .storage
+        .insert_paired_peer(&identity, &peer.to_string())
+    {
+        error!(logger, "Failed to store the paired peer: {err}");
+    }
+    state.auth.add_paired(key);
+
+    info!(logger, "Paired with {peer} as {identity}");
+
+    state
+        .event_tx
+        .send(Event::PairingCompleted { peer, identity })
+        .await
+        .expect("Failed to send PairingCompleted event");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_attempt() {
+        let mut store = PairingStore::default();
+
+        let code = store.start(Duration::from_secs(60));
+        assert_eq!(store.take().unwrap(), code);
+        assert!(matches!(store.take(), Err(Error::PairingUnavailable)));
+
+        store.start(Duration::from_secs(60));
+        store.cancel().unwrap();
+        assert!(matches!(store.take(), Err(Error::PairingUnavailable)));
+        assert!(matches!(store.cancel(), Err(Error::PairingUnavailable)));
+    }
+
+    #[test]
+    fn expiry() {
+        let mut store = PairingStore::default();
+
+        store.start(Duration::ZERO);
+        assert!(matches!(store.take(), Err(Error::PairingUnavailable)));
+    }
+}
@@ -0,0 +1,211 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::mpsc::{self, error::SendError};
+use uuid::Uuid;
+
+use crate::{Event, FileId, Transfer};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    // Outgoing transfer waiting for the peer to accept the connection
+    Connecting,
+    Connected,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileState {
+    Pending,
+    InProgress,
+    Completed,
+    Failed,
+    Canceled,
+    Rejected,
+}
+
+#[derive(Debug, Clone)]
+pub struct FileProgress {
+    pub state: FileState,
+    pub bytes: u64,
+}
+
+/// Snapshot of a transfer that is currently connecting or connected
+#[derive(Debug, Clone)]
+pub struct ActiveTransfer {
+    pub transfer: Transfer,
+    pub outgoing: bool,
+    pub connection: ConnectionState,
+    // Protocol version, unknown until connected
+    pub version: Option<String>,
+    pub files: HashMap<FileId, FileProgress>,
+    pub rejected: Vec<FileId>,
+}
+
+/// Per file state of the running transfers, built from the emitted events
+#[derive(Default)]
+pub(crate) struct ProgressTracker {
+    transfers: HashMap<Uuid, HashMap<FileId, FileProgress>>,
+}
+
+impl ProgressTracker {
+    fn update(&mut self, event: &Event) {
+        let (id, file, state, bytes) = match event {
+            Event::FileUploadStarted(xfer, file) | Event::FileDownloadStarted(xfer, file, _) => {
+                (xfer.id(), file, FileState::InProgress, None)
+            }
+            Event::FileUploadProgress(xfer, file, bytes)
+            | Event::FileDownloadProgress(xfer, file, bytes) => {
+                (xfer.id(), file, FileState::InProgress, Some(*bytes))
+            }
+            Event::FileUploadSuccess(xfer, file) => (
+                xfer.id(),
+                file,
+                FileState::Completed,
+                xfer.files().get(file).map(|f| f.size()),
+            ),
+            Event::FileDownloadSuccess(xfer, success) => (
+                xfer.id(),
+                &success.id,
+                FileState::Completed,
+                xfer.files().get(&success.id).map(|f| f.size()),
+            ),
+            Event::FileUploadCancelled(xfer, file, ..)
+            | Event::FileDownloadCancelled(xfer, file, ..) => {
+                (xfer.id(), file, FileState::Canceled, None)
+            }
+            Event::FileUploadFailed(xfer, file, _) | Event::FileDownloadFailed(xfer, file, _) => {
+                (xfer.id(), file, FileState::Failed, None)
+            }
+            Event::FileUploadRejected {
+                transfer_id,
+                file_id,
+                ..
+            }
+            | Event::FileDownloadRejected {
+                transfer_id,
+                file_id,
+                ..
+            } => (*transfer_id, file_id, FileState::Rejected, None),
+            _ => return,
+        };
+
+        let progress = self
+            .transfers
+            .entry(id)
+            .or_default()
+            .entry(file.clone())
+            .or_insert(FileProgress {
+                state: FileState::Pending,
+                bytes: 0,
+            });
+
+        progress.state = state;
+        if let Some(bytes) = bytes {
+            progress.bytes = bytes;
+        }
+    }
+
+    /// Progress of all of the files in the transfer, the files without any
+    /// events so far are pending
+    pub(crate) fn files(&self, xfer: &Transfer) -> HashMap<FileId, FileProgress> {
+        let tracked = self.transfers.get(&xfer.id());
+
+        xfer.files()
+            .keys()
+            .map(|id| {
+                let progress =
+                    tracked
+                        .and_then(|files| files.get(id))
+                        .cloned()
+                        .unwrap_or(FileProgress {
+                            state: FileState::Pending,
+                            bytes: 0,
+                        });
+
+                (id.clone(), progress)
+            })
+            .collect()
+    }
+
+    pub(crate) fn remove(&mut self, id: Uuid) {
+        self.transfers.remove(&id);
+    }
+}
+
+/// Event sender which updates the progress tracker before passing the event on
+/// to the application
+#[derive(Clone)]
+pub(crate) struct EventTx {
+    tx: mpsc::Sender<Event>,
+    tracker: Arc<Mutex<ProgressTracker>>,
+}
+
+impl EventTx {
+    pub(crate) fn new(tx: mpsc::Sender<Event>) -> Self {
+        Self {
+            tx,
+            tracker: Arc::default(),
+        }
+    }
+
+    pub(crate) async fn send(&self, event: Event) -> Result<(), SendError<Event>> {
+        self.tracker().update(&event);
+        self.tx.send(event).await
+    }
+
+    pub(crate) fn tracker(&self) -> std::sync::MutexGuard<'_, ProgressTracker> {
+        self.tracker.lock().expect("Progress tracker lock poisoned")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+    use crate::{event::DownloadSuccess, utils::Hidden, File};
+
+    #[test]
+    fn file_progress() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("a.txt");
+        std::fs::write(&path, [0u8; 16]).unwrap();
+
+        let config = drop_config::DropConfig::default();
+        let files = File::from_path(&path, &config).unwrap();
+        let id = files[0].id().clone();
+
+        let xfer = Transfer::new(Ipv4Addr::LOCALHOST.into(), files, &config).unwrap();
+        let mut tracker = ProgressTracker::default();
+
+        assert_eq!(tracker.files(&xfer)[&id].state, FileState::Pending);
+
+        tracker.update(&Event::FileDownloadStarted(
+            xfer.clone(),
+            id.clone(),
+            String::new(),
+        ));
+        tracker.update(&Event::FileDownloadProgress(xfer.clone(), id.clone(), 8));
+
+        let progress = &tracker.files(&xfer)[&id];
+        assert_eq!(progress.state, FileState::InProgress);
+        assert_eq!(progress.bytes, 8);
+
+        tracker.update(&Event::FileDownloadSuccess(
+            xfer.clone(),
+            DownloadSuccess {
+                id: id.clone(),
+                final_path: Hidden(path.into_boxed_path()),
+            },
+        ));
+
+        let progress = &tracker.files(&xfer)[&id];
+        assert_eq!(progress.state, FileState::Completed);
+        assert_eq!(progress.bytes, 16);
+
+        tracker.remove(xfer.id());
+        assert_eq!(tracker.files(&xfer)[&id].state, FileState::Pending);
+    }
+}
@@ -4,6 +4,7 @@ pub mod v2 {
 }
 pub mod v4;
 pub mod v5;
+pub mod v6;
 
 #[derive(Copy, Clone, strum::Display, strum::EnumString)]
 pub enum Version {
@@ -17,4 +18,19 @@ pub enum Version {
     V4,
     #[strum(serialize = "v5")]
     V5,
+    #[strum(serialize = "v6")]
+    V6,
+}
+
+/// Directories, symlinks and archives cannot be expressed in the protocols
+/// older than v6. Fail instead of silently offering only a part of the transfer
+pub(crate) fn ensure_only_regular_files(xfer: &crate::Transfer) -> crate::Result<()> {
+    match xfer.files().values().find(|file| !file.is_regular()) {
+        Some(file) => Err(crate::Error::BadTransferState(format!(
+            "The peer's protocol version does not support directory, symlink or archive entries: \
+             {:?}",
+            file.subpath()
+        ))),
+        None => Ok(()),
+    }
 }
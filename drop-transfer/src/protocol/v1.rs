@@ -104,6 +104,8 @@ impl TryFrom<&crate::Transfer> for TransferRequest {
     type Error = crate::Error;
 
     fn try_from(value: &crate::Transfer) -> Result<Self, Self::Error> {
+        super::ensure_only_regular_files(value)?;
+
         let mut files: Vec<File> = Vec::new();
 
         for file in value.files().values() {
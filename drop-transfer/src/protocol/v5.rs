@@ -182,9 +182,13 @@ where
     }
 }
 
-impl From<&crate::Transfer> for TransferRequest {
-    fn from(value: &crate::Transfer) -> Self {
-        Self {
+impl TryFrom<&crate::Transfer> for TransferRequest {
+    type Error = crate::Error;
+
+    fn try_from(value: &crate::Transfer) -> Result<Self, Self::Error> {
+        super::ensure_only_regular_files(value)?;
+
+        Ok(Self {
             files: value
                 .files()
                 .values()
@@ -195,7 +199,7 @@ impl From<&crate::Transfer> for TransferRequest {
                 })
                 .collect(),
             id: value.id(),
-        }
+        })
     }
 }
 
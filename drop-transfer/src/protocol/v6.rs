@@ -0,0 +1,446 @@
+//! # File download flow
+//!
+//! * client (sender)   -> server (receiver): `TransferRequest`
+//!
+//! Huge manifests are split, the rest of the files follows the request in
+//! `ManifestUpdate` messages. The server handles each of them on arrival
+//!
+//! The transfer request can contain directory entries. These describe
+//! directories with no content that should be recreated on the receiver
+//! side. A directory entry is "downloaded" by the server by creating the
+//! directory and reporting `Done` right away, without any `Start`.
+//!
+//! Symbolic links are sent as entries with the link target. Those are
+//! recreated by the server only when the target resolves inside of the
+//! destination directory, in the same way as directories.
+//!
+//! Archive entries carry a whole directory as a single tar stream. The size is
+//! the size of the uncompressed stream and all the offsets refer to it. With
+//! compression enabled the chunks contain raw deflate data, flushed at the end
+//! of each chunk. The server extracts the archive once it is downloaded.
+//!
+//! The client can offer more files while the transfer is running. The new
+//! files are handled by the server in the same way as the ones from the
+//! initial request. Huge updates are split into multiple messages
+//! * client (sender)   -> server (receiver): `ManifestUpdate`
+//!
+//! If the server has the file or a part of it, the server can request checksum
+//! from the client. In that case sender must report the checksum. The request
+//! can be repeated
+//! * server (receiver) ->   client (sender): `ReqChsum (file)`
+//! * client (sender)   -> server (receiver): `ReportChsum (file)`
+//!
+//! If the server needs to download something:
+//! * server (receiver) ->   client (sender): `Start (file)`
+//! * client (sender)   -> server (receiver): `Chunk (file)`
+//! * server (receiver) ->   client (sender): `Progress (file)`
+//!
+//! This message indicate that the file is downloaded. Can be sent without
+//! `Start` in case the downloaded file is already there
+//! * server (receiver) ->   client (sender): `Done (file)`
+//!
+//! There is also a posibility to delete file from the transfer (reject)
+//! * server (receiver) ->   client (sender): `Reject (file)`
+//!
+//! This can also be send by the client
+//! * client (receiver) ->   server (sender): `Reject (file)`
+//!
+//! The operation cannot be undone and subsequest downloads of this file
+//! will result in error
+//!
+//! Both `Cancel` and `Reject` can carry an optional reason with an application
+//! defined code and a human readable message
+//!
+//! Either side can reject the whole transfer at once. All of the files are
+//! then treated as rejected and the connection is closed
+//! * server (receiver) <->  client (sender): `RejectTransfer`
+
+use serde::{Deserialize, Serialize};
+
+pub use super::v5::{Chunk, Done, Error, Progress, ReportChsum, ReqChsum, Start};
+use crate::{file::FileSubPath, FileId};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Eq, PartialEq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum FileKind {
+    #[default]
+    File,
+    Dir,
+    Symlink,
+    Archive,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Eq, PartialEq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    #[default]
+    None,
+    Deflate,
+}
+
+impl Compression {
+    fn is_none(&self) -> bool {
+        *self == Self::None
+    }
+}
+
+impl From<Compression> for crate::file::Compression {
+    fn from(value: Compression) -> Self {
+        match value {
+            Compression::None => Self::None,
+            Compression::Deflate => Self::Deflate,
+        }
+    }
+}
+
+impl From<crate::file::Compression> for Compression {
+    fn from(value: crate::file::Compression) -> Self {
+        match value {
+            crate::file::Compression::None => Self::None,
+            crate::file::Compression::Deflate => Self::Deflate,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct File {
+    pub path: FileSubPath,
+    pub id: FileId,
+    pub size: u64,
+    #[serde(default)]
+    pub kind: FileKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    #[serde(default, skip_serializing_if = "Compression::is_none")]
+    pub compression: Compression,
+}
+
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct TransferRequest {
+    pub files: Vec<File>,
+    pub id: uuid::Uuid,
+}
+
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct Reason {
+    pub code: u32,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct Cancel {
+    pub file: FileId,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<Reason>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct Reject {
+    pub file: FileId,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<Reason>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct RejectTransfer {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<Reason>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct ManifestUpdate {
+    pub files: Vec<File>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq)]
+#[serde(tag = "type")]
+pub enum ServerMsg {
+    Progress(Progress<FileId>),
+    Done(Done),
+    Error(Error<FileId>),
+    ReqChsum(ReqChsum),
+    Start(Start),
+    Cancel(Cancel),
+    Reject(Reject),
+    RejectTransfer(RejectTransfer),
+}
+
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq)]
+#[serde(tag = "type")]
+pub enum ClientMsg {
+    ReportChsum(ReportChsum),
+    Error(Error<FileId>),
+    Cancel(Cancel),
+    Reject(Reject),
+    RejectTransfer(RejectTransfer),
+    ManifestUpdate(ManifestUpdate),
+}
+
+impl From<&ServerMsg> for warp::ws::Message {
+    fn from(value: &ServerMsg) -> Self {
+        let msg = serde_json::to_string(value).expect("Failed to serialize server message");
+        Self::text(msg)
+    }
+}
+
+impl From<&ClientMsg> for tokio_tungstenite::tungstenite::Message {
+    fn from(value: &ClientMsg) -> Self {
+        let msg = serde_json::to_string(value).expect("Failed to serialize client message");
+        Self::Text(msg)
+    }
+}
+
+impl From<&crate::File> for File {
+    fn from(f: &crate::File) -> Self {
+        Self {
+            path: f.subpath().clone(),
+            id: f.id().clone(),
+            size: f.size(),
+            kind: if f.is_dir() {
+                FileKind::Dir
+            } else if f.symlink_target().is_some() {
+                FileKind::Symlink
+            } else if f.is_archive() {
+                FileKind::Archive
+            } else {
+                FileKind::File
+            },
+            target: f
+                .symlink_target()
+                .map(|target| target.to_string_lossy().to_string()),
+            compression: f.compression().into(),
+        }
+    }
+}
+
+impl From<&crate::Transfer> for TransferRequest {
+    fn from(value: &crate::Transfer) -> Self {
+        Self {
+            files: value.files().values().map(From::from).collect(),
+            id: value.id(),
+        }
+    }
+}
+
+impl From<crate::Reason> for Reason {
+    fn from(value: crate::Reason) -> Self {
+        Self {
+            code: value.code,
+            message: value.message,
+        }
+    }
+}
+
+impl From<Reason> for crate::Reason {
+    fn from(value: Reason) -> Self {
+        Self {
+            code: value.code,
+            message: value.message,
+        }
+    }
+}
+
+impl From<&TransferRequest> for tokio_tungstenite::tungstenite::Message {
+    fn from(value: &TransferRequest) -> Self {
+        let msg = serde_json::to_string(value).expect("Failed to serialize client message");
+        Self::Text(msg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::de::DeserializeOwned;
+
+    use super::*;
+
+    fn test_json<T: Serialize + DeserializeOwned + Eq>(message: T, expected: &str) {
+        let json_msg = serde_json::to_value(&message).expect("Failed to serialize");
+        let json_exp: serde_json::Value =
+            serde_json::from_str(expected).expect("Failed to convert expected josn to value");
+        assert_eq!(json_msg, json_exp);
+
+        let deserialized: T = serde_json::from_str(expected).expect("Failed to serialize");
+        assert!(deserialized == message);
+    }
+
+    #[test]
+    fn client_json_messages() {
+        test_json(
+            TransferRequest {
+                files: vec![
+                    File {
+                        path: "dir/a.txt".into(),
+                        id: "ID1".into(),
+                        size: 41,
+                        kind: FileKind::File,
+                        target: None,
+                        compression: Compression::None,
+                    },
+                    File {
+                        path: "dir/empty".into(),
+                        id: "ID2".into(),
+                        size: 0,
+                        kind: FileKind::Dir,
+                        target: None,
+                        compression: Compression::None,
+                    },
+                    File {
+                        path: "dir/link".into(),
+                        id: "ID3".into(),
+                        size: 0,
+                        kind: FileKind::Symlink,
+                        target: Some("a.txt".into()),
+                        compression: Compression::None,
+                    },
+                    File {
+                        path: "tree".into(),
+                        id: "ID4".into(),
+                        size: 10240,
+                        kind: FileKind::Archive,
+                        target: None,
+                        compression: Compression::Deflate,
+                    },
+                ],
+                id: uuid::uuid!("1b0397eb-66e9-4252-b7cf-71782698ee3d"),
+            },
+            r#"
+            {
+              "files": [
+                {
+                  "path": "dir/a.txt",
+                  "id": "ID1",
+                  "size": 41,
+                  "kind": "file"
+                },
+                {
+                  "path": "dir/empty",
+                  "id": "ID2",
+                  "size": 0,
+                  "kind": "dir"
+                },
+                {
+                  "path": "dir/link",
+                  "id": "ID3",
+                  "size": 0,
+                  "kind": "symlink",
+                  "target": "a.txt"
+                },
+                {
+                  "path": "tree",
+                  "id": "ID4",
+                  "size": 10240,
+                  "kind": "archive",
+                  "compression": "deflate"
+                }
+              ],
+              "id": "1b0397eb-66e9-4252-b7cf-71782698ee3d"
+            }"#,
+        );
+    }
+
+    #[test]
+    fn manifest_update_json() {
+        test_json(
+            ClientMsg::ManifestUpdate(ManifestUpdate {
+                files: vec![File {
+                    path: "b.txt".into(),
+                    id: "ID5".into(),
+                    size: 12,
+                    kind: FileKind::File,
+                    target: None,
+                    compression: Compression::None,
+                }],
+            }),
+            r#"
+            {
+              "type": "ManifestUpdate",
+              "files": [
+                {
+                  "path": "b.txt",
+                  "id": "ID5",
+                  "size": 12,
+                  "kind": "file"
+                }
+              ]
+            }"#,
+        );
+    }
+
+    #[test]
+    fn reason_json() {
+        test_json(
+            ServerMsg::Cancel(Cancel {
+                file: "ID1".into(),
+                reason: Some(Reason {
+                    code: 3,
+                    message: "Disk full".into(),
+                }),
+            }),
+            r#"
+            {
+              "type": "Cancel",
+              "file": "ID1",
+              "reason": {
+                "code": 3,
+                "message": "Disk full"
+              }
+            }"#,
+        );
+
+        test_json(
+            ClientMsg::Reject(Reject {
+                file: "ID1".into(),
+                reason: Some(Reason {
+                    code: 7,
+                    message: String::new(),
+                }),
+            }),
+            r#"
+            {
+              "type": "Reject",
+              "file": "ID1",
+              "reason": {
+                "code": 7
+              }
+            }"#,
+        );
+
+        test_json(
+            ServerMsg::RejectTransfer(RejectTransfer {
+                reason: Some(Reason {
+                    code: 1,
+                    message: "Not now".into(),
+                }),
+            }),
+            r#"
+            {
+              "type": "RejectTransfer",
+              "reason": {
+                "code": 1,
+                "message": "Not now"
+              }
+            }"#,
+        );
+
+        test_json(
+            ClientMsg::RejectTransfer(RejectTransfer { reason: None }),
+            r#"
+            {
+              "type": "RejectTransfer"
+            }"#,
+        );
+
+        test_json(
+            ClientMsg::Cancel(Cancel {
+                file: "ID1".into(),
+                reason: None,
+            }),
+            r#"
+            {
+              "type": "Cancel",
+              "file": "ID1"
+            }"#,
+        );
+    }
+}
@@ -1,8 +1,10 @@
 use std::{
+    collections::HashMap,
     fs,
     net::IpAddr,
-    path::{Component, Path},
-    sync::Arc,
+    path::{Component, Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::Duration,
 };
 
 use drop_analytics::Moose;
@@ -10,7 +12,10 @@ use drop_config::DropConfig;
 use drop_storage::Storage;
 use slog::{debug, error, warn, Logger};
 use tokio::{
-    sync::{mpsc, Mutex},
+    sync::{
+        mpsc::{self, UnboundedSender},
+        Mutex, Notify,
+    },
     task::JoinHandle,
 };
 use tokio_util::sync::CancellationToken;
@@ -19,7 +24,12 @@ use uuid::Uuid;
 use crate::{
     auth,
     error::ResultExt,
+    file_request::{ExportStore, FileRequest},
     manager::TransferConnection,
+    pairing::{self, PairingStore},
+    progress::{ActiveTransfer, EventTx},
+    share::ShareStore,
+    transfer::ManifestFeed,
     ws::{
         self,
         client::ClientReq,
@@ -29,21 +39,45 @@ use crate::{
 };
 
 pub(super) struct State {
-    pub(super) event_tx: mpsc::Sender<Event>,
+    pub(super) event_tx: EventTx,
     pub(super) transfer_manager: Mutex<TransferManager>,
     pub(crate) moose: Arc<dyn Moose>,
     pub(crate) auth: Arc<auth::Context>,
-    pub(crate) config: Arc<DropConfig>,
+    config: RwLock<Arc<DropConfig>>,
     pub(crate) storage: Arc<Storage>,
+    pub(crate) shares: Mutex<ShareStore>,
+    pub(crate) exports: Mutex<ExportStore>,
+    pub(crate) pairing: Mutex<PairingStore>,
+    // Wakes up the outgoing connections waiting to retry
+    pub(crate) network_changed: Notify,
+}
+
+impl State {
+    /// Snapshot of the current configuration, it may be replaced at runtime
+    pub(crate) fn config(&self) -> Arc<DropConfig> {
+        self.config.read().expect("Config lock poisoned").clone()
+    }
 }
 
 pub struct Service {
     pub(super) state: Arc<State>,
     pub(crate) stop: CancellationToken,
-    join_handle: JoinHandle<()>,
+    listeners: HashMap<IpAddr, Listener>,
     pub(super) logger: Logger,
 }
 
+struct Listener {
+    stop: CancellationToken,
+    join_handle: JoinHandle<()>,
+}
+
+impl Listener {
+    async fn stop(self) -> Result<(), Error> {
+        self.stop.cancel();
+        self.join_handle.await.map_err(|_| Error::ServiceStop)
+    }
+}
+
 macro_rules! moose_try_file {
     ($moose:expr, $func:expr, $xfer_id:expr, $file_info:expr) => {
         match $func {
@@ -66,7 +100,7 @@ macro_rules! moose_try_file {
 // todo: better name to reduce confusion
 impl Service {
     pub fn start(
-        addr: IpAddr,
+        addrs: &[IpAddr],
         storage: Arc<Storage>,
         event_tx: mpsc::Sender<Event>,
         logger: Logger,
@@ -75,25 +109,37 @@ impl Service {
         auth: Arc<auth::Context>,
     ) -> Result<Self, Error> {
         let task = || {
+            pairing::load_paired(&auth, &storage, &logger);
+
             let state = Arc::new(State {
-                event_tx,
+                event_tx: EventTx::new(event_tx),
                 transfer_manager: Mutex::default(),
                 moose: moose.clone(),
-                config,
+                config: RwLock::new(config),
                 auth: auth.clone(),
                 storage,
+                shares: Mutex::default(),
+                exports: Mutex::default(),
+                pairing: Mutex::default(),
+                network_changed: Notify::new(),
             });
 
-            let stop = CancellationToken::new();
-            let join_handle =
-                ws::server::start(addr, stop.clone(), state.clone(), auth, logger.clone())?;
-
-            Ok(Self {
+            let mut service = Self {
                 state,
-                join_handle,
-                stop,
+                stop: CancellationToken::new(),
+                listeners: HashMap::new(),
                 logger,
-            })
+            };
+
+            for addr in addrs {
+                if let Err(err) = service.enable_listener(*addr) {
+                    // Shut down the already started listeners
+                    service.stop.cancel();
+                    return Err(err);
+                }
+            }
+
+            Ok(service)
         };
 
         let res = task();
@@ -102,10 +148,20 @@ impl Service {
         res
     }
 
-    pub async fn stop(self) -> Result<(), Error> {
+    pub async fn stop(mut self) -> Result<(), Error> {
+        let listeners = std::mem::take(&mut self.listeners);
+
         let task = async {
             self.stop.cancel();
-            self.join_handle.await.map_err(|_| Error::ServiceStop)
+
+            let mut res = Ok(());
+            for listener in listeners.into_values() {
+                if let Err(err) = listener.stop().await {
+                    res = Err(err);
+                }
+            }
+
+            res
         };
 
         let res = task.await;
@@ -117,6 +173,94 @@ impl Service {
         res
     }
 
+    /// Move the server to the new set of addresses, e.g. after the network
+    /// change. The incoming connections are kept, the outgoing transfers are
+    /// dialed again over the new route and the ones that are still connecting
+    /// retry right away instead of waiting for the backoff. When one of the
+    /// addresses cannot be bound the previous listeners are restored
+    pub async fn rebind(&mut self, addrs: &[IpAddr]) -> crate::Result<()> {
+        debug!(self.logger, "Rebinding the server to: {addrs:?}");
+
+        // The old listeners go first as they may hold the port on an
+        // overlapping address
+        let stale: Vec<_> = self
+            .listeners
+            .keys()
+            .filter(|addr| !addrs.contains(addr))
+            .copied()
+            .collect();
+
+        for &addr in &stale {
+            self.disable_listener(addr).await?;
+        }
+
+        let mut enabled = Vec::new();
+        for &addr in addrs {
+            if self.listeners.contains_key(&addr) {
+                continue;
+            }
+
+            if let Err(err) = self.enable_listener(addr) {
+                error!(self.logger, "Failed to listen on {addr}: {err}");
+                self.restore_listeners(enabled, stale).await;
+                return Err(err);
+            }
+
+            enabled.push(addr);
+        }
+
+        self.state.network_changed.notify_waiters();
+
+        Ok(())
+    }
+
+    async fn restore_listeners(&mut self, enabled: Vec<IpAddr>, stale: Vec<IpAddr>) {
+        for addr in enabled {
+            if let Err(err) = self.disable_listener(addr).await {
+                warn!(self.logger, "Failed to stop the listener on {addr}: {err}");
+            }
+        }
+
+        for addr in stale {
+            if let Err(err) = self.enable_listener(addr) {
+                warn!(
+                    self.logger,
+                    "Failed to restore the listener on {addr}: {err}"
+                );
+            }
+        }
+    }
+
+    /// Start accepting the transfers on the address. Does nothing if the
+    /// server already listens on it
+    pub fn enable_listener(&mut self, addr: IpAddr) -> crate::Result<()> {
+        if self.listeners.contains_key(&addr) {
+            return Ok(());
+        }
+
+        // Stopping the listener does not affect the established connections
+        let stop = self.stop.child_token();
+
+        let join_handle = ws::server::start(
+            addr,
+            self.stop.clone(),
+            stop.clone(),
+            self.state.clone(),
+            self.state.auth.clone(),
+            self.logger.clone(),
+        )?;
+
+        self.listeners.insert(addr, Listener { stop, join_handle });
+        Ok(())
+    }
+
+    /// Stop accepting the transfers on the address, the transfers that arrived
+    /// on it keep going
+    pub async fn disable_listener(&mut self, addr: IpAddr) -> crate::Result<()> {
+        let listener = self.listeners.remove(&addr).ok_or(Error::InvalidArgument)?;
+        listener.stop().await
+    }
+
     pub fn purge_transfers(&self, transfer_ids: Vec<String>) -> Result<(), Error> {
         if let Err(e) = self.state.storage.purge_transfers(transfer_ids) {
             error!(self.logger, "Failed to purge transfers: {e}");
@@ -135,6 +279,26 @@ impl Service {
         Ok(())
     }
 
+    /// Identity of the peer last seen at the given address
+    pub fn peer_identity(&self, addr: IpAddr) -> Result<Option<String>, Error> {
+        self.state
+            .storage
+            .peer_identity(&addr.to_string())
+            .map_err(|e| {
+                error!(self.logger, "Failed to get peer identity: {e}");
+                Error::StorageError
+            })
+    }
+
+    /// Addresses the peer with the given identity was seen at, most recent
+    /// first
+    pub fn peer_addresses(&self, identity: &str) -> Result<Vec<String>, Error> {
+        self.state.storage.peer_addresses(identity).map_err(|e| {
+            error!(self.logger, "Failed to get peer addresses: {e}");
+            Error::StorageError
+        })
+    }
+
     pub fn transfers_since(
         &self,
         since_timestamp: i64,
@@ -169,48 +333,214 @@ impl Service {
     }
 
     pub async fn send_request(&mut self, xfer: crate::Transfer) {
-        self.state.moose.service_quality_transfer_batch(
-            drop_analytics::Phase::Start,
-            xfer.id().to_string(),
-            xfer.info(),
+        spawn_request(
+            self.state.clone(),
+            self.stop.clone(),
+            xfer,
+            self.logger.clone(),
         );
+    }
 
-        if let Err(err) = self.state.storage.insert_transfer(&xfer.storage_info()) {
-            error!(self.logger, "Failed to insert transfer into storage: {err}",);
+    /// Same as `send_request()` but the rest of the files arrive through the
+    /// feed while the transfer is already running. The peers which cannot
+    /// take appended files get the request once the feed is drained
+    pub async fn send_request_streamed(&mut self, xfer: crate::Transfer, feed: ManifestFeed) {
+        spawn_outgoing(
+            self.state.clone(),
+            self.stop.clone(),
+            xfer,
+            Some(feed),
+            None,
+            self.logger.clone(),
+        );
+    }
+
+    /// Publish the files under a random token. Authenticated peers can then
+    /// pull them with `pull()` until the share expires or the download limit
+    /// is reached
+    pub async fn publish(
+        &self,
+        files: Vec<crate::File>,
+        lifetime: Duration,
+        max_downloads: Option<u32>,
+    ) -> crate::Result<String> {
+        if files.len() > self.state.config().transfer_file_limit {
+            return Err(Error::TransferLimitsExceeded);
         }
 
-        let stop_job = {
-            let state = self.state.clone();
-            let xfer = xfer.clone();
-            let logger = self.logger.clone();
+        self.state
+            .shares
+            .lock()
+            .await
+            .publish(files, lifetime, max_downloads)
+    }
+
+    pub async fn unpublish(&self, token: &str) -> crate::Result<()> {
+        self.state.shares.lock().await.unpublish(token)
+    }
+
+    /// Ask the peer for the files published under the token. On success the
+    /// files are received over the same connection, in a regular incoming
+    /// transfer the ID of which is returned
+    pub async fn pull(&self, peer: IpAddr, token: &str) -> crate::Result<Uuid> {
+        ws::client::pull(&self.state, self.stop.clone(), peer, token, &self.logger).await
+    }
+
+    /// Allow the peers to request files from the directory under the given
+    /// name
+    pub async fn export_dir(&self, name: String, dir: &Path) -> crate::Result<()> {
+        self.state.exports.lock().await.export(name, dir)
+    }
+
+    pub async fn unexport_dir(&self, name: &str) -> crate::Result<()> {
+        self.state.exports.lock().await.unexport(name)
+    }
+
+    /// Ask the peer for the paths under its exported directory. Returns the ID
+    /// of the request, which is also the ID of the transfer started by the
+    /// peer after the approval
+    pub async fn request_files(
+        &self,
+        peer: IpAddr,
+        export: String,
+        paths: Vec<String>,
+    ) -> crate::Result<Uuid> {
+        let req = FileRequest { export, paths };
+        let request_id = ws::client::request_files(&self.state, peer, &req, &self.logger).await?;
+
+        self.state.exports.lock().await.sent(peer, request_id);
+        Ok(request_id)
+    }
+
+    /// Send the requested files to the peer
+    pub async fn approve_file_request(&self, request_id: Uuid) -> crate::Result<()> {
+        let req = self.state.exports.lock().await.take(request_id)?;
+
+        let config = self.state.config();
+        let files = tokio::task::spawn_blocking(move || {
+            req.paths.iter().try_fold(Vec::new(), |mut files, path| {
+                files.extend(crate::File::from_path(path, &config)?);
+                crate::Result::Ok(files)
+            })
+        })
+        .await
+        .map_err(|err| Error::BadTransferState(err.to_string()))??;
+
+        let xfer =
+            crate::Transfer::new_with_uuid(req.peer, files, request_id, &self.state.config())?;
+
+        spawn_request(
+            self.state.clone(),
+            self.stop.clone(),
+            xfer,
+            self.logger.clone(),
+        );
+
+        Ok(())
+    }
 
-            async move {
-                // Stop the download job
-                warn!(logger, "Aborting transfer download");
+    /// Drop the file request. The peer is notified in the background and
+    /// receives the `FileRequestDenied` event
+    pub async fn deny_file_request(&self, request_id: Uuid) -> crate::Result<()> {
+        let req = self.state.exports.lock().await.take(request_id)?;
 
-                state
-                    .event_tx
-                    .send(Event::TransferFailed(xfer, crate::Error::Canceled, true))
-                    .await
-                    .expect("Failed to send TransferFailed event");
+        let state = self.state.clone();
+        let logger = self.logger.clone();
+        tokio::spawn(async move {
+            if let Err(err) =
+                ws::client::deny_file_request(&state, req.peer, request_id, &logger).await
+            {
+                warn!(
+                    logger,
+                    "Failed to notify {} about the denied file request {request_id}: {err}",
+                    req.peer
+                );
             }
-        };
+        });
+
+        Ok(())
+    }
+
+    /// Generate the code for an unknown peer to pair with us. The code allows
+    /// a single attempt within its lifetime and replaces the previous one
+    pub async fn start_pairing(&self, lifetime: Duration) -> String {
+        self.state.pairing.lock().await.start(lifetime)
+    }
 
-        let client_job = ws::client::run(self.state.clone(), xfer, self.logger.clone());
+    pub async fn cancel_pairing(&self) -> crate::Result<()> {
+        self.state.pairing.lock().await.cancel()
+    }
+
+    /// Pair with the peer using the code it generated. Both sides exchange and
+    /// persist their public keys, the outcome is reported with the pairing
+    /// events
+    pub fn pair(&self, peer: IpAddr, code: String) {
+        let state = self.state.clone();
         let stop = self.stop.clone();
+        let logger = self.logger.clone();
 
         tokio::spawn(async move {
-            tokio::select! {
+            state
+                .event_tx
+                .send(Event::PairingStarted(peer))
+                .await
+                .expect("Failed to send PairingStarted event");
+
+            let res = tokio::select! {
                 biased;
 
-                _ = stop.cancelled() => {
-                    stop_job.await;
-                },
-                _ = client_job => (),
+                _ = stop.cancelled() => Err(Error::Canceled),
+                res = ws::client::pair(&state, peer, &code, &logger) => res,
+            };
+
+            match res {
+                Ok(key) => pairing::complete(&state, peer, key, &logger).await,
+                Err(err) => {
+                    warn!(logger, "Failed to pair with {peer}: {err}");
+
+                    state
+                        .event_tx
+                        .send(Event::PairingFailed(peer, err))
+                        .await
+                        .expect("Failed to send PairingFailed event");
+                }
             }
         });
     }
 
+    /// Offer more files within an already running outgoing transfer. Requires
+    /// the peer to support protocol v6
+    pub async fn append_files(
+        &self,
+        transfer_id: Uuid,
+        files: Vec<crate::File>,
+    ) -> crate::Result<()> {
+        let mut lock = self.state.transfer_manager.lock().await;
+        let (xfer, ids, conn) =
+            lock.append_outgoing_files(transfer_id, files, &self.state.config())?;
+
+        let storage_info = xfer.files_storage_info(&ids);
+
+        // Nothing is kept when the connection is already gone
+        if let Err(err) = conn.send(ClientReq::Append {
+            xfer,
+            files: ids.clone(),
+        }) {
+            lock.remove_outgoing_files(transfer_id, &ids);
+            return Err(Error::BadTransferState(err.to_string()));
+        }
+        drop(lock);
+
+        if let Err(err) = self.state.storage.insert_transfer_paths(&storage_info) {
+            error!(
+                self.logger,
+                "Failed to insert appended paths into storage: {err}"
+            );
+        }
+
+        Ok(())
+    }
+
     pub async fn download(
         &mut self,
         uuid: Uuid,
@@ -225,26 +555,54 @@ impl Service {
             parent_dir.display()
         );
 
-        let fetch_xfer = async {
+        let fetched = {
             let mut lock = self.state.transfer_manager.lock().await;
-            lock.ensure_file_not_rejected(uuid, file_id)?;
-
-            let chann = lock.connection(uuid).ok_or(Error::BadTransfer)?;
-            let chann = match chann {
-                TransferConnection::Server(chann) => chann.clone(),
-                _ => return Err(Error::BadTransfer),
-            };
+            fetch_download(&mut lock, uuid, file_id, parent_dir)
+        };
 
-            let mapped_file_path =
-                parent_dir.join(lock.apply_dir_mapping(uuid, parent_dir, file_id)?);
+        self.start_download(uuid, file_id, parent_dir, fetched)
+    }
 
-            let xfer = lock.transfer(&uuid).ok_or(Error::BadTransfer)?.clone();
+    /// Download multiple files of a transfer into the same directory. The
+    /// transfer manager is locked only once. Returns the result for each of
+    /// the files, in order
+    pub async fn download_files(
+        &mut self,
+        uuid: Uuid,
+        file_ids: &[FileId],
+        parent_dir: &Path,
+    ) -> Vec<crate::Result<()>> {
+        debug!(
+            self.logger,
+            "Client::download_files() called with Uuid: {}, files: {}, parent_dir: {}",
+            uuid,
+            file_ids.len(),
+            parent_dir.display()
+        );
 
-            Ok((xfer, chann, mapped_file_path))
+        let fetched: Vec<_> = {
+            let mut lock = self.state.transfer_manager.lock().await;
+            file_ids
+                .iter()
+                .map(|file_id| fetch_download(&mut lock, uuid, file_id, parent_dir))
+                .collect()
         };
 
-        let (xfer, channel, absolute_path) =
-            moose_try_file!(self.state.moose, fetch_xfer.await, uuid, None);
+        file_ids
+            .iter()
+            .zip(fetched)
+            .map(|(file_id, fetched)| self.start_download(uuid, file_id, parent_dir, fetched))
+            .collect()
+    }
+
+    fn start_download(
+        &self,
+        uuid: Uuid,
+        file_id: &FileId,
+        parent_dir: &Path,
+        fetched: crate::Result<(crate::Transfer, UnboundedSender<ServerReq>, PathBuf)>,
+    ) -> crate::Result<()> {
+        let (xfer, channel, absolute_path) = moose_try_file!(self.state.moose, fetched, uuid, None);
 
         let file = moose_try_file!(
             self.state.moose,
@@ -315,35 +673,70 @@ impl Service {
         Ok(())
     }
 
-    /// Cancel a single file in a transfer
-    pub async fn cancel(&mut self, xfer_uuid: Uuid, file: FileId) -> crate::Result<()> {
+    /// Cancel a single file in a transfer. The optional reason is passed on to
+    /// the peer
+    pub async fn cancel(
+        &mut self,
+        xfer_uuid: Uuid,
+        file: FileId,
+        reason: Option<crate::Reason>,
+    ) -> crate::Result<()> {
         let lock = self.state.transfer_manager.lock().await;
-        lock.ensure_file_not_rejected(xfer_uuid, &file)?;
-
-        let conn = lock.connection(xfer_uuid).ok_or(Error::BadTransfer)?;
+        cancel_file(&lock, xfer_uuid, file, reason)
+    }
 
-        match conn {
-            TransferConnection::Client(conn) => {
-                conn.send(ClientReq::Cancel { file })
-                    .map_err(|err| Error::BadTransferState(err.to_string()))?;
-            }
-            TransferConnection::Server(conn) => {
-                conn.send(ServerReq::Cancel { file })
-                    .map_err(|err| Error::BadTransferState(err.to_string()))?;
-            }
-        }
+    /// Cancel multiple files in a transfer under a single lock. Returns the
+    /// result for each of the files, in order
+    pub async fn cancel_files(
+        &mut self,
+        xfer_uuid: Uuid,
+        files: Vec<FileId>,
+    ) -> Vec<crate::Result<()>> {
+        let lock = self.state.transfer_manager.lock().await;
 
-        Ok(())
+        files
+            .into_iter()
+            .map(|file| cancel_file(&lock, xfer_uuid, file, None))
+            .collect()
     }
 
     /// Reject a single file in a transfer. After rejection the file can no
-    /// logner be transfered
-    pub async fn reject(&self, transfer_id: Uuid, file: FileId) -> crate::Result<()> {
+    /// logner be transfered. The optional reason is passed on to the peer
+    pub async fn reject(
+        &self,
+        transfer_id: Uuid,
+        file: FileId,
+        reason: Option<crate::Reason>,
+    ) -> crate::Result<()> {
         let mut lock = self.state.transfer_manager.lock().await;
+        reject_file(&mut lock, transfer_id, file, reason)
+    }
 
-        if !lock.reject_file(transfer_id, file.clone())? {
-            return Err(crate::Error::Rejected);
-        }
+    /// Reject multiple files in a transfer under a single lock. Returns the
+    /// result for each of the files, in order
+    pub async fn reject_files(
+        &self,
+        transfer_id: Uuid,
+        files: Vec<FileId>,
+    ) -> Vec<crate::Result<()>> {
+        let mut lock = self.state.transfer_manager.lock().await;
+
+        files
+            .into_iter()
+            .map(|file| reject_file(&mut lock, transfer_id, file, None))
+            .collect()
+    }
+
+    /// Reject all of the files in a transfer with a single message to the
+    /// peer. The connection is closed afterwards
+    pub async fn reject_transfer(
+        &self,
+        transfer_id: Uuid,
+        reason: Option<crate::Reason>,
+    ) -> crate::Result<()> {
+        let mut lock = self.state.transfer_manager.lock().await;
+
+        let (_, files) = lock.reject_all_files(transfer_id)?;
 
         let conn = lock
             .connection(transfer_id)
@@ -351,11 +744,11 @@ impl Service {
 
         match conn {
             TransferConnection::Client(conn) => {
-                conn.send(ClientReq::Reject { file })
+                conn.send(ClientReq::RejectTransfer { files, reason })
                     .map_err(|err| Error::BadTransferState(err.to_string()))?;
             }
             TransferConnection::Server(conn) => {
-                conn.send(ServerReq::Reject { file })
+                conn.send(ServerReq::RejectTransfer { files, reason })
                     .map_err(|err| Error::BadTransferState(err.to_string()))?;
             }
         }
@@ -363,6 +756,34 @@ impl Service {
         Ok(())
     }
 
+    /// Replace the configuration of the running service. Limits and timeouts
+    /// apply to the requests and connections made from now on. The storage
+    /// path is fixed for the lifetime of the service, same as the storage,
+    /// auth context and listen addresses passed to `Service::start()`. The
+    /// latter are changed with `enable_listener()`, `disable_listener()` and
+    /// `rebind()`
+    pub fn update_config(&self, config: DropConfig) -> crate::Result<()> {
+        let mut current = self.state.config.write().expect("Config lock poisoned");
+
+        if current.storage_path != config.storage_path {
+            return Err(Error::ConfigNotUpdatable("storage_path".into()));
+        }
+
+        debug!(self.logger, "Updating config: {:?}", config);
+        *current = Arc::new(config);
+
+        Ok(())
+    }
+
+    /// Snapshot of the live state of all of the connecting and connected
+    /// transfers
+    pub async fn active_transfers(&self) -> Vec<ActiveTransfer> {
+        let lock = self.state.transfer_manager.lock().await;
+        let tracker = self.state.event_tx.tracker();
+
+        lock.active_transfers(&tracker)
+    }
+
     /// Cancel all of the files in a transfer
     pub async fn cancel_all(&mut self, transfer_id: Uuid) -> crate::Result<()> {
         let mut lock = self.state.transfer_manager.lock().await;
@@ -389,7 +810,155 @@ impl Service {
                 "Could not cancel transfer(client): {}. xfer: {}", e, transfer_id,
             );
         }
+        lock.forget_transfer(transfer_id);
 
         Ok(())
     }
 }
+
+fn fetch_download(
+    lock: &mut TransferManager,
+    uuid: Uuid,
+    file_id: &FileId,
+    parent_dir: &Path,
+) -> crate::Result<(crate::Transfer, UnboundedSender<ServerReq>, PathBuf)> {
+    lock.ensure_file_not_rejected(uuid, file_id)?;
+
+    let chann = lock.connection(uuid).ok_or(Error::BadTransfer)?;
+    let chann = match chann {
+        TransferConnection::Server(chann) => chann.clone(),
+        _ => return Err(Error::BadTransfer),
+    };
+
+    let mapped_file_path = parent_dir.join(lock.apply_dir_mapping(uuid, parent_dir, file_id)?);
+
+    let xfer = lock.transfer(&uuid).ok_or(Error::BadTransfer)?.clone();
+
+    Ok((xfer, chann, mapped_file_path))
+}
+
+fn cancel_file(
+    lock: &TransferManager,
+    xfer_uuid: Uuid,
+    file: FileId,
+    reason: Option<crate::Reason>,
+) -> crate::Result<()> {
+    lock.ensure_file_not_rejected(xfer_uuid, &file)?;
+
+    let conn = lock.connection(xfer_uuid).ok_or(Error::BadTransfer)?;
+
+    match conn {
+        TransferConnection::Client(conn) => {
+            conn.send(ClientReq::Cancel { file, reason })
+                .map_err(|err| Error::BadTransferState(err.to_string()))?;
+        }
+        TransferConnection::Server(conn) => {
+            conn.send(ServerReq::Cancel { file, reason })
+                .map_err(|err| Error::BadTransferState(err.to_string()))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn reject_file(
+    lock: &mut TransferManager,
+    transfer_id: Uuid,
+    file: FileId,
+    reason: Option<crate::Reason>,
+) -> crate::Result<()> {
+    if !lock.reject_file(transfer_id, file.clone())? {
+        return Err(crate::Error::Rejected);
+    }
+
+    let conn = lock
+        .connection(transfer_id)
+        .expect("The transfer is present since reject was sucessful");
+
+    match conn {
+        TransferConnection::Client(conn) => {
+            conn.send(ClientReq::Reject { file, reason })
+                .map_err(|err| Error::BadTransferState(err.to_string()))?;
+        }
+        TransferConnection::Server(conn) => {
+            conn.send(ServerReq::Reject { file, reason })
+                .map_err(|err| Error::BadTransferState(err.to_string()))?;
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) fn spawn_request(
+    state: Arc<State>,
+    stop: CancellationToken,
+    xfer: crate::Transfer,
+    logger: Logger,
+) {
+    spawn_outgoing(state, stop, xfer, None, None, logger)
+}
+
+/// Send the pulled share over the connection the peer opened to our server
+pub(crate) fn spawn_pulled_request(
+    state: Arc<State>,
+    stop: CancellationToken,
+    xfer: crate::Transfer,
+    socket: warp::ws::WebSocket,
+    logger: Logger,
+) {
+    spawn_outgoing(state, stop, xfer, None, Some(socket), logger)
+}
+
+fn spawn_outgoing(
+    state: Arc<State>,
+    stop: CancellationToken,
+    xfer: crate::Transfer,
+    feed: Option<ManifestFeed>,
+    socket: Option<warp::ws::WebSocket>,
+    logger: Logger,
+) {
+    state.moose.service_quality_transfer_batch(
+        drop_analytics::Phase::Start,
+        xfer.id().to_string(),
+        xfer.info(),
+    );
+
+    if let Err(err) = state.storage.insert_transfer(&xfer.storage_info()) {
+        error!(logger, "Failed to insert transfer into storage: {err}",);
+    }
+
+    let stop_job = {
+        let state = state.clone();
+        let xfer = xfer.clone();
+        let logger = logger.clone();
+
+        async move {
+            // Stop the download job
+            warn!(logger, "Aborting transfer download");
+
+            state
+                .event_tx
+                .send(Event::TransferFailed(xfer, crate::Error::Canceled, true))
+                .await
+                .expect("Failed to send TransferFailed event");
+        }
+    };
+
+    let client_job = async move {
+        match socket {
+            Some(socket) => ws::client::run_pulled(state, xfer, socket, logger).await,
+            None => ws::client::run(state, xfer, feed, logger).await,
+        }
+    };
+
+    tokio::spawn(async move {
+        tokio::select! {
+            biased;
+
+            _ = stop.cancelled() => {
+                stop_job.await;
+            },
+            _ = client_job => (),
+        }
+    });
+}
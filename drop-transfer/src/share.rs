@@ -0,0 +1,134 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{Error, File};
+
+/// Sent by the peer pulling the share as the first message on the connection
+#[derive(Serialize, Deserialize)]
+pub(crate) struct PullRequest {
+    pub token: String,
+}
+
+/// Response of the publisher. The files of an accepted pull are then offered
+/// over the same connection, in a regular transfer with the given ID
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub(crate) enum PullResponse {
+    Accepted { transfer: Uuid },
+    Unavailable,
+}
+
+struct Share {
+    files: Vec<File>,
+    expires_at: Instant,
+    downloads_left: Option<u32>,
+}
+
+/// Files published under random tokens, waiting for the peers to pull them
+#[derive(Default)]
+pub(crate) struct ShareStore {
+    shares: HashMap<String, Share>,
+}
+
+impl ShareStore {
+    /// Register the files and return the token under which they can be pulled.
+    /// `max_downloads` of `None` means there is no limit
+    pub(crate) fn publish(
+        &mut self,
+        files: Vec<File>,
+        lifetime: Duration,
+        max_downloads: Option<u32>,
+    ) -> crate::Result<String> {
+        if max_downloads == Some(0) {
+            return Err(Error::InvalidArgument);
+        }
+
+        self.remove_expired();
+
+        let token = Uuid::new_v4().simple().to_string();
+        self.shares.insert(
+            token.clone(),
+            Share {
+                files,
+                expires_at: Instant::now() + lifetime,
+                downloads_left: max_downloads,
+            },
+        );
+
+        Ok(token)
+    }
+
+    pub(crate) fn unpublish(&mut self, token: &str) -> crate::Result<()> {
+        self.shares
+            .remove(token)
+            .map(|_| ())
+            .ok_or(Error::ShareUnavailable)
+    }
+
+    /// Consume one download of the share. The share is removed once the
+    /// download limit is reached
+    pub(crate) fn take(&mut self, token: &str) -> crate::Result<Vec<File>> {
+        self.remove_expired();
+
+        let share = self.shares.get_mut(token).ok_or(Error::ShareUnavailable)?;
+        let files = share.files.clone();
+
+        if let Some(left) = &mut share.downloads_left {
+            *left -= 1;
+            if *left == 0 {
+                self.shares.remove(token);
+            }
+        }
+
+        Ok(files)
+    }
+
+    fn remove_expired(&mut self) {
+        let now = Instant::now();
+        self.shares.retain(|_, share| share.expires_at > now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn download_limit() {
+        let mut store = ShareStore::default();
+
+        let token = store
+            .publish(vec![], Duration::from_secs(60), Some(2))
+            .unwrap();
+
+        assert!(store.take(&token).is_ok());
+        assert!(store.take(&token).is_ok());
+        assert!(matches!(store.take(&token), Err(Error::ShareUnavailable)));
+
+        assert!(store
+            .publish(vec![], Duration::from_secs(60), Some(0))
+            .is_err());
+    }
+
+    #[test]
+    fn expiry() {
+        let mut store = ShareStore::default();
+
+        let expired = store.publish(vec![], Duration::ZERO, None).unwrap();
+        let valid = store
+            .publish(vec![], Duration::from_secs(60), None)
+            .unwrap();
+
+        assert!(matches!(store.take(&expired), Err(Error::ShareUnavailable)));
+        assert!(store.take(&valid).is_ok());
+        assert!(store.take(&valid).is_ok());
+
+        store.unpublish(&valid).unwrap();
+        assert!(matches!(store.take(&valid), Err(Error::ShareUnavailable)));
+    }
+}
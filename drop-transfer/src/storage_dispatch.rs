@@ -21,7 +21,11 @@ impl<'a> StorageDispatch<'a> {
     }
 
     pub fn handle_event(&mut self, event: &crate::Event) -> Result<(), Error> {
-        let event = Into::<Event>::into(event);
+        let event = match Into::<Option<Event>>::into(event) {
+            Some(event) => event,
+            None => return Ok(()),
+        };
+
         match event {
             Event::Pending { transfer_info } => match &transfer_info.files {
                 TransferFiles::Incoming(files) => {
@@ -59,6 +63,7 @@ impl<'a> StorageDispatch<'a> {
                 transfer_id,
                 file_id,
                 by_peer,
+                reason,
             } => match transfer_type {
                 TransferType::Incoming => {
                     let progress = self.get_file_progress(transfer_id, &file_id);
@@ -67,6 +72,7 @@ impl<'a> StorageDispatch<'a> {
                         &file_id,
                         by_peer,
                         progress,
+                        reason.as_ref(),
                     )?
                 }
                 TransferType::Outgoing => {
@@ -76,6 +82,7 @@ impl<'a> StorageDispatch<'a> {
                         &file_id,
                         by_peer,
                         progress,
+                        reason.as_ref(),
                     )?
                 }
             },
@@ -113,6 +120,10 @@ impl<'a> StorageDispatch<'a> {
                 .storage
                 .insert_transfer_failed_state(transfer_info.id, error_code)?,
 
+            Event::TransferExpired { transfer_id } => {
+                self.storage.insert_transfer_expired_state(transfer_id)?
+            }
+
             Event::FileFailed {
                 transfer_type,
                 transfer_id,
@@ -152,16 +163,19 @@ impl<'a> StorageDispatch<'a> {
                 transfer_id,
                 file_id,
                 by_peer,
+                reason,
             } => match transfer_type {
                 TransferType::Incoming => self.storage.insert_incoming_path_reject_state(
                     transfer_id,
                     &file_id,
                     by_peer,
+                    reason.as_ref(),
                 )?,
                 TransferType::Outgoing => self.storage.insert_outgoing_path_reject_state(
                     transfer_id,
                     &file_id,
                     by_peer,
+                    reason.as_ref(),
                 )?,
             },
         }
@@ -176,15 +190,33 @@ impl<'a> StorageDispatch<'a> {
     }
 }
 
-impl From<&crate::Event> for Event {
+impl From<&crate::Event> for Option<Event> {
     fn from(event: &crate::Event) -> Self {
-        match event {
-            crate::Event::RequestReceived(transfer) => Event::Pending {
+        let event = match event {
+            // The archive is stored as a single path, its entries are not tracked
+            crate::Event::FileUploadArchiveEntry(..)
+            | crate::Event::FileDownloadArchiveEntry(..) => return None,
+            // Nothing is stored until the request is approved
+            crate::Event::FileRequestReceived { .. } | crate::Event::FileRequestDenied { .. } => {
+                return None
+            }
+            // The paired peers are stored once the pairing completes
+            crate::Event::PairingStarted(_)
+            | crate::Event::PairingRequested(_)
+            | crate::Event::PairingCompleted { .. }
+            | crate::Event::PairingFailed(..) => return None,
+            // The files are stored as rejected one by one
+            crate::Event::TransferRejected(..) => return None,
+            crate::Event::RequestReceived(transfer, _) => Event::Pending {
                 transfer_info: transfer.storage_info(),
             },
             crate::Event::RequestQueued(transfer) => Event::Pending {
                 transfer_info: transfer.storage_info(),
             },
+            crate::Event::RequestAppendReceived(transfer, files)
+            | crate::Event::RequestAppendQueued(transfer, files) => Event::Pending {
+                transfer_info: transfer.files_storage_info(files),
+            },
             crate::Event::FileDownloadStarted(transfer, file, base_dir) => {
                 Event::FileDownloadStarted {
                     transfer_id: transfer.id(),
@@ -196,18 +228,24 @@ impl From<&crate::Event> for Event {
                 transfer_id: transfer.id(),
                 file_id: file.to_string(),
             },
-            crate::Event::FileDownloadCancelled(transfer, file, by_peer) => Event::FileCanceled {
-                transfer_type: TransferType::Incoming,
-                transfer_id: transfer.id(),
-                file_id: file.to_string(),
-                by_peer: *by_peer,
-            },
-            crate::Event::FileUploadCancelled(transfer, file, by_peer) => Event::FileCanceled {
-                transfer_type: TransferType::Outgoing,
-                transfer_id: transfer.id(),
-                file_id: file.to_string(),
-                by_peer: *by_peer,
-            },
+            crate::Event::FileDownloadCancelled(transfer, file, by_peer, reason) => {
+                Event::FileCanceled {
+                    transfer_type: TransferType::Incoming,
+                    transfer_id: transfer.id(),
+                    file_id: file.to_string(),
+                    by_peer: *by_peer,
+                    reason: reason.clone().map(From::from),
+                }
+            }
+            crate::Event::FileUploadCancelled(transfer, file, by_peer, reason) => {
+                Event::FileCanceled {
+                    transfer_type: TransferType::Outgoing,
+                    transfer_id: transfer.id(),
+                    file_id: file.to_string(),
+                    by_peer: *by_peer,
+                    reason: reason.clone().map(From::from),
+                }
+            }
             crate::Event::FileDownloadSuccess(transfer, file) => Event::FileDownloadComplete {
                 transfer_id: transfer.id(),
                 file_id: file.id.to_string(),
@@ -253,6 +291,9 @@ impl From<&crate::Event> for Event {
                     error_code: error.into(),
                 }
             }
+            crate::Event::TransferExpired(transfer) => Event::TransferExpired {
+                transfer_id: transfer.id(),
+            },
             crate::Event::FileDownloadProgress(transfer, file, progress) => Event::FileProgress {
                 transfer_id: transfer.id(),
                 file_id: file.to_string(),
@@ -267,22 +308,28 @@ impl From<&crate::Event> for Event {
                 transfer_id,
                 file_id,
                 by_peer,
+                reason,
             } => Event::FileReject {
                 transfer_type: TransferType::Incoming,
                 transfer_id: *transfer_id,
                 file_id: file_id.to_string(),
                 by_peer: *by_peer,
+                reason: reason.clone().map(From::from),
             },
             crate::Event::FileUploadRejected {
                 transfer_id,
                 file_id,
                 by_peer,
+                reason,
             } => Event::FileReject {
                 transfer_type: TransferType::Outgoing,
                 transfer_id: *transfer_id,
                 file_id: file_id.to_string(),
                 by_peer: *by_peer,
+                reason: reason.clone().map(From::from),
             },
-        }
+        };
+
+        Some(event)
     }
 }
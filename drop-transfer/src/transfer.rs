@@ -1,4 +1,8 @@
-use std::{collections::HashMap, net::IpAddr};
+use std::{
+    collections::{HashMap, HashSet},
+    net::IpAddr,
+    sync::{Arc, Mutex},
+};
 
 use drop_analytics::TransferInfo;
 use drop_config::DropConfig;
@@ -12,6 +16,11 @@ use crate::{
 
 type Result<T> = std::result::Result<T, Error>;
 
+/// The rest of the files of an outgoing transfer, still being found when the
+/// transfer starts. The files are offered to the peer as they arrive and an
+/// error fails the transfer
+pub type ManifestFeed = tokio::sync::mpsc::Receiver<Result<Vec<File>>>;
+
 #[derive(Clone, Debug)]
 pub struct Transfer {
     peer: IpAddr,
@@ -19,14 +28,27 @@ pub struct Transfer {
 
     // all the files
     files: HashMap<FileId, File>,
+
+    // set for the transfers sending the same files to multiple peers
+    group: Option<Uuid>,
+    checksums: Arc<ChecksumCache>,
+    // Source address of the outgoing connection, overrides the configured one
+    source: Option<IpAddr>,
+    // Identity derived from the public key of the peer, if known
+    identity: Option<String>,
 }
 
+// Checksums of the outgoing files keyed by the file and the limit. Shared
+// between the transfers of a group so each file is read only once
+#[derive(Debug, Default)]
+struct ChecksumCache(Mutex<HashMap<(FileId, u64), [u8; 32]>>);
+
 impl Transfer {
     pub fn new(peer: IpAddr, files: Vec<File>, config: &DropConfig) -> Result<Self> {
         Self::new_with_uuid(peer, files, Uuid::new_v4(), config)
     }
 
-    pub(crate) fn new_with_uuid(
+    pub fn new_with_uuid(
         peer: IpAddr,
         files: Vec<File>,
         uuid: Uuid,
@@ -41,7 +63,90 @@ impl Transfer {
             .map(|file| (file.file_id.clone(), file))
             .collect();
 
-        Ok(Self { peer, uuid, files })
+        Ok(Self {
+            peer,
+            uuid,
+            files,
+            group: None,
+            checksums: Default::default(),
+            source: None,
+            identity: None,
+        })
+    }
+
+    /// Create one transfer of the same files for each of the peers. The
+    /// transfers share the group ID and the checksum cache
+    pub fn new_group(peers: &[IpAddr], files: Vec<File>, config: &DropConfig) -> Result<Vec<Self>> {
+        if peers.is_empty() {
+            return Err(Error::BadTransfer);
+        }
+
+        let group = Uuid::new_v4();
+        let checksums = Arc::new(ChecksumCache::default());
+
+        peers
+            .iter()
+            .map(|&peer| {
+                let mut xfer = Self::new(peer, files.clone(), config)?;
+                xfer.group = Some(group);
+                xfer.checksums = checksums.clone();
+                Ok(xfer)
+            })
+            .collect()
+    }
+
+    /// Extend the transfer with more files. Returns the IDs of the appended
+    /// files
+    pub(crate) fn append_files(
+        &mut self,
+        files: Vec<File>,
+        config: &DropConfig,
+    ) -> Result<Vec<FileId>> {
+        if self.files.len() + files.len() > config.transfer_file_limit {
+            return Err(Error::TransferLimitsExceeded);
+        }
+
+        let mut ids = HashSet::new();
+        if !files
+            .iter()
+            .all(|file| !self.files.contains_key(&file.file_id) && ids.insert(&file.file_id))
+        {
+            return Err(Error::BadTransferState(
+                "File is already part of the transfer".into(),
+            ));
+        }
+
+        let ids = files.iter().map(|file| file.file_id.clone()).collect();
+        self.files
+            .extend(files.into_iter().map(|file| (file.file_id.clone(), file)));
+
+        Ok(ids)
+    }
+
+    pub(crate) fn remove_files(&mut self, ids: &[FileId]) {
+        for id in ids {
+            self.files.remove(id);
+        }
+    }
+
+    /// Checksum of the first `limit` bytes of the outgoing file. The results
+    /// are cached and shared with the other transfers of the group
+    pub(crate) fn file_checksum(&self, file_id: &FileId, limit: u64) -> Result<[u8; 32]> {
+        let file = self.files.get(file_id).ok_or(Error::BadFileId)?;
+
+        let key = (file_id.clone(), limit);
+        if let Some(csum) = self.checksums.0.lock().expect("Poisoned lock").get(&key) {
+            return Ok(*csum);
+        }
+
+        let csum = file.checksum(limit)?;
+        self.checksums
+            .0
+            .lock()
+            .expect("Poisoned lock")
+            .insert(key, csum);
+
+        Ok(csum)
     }
 
     pub(crate) fn file_by_subpath(&self, file_subpath: &FileSubPath) -> Option<&File> {
@@ -88,29 +193,43 @@ impl Transfer {
     }
 
     pub fn storage_info(&self) -> StorageInfo {
+        self.storage_info_of(self.files.values().collect())
+    }
+
+    /// Same as `storage_info()` but lists only the given files
+    pub fn files_storage_info(&self, ids: &[FileId]) -> StorageInfo {
+        self.storage_info_of(ids.iter().filter_map(|id| self.files.get(id)).collect())
+    }
+
+    fn storage_info_of(&self, files: Vec<&File>) -> StorageInfo {
         // TODO(msz): this insane check wouldn't be needed if we had two different
         // `Transfer` types
-        let is_incoming = match self.files.values().next() {
-            Some(files) => matches!(files.kind, FileKind::FileToRecv { .. }),
+        let is_incoming = match files.first() {
+            Some(files) => matches!(
+                files.kind,
+                FileKind::FileToRecv { .. }
+                    | FileKind::DirToRecv
+                    | FileKind::SymlinkToRecv { .. }
+                    | FileKind::ArchiveToRecv { .. }
+            ),
             None => true, // TODO(msz): Arbitrarily chosen, there is no way to differentiate here
         };
 
         let files = if is_incoming {
-            let files = self
-                .files
-                .values()
+            let files = files
+                .into_iter()
                 .map(|f| drop_storage::types::TransferIncomingPath {
                     file_id: f.file_id.to_string(),
                     relative_path: f.subpath.to_string(),
                     size: f.size() as _,
+                    kind: path_kind(f),
                 })
                 .collect();
 
             drop_storage::types::TransferFiles::Incoming(files)
         } else {
-            let files = self
-                .files
-                .values()
+            let files = files
+                .into_iter()
                 .filter_map(|f| {
                     let base_path = match &f.kind {
                         FileKind::FileToSend { source, .. } => match source {
@@ -123,6 +242,14 @@ impl Transfer {
                                                        * working dir. The FDs are only used on
                                                        * Android, */
                         },
+                        FileKind::DirToSend { path } | FileKind::SymlinkToSend { path, .. } => {
+                            path.ancestors().nth(f.subpath.iter().count())?.to_str()?
+                        }
+                        FileKind::ArchiveToSend { archive, .. } => archive
+                            .root()
+                            .ancestors()
+                            .nth(f.subpath.iter().count())?
+                            .to_str()?,
                         _ => return None,
                     };
 
@@ -131,6 +258,7 @@ impl Transfer {
                         relative_path: f.subpath.to_string(),
                         base_path: base_path.to_string(),
                         size: f.size() as _,
+                        kind: path_kind(f),
                     })
                 })
                 .collect();
@@ -141,6 +269,8 @@ impl Transfer {
         StorageInfo {
             id: self.id(),
             peer: self.peer().to_string(),
+            group_id: self.group,
+            identity: self.identity.clone(),
             files,
         }
     }
@@ -152,4 +282,90 @@ impl Transfer {
     pub fn peer(&self) -> IpAddr {
         self.peer
     }
+
+    pub fn group(&self) -> Option<Uuid> {
+        self.group
+    }
+
+    pub fn source_addr(&self) -> Option<IpAddr> {
+        self.source
+    }
+
+    /// Connect to the peer from the given address instead of the configured one
+    pub fn set_source_addr(&mut self, addr: IpAddr) {
+        self.source = Some(addr);
+    }
+
+    pub fn identity(&self) -> Option<&str> {
+        self.identity.as_deref()
+    }
+
+    pub fn set_identity(&mut self, identity: Option<String>) {
+        self.identity = identity;
+    }
+}
+
+fn path_kind(file: &File) -> drop_storage::types::PathKind {
+    if file.is_dir() {
+        drop_storage::types::PathKind::Dir
+    } else if file.symlink_target().is_some() {
+        drop_storage::types::PathKind::Symlink
+    } else if file.is_archive() {
+        drop_storage::types::PathKind::Archive
+    } else {
+        drop_storage::types::PathKind::File
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    fn group_shares_checksums() {
+        let config = DropConfig::default();
+
+        let mut tmp = tempfile::NamedTempFile::new().expect("Failed to create tmp file");
+        tmp.write_all(b"abc").unwrap();
+
+        let files = File::from_path(tmp.path(), &config).unwrap();
+        let file_id = files[0].id().clone();
+
+        let peers = ["172.17.0.2".parse().unwrap(), "172.17.0.3".parse().unwrap()];
+        let xfers = Transfer::new_group(&peers, files, &config).unwrap();
+
+        assert_eq!(xfers.len(), 2);
+        assert_ne!(xfers[0].id(), xfers[1].id());
+        assert!(xfers[0].group().is_some());
+        assert_eq!(xfers[0].group(), xfers[1].group());
+
+        let csum = xfers[0].file_checksum(&file_id, 3).unwrap();
+
+        // The second transfer must reuse the cached value instead of reading the
+        // file again
+        tmp.as_file().set_len(0).unwrap();
+        assert_eq!(xfers[1].file_checksum(&file_id, 3).unwrap(), csum);
+
+        assert!(Transfer::new_group(&[], vec![], &config).is_err());
+    }
+
+    #[test]
+    fn legacy_requests_refuse_directories() {
+        let config = DropConfig::default();
+
+        let tmp = tempfile::tempdir().expect("Failed to create tmp dir");
+        let root = tmp.path().join("root");
+        std::fs::create_dir_all(root.join("empty")).unwrap();
+        std::fs::write(root.join("file.txt"), b"abc").unwrap();
+
+        let files = File::from_path(&root, &config).unwrap();
+        let xfer = Transfer::new("172.17.0.2".parse().unwrap(), files, &config).unwrap();
+
+        assert!(crate::protocol::v5::TransferRequest::try_from(&xfer).is_err());
+        assert!(crate::protocol::v1::TransferRequest::try_from(&xfer).is_err());
+        let req = crate::protocol::v6::TransferRequest::from(&xfer);
+        assert_eq!(req.files.len(), 2);
+    }
 }
@@ -1,7 +1,7 @@
 use std::{
     borrow::Borrow,
     fmt, io, iter, ops,
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
 };
 
 use serde::{Deserialize, Serialize};
@@ -146,8 +146,67 @@ pub fn make_path_absolute(path: impl AsRef<Path>) -> io::Result<PathBuf> {
     Ok(abs)
 }
 
+/// Ensure the symlink target resolves inside of the `root` directory. The
+/// target is resolved against the filesystem, relative to the link location.
+/// Paths crossing an already existing symlink are rejected, as are `..`
+/// components not leaving a real directory, because the OS would follow the
+/// links instead of resolving such paths lexically
+pub(crate) fn check_link_target(link: &Path, target: &Path, root: &Path) -> crate::Result<()> {
+    let escapes = || {
+        crate::Error::BadPath("Symlink target points outside of the destination directory".into())
+    };
+
+    let location = link
+        .parent()
+        .and_then(|parent| parent.strip_prefix(root).ok())
+        .ok_or_else(escapes)?;
+
+    let mut resolved = root.to_path_buf();
+    let mut components = location.components().chain(target.components()).peekable();
+
+    while let Some(component) = components.next() {
+        match component {
+            Component::Normal(name) => {
+                resolved.push(name);
+
+                let is_link = matches!(
+                    resolved.symlink_metadata(),
+                    Ok(meta) if meta.file_type().is_symlink()
+                );
+
+                if is_link && components.peek().is_some() {
+                    return Err(crate::Error::BadPath(
+                        "Symlink target must not cross other symlinks".into(),
+                    ));
+                }
+            }
+            Component::CurDir => (),
+            Component::ParentDir => {
+                let is_dir = matches!(
+                    resolved.symlink_metadata(),
+                    Ok(meta) if meta.file_type().is_dir()
+                );
+
+                if resolved == root || !is_dir {
+                    return Err(escapes());
+                }
+
+                resolved.pop();
+            }
+            _ => {
+                return Err(crate::Error::BadPath(
+                    "Symlink target must be a relative path".into(),
+                ))
+            }
+        }
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
+    use std::fs;
 
     use super::*;
 
@@ -186,4 +245,29 @@ mod tests {
         assert_eq!(iter.next(), Some(PathBuf::from("file(2).ext")));
         assert_eq!(iter.next(), Some(PathBuf::from("file(3).ext")));
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn chained_symlinks_cannot_escape_root() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().join("root");
+        fs::create_dir_all(root.join("a")).unwrap();
+
+        let check =
+            |link: &str, target: &str| check_link_target(&root.join(link), target.as_ref(), &root);
+
+        assert!(check("a/x", "..").is_ok());
+        assert!(check("a/x", "../..").is_err());
+        assert!(check("a/x", "/etc").is_err());
+        std::os::unix::fs::symlink("..", root.join("a/x")).unwrap();
+
+        // Lexically `<root>`, but the OS follows `a/x` first
+        assert!(check("y", "a/x/../..").is_err());
+        assert!(check("y", "a/x/..").is_err());
+        // `..` of a directory that does not exist yet could become a link later
+        assert!(check("y", "a/z/..").is_err());
+
+        assert!(check("y", "a/x").is_ok());
+        assert!(check("y", "a/../a").is_ok());
+    }
 }
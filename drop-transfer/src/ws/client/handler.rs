@@ -15,6 +15,11 @@ pub trait HandlerInit {
 
     fn upgrade(self, msg_tx: Sender<Message>, xfer: crate::Transfer) -> Self::Loop;
     fn pinger(&mut self) -> Self::Pinger;
+
+    // Whether more files can be offered once the transfer is started
+    fn supports_append(&self) -> bool {
+        false
+    }
 }
 
 #[async_trait::async_trait]
@@ -2,6 +2,7 @@ mod handler;
 mod v2;
 mod v4;
 mod v5;
+mod v6;
 
 use std::{
     io,
@@ -11,11 +12,16 @@ use std::{
 };
 
 use anyhow::Context;
+use drop_auth::{
+    pake::{self, Pake},
+    PublicKey,
+};
+use drop_config::DropConfig;
 use futures::{SinkExt, StreamExt};
 use hyper::{http::HeaderValue, StatusCode};
 use slog::{debug, error, info, warn, Logger};
 use tokio::{
-    net::TcpStream,
+    net::{TcpSocket, TcpStream},
     sync::mpsc::{self, UnboundedReceiver},
     task::JoinHandle,
 };
@@ -23,25 +29,54 @@ use tokio_tungstenite::{
     tungstenite::{self, client::IntoClientRequest, protocol::Role, Message},
     WebSocketStream,
 };
+use tokio_util::sync::CancellationToken;
 
 use self::handler::{HandlerInit, HandlerLoop, Uploader};
 use super::events::FileEventTx;
 use crate::{
-    auth,
     error::ResultExt,
-    file::FileId,
+    file::{archive::Encoder, Compression, FileId},
+    file_request::{FileRequest, FileRequestDenied, FileRequestResponse},
     manager::{TransferConnection, TransferGuard},
+    pairing::{self, PairingMsg},
     protocol,
     service::State,
-    ws::Pinger,
+    share::{PullRequest, PullResponse},
+    transfer::ManifestFeed,
+    ws::{self, Pinger},
     Event,
 };
 
-pub type WebSocket = WebSocketStream<TcpStream>;
+pub type WebSocket = super::socket::ClientSocket;
 
 pub enum ClientReq {
-    Cancel { file: FileId },
-    Reject { file: FileId },
+    Cancel {
+        file: FileId,
+        reason: Option<crate::Reason>,
+    },
+    Reject {
+        file: FileId,
+        reason: Option<crate::Reason>,
+    },
+    // Rejects the given files and closes the connection
+    RejectTransfer {
+        files: Vec<FileId>,
+        reason: Option<crate::Reason>,
+    },
+    Append {
+        xfer: crate::Transfer,
+        files: Vec<FileId>,
+    },
+}
+
+/// How the connection of the transfer was established
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Origin {
+    Dialed,
+    // Dialed again after the network change
+    Redialed,
+    // Opened by the peer pulling the share, cannot be dialed again
+    Pulled,
 }
 
 struct RunContext<'a> {
@@ -49,57 +84,149 @@ struct RunContext<'a> {
     state: Arc<State>,
     socket: WebSocket,
     xfer: crate::Transfer,
+    version: protocol::Version,
+    origin: Origin,
+    // The rest of the files, still being found
+    feed: Option<&'a mut ManifestFeed>,
 }
 
-pub(crate) async fn run(state: Arc<State>, xfer: crate::Transfer, logger: Logger) {
-    let (socket, ver) = match establish_ws_conn(&state, xfer.peer(), &logger).await {
-        Ok(res) => res,
-        Err(err) => {
-            error!(logger, "Could not connect to peer {}: {}", xfer.id(), err);
+pub(crate) async fn run(
+    state: Arc<State>,
+    mut xfer: crate::Transfer,
+    mut feed: Option<ManifestFeed>,
+    logger: Logger,
+) {
+    let _guard = TransferGuard::new(state.clone(), xfer.id());
+    let mut origin = Origin::Dialed;
 
-            state
-                .event_tx
-                .send(Event::TransferFailed(xfer, err, false))
-                .await
-                .expect("Failed to send TransferFailed event");
+    loop {
+        state
+            .transfer_manager
+            .lock()
+            .await
+            .insert_connecting(xfer.clone());
 
-            return;
+        let (socket, ver, peer_key) =
+            match establish_ws_conn(&state, xfer.peer(), xfer.source_addr(), &logger).await {
+                Ok(res) => res,
+                Err(err) => {
+                    error!(logger, "Could not connect to peer {}: {}", xfer.id(), err);
+
+                    state
+                        .event_tx
+                        .send(Event::TransferFailed(xfer, err, false))
+                        .await
+                        .expect("Failed to send TransferFailed event");
+
+                    return;
+                }
+            };
+
+        info!(logger, "Client connected, using version: {ver}");
+        set_identity(&state, &mut xfer, peer_key, &logger);
+
+        match run_over(
+            state.clone(),
+            xfer,
+            socket,
+            ver,
+            origin,
+            feed.as_mut(),
+            &logger,
+        )
+        .await
+        {
+            Some(current) => {
+                xfer = current;
+                origin = Origin::Redialed;
+            }
+            None => return,
         }
-    };
+    }
+}
 
-    info!(logger, "Client connected, using version: {ver}");
+/// Send the pulled share over the connection the peer opened to our server
+pub(crate) async fn run_pulled(
+    state: Arc<State>,
+    xfer: crate::Transfer,
+    socket: warp::ws::WebSocket,
+    logger: Logger,
+) {
+    let _guard = TransferGuard::new(state.clone(), xfer.id());
+
+    // Pulling was introduced together with the latest protocol version
+    let ver = protocol::Version::V6;
+    let socket = WebSocket::Accepted(socket);
+    run_over(state, xfer, socket, ver, Origin::Pulled, None, &logger).await;
+}
 
+/// Returns the transfer to be dialed again when the network changes
+async fn run_over(
+    state: Arc<State>,
+    xfer: crate::Transfer,
+    socket: WebSocket,
+    ver: protocol::Version,
+    origin: Origin,
+    feed: Option<&mut ManifestFeed>,
+    logger: &Logger,
+) -> Option<crate::Transfer> {
     let ctx = RunContext {
-        logger: &logger,
+        logger,
         state: state.clone(),
         socket,
         xfer,
+        version: ver,
+        origin,
+        feed,
     };
 
     match ver {
-        protocol::Version::V1 => {
-            ctx.run(v2::HandlerInit::<false>::new(&state, &logger))
-                .await
+        protocol::Version::V1 => ctx.run(v2::HandlerInit::<false>::new(&state, logger)).await,
+        protocol::Version::V2 => ctx.run(v2::HandlerInit::<true>::new(&state, logger)).await,
+        protocol::Version::V4 => ctx.run(v4::HandlerInit::new(state, logger)).await,
+        protocol::Version::V5 => ctx.run(v5::HandlerInit::new(state, logger)).await,
+        protocol::Version::V6 => ctx.run(v6::HandlerInit::new(state, logger)).await,
+    }
+}
+
+/// Record the identity of the peer proved in the handshake. The peer is not
+/// known before the connection is made
+fn set_identity(
+    state: &State,
+    xfer: &mut crate::Transfer,
+    peer_key: Option<PublicKey>,
+    logger: &Logger,
+) {
+    let identity = peer_key.as_ref().map(drop_auth::peer_identity);
+
+    if let Some(identity) = &identity {
+        if let Err(err) =
+            state
+                .storage
+                .update_transfer_identity(xfer.id(), identity, &xfer.peer().to_string())
+        {
+            error!(logger, "Failed to store the peer identity: {err}");
         }
-        protocol::Version::V2 => ctx.run(v2::HandlerInit::<true>::new(&state, &logger)).await,
-        protocol::Version::V4 => ctx.run(v4::HandlerInit::new(state, &logger)).await,
-        protocol::Version::V5 => ctx.run(v5::HandlerInit::new(state, &logger)).await,
     }
+
+    xfer.set_identity(identity);
 }
 
 async fn establish_ws_conn(
     state: &State,
     ip: IpAddr,
+    source: Option<IpAddr>,
     logger: &Logger,
-) -> crate::Result<(WebSocket, protocol::Version)> {
+) -> crate::Result<(WebSocket, protocol::Version, Option<PublicKey>)> {
     let mut socket = tokio::time::timeout(
-        state.config.req_connection_timeout,
-        tcp_connect(state, ip, logger),
+        state.config().req_connection_timeout,
+        tcp_connect(state, ip, source, logger),
     )
     .await
     .map_err(|err| io::Error::new(io::ErrorKind::TimedOut, err))?;
 
     let mut versions_to_try = [
+        protocol::Version::V6,
         protocol::Version::V5,
         protocol::Version::V4,
         protocol::Version::V2,
@@ -107,7 +234,7 @@ async fn establish_ws_conn(
     ]
     .into_iter();
 
-    let ver = loop {
+    let (ver, peer_key) = loop {
         let ver = versions_to_try.next().ok_or_else(|| {
             crate::Error::Io(io::Error::new(
                 io::ErrorKind::NotFound,
@@ -115,8 +242,9 @@ async fn establish_ws_conn(
             ))
         })?;
 
-        match make_request(&mut socket, ip, ver, state.auth.as_ref(), logger).await {
-            Ok(_) => break ver,
+        let path = format!("drop/{ver}");
+        match make_request(&mut socket, ip, &path, state, logger).await {
+            Ok(peer_key) => break (ver, peer_key),
             Err(tungstenite::Error::Http(resp)) if resp.status().is_client_error() => {
                 if resp.status() == StatusCode::UNAUTHORIZED {
                     return Err(crate::Error::AuthenticationFailed);
@@ -132,26 +260,28 @@ async fn establish_ws_conn(
     };
 
     let client = WebSocketStream::from_raw_socket(socket, Role::Client, None).await;
-    Ok((client, ver))
+    Ok((WebSocket::Dialed(client), ver, peer_key))
 }
 
+/// Returns the key of the peer the authorization ticket was made for, `None`
+/// when the peer did not ask for it
 async fn make_request(
     socket: &mut TcpStream,
     ip: IpAddr,
-    version: protocol::Version,
-    auth: &auth::Context,
+    path: &str,
+    state: &State,
     logger: &slog::Logger,
-) -> Result<(), tungstenite::Error> {
+) -> Result<Option<PublicKey>, tungstenite::Error> {
     let addr = SocketAddr::new(ip, drop_config::PORT);
 
-    let url = format!("ws://{addr}/drop/{version}",);
+    let url = format!("ws://{addr}/{path}");
 
     debug!(logger, "Making HTTP request: {url}");
 
     let err = match tokio_tungstenite::client_async(&url, &mut *socket).await {
         Ok(_) => {
             debug!(logger, "Connected to {url} without authorization");
-            return Ok(());
+            return Ok(None);
         }
         Err(err) => err,
     };
@@ -167,23 +297,35 @@ async fn make_request(
                     .context("Missing 'www-authenticate' header")?
                     .to_str()?;
 
-                auth.create_ticket_header_val(ip, val)
+                // The address only hints which paired key to use, the peer
+                // still has to prove it owns the key
+                let identity = state
+                    .storage
+                    .peer_identity(&ip.to_string())
+                    .unwrap_or_else(|err| {
+                        warn!(logger, "Failed to look up the identity of {ip}: {err}");
+                        None
+                    });
+
+                state
+                    .auth
+                    .create_ticket_header_val(ip, identity.as_deref(), val)
             };
 
             debug!(logger, "Extracting peers ({ip}) public key");
             match extract_www_auth() {
-                Ok(auth_header) => {
+                Ok((auth_header, peer_key)) => {
                     debug!(logger, "Building 'authorization' request");
 
                     let mut req = url.into_client_request()?;
                     req.headers_mut().insert(
                         drop_auth::http::Authorization::KEY,
-                        HeaderValue::from_str(&auth_header.to_string())?,
+                        HeaderValue::from_str(&auth_header)?,
                     );
 
                     debug!(logger, "Re-sending request with the 'authorization' header");
                     tokio_tungstenite::client_async(req, &mut *socket).await?;
-                    return Ok(());
+                    return Ok(Some(peer_key));
                 }
                 Err(err) => warn!(
                     logger,
@@ -196,11 +338,287 @@ async fn make_request(
     Err(err)
 }
 
-async fn tcp_connect(state: &State, ip: IpAddr, logger: &Logger) -> TcpStream {
+/// Pull the share published by the peer. The files are received over the same
+/// connection, so the publisher does not need to reach us. Returns the ID of
+/// the transfer
+pub(crate) async fn pull(
+    state: &Arc<State>,
+    stop: CancellationToken,
+    ip: IpAddr,
+    token: &str,
+    logger: &Logger,
+) -> crate::Result<uuid::Uuid> {
+    let task = async {
+        let (mut client, peer_key) = open(state, ip, "drop/pull", logger).await?;
+
+        let req = PullRequest {
+            token: token.to_owned(),
+        };
+        let req = serde_json::to_string(&req).expect("Failed to serialize pull request");
+        client.send(Message::Text(req)).await?;
+
+        let resp = recv_text(&mut client).await?;
+        match serde_json::from_str(&resp).map_err(|_| crate::Error::UnexpectedData)? {
+            PullResponse::Accepted { transfer } => Ok((client, peer_key, transfer)),
+            PullResponse::Unavailable => {
+                let _ = client.close(None).await;
+                Err(crate::Error::ShareUnavailable)
+            }
+        }
+    };
+
+    let (client, peer_key, transfer) =
+        tokio::time::timeout(state.config().req_connection_timeout, task)
+            .await
+            .map_err(|err| io::Error::new(io::ErrorKind::TimedOut, err))??;
+
+    tokio::spawn(ws::server::run_pulled(
+        state.clone(),
+        stop,
+        client,
+        ip,
+        peer_key,
+        logger.clone(),
+    ));
+
+    Ok(transfer)
+}
+
+/// Ask the peer for the files from its exported directory. Returns the ID of
+/// the request, the files are sent in a transfer with the same ID once the
+/// peer approves it
+pub(crate) async fn request_files(
+    state: &State,
+    ip: IpAddr,
+    req: &FileRequest,
+    logger: &Logger,
+) -> crate::Result<uuid::Uuid> {
+    let req = serde_json::to_string(req).expect("Failed to serialize file request");
+    let resp = exchange(state, ip, "drop/request", Some(req), logger).await?;
+
+    match serde_json::from_str(&resp).map_err(|_| crate::Error::UnexpectedData)? {
+        FileRequestResponse::Queued { request } => Ok(request),
+        FileRequestResponse::Refused { reason } => Err(reason.into()),
+    }
+}
+
+/// Let the peer know the file request it sent us was denied
+pub(crate) async fn deny_file_request(
+    state: &State,
+    ip: IpAddr,
+    request: uuid::Uuid,
+    logger: &Logger,
+) -> crate::Result<()> {
+    let msg = serde_json::to_string(&FileRequestDenied { request })
+        .expect("Failed to serialize file request denial");
+
+    let task = async {
+        let (mut client, _) = open(state, ip, "drop/request/denied", logger).await?;
+        client.send(Message::Text(msg)).await?;
+        let _ = client.close(None).await;
+
+        crate::Result::Ok(())
+    };
+
+    tokio::time::timeout(state.config().req_connection_timeout, task)
+        .await
+        .map_err(|err| io::Error::new(io::ErrorKind::TimedOut, err))?
+}
+
+/// Pair with the peer using the code it generated. Returns the public key of
+/// the peer once both sides confirmed the keys
+pub(crate) async fn pair(
+    state: &State,
+    ip: IpAddr,
+    code: &str,
+    logger: &Logger,
+) -> crate::Result<PublicKey> {
+    let task = async {
+        let socket = tcp_connect(state, ip, None, logger).await;
+
+        let url = format!("ws://{}/drop/pair", SocketAddr::new(ip, drop_config::PORT));
+        let (mut client, _) = tokio_tungstenite::client_async(&url, socket).await?;
+
+        let pake = Pake::new(pake::Role::Client, code);
+        let client_key = state.auth.public_key();
+
+        let start = PairingMsg::Start {
+            message: pairing::encode(&pake.message()),
+            key: drop_auth::peer_identity(&client_key),
+        };
+        send_pairing_msg(&mut client, &start).await?;
+
+        let (message, server_key, confirm) = match recv_pairing_msg(&mut client).await? {
+            PairingMsg::Response {
+                message,
+                key,
+                confirm,
+            } => (
+                pairing::decode(&message)?,
+                pairing::decode_key(&key)?,
+                pairing::decode(&confirm)?,
+            ),
+            PairingMsg::Unavailable => return Err(crate::Error::PairingUnavailable),
+            _ => return Err(crate::Error::UnexpectedData),
+        };
+
+        let shared = pake.finish(&message).ok_or(crate::Error::UnexpectedData)?;
+        if !shared.verify(pake::Role::Server, &client_key, &server_key, &confirm) {
+            let _ = client.close(None).await;
+            return Err(crate::Error::AuthenticationFailed);
+        }
+
+        let confirm = PairingMsg::Confirm {
+            confirm: pairing::encode(&shared.confirmation(
+                pake::Role::Client,
+                &client_key,
+                &server_key,
+            )),
+        };
+        send_pairing_msg(&mut client, &confirm).await?;
+
+        // The peer drops the connection when our tag does not match its code
+        let res = match recv_pairing_msg(&mut client).await {
+            Ok(PairingMsg::Done) => Ok(server_key),
+            _ => Err(crate::Error::AuthenticationFailed),
+        };
+        let _ = client.close(None).await;
+
+        res
+    };
+
+    tokio::time::timeout(state.config().req_connection_timeout, task)
+        .await
+        .map_err(|err| io::Error::new(io::ErrorKind::TimedOut, err))?
+}
+
+async fn recv_pairing_msg(client: &mut WebSocketStream<TcpStream>) -> crate::Result<PairingMsg> {
+    while let Some(msg) = client.next().await {
+        if let Message::Text(msg) = msg? {
+            return serde_json::from_str(&msg).map_err(|_| crate::Error::UnexpectedData);
+        }
+    }
+
+    Err(crate::Error::UnexpectedData)
+}
+
+async fn send_pairing_msg(
+    client: &mut WebSocketStream<TcpStream>,
+    msg: &PairingMsg,
+) -> crate::Result<()> {
+    let msg = serde_json::to_string(msg).expect("Failed to serialize pairing message");
+    client.send(Message::Text(msg)).await?;
+    Ok(())
+}
+
+// Single message exchange with the peer server, used by the requests which
+// are not transfers by themselves
+async fn exchange(
+    state: &State,
+    ip: IpAddr,
+    path: &str,
+    msg: Option<String>,
+    logger: &Logger,
+) -> crate::Result<String> {
+    let task = async {
+        let (mut client, _) = open(state, ip, path, logger).await?;
+
+        if let Some(msg) = msg {
+            client.send(Message::Text(msg)).await?;
+        }
+
+        let resp = recv_text(&mut client).await?;
+        let _ = client.close(None).await;
+
+        Ok(resp)
+    };
+
+    tokio::time::timeout(state.config().req_connection_timeout, task)
+        .await
+        .map_err(|err| io::Error::new(io::ErrorKind::TimedOut, err))?
+}
+
+// Open the authorized connection to the given path of the peer server
+async fn open(
+    state: &State,
+    ip: IpAddr,
+    path: &str,
+    logger: &Logger,
+) -> crate::Result<(WebSocketStream<TcpStream>, Option<PublicKey>)> {
+    let mut socket = tcp_connect(state, ip, None, logger).await;
+
+    let peer_key = match make_request(&mut socket, ip, path, state, logger).await {
+        Ok(peer_key) => peer_key,
+        Err(tungstenite::Error::Http(resp)) if resp.status() == StatusCode::UNAUTHORIZED => {
+            return Err(crate::Error::AuthenticationFailed);
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    let client = WebSocketStream::from_raw_socket(socket, Role::Client, None).await;
+    Ok((client, peer_key))
+}
+
+async fn recv_text(client: &mut WebSocketStream<TcpStream>) -> crate::Result<String> {
+    while let Some(msg) = client.next().await {
+        if let Message::Text(text) = msg? {
+            return Ok(text);
+        }
+    }
+
+    Err(crate::Error::UnexpectedData)
+}
+
+// Opens the socket from the source address of the transfer, or the configured
+// one, and on the configured device
+async fn connect(config: &DropConfig, ip: IpAddr, source: Option<IpAddr>) -> io::Result<TcpStream> {
+    let socket = match ip {
+        IpAddr::V4(_) => TcpSocket::new_v4()?,
+        IpAddr::V6(_) => TcpSocket::new_v6()?,
+    };
+
+    if let Some(source) = source.or(config.source_addr) {
+        socket.bind(SocketAddr::new(source, 0))?;
+    }
+
+    if let Some(device) = &config.bind_device {
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        socket.bind_device(Some(device.as_bytes()))?;
+
+        #[cfg(not(any(target_os = "android", target_os = "linux")))]
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("Binding to the device {device} is not supported on this platform"),
+        ));
+    }
+
+    socket.connect(SocketAddr::new(ip, drop_config::PORT)).await
+}
+
+async fn tcp_connect(
+    state: &State,
+    ip: IpAddr,
+    source: Option<IpAddr>,
+    logger: &Logger,
+) -> TcpStream {
     let mut sleep_time = Duration::from_millis(200);
 
     loop {
-        match TcpStream::connect((ip, drop_config::PORT)).await {
+        // Attempts on the old route are abandoned when the network changes
+        let network_changed = state.network_changed.notified();
+        tokio::pin!(network_changed);
+
+        let config = state.config();
+        let res = tokio::select! {
+            res = connect(&config, ip, source) => res,
+            _ = &mut network_changed => {
+                debug!(logger, "Network changed, reconnecting");
+                sleep_time = Duration::from_millis(200);
+                continue;
+            }
+        };
+
+        match res {
             Ok(sock) => break sock,
             Err(err) => {
                 debug!(
@@ -210,13 +628,19 @@ async fn tcp_connect(state: &State, ip: IpAddr, logger: &Logger) -> TcpStream {
                     sleep_time.as_millis(),
                 );
 
-                tokio::time::sleep(sleep_time).await;
-
-                // Exponential backoff but with upper limit
-                sleep_time = state
-                    .config
-                    .connection_max_retry_interval
-                    .min(sleep_time * 2);
+                tokio::select! {
+                    _ = tokio::time::sleep(sleep_time) => {
+                        // Exponential backoff but with upper limit
+                        sleep_time = state
+                            .config()
+                            .connection_max_retry_interval
+                            .min(sleep_time * 2);
+                    }
+                    _ = network_changed => {
+                        debug!(logger, "Network changed, retrying right away");
+                        sleep_time = Duration::from_millis(200);
+                    }
+                }
             }
         }
     }
@@ -227,28 +651,46 @@ impl RunContext<'_> {
         &mut self,
         handler: &mut impl HandlerInit,
     ) -> crate::Result<UnboundedReceiver<ClientReq>> {
+        // The peers which cannot take appended files get the whole manifest at once
+        if !handler.supports_append() {
+            if let Some(feed) = self.feed.take() {
+                while let Some(files) = feed.recv().await {
+                    let ids = self.xfer.append_files(files?, &self.state.config())?;
+                    store_appended(&self.state, &self.xfer, &ids, self.logger);
+                }
+            }
+        }
+
         handler.start(&mut self.socket, &self.xfer).await?;
 
         let (tx, rx) = mpsc::unbounded_channel();
 
-        self.state
-            .transfer_manager
-            .lock()
-            .await
-            .insert_transfer(self.xfer.clone(), TransferConnection::Client(tx))?;
+        {
+            let mut lock = self.state.transfer_manager.lock().await;
+            lock.insert_transfer(
+                self.xfer.clone(),
+                TransferConnection::Client(tx),
+                self.version,
+            )?;
 
-        self.state
-            .event_tx
-            .send(Event::RequestQueued(self.xfer.clone()))
-            .await
-            .expect("Could not send a RequestQueued event, channel closed");
+            if handler.supports_append() {
+                lock.allow_append(self.xfer.id());
+            }
+        }
+
+        // The app already knows about the transfer that is dialed again
+        if self.origin != Origin::Redialed {
+            self.state
+                .event_tx
+                .send(Event::RequestQueued(self.xfer.clone()))
+                .await
+                .expect("Could not send a RequestQueued event, channel closed");
+        }
 
         Ok(rx)
     }
 
-    async fn run(mut self, mut handler: impl HandlerInit) {
-        let _guard = TransferGuard::new(self.state.clone(), self.xfer.id());
-
+    async fn run(mut self, mut handler: impl HandlerInit) -> Option<crate::Transfer> {
         let mut api_req_rx = match self.start(&mut handler).await {
             Ok(rx) => rx,
             Err(err) => {
@@ -265,14 +707,20 @@ impl RunContext<'_> {
                     .await
                     .expect("Failed to send TransferFailed event");
 
-                return;
+                return None;
             }
         };
 
+        let xfer_id = self.xfer.id();
         let (upload_tx, mut upload_rx) = mpsc::channel(2);
         let mut ping = handler.pinger();
         let mut handler = handler.upgrade(upload_tx, self.xfer);
 
+        // The connection may be stuck on the old route after the network change
+        let network_changed = self.state.network_changed.notified();
+        tokio::pin!(network_changed);
+        let mut redial = false;
+
         let task = async {
             loop {
                 tokio::select! {
@@ -281,7 +729,14 @@ impl RunContext<'_> {
                     // API request
                     req = api_req_rx.recv() => {
                         if let Some(req) = req {
+                            let close = matches!(req, ClientReq::RejectTransfer { .. });
                             handler.on_req(&mut self.socket, req).await.context("Handler on API req")?;
+
+                            if close {
+                                debug!(self.logger, "Transfer rejected, closing client connection");
+                                self.socket.close(None).await.context("Failed to close WS")?;
+                                break;
+                            }
                         } else {
                             debug!(self.logger, "Stopping client connection gracefuly");
                             self.socket.close(None).await.context("Failed to close WS")?;
@@ -300,6 +755,20 @@ impl RunContext<'_> {
                             None => break,
                         }
                     },
+                    // More files found
+                    files = recv_feed(&mut self.feed) => {
+                        match files {
+                            Some(files) => {
+                                let (xfer, ids, _) = self.state.transfer_manager.lock().await
+                                    .append_outgoing_files(xfer_id, files?, &self.state.config())?;
+
+                                handler.on_req(&mut self.socket, ClientReq::Append { xfer: xfer.clone(), files: ids.clone() })
+                                    .await.context("Handler on appended files")?;
+                                store_appended(&self.state, &xfer, &ids, self.logger);
+                            }
+                            None => self.feed = None,
+                        }
+                    },
                     // Message to send down the wire
                     msg = upload_rx.recv() => {
                         let msg = msg.expect("Handler channel should always be open");
@@ -308,6 +777,11 @@ impl RunContext<'_> {
                     _ = ping.tick() => {
                         self.socket.send(Message::Ping(Vec::new())).await.context("Failed to send PING")?;
                     }
+                    _ = &mut network_changed, if self.origin != Origin::Pulled => {
+                        debug!(self.logger, "Network changed, dialing the peer again");
+                        redial = true;
+                        break;
+                    }
                 }
             }
 
@@ -317,6 +791,10 @@ impl RunContext<'_> {
         let result = task.await;
         handler.on_stop().await;
 
+        if redial {
+            return close_for_redial(&self.state, &mut self.socket, xfer_id, self.logger).await;
+        }
+
         if let Err(err) = result {
             handler.finalize_failure(err).await;
         } else {
@@ -335,7 +813,57 @@ impl RunContext<'_> {
                 debug!(self.logger, "WS client disconnected");
             }
         }
+
+        None
+    }
+}
+
+async fn recv_feed(
+    feed: &mut Option<&mut ManifestFeed>,
+) -> Option<crate::Result<Vec<crate::File>>> {
+    match feed {
+        Some(feed) => feed.recv().await,
+        None => futures::future::pending().await,
+    }
+}
+
+fn store_appended(state: &State, xfer: &crate::Transfer, ids: &[FileId], logger: &Logger) {
+    if let Err(err) = state
+        .storage
+        .insert_transfer_paths(&xfer.files_storage_info(ids))
+    {
+        error!(
+            logger,
+            "Failed to insert appended paths into storage: {err}"
+        );
+    }
+}
+
+/// Close the connection on the old route and take back the transfer, together
+/// with the files appended in the meantime, so that it's dialed again
+async fn close_for_redial(
+    state: &State,
+    socket: &mut WebSocket,
+    xfer_id: uuid::Uuid,
+    logger: &Logger,
+) -> Option<crate::Transfer> {
+    // The peer has to let go of the transfer before it is offered again
+    let close = async {
+        socket.close(None).await?;
+        while socket.next().await.transpose()?.is_some() {}
+        anyhow::Ok(())
+    };
+
+    match tokio::time::timeout(state.config().req_connection_timeout, close).await {
+        Ok(Ok(())) => (),
+        Ok(Err(err)) => debug!(logger, "Failed to close the old connection: {err}"),
+        Err(_) => debug!(logger, "Timed out closing the old connection"),
     }
+
+    let mut lock = state.transfer_manager.lock().await;
+    let xfer = lock.transfer(&xfer_id).cloned();
+    let _ = lock.cancel_transfer(xfer_id);
+    xfer
 }
 
 async fn start_upload(
@@ -346,12 +874,16 @@ async fn start_upload(
     xfer: crate::Transfer,
     file_id: FileId,
 ) -> anyhow::Result<JoinHandle<()>> {
-    let xfile = xfer
+    let mut xfile = xfer
         .files()
         .get(&file_id)
         .context("File not found")?
         .clone();
 
+    if let Err(err) = xfile.sniff_mime() {
+        warn!(logger, "Failed to detect the mime type of a file: {}", err);
+    }
+
     events
         .start(Event::FileUploadStarted(xfer.clone(), xfile.id().clone()))
         .await;
@@ -379,10 +911,18 @@ async fn start_upload(
                 }
             };
 
+            let mut encoder = match xfile.compression() {
+                Compression::Deflate => Some(Encoder::new()),
+                Compression::None => None,
+            };
+
             loop {
-                match iofile.read_chunk()? {
-                    Some(chunk) => uploader.chunk(chunk).await?,
-                    None => return Ok(()),
+                match (iofile.read_chunk()?, &mut encoder) {
+                    (Some(chunk), Some(encoder)) => {
+                        uploader.chunk(&encoder.compress(chunk)?).await?
+                    }
+                    (Some(chunk), None) => uploader.chunk(chunk).await?,
+                    (None, _) => return Ok(()),
                 }
             }
         };
@@ -122,6 +122,7 @@ impl<const PING: bool> HandlerLoop<'_, PING> {
                         self.xfer.clone(),
                         file_id.clone(),
                         false,
+                        None,
                     ))
                     .await;
             }
@@ -142,6 +143,7 @@ impl<const PING: bool> HandlerLoop<'_, PING> {
                     transfer_id: self.xfer.id(),
                     file_id,
                     by_peer: false,
+                    reason: None,
                 })
                 .await
                 .expect("Event channel should be open");
@@ -173,6 +175,7 @@ impl<const PING: bool> HandlerLoop<'_, PING> {
                         self.xfer.clone(),
                         file.id().clone(),
                         by_peer,
+                        None,
                     ))
                     .await;
             }
@@ -302,8 +305,33 @@ impl<const PING: bool> HandlerLoop<'_, PING> {
 impl<const PING: bool> handler::HandlerLoop for HandlerLoop<'_, PING> {
     async fn on_req(&mut self, socket: &mut WebSocket, req: ClientReq) -> anyhow::Result<()> {
         match req {
-            ClientReq::Cancel { file } => self.issue_cancel(socket, file).await,
-            ClientReq::Reject { file } => self.issue_reject(socket, file).await,
+            ClientReq::Cancel { file, .. } => self.issue_cancel(socket, file).await,
+            ClientReq::Reject { file, .. } => self.issue_reject(socket, file).await,
+            ClientReq::RejectTransfer { files, reason } => {
+                // The protocol has no transfer level rejection
+                for file in files {
+                    self.issue_reject(socket, file).await?;
+                }
+
+                self.state
+                    .event_tx
+                    .send(crate::Event::TransferRejected(
+                        self.xfer.clone(),
+                        false,
+                        reason,
+                    ))
+                    .await
+                    .expect("Event channel should be open");
+
+                Ok(())
+            }
+            ClientReq::Append { .. } => {
+                warn!(
+                    self.logger,
+                    "Appending files is not supported by the protocol"
+                );
+                Ok(())
+            }
         }
     }
 
@@ -422,7 +450,7 @@ impl<const PING: bool> handler::HandlerLoop for HandlerLoop<'_, PING> {
         if PING {
             Some(
                 self.state
-                    .config
+                    .config()
                     .transfer_idle_lifetime
                     .saturating_sub(self.last_recv.elapsed()),
             )
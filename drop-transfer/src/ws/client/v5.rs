@@ -52,7 +52,7 @@ impl<'a> handler::HandlerInit for HandlerInit<'a> {
     type Loop = HandlerLoop<'a>;
 
     async fn start(&mut self, socket: &mut WebSocket, xfer: &crate::Transfer) -> crate::Result<()> {
-        let req = prot::TransferRequest::from(xfer);
+        let req = prot::TransferRequest::try_from(xfer)?;
         socket.send(Message::from(&req)).await?;
         Ok(())
     }
@@ -72,7 +72,7 @@ impl<'a> handler::HandlerInit for HandlerInit<'a> {
     }
 
     fn pinger(&mut self) -> Self::Pinger {
-        tokio::time::interval(self.state.config.ping_interval())
+        tokio::time::interval(self.state.config().ping_interval())
     }
 }
 
@@ -131,6 +131,7 @@ impl HandlerLoop<'_> {
                         self.xfer.clone(),
                         file.id().clone(),
                         by_peer,
+                        None,
                     ))
                     .await;
             }
@@ -168,6 +169,7 @@ impl HandlerLoop<'_> {
                         self.xfer.clone(),
                         file_id.clone(),
                         by_peer,
+                        None,
                     ))
                     .await;
             }
@@ -193,6 +195,7 @@ impl HandlerLoop<'_> {
                 transfer_id: self.xfer.id(),
                 file_id,
                 by_peer,
+                reason: None,
             })
             .await
             .expect("Event channel should be open");
@@ -241,8 +244,8 @@ impl HandlerLoop<'_> {
                     .ensure_file_not_rejected(self.xfer.id(), &file_id)?;
             }
 
-            let xfile = self.xfer.files().get(&file_id).context("File not found")?;
-            let checksum = tokio::task::block_in_place(|| xfile.checksum(limit))?;
+            let checksum =
+                tokio::task::block_in_place(|| self.xfer.file_checksum(&file_id, limit))?;
 
             anyhow::Ok(prot::ReportChsum {
                 file: file_id.clone(),
@@ -381,8 +384,33 @@ impl HandlerLoop<'_> {
 impl handler::HandlerLoop for HandlerLoop<'_> {
     async fn on_req(&mut self, socket: &mut WebSocket, req: ClientReq) -> anyhow::Result<()> {
         match req {
-            ClientReq::Cancel { file } => self.issue_cancel(socket, file).await,
-            ClientReq::Reject { file } => self.issue_reject(socket, file).await,
+            ClientReq::Cancel { file, .. } => self.issue_cancel(socket, file).await,
+            ClientReq::Reject { file, .. } => self.issue_reject(socket, file).await,
+            ClientReq::RejectTransfer { files, reason } => {
+                // The protocol has no transfer level rejection
+                for file in files {
+                    self.issue_reject(socket, file).await?;
+                }
+
+                self.state
+                    .event_tx
+                    .send(crate::Event::TransferRejected(
+                        self.xfer.clone(),
+                        false,
+                        reason,
+                    ))
+                    .await
+                    .expect("Event channel should be open");
+
+                Ok(())
+            }
+            ClientReq::Append { .. } => {
+                warn!(
+                    self.logger,
+                    "Appending files is not supported by the protocol"
+                );
+                Ok(())
+            }
         }
     }
 
@@ -512,7 +540,7 @@ impl handler::HandlerLoop for HandlerLoop<'_> {
     fn recv_timeout(&mut self) -> Option<Duration> {
         Some(
             self.state
-                .config
+                .config()
                 .transfer_idle_lifetime
                 .saturating_sub(self.last_recv.elapsed()),
         )
@@ -0,0 +1,725 @@
+use std::{
+    collections::{hash_map::Entry, HashMap, HashSet},
+    ops::ControlFlow,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use futures::SinkExt;
+use slog::{debug, error, info, warn};
+use tokio::{sync::mpsc::Sender, task::JoinHandle};
+use tokio_tungstenite::tungstenite::{self, Message};
+
+use super::{handler, ClientReq, WebSocket};
+use crate::{protocol::v6 as prot, service::State, ws, FileId};
+
+/// Maximum number of files sent in a single manifest message
+const MANIFEST_CHUNK_SIZE: usize = 1000;
+
+pub struct HandlerInit<'a> {
+    state: Arc<State>,
+    logger: &'a slog::Logger,
+}
+
+pub struct HandlerLoop<'a> {
+    state: Arc<State>,
+    logger: &'a slog::Logger,
+    upload_tx: Sender<Message>,
+    tasks: HashMap<FileId, FileTask>,
+    done: HashSet<FileId>,
+    last_recv: Instant,
+    xfer: crate::Transfer,
+}
+
+struct FileTask {
+    job: JoinHandle<()>,
+    events: Arc<ws::events::FileEventTx>,
+    // Last progress reported by the server
+    progress: u64,
+}
+
+struct Uploader {
+    sink: Sender<Message>,
+    file_id: FileId,
+    offset: u64,
+}
+
+impl<'a> HandlerInit<'a> {
+    pub(crate) fn new(state: Arc<State>, logger: &'a slog::Logger) -> Self {
+        Self { state, logger }
+    }
+}
+
+#[async_trait::async_trait]
+impl<'a> handler::HandlerInit for HandlerInit<'a> {
+    type Pinger = tokio::time::Interval;
+    type Loop = HandlerLoop<'a>;
+
+    async fn start(&mut self, socket: &mut WebSocket, xfer: &crate::Transfer) -> crate::Result<()> {
+        let mut req = prot::TransferRequest::from(xfer);
+        let rest = req
+            .files
+            .split_off(req.files.len().min(MANIFEST_CHUNK_SIZE));
+        socket.send(Message::from(&req)).await?;
+
+        // The rest of the manifest is handled by the server on arrival
+        for chunk in rest.chunks(MANIFEST_CHUNK_SIZE) {
+            let msg = prot::ClientMsg::ManifestUpdate(prot::ManifestUpdate {
+                files: chunk.to_vec(),
+            });
+            socket.send(Message::from(&msg)).await?;
+        }
+
+        Ok(())
+    }
+
+    fn upgrade(self, upload_tx: Sender<Message>, xfer: crate::Transfer) -> Self::Loop {
+        let Self { state, logger } = self;
+
+        HandlerLoop {
+            state,
+            logger,
+            upload_tx,
+            xfer,
+            tasks: HashMap::new(),
+            done: HashSet::new(),
+            last_recv: Instant::now(),
+        }
+    }
+
+    fn pinger(&mut self) -> Self::Pinger {
+        tokio::time::interval(self.state.config().ping_interval())
+    }
+
+    fn supports_append(&self) -> bool {
+        true
+    }
+}
+
+impl HandlerLoop<'_> {
+    async fn issue_cancel(
+        &mut self,
+        socket: &mut WebSocket,
+        file_id: FileId,
+        reason: Option<crate::Reason>,
+    ) -> anyhow::Result<()> {
+        let msg = prot::ClientMsg::Cancel(prot::Cancel {
+            file: file_id.clone(),
+            reason: reason.clone().map(From::from),
+        });
+        socket.send(Message::from(&msg)).await?;
+
+        self.on_cancel(file_id, false, reason).await;
+
+        Ok(())
+    }
+
+    async fn issue_reject(
+        &mut self,
+        socket: &mut WebSocket,
+        file_id: FileId,
+        reason: Option<crate::Reason>,
+    ) -> anyhow::Result<()> {
+        let msg = prot::ClientMsg::Reject(prot::Reject {
+            file: file_id.clone(),
+            reason: reason.clone().map(From::from),
+        });
+        socket.send(Message::from(&msg)).await?;
+
+        self.on_reject(file_id, false, reason).await;
+
+        Ok(())
+    }
+
+    async fn issue_reject_transfer(
+        &mut self,
+        socket: &mut WebSocket,
+        files: Vec<FileId>,
+        reason: Option<crate::Reason>,
+    ) -> anyhow::Result<()> {
+        let msg = prot::ClientMsg::RejectTransfer(prot::RejectTransfer {
+            reason: reason.clone().map(From::from),
+        });
+        socket.send(Message::from(&msg)).await?;
+
+        self.on_reject_transfer(files, false, reason).await;
+
+        Ok(())
+    }
+
+    async fn issue_append(
+        &mut self,
+        socket: &mut WebSocket,
+        xfer: crate::Transfer,
+        files: Vec<FileId>,
+    ) -> anyhow::Result<()> {
+        self.xfer = xfer;
+
+        for chunk in files.chunks(MANIFEST_CHUNK_SIZE) {
+            let msg = prot::ClientMsg::ManifestUpdate(prot::ManifestUpdate {
+                files: chunk
+                    .iter()
+                    .filter_map(|id| self.xfer.files().get(id))
+                    .map(From::from)
+                    .collect(),
+            });
+            socket.send(Message::from(&msg)).await?;
+        }
+
+        self.state
+            .event_tx
+            .send(crate::Event::RequestAppendQueued(self.xfer.clone(), files))
+            .await
+            .expect("Event channel should be open");
+
+        Ok(())
+    }
+
+    async fn on_cancel(&mut self, file_id: FileId, by_peer: bool, reason: Option<crate::Reason>) {
+        if let Some(task) = self.tasks.remove(&file_id) {
+            if !task.job.is_finished() {
+                task.job.abort();
+
+                let file = self
+                    .xfer
+                    .files()
+                    .get(&file_id)
+                    .expect("File should exists since we have a transfer task running");
+
+                self.state.moose.service_quality_transfer_file(
+                    Err(u32::from(&crate::Error::Canceled) as i32),
+                    drop_analytics::Phase::End,
+                    self.xfer.id().to_string(),
+                    0,
+                    file.info(),
+                );
+
+                task.events
+                    .stop(crate::Event::FileUploadCancelled(
+                        self.xfer.clone(),
+                        file.id().clone(),
+                        by_peer,
+                        reason.clone(),
+                    ))
+                    .await;
+            }
+        }
+    }
+
+    async fn on_reject(&mut self, file_id: FileId, by_peer: bool, reason: Option<crate::Reason>) {
+        if by_peer {
+            match self
+                .state
+                .transfer_manager
+                .lock()
+                .await
+                .reject_file(self.xfer.id(), file_id.clone())
+            {
+                Ok(true) => (),
+                res => {
+                    debug!(
+                        self.logger,
+                        "Failed to run rejection procedure on peers request: {res:?}"
+                    );
+                    return;
+                }
+            }
+        }
+
+        info!(self.logger, "Rejecting file {file_id}, by_peer?: {by_peer}");
+
+        if let Some(task) = self.tasks.remove(&file_id) {
+            if !task.job.is_finished() {
+                task.job.abort();
+
+                task.events
+                    .stop(crate::Event::FileUploadCancelled(
+                        self.xfer.clone(),
+                        file_id.clone(),
+                        by_peer,
+                        reason.clone(),
+                    ))
+                    .await;
+            }
+        }
+
+        let file = self
+            .xfer
+            .files()
+            .get(&file_id)
+            .expect("The file is correct since manager was able to reject the file");
+
+        self.state.moose.service_quality_transfer_file(
+            Err(drop_core::Status::FileRejected as i32),
+            drop_analytics::Phase::End,
+            self.xfer.id().to_string(),
+            0,
+            file.info(),
+        );
+
+        self.state
+            .event_tx
+            .send(crate::Event::FileUploadRejected {
+                transfer_id: self.xfer.id(),
+                file_id,
+                by_peer,
+                reason,
+            })
+            .await
+            .expect("Event channel should be open");
+    }
+
+    async fn on_reject_transfer(
+        &mut self,
+        files: Vec<FileId>,
+        by_peer: bool,
+        reason: Option<crate::Reason>,
+    ) {
+        for file in files {
+            self.on_reject(file, by_peer, reason.clone()).await;
+        }
+
+        self.state
+            .event_tx
+            .send(crate::Event::TransferRejected(
+                self.xfer.clone(),
+                by_peer,
+                reason,
+            ))
+            .await
+            .expect("Event channel should be open");
+    }
+
+    async fn on_progress(&mut self, file_id: FileId, transfered: u64) {
+        if let Some(task) = self.tasks.get_mut(&file_id) {
+            task.events
+                .emit(crate::Event::FileUploadProgress(
+                    self.xfer.clone(),
+                    file_id.clone(),
+                    transfered,
+                ))
+                .await;
+
+            task.report_archive_entries(&self.xfer, &file_id, transfered)
+                .await;
+        }
+    }
+
+    async fn on_done(&mut self, file_id: FileId) {
+        let event = crate::Event::FileUploadSuccess(self.xfer.clone(), file_id.clone());
+
+        if let Some(mut task) = self.tasks.remove(&file_id) {
+            task.report_archive_entries(&self.xfer, &file_id, u64::MAX)
+                .await;
+            task.events.stop(event).await;
+        } else if !self.done.contains(&file_id) {
+            self.state
+                .event_tx
+                .send(event)
+                .await
+                .expect("Failed to emit event");
+        }
+
+        self.done.insert(file_id);
+    }
+
+    async fn on_checksum(
+        &mut self,
+        socket: &mut WebSocket,
+        file_id: FileId,
+        limit: u64,
+    ) -> anyhow::Result<()> {
+        let f = async {
+            {
+                self.state
+                    .transfer_manager
+                    .lock()
+                    .await
+                    .ensure_file_not_rejected(self.xfer.id(), &file_id)?;
+            }
+
+            let checksum =
+                tokio::task::block_in_place(|| self.xfer.file_checksum(&file_id, limit))?;
+
+            anyhow::Ok(prot::ReportChsum {
+                file: file_id.clone(),
+                limit,
+                checksum,
+            })
+        };
+
+        match f.await {
+            Ok(report) => {
+                socket
+                    .send(Message::from(&prot::ClientMsg::ReportChsum(report)))
+                    .await
+                    .context("Failed to send checksum report")?;
+            }
+            Err(err) => {
+                error!(self.logger, "Failed to report checksum: {:?}", err);
+
+                let msg = prot::Error {
+                    file: Some(file_id),
+                    msg: err.to_string(),
+                };
+                socket
+                    .send(Message::from(&prot::ClientMsg::Error(msg)))
+                    .await
+                    .context("Failed to report error")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn on_start(
+        &mut self,
+        socket: &mut WebSocket,
+        file_id: FileId,
+        offset: u64,
+    ) -> anyhow::Result<()> {
+        let start = async {
+            {
+                self.state
+                    .transfer_manager
+                    .lock()
+                    .await
+                    .ensure_file_not_rejected(self.xfer.id(), &file_id)?;
+            }
+
+            match self.tasks.entry(file_id.clone()) {
+                Entry::Occupied(o) => {
+                    let task = o.into_mut();
+
+                    if task.job.is_finished() {
+                        *task = FileTask::start(
+                            self.state.clone(),
+                            self.logger,
+                            self.upload_tx.clone(),
+                            self.xfer.clone(),
+                            file_id.clone(),
+                            offset,
+                        )
+                        .await?;
+                    } else {
+                        anyhow::bail!("Transfer already in progress");
+                    }
+                }
+                Entry::Vacant(v) => {
+                    let task = FileTask::start(
+                        self.state.clone(),
+                        self.logger,
+                        self.upload_tx.clone(),
+                        self.xfer.clone(),
+                        file_id.clone(),
+                        offset,
+                    )
+                    .await?;
+
+                    v.insert(task);
+                }
+            };
+
+            self.done.remove(&file_id);
+            anyhow::Ok(())
+        };
+
+        if let Err(err) = start.await {
+            error!(self.logger, "Failed to start upload: {:?}", err);
+
+            let msg = prot::Error {
+                file: Some(file_id),
+                msg: err.to_string(),
+            };
+            socket
+                .send(Message::from(&prot::ClientMsg::Error(msg)))
+                .await
+                .context("Failed to report error")?;
+        }
+
+        Ok(())
+    }
+
+    async fn on_error(&mut self, file_id: Option<FileId>, msg: String) {
+        error!(
+            self.logger,
+            "Server reported and error: file: {file_id:?}, message: {msg}",
+        );
+
+        if let Some(file_id) = file_id {
+            if let Some(task) = self.tasks.remove(&file_id) {
+                if !task.job.is_finished() {
+                    task.job.abort();
+                }
+
+                let file = self
+                    .xfer
+                    .files()
+                    .get(&file_id)
+                    .expect("File should exists since we have a transfer task running");
+
+                task.events
+                    .stop(crate::Event::FileUploadFailed(
+                        self.xfer.clone(),
+                        file.id().clone(),
+                        crate::Error::BadTransferState(format!(
+                            "Receiver reported an error: {msg}"
+                        )),
+                    ))
+                    .await;
+
+                self.done.insert(file_id);
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl handler::HandlerLoop for HandlerLoop<'_> {
+    async fn on_req(&mut self, socket: &mut WebSocket, req: ClientReq) -> anyhow::Result<()> {
+        match req {
+            ClientReq::Cancel { file, reason } => self.issue_cancel(socket, file, reason).await,
+            ClientReq::Reject { file, reason } => self.issue_reject(socket, file, reason).await,
+            ClientReq::RejectTransfer { files, reason } => {
+                self.issue_reject_transfer(socket, files, reason).await
+            }
+            ClientReq::Append { xfer, files } => self.issue_append(socket, xfer, files).await,
+        }
+    }
+
+    async fn on_close(&mut self, by_peer: bool) {
+        debug!(self.logger, "ClientHandler::on_close(by_peer: {})", by_peer);
+
+        self.xfer
+            .files()
+            .values()
+            .filter(|file| {
+                self.tasks
+                    .get(file.id())
+                    .map_or(false, |task| !task.job.is_finished())
+            })
+            .for_each(|file| {
+                self.state.moose.service_quality_transfer_file(
+                    Err(u32::from(&crate::Error::Canceled) as i32),
+                    drop_analytics::Phase::End,
+                    self.xfer.id().to_string(),
+                    0,
+                    file.info(),
+                )
+            });
+
+        self.on_stop().await;
+
+        self.state
+            .event_tx
+            .send(crate::Event::TransferCanceled(
+                self.xfer.clone(),
+                true,
+                by_peer,
+            ))
+            .await
+            .expect("Could not send a transfer cancelled event, channel closed");
+    }
+
+    async fn on_recv(
+        &mut self,
+        socket: &mut WebSocket,
+        msg: Message,
+    ) -> anyhow::Result<ControlFlow<()>> {
+        self.last_recv = Instant::now();
+
+        match msg {
+            Message::Text(json) => {
+                debug!(self.logger, "Received:\n\t{json}");
+
+                let msg: prot::ServerMsg =
+                    serde_json::from_str(&json).context("Failed to deserialize server message")?;
+
+                match msg {
+                    prot::ServerMsg::Progress(prot::Progress {
+                        file,
+                        bytes_transfered,
+                    }) => self.on_progress(file, bytes_transfered).await,
+                    prot::ServerMsg::Done(prot::Done {
+                        file,
+                        bytes_transfered: _,
+                    }) => self.on_done(file).await,
+                    prot::ServerMsg::Error(prot::Error { file, msg }) => {
+                        self.on_error(file, msg).await
+                    }
+                    prot::ServerMsg::ReqChsum(prot::ReqChsum { file, limit }) => {
+                        self.on_checksum(socket, file, limit).await?
+                    }
+                    prot::ServerMsg::Start(prot::Start { file, offset }) => {
+                        self.on_start(socket, file, offset).await?
+                    }
+                    prot::ServerMsg::Cancel(prot::Cancel { file, reason }) => {
+                        self.on_cancel(file, true, reason.map(From::from)).await
+                    }
+                    prot::ServerMsg::Reject(prot::Reject { file, reason }) => {
+                        self.on_reject(file, true, reason.map(From::from)).await
+                    }
+                    prot::ServerMsg::RejectTransfer(prot::RejectTransfer { reason }) => {
+                        let files = self.xfer.files().keys().cloned().collect();
+                        self.on_reject_transfer(files, true, reason.map(From::from))
+                            .await;
+
+                        return Ok(ControlFlow::Break(()));
+                    }
+                }
+            }
+            Message::Close(_) => {
+                debug!(self.logger, "Got CLOSE frame");
+                self.on_close(true).await;
+                return Ok(ControlFlow::Break(()));
+            }
+            Message::Ping(_) => {
+                debug!(self.logger, "PING");
+            }
+            Message::Pong(_) => {
+                debug!(self.logger, "PONG");
+            }
+            _ => warn!(self.logger, "Client received invalid WS message type"),
+        }
+
+        Ok(ControlFlow::Continue(()))
+    }
+
+    async fn on_stop(&mut self) {
+        debug!(self.logger, "Waiting for background jobs to finish");
+
+        let tasks = self.tasks.drain().map(|(_, task)| {
+            task.job.abort();
+
+            async move {
+                task.events.stop_silent().await;
+            }
+        });
+
+        futures::future::join_all(tasks).await;
+    }
+
+    async fn finalize_failure(self, err: anyhow::Error) {
+        error!(self.logger, "Client failed on WS loop: {err:?}");
+
+        let err = match err.downcast::<crate::Error>() {
+            Ok(err) => err,
+            Err(err) => err.downcast::<tungstenite::Error>().map_or_else(
+                |err| crate::Error::BadTransferState(err.to_string()),
+                Into::into,
+            ),
+        };
+
+        self.state
+            .event_tx
+            .send(crate::Event::TransferFailed(self.xfer.clone(), err, false))
+            .await
+            .expect("Event channel should always be open");
+    }
+
+    fn recv_timeout(&mut self) -> Option<Duration> {
+        Some(
+            self.state
+                .config()
+                .transfer_idle_lifetime
+                .saturating_sub(self.last_recv.elapsed()),
+        )
+    }
+}
+impl Drop for HandlerLoop<'_> {
+    fn drop(&mut self) {
+        debug!(self.logger, "Stopping client handler");
+        self.tasks.values().for_each(|task| task.job.abort());
+    }
+}
+
+#[async_trait::async_trait]
+impl handler::Uploader for Uploader {
+    async fn chunk(&mut self, chunk: &[u8]) -> Result<(), crate::Error> {
+        let msg = prot::Chunk {
+            file: self.file_id.clone(),
+            data: chunk.to_vec(),
+        };
+
+        self.sink
+            .send(Message::from(msg))
+            .await
+            .map_err(|_| crate::Error::Canceled)?;
+
+        Ok(())
+    }
+
+    async fn error(&mut self, msg: String) {
+        let msg = prot::ClientMsg::Error(prot::Error {
+            file: Some(self.file_id.clone()),
+            msg,
+        });
+
+        let _ = self.sink.send(Message::from(&msg)).await;
+    }
+
+    fn offset(&self) -> u64 {
+        self.offset
+    }
+}
+
+impl FileTask {
+    async fn start(
+        state: Arc<State>,
+        logger: &slog::Logger,
+        sink: Sender<Message>,
+        xfer: crate::Transfer,
+        file_id: FileId,
+        offset: u64,
+    ) -> anyhow::Result<Self> {
+        let events = Arc::new(ws::events::FileEventTx::new(&state));
+
+        let uploader = Uploader {
+            sink,
+            file_id: file_id.clone(),
+            offset,
+        };
+
+        let job = super::start_upload(
+            state,
+            logger.clone(),
+            Arc::clone(&events),
+            uploader,
+            xfer,
+            file_id,
+        )
+        .await?;
+
+        Ok(Self {
+            job,
+            events,
+            progress: offset,
+        })
+    }
+
+    // Map the archive stream progress onto the entries inside of it
+    async fn report_archive_entries(
+        &mut self,
+        xfer: &crate::Transfer,
+        file_id: &FileId,
+        transfered: u64,
+    ) {
+        let archive = match xfer.files().get(file_id).and_then(|f| f.archive()) {
+            Some(archive) => archive,
+            None => return,
+        };
+
+        for entry in archive.entries_done(self.progress, transfered) {
+            self.events
+                .emit(crate::Event::FileUploadArchiveEntry(
+                    xfer.clone(),
+                    file_id.clone(),
+                    entry,
+                ))
+                .await;
+        }
+
+        self.progress = transfered;
+    }
+}
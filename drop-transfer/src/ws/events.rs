@@ -1,10 +1,10 @@
-use tokio::sync::{mpsc::Sender, RwLock};
+use tokio::sync::RwLock;
 
-use crate::{service::State, Event};
+use crate::{progress::EventTx, service::State, Event};
 
 struct FileEventTxInner {
     running: bool,
-    tx: Sender<Event>,
+    tx: EventTx,
 }
 
 pub struct FileEventTx {
@@ -1,6 +1,7 @@
 pub mod client;
 mod events;
 pub mod server;
+mod socket;
 mod utils;
 
 #[async_trait::async_trait]
@@ -1,9 +1,9 @@
 use std::{fs, ops::ControlFlow, path::PathBuf, time::Duration};
 
 use tokio::sync::mpsc::Sender;
-use warp::ws::{Message, WebSocket};
+use warp::ws::Message;
 
-use super::ServerReq;
+use super::{ServerReq, WebSocket};
 use crate::{utils::Hidden, ws};
 
 #[async_trait::async_trait]
@@ -2,11 +2,12 @@ mod handler;
 mod v2;
 mod v4;
 mod v5;
+mod v6;
 
 use std::{
     collections::HashMap,
     fs,
-    io::{self, Write},
+    io::{self, Read, Write},
     net::{IpAddr, SocketAddr},
     path::{Path, PathBuf},
     sync::Arc,
@@ -14,7 +15,10 @@ use std::{
 };
 
 use anyhow::Context;
-use drop_auth::Nonce;
+use drop_auth::{
+    pake::{self, Pake},
+    Nonce, PublicKey,
+};
 use futures::{SinkExt, StreamExt};
 use handler::{Downloader, HandlerInit, HandlerLoop, Request};
 use hyper::StatusCode;
@@ -26,22 +30,27 @@ use tokio::{
     },
     task::JoinHandle,
 };
+use tokio_tungstenite::WebSocketStream;
 use tokio_util::sync::CancellationToken;
-use warp::{
-    ws::{Message, WebSocket},
-    Filter,
-};
+use warp::{ws::Message, Filter};
 
 use super::events::FileEventTx;
 use crate::{
     auth,
     error::ResultExt,
     event::DownloadSuccess,
-    file,
+    file::{
+        self,
+        archive::{self, Decoder, Tracker},
+        Compression,
+    },
+    file_request::{FileRequest, FileRequestDenied, FileRequestResponse},
     manager::{TransferConnection, TransferGuard},
+    pairing::{self, PairingMsg},
     protocol,
     quarantine::PathExt,
-    service::State,
+    service::{self, State},
+    share::{PullRequest, PullResponse},
     utils::Hidden,
     ws::Pinger,
     Error, Event, FileId,
@@ -52,10 +61,25 @@ const MAX_FILE_SUFFIX_LEN: usize = 5; // Assume that the suffix will fit into 5
                                       // `<filename>(999).<ext>`
 const REPORT_PROGRESS_THRESHOLD: u64 = 1024 * 64;
 
+pub type WebSocket = super::socket::ServerSocket;
+
 pub enum ServerReq {
-    Download { task: Box<FileXferTask> },
-    Cancel { file: FileId },
-    Reject { file: FileId },
+    Download {
+        task: Box<FileXferTask>,
+    },
+    Cancel {
+        file: FileId,
+        reason: Option<crate::Reason>,
+    },
+    Reject {
+        file: FileId,
+        reason: Option<crate::Reason>,
+    },
+    // Rejects the given files and closes the connection
+    RejectTransfer {
+        files: Vec<FileId>,
+        reason: Option<crate::Reason>,
+    },
 }
 
 pub struct FileXferTask {
@@ -73,6 +97,7 @@ struct TmpFileState {
 pub(crate) fn start(
     addr: IpAddr,
     stop: CancellationToken,
+    shutdown: CancellationToken,
     state: Arc<State>,
     auth: Arc<auth::Context>,
     logger: Logger,
@@ -109,6 +134,147 @@ pub(crate) fn start(
         }
     }
 
+    async fn authorize(
+        nonces: &Mutex<HashMap<SocketAddr, Nonce>>,
+        auth: &auth::Context,
+        peer: SocketAddr,
+        auth_header: Option<String>,
+    ) -> Result<PublicKey, warp::Rejection> {
+        // Uncache the peer nonce first
+        let nonce = nonces.lock().await.remove(&peer);
+
+        let auth_header = auth_header.ok_or_else(|| warp::reject::custom(MissingAuth(peer)))?;
+        let nonce = nonce.ok_or_else(|| warp::reject::custom(Unauthrorized))?;
+
+        auth.authorize(peer.ip(), &auth_header, &nonce)
+            .ok_or_else(|| warp::reject::custom(Unauthrorized))
+    }
+
+    // Pulling the published files. The peer sends the token and gets the ID of
+    // the transfer in response, the files are then sent to it over the same
+    // connection
+    let pull = {
+        let stop = stop.clone();
+        let logger = logger.clone();
+        let nonces = nonce_store.clone();
+        let auth = auth.clone();
+        let state = state.clone();
+
+        warp::path!("drop" / "pull")
+            .and(
+                warp::filters::addr::remote().then(|peer: Option<SocketAddr>| async move {
+                    peer.expect("Transport should use IP addresses")
+                }),
+            )
+            .and(warp::filters::header::optional("authorization"))
+            .and_then(move |peer: SocketAddr, auth_header: Option<String>| {
+                let nonces = nonces.clone();
+                let auth = auth.clone();
+
+                async move {
+                    let key = authorize(&nonces, &auth, peer, auth_header).await?;
+                    Ok::<_, warp::Rejection>((peer, key))
+                }
+            })
+            .untuple_one()
+            .and(warp::ws())
+            .map(move |peer: SocketAddr, key: PublicKey, ws: warp::ws::Ws| {
+                let state = Arc::clone(&state);
+                let stop = stop.clone();
+                let logger = logger.clone();
+
+                ws.on_upgrade(move |socket| on_pull(socket, peer.ip(), key, state, stop, logger))
+            })
+    };
+
+    // Requesting the files from the exported directories. The request is
+    // queued until the user approves it
+    let request = {
+        let logger = logger.clone();
+        let nonces = nonce_store.clone();
+        let auth = auth.clone();
+        let state = state.clone();
+
+        warp::path!("drop" / "request")
+            .and(
+                warp::filters::addr::remote().then(|peer: Option<SocketAddr>| async move {
+                    peer.expect("Transport should use IP addresses")
+                }),
+            )
+            .and(warp::filters::header::optional("authorization"))
+            .and_then(move |peer: SocketAddr, auth_header: Option<String>| {
+                let nonces = nonces.clone();
+                let auth = auth.clone();
+
+                async move {
+                    authorize(&nonces, &auth, peer, auth_header).await?;
+                    Ok::<_, warp::Rejection>(peer)
+                }
+            })
+            .and(warp::ws())
+            .map(move |peer: SocketAddr, ws: warp::ws::Ws| {
+                let state = Arc::clone(&state);
+                let logger = logger.clone();
+
+                ws.on_upgrade(move |socket| on_file_request(socket, peer.ip(), state, logger))
+            })
+    };
+
+    // The peer denied the files we requested from it
+    let denied = {
+        let logger = logger.clone();
+        let nonces = nonce_store.clone();
+        let auth = auth.clone();
+        let state = state.clone();
+
+        warp::path!("drop" / "request" / "denied")
+            .and(
+                warp::filters::addr::remote().then(|peer: Option<SocketAddr>| async move {
+                    peer.expect("Transport should use IP addresses")
+                }),
+            )
+            .and(warp::filters::header::optional("authorization"))
+            .and_then(move |peer: SocketAddr, auth_header: Option<String>| {
+                let nonces = nonces.clone();
+                let auth = auth.clone();
+
+                async move {
+                    authorize(&nonces, &auth, peer, auth_header).await?;
+                    Ok::<_, warp::Rejection>(peer)
+                }
+            })
+            .and(warp::ws())
+            .map(move |peer: SocketAddr, ws: warp::ws::Ws| {
+                let state = Arc::clone(&state);
+                let logger = logger.clone();
+
+                ws.on_upgrade(move |socket| {
+                    on_file_request_denied(socket, peer.ip(), state, logger)
+                })
+            })
+    };
+
+    // Pairing with the peers whose keys are not known yet. There is no
+    // authorization, the peer proves the knowledge of the code instead
+    let pair = {
+        let logger = logger.clone();
+        let state = state.clone();
+
+        warp::path!("drop" / "pair")
+            .and(
+                warp::filters::addr::remote().then(|peer: Option<SocketAddr>| async move {
+                    peer.expect("Transport should use IP addresses")
+                }),
+            )
+            .and(warp::ws())
+            .map(move |peer: SocketAddr, ws: warp::ws::Ws| {
+                let state = Arc::clone(&state);
+                let logger = logger.clone();
+
+                ws.on_upgrade(move |socket| on_pairing(socket, peer.ip(), state, logger))
+            })
+    };
+
     let service = {
         let stop = stop.clone();
         let logger = logger.clone();
@@ -132,32 +298,26 @@ pub(crate) fn start(
                     let auth = auth.clone();
 
                     async move {
-                        // Uncache the peer nonce first
-                        let nonce = nonces.lock().await.remove(&peer);
-
-                        match version {
-                            protocol::Version::V1 | protocol::Version::V2 => (),
-                            _ => {
-                                let auth_header = auth_header
-                                    .ok_or_else(|| warp::reject::custom(MissingAuth(peer)))?;
-
-                                let nonce =
-                                    nonce.ok_or_else(|| warp::reject::custom(Unauthrorized))?;
-
-                                if !auth.authorize(peer.ip(), &auth_header, &nonce) {
-                                    return Err(warp::reject::custom(Unauthrorized));
-                                }
+                        let key = match version {
+                            protocol::Version::V1 | protocol::Version::V2 => {
+                                // Uncache the peer nonce
+                                nonces.lock().await.remove(&peer);
+                                None
                             }
+                            _ => Some(authorize(&nonces, &auth, peer, auth_header).await?),
                         };
 
-                        Ok((version, peer))
+                        Ok::<_, warp::Rejection>((version, peer, key))
                     }
                 },
             )
             .untuple_one()
             .and(warp::ws())
             .map(
-                move |version: protocol::Version, peer: SocketAddr, ws: warp::ws::Ws| {
+                move |version: protocol::Version,
+                      peer: SocketAddr,
+                      peer_key: Option<PublicKey>,
+                      ws: warp::ws::Ws| {
                     let state = Arc::clone(&state);
                     let stop = stop.clone();
                     let logger = logger.clone();
@@ -168,8 +328,11 @@ pub(crate) fn start(
                         let ctx = RunContext {
                             logger: &logger,
                             state: state.clone(),
-                            socket,
+                            socket: WebSocket::Accepted(socket),
                             stop: &stop,
+                            version,
+                            local_addr: addr,
+                            peer_key,
                         };
 
                         match version {
@@ -189,19 +352,29 @@ pub(crate) fn start(
                                 ctx.run(v5::HandlerInit::new(peer.ip(), state, &logger))
                                     .await
                             }
+                            protocol::Version::V6 => {
+                                ctx.run(v6::HandlerInit::new(peer.ip(), state, &logger))
+                                    .await
+                            }
                         }
                     })
                 },
             )
-            .recover(move |err| {
-                let nonces = Arc::clone(&nonce_store);
-                async move { handle_rejection(&nonces, err).await }
-            })
     };
 
-    let future = match warp::serve(service)
+    let routes = pull
+        .or(request)
+        .or(denied)
+        .or(pair)
+        .or(service)
+        .recover(move |err| {
+            let nonces = Arc::clone(&nonce_store);
+            async move { handle_rejection(&nonces, err).await }
+        });
+
+    let future = match warp::serve(routes)
         .try_bind_with_graceful_shutdown((addr, drop_config::PORT), async move {
-            stop.cancelled().await
+            shutdown.cancelled().await
         }) {
         Ok((socket, future)) => {
             debug!(logger, "WS server is bound to: {socket}");
@@ -239,11 +412,323 @@ pub(crate) fn start(
     Ok(task)
 }
 
+async fn on_pull(
+    mut socket: warp::ws::WebSocket,
+    peer: IpAddr,
+    peer_key: PublicKey,
+    state: Arc<State>,
+    stop: CancellationToken,
+    logger: Logger,
+) {
+    let req = match recv_json::<PullRequest>(&mut socket).await {
+        Ok(req) => req,
+        Err(err) => {
+            warn!(logger, "Invalid pull request from {peer}: {err}");
+            return;
+        }
+    };
+
+    let xfer = state
+        .shares
+        .lock()
+        .await
+        .take(&req.token)
+        .and_then(|files| crate::Transfer::new(peer, files, &state.config()));
+
+    let (resp, xfer) = match xfer {
+        Ok(xfer) => {
+            info!(logger, "Peer {peer} pulled the share as {}", xfer.id());
+
+            let resp = PullResponse::Accepted {
+                transfer: xfer.id(),
+            };
+            (resp, Some(xfer))
+        }
+        Err(err) => {
+            warn!(logger, "Rejecting pull from {peer}: {err}");
+            (PullResponse::Unavailable, None)
+        }
+    };
+
+    let msg = serde_json::to_string(&resp).expect("Failed to serialize pull response");
+    if let Err(err) = socket.send(Message::text(msg)).await {
+        error!(logger, "Failed to respond to the pull: {err}");
+        return;
+    }
+
+    match xfer {
+        Some(mut xfer) => {
+            xfer.set_identity(Some(drop_auth::peer_identity(&peer_key)));
+            service::spawn_pulled_request(state, stop, xfer, socket, logger)
+        }
+        None => {
+            let _ = socket.close().await;
+        }
+    }
+}
+
+async fn on_file_request(
+    mut socket: warp::ws::WebSocket,
+    peer: IpAddr,
+    state: Arc<State>,
+    logger: Logger,
+) {
+    let req = loop {
+        match socket.next().await {
+            Some(Ok(msg)) if msg.is_text() => {
+                match serde_json::from_str::<FileRequest>(msg.to_str().unwrap_or_default()) {
+                    Ok(req) => break req,
+                    Err(err) => {
+                        warn!(logger, "Invalid file request from {peer}: {err}");
+                        return;
+                    }
+                }
+            }
+            Some(Ok(_)) => continue,
+            Some(Err(err)) => {
+                warn!(logger, "Failed to receive file request from {peer}: {err}");
+                return;
+            }
+            None => return,
+        }
+    };
+
+    let resp = match state.exports.lock().await.queue(peer, &req) {
+        Ok(request_id) => {
+            info!(logger, "Peer {peer} requested files as {request_id}");
+
+            state
+                .event_tx
+                .send(Event::FileRequestReceived {
+                    request_id,
+                    peer,
+                    export: req.export,
+                    paths: req.paths,
+                })
+                .await
+                .expect("Failed to send FileRequestReceived event");
+
+            FileRequestResponse::Queued {
+                request: request_id,
+            }
+        }
+        Err(err) => {
+            warn!(logger, "Refusing file request from {peer}: {err}");
+            FileRequestResponse::Refused {
+                reason: From::from(&err),
+            }
+        }
+    };
+
+    let msg = serde_json::to_string(&resp).expect("Failed to serialize file request response");
+    if let Err(err) = socket.send(Message::text(msg)).await {
+        error!(logger, "Failed to respond to the file request: {err}");
+        return;
+    }
+    let _ = socket.close().await;
+}
+
+async fn on_file_request_denied(
+    mut socket: warp::ws::WebSocket,
+    peer: IpAddr,
+    state: Arc<State>,
+    logger: Logger,
+) {
+    let denial = loop {
+        match socket.next().await {
+            Some(Ok(msg)) if msg.is_text() => {
+                match serde_json::from_str::<FileRequestDenied>(msg.to_str().unwrap_or_default()) {
+                    Ok(denial) => break denial,
+                    Err(err) => {
+                        warn!(logger, "Invalid file request denial from {peer}: {err}");
+                        return;
+                    }
+                }
+            }
+            Some(Ok(_)) => continue,
+            Some(Err(err)) => {
+                warn!(
+                    logger,
+                    "Failed to receive file request denial from {peer}: {err}"
+                );
+                return;
+            }
+            None => return,
+        }
+    };
+    let _ = socket.close().await;
+
+    if !state.exports.lock().await.denied(peer, denial.request) {
+        warn!(
+            logger,
+            "Peer {peer} denied unknown file request {}", denial.request
+        );
+        return;
+    }
+
+    info!(logger, "Peer {peer} denied file request {}", denial.request);
+
+    state
+        .event_tx
+        .send(Event::FileRequestDenied {
+            request_id: denial.request,
+            peer,
+        })
+        .await
+        .expect("Failed to send FileRequestDenied event");
+}
+
+async fn on_pairing(
+    mut socket: warp::ws::WebSocket,
+    peer: IpAddr,
+    state: Arc<State>,
+    logger: Logger,
+) {
+    let timeout = state.config().req_connection_timeout;
+
+    // The code is consumed only once the peer starts the handshake
+    let (message, key) = match tokio::time::timeout(timeout, recv_json(&mut socket)).await {
+        Ok(Ok(PairingMsg::Start { message, key })) => (message, key),
+        _ => {
+            warn!(logger, "Peer {peer} did not start pairing");
+            let _ = socket.close().await;
+            return;
+        }
+    };
+
+    let code = match state.pairing.lock().await.take() {
+        Ok(code) => code,
+        Err(err) => {
+            warn!(logger, "Rejecting pairing from {peer}: {err}");
+            let _ = send_pairing_msg(&mut socket, &PairingMsg::Unavailable).await;
+            let _ = socket.close().await;
+            return;
+        }
+    };
+
+    info!(logger, "Peer {peer} started pairing");
+
+    state
+        .event_tx
+        .send(Event::PairingRequested(peer))
+        .await
+        .expect("Failed to send PairingRequested event");
+
+    let task = async {
+        let (message, client_key) = (pairing::decode(&message)?, pairing::decode_key(&key)?);
+
+        let pake = Pake::new(pake::Role::Server, &code);
+        let server_message = pake.message();
+        let shared = pake.finish(&message).ok_or(Error::UnexpectedData)?;
+        let server_key = state.auth.public_key();
+
+        let resp = PairingMsg::Response {
+            message: pairing::encode(&server_message),
+            key: drop_auth::peer_identity(&server_key),
+            confirm: pairing::encode(&shared.confirmation(
+                pake::Role::Server,
+                &client_key,
+                &server_key,
+            )),
+        };
+        send_pairing_msg(&mut socket, &resp).await?;
+
+        // The peer drops the connection when our tag does not match its code
+        let confirm = match recv_json(&mut socket).await {
+            Ok(PairingMsg::Confirm { confirm }) => pairing::decode(&confirm)?,
+            _ => return Err(Error::AuthenticationFailed),
+        };
+        if !shared.verify(pake::Role::Client, &client_key, &server_key, &confirm) {
+            return Err(Error::AuthenticationFailed);
+        }
+
+        send_pairing_msg(&mut socket, &PairingMsg::Done).await?;
+        Ok(client_key)
+    };
+
+    let res = match tokio::time::timeout(timeout, task).await {
+        Ok(res) => res,
+        Err(err) => Err(io::Error::new(io::ErrorKind::TimedOut, err).into()),
+    };
+    let _ = socket.close().await;
+
+    match res {
+        Ok(key) => pairing::complete(&state, peer, key, &logger).await,
+        Err(err) => {
+            warn!(logger, "Pairing with {peer} failed: {err}");
+
+            state
+                .event_tx
+                .send(Event::PairingFailed(peer, err))
+                .await
+                .expect("Failed to send PairingFailed event");
+        }
+    }
+}
+
+async fn recv_json<T: serde::de::DeserializeOwned>(
+    socket: &mut warp::ws::WebSocket,
+) -> crate::Result<T> {
+    while let Some(msg) = socket.next().await {
+        let msg = msg?;
+        if msg.is_text() {
+            return serde_json::from_str(msg.to_str().unwrap_or_default())
+                .map_err(|_| Error::UnexpectedData);
+        }
+    }
+
+    Err(Error::UnexpectedData)
+}
+
+async fn send_pairing_msg(socket: &mut warp::ws::WebSocket, msg: &PairingMsg) -> crate::Result<()> {
+    let msg = serde_json::to_string(msg).expect("Failed to serialize pairing message");
+    socket.send(Message::text(msg)).await?;
+    Ok(())
+}
+
+/// Receive the pulled share over the connection we opened to the publisher
+pub(crate) async fn run_pulled(
+    state: Arc<State>,
+    stop: CancellationToken,
+    socket: WebSocketStream<tokio::net::TcpStream>,
+    peer: IpAddr,
+    peer_key: Option<PublicKey>,
+    logger: Logger,
+) {
+    let local_addr = match socket.get_ref().local_addr() {
+        Ok(addr) => addr.ip(),
+        Err(err) => {
+            error!(
+                logger,
+                "Failed to read the local address of the pull: {err}"
+            );
+            return;
+        }
+    };
+
+    let ctx = RunContext {
+        logger: &logger,
+        state: state.clone(),
+        stop: &stop,
+        socket: WebSocket::Dialed(socket),
+        // Pulling was introduced together with the latest protocol version
+        version: protocol::Version::V6,
+        local_addr,
+        peer_key,
+    };
+
+    ctx.run(v6::HandlerInit::new(peer, state, &logger)).await
+}
+
 struct RunContext<'a> {
     logger: &'a slog::Logger,
     state: Arc<State>,
     stop: &'a CancellationToken,
     socket: WebSocket,
+    version: protocol::Version,
+    local_addr: IpAddr,
+    // Key the peer proved to own when connecting
+    peer_key: Option<PublicKey>,
 }
 
 impl RunContext<'_> {
@@ -268,7 +753,7 @@ impl RunContext<'_> {
             },
         };
 
-        let xfer = match xfer.parse() {
+        let mut xfer = match xfer.parse() {
             Ok(xfer) => {
                 debug!(self.logger, "RunContext::run() called with {:?}", xfer);
                 xfer
@@ -285,6 +770,9 @@ impl RunContext<'_> {
             }
         };
 
+        // Only the authenticated peers can be identified by their key
+        xfer.set_identity(self.peer_key.as_ref().map(drop_auth::peer_identity));
+
         let stop_job = async {
             // Stop the download job
             info!(self.logger, "Aborting transfer download");
@@ -296,7 +784,15 @@ impl RunContext<'_> {
                 .expect("Failed to send TransferFailed event");
         };
 
-        let job = handle_client(&self.state, self.logger, self.socket, handler, xfer.clone());
+        let job = handle_client(
+            &self.state,
+            self.logger,
+            self.socket,
+            handler,
+            xfer.clone(),
+            self.version,
+            self.local_addr,
+        );
 
         tokio::select! {
             biased;
@@ -315,17 +811,18 @@ async fn handle_client(
     mut socket: WebSocket,
     mut hander: impl handler::HandlerInit,
     xfer: crate::Transfer,
+    version: protocol::Version,
+    local_addr: IpAddr,
 ) {
     let _guard = TransferGuard::new(state.clone(), xfer.id());
     let (req_send, mut req_rx) = mpsc::unbounded_channel();
 
     {
-        if let Err(err) = state
-            .transfer_manager
-            .lock()
-            .await
-            .insert_transfer(xfer.clone(), TransferConnection::Server(req_send))
-        {
+        if let Err(err) = state.transfer_manager.lock().await.insert_transfer(
+            xfer.clone(),
+            TransferConnection::Server(req_send),
+            version,
+        ) {
             error!(logger, "Failed to insert a new trasfer: {}", err);
 
             let _ = hander
@@ -344,7 +841,7 @@ async fn handle_client(
 
             state
                 .event_tx
-                .send(Event::RequestReceived(xfer.clone()))
+                .send(Event::RequestReceived(xfer.clone(), local_addr))
                 .await
                 .expect("Failed to notify receiving peer!");
         }
@@ -352,6 +849,28 @@ async fn handle_client(
 
     let mut ping = hander.pinger();
 
+    // The transfer expires unless the user acts on it in time. The deadline is
+    // kept when the peer reconnects and dropped once the transfer is answered
+    let xfer_id = xfer.id();
+    let (mut answered, deadline) = {
+        let mut lock = state.transfer_manager.lock().await;
+        let answered = lock.is_answered(xfer_id);
+        let deadline = state
+            .config()
+            .transfer_accept_timeout
+            .filter(|_| !answered)
+            .map(|timeout| lock.accept_deadline(xfer_id, timeout));
+
+        (answered, deadline)
+    };
+    let accept_timeout = async {
+        match deadline {
+            Some(deadline) => tokio::time::sleep_until(deadline).await,
+            None => futures::future::pending().await,
+        }
+    };
+    tokio::pin!(accept_timeout);
+
     let (send_tx, mut send_rx) = mpsc::channel(2);
     let mut handler = if let Some(handler) = hander.upgrade(&mut socket, send_tx, xfer).await {
         handler
@@ -368,7 +887,18 @@ async fn handle_client(
                 // API request
                 req = req_rx.recv() => {
                     if let Some(req) = req {
+                        if !answered {
+                            answered = true;
+                            state.transfer_manager.lock().await.mark_answered(xfer_id);
+                        }
+
+                        let close = matches!(req, ServerReq::RejectTransfer { .. });
                         handler.on_req(&mut socket, req).await?;
+
+                        if close {
+                            debug!(logger, "Transfer rejected, closing server connection");
+                            break;
+                        }
                     } else {
                         debug!(logger, "Stoppping server connection gracefuly");
                         handler.on_close(false).await;
@@ -394,6 +924,30 @@ async fn handle_client(
                 _ = ping.tick() => {
                     socket.send(Message::ping(Vec::new())).await.context("Failed to send PING message")?;
                 }
+                _ = &mut accept_timeout, if !answered => {
+                    info!(logger, "Transfer {xfer_id} expired without being accepted");
+
+                    let (xfer, files) = state
+                        .transfer_manager
+                        .lock()
+                        .await
+                        .reject_all_files(xfer_id)?;
+
+                    // Rejecting lets the sender know the files won't be downloaded
+                    for file in files {
+                        handler
+                            .on_req(&mut socket, ServerReq::Reject { file, reason: None })
+                            .await?;
+                    }
+
+                    state
+                        .event_tx
+                        .send(Event::TransferExpired(xfer))
+                        .await
+                        .expect("Failed to send TransferExpired event");
+
+                    break;
+                }
             };
         }
         anyhow::Ok(())
@@ -439,6 +993,72 @@ impl FileXferTask {
         })
     }
 
+    /// Recreate the symlink received in the transfer. The link is created only
+    /// when the target resolves inside of the top level directory of the
+    /// transfer
+    fn create_symlink(&self, target: &Path) -> crate::Result<()> {
+        let root = self
+            .absolute_path
+            .strip_prefix(&self.base_dir.0)
+            .ok()
+            .and_then(|subpath| subpath.components().next())
+            .map(|first| self.base_dir.join(first))
+            .ok_or_else(|| crate::Error::BadPath("Missing destination root".into()))?;
+
+        crate::utils::check_link_target(&self.absolute_path, target, &root)?;
+
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(target, &self.absolute_path.0)?;
+
+        #[cfg(not(unix))]
+        return Err(crate::Error::Io(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Symlinks are not supported on this platform",
+        )));
+
+        #[cfg(unix)]
+        Ok(())
+    }
+
+    // Blocking operation
+    fn extract_archive(
+        &self,
+        tmp_location: &Hidden<PathBuf>,
+        logger: &Logger,
+    ) -> crate::Result<PathBuf> {
+        let result = archive::extract(&tmp_location.0, &self.absolute_path.0);
+
+        if let Err(err) = fs::remove_file(&tmp_location.0) {
+            warn!(
+                logger,
+                "Failed to remove extracted archive {tmp_location:?}: {err}"
+            );
+        }
+
+        result?;
+        Ok(self.absolute_path.0.clone())
+    }
+
+    // Feed the tracker with the part of the archive downloaded so far
+    fn restore_tracker(
+        &self,
+        tracker: &mut Tracker,
+        tmp_location: &Hidden<PathBuf>,
+        offset: u64,
+    ) -> crate::Result<()> {
+        let mut prefix = fs::File::open(&tmp_location.0)?.take(offset);
+        let mut buf = vec![0u8; 64 * 1024];
+
+        loop {
+            let n = prefix.read(&mut buf)?;
+            if n == 0 {
+                return Ok(());
+            }
+
+            tracker.feed(&buf[..n]);
+        }
+    }
+
     fn move_tmp_to_dst(
         &self,
         tmp_location: &Hidden<PathBuf>,
@@ -504,6 +1124,21 @@ impl FileXferTask {
             }
         };
 
+        let mut decoder = match self.file.compression() {
+            Compression::Deflate => Some(Decoder::new()),
+            Compression::None => None,
+        };
+
+        let mut tracker = if self.file.is_archive() {
+            let mut tracker = Tracker::new(self.file.subpath().clone());
+            if offset > 0 {
+                self.restore_tracker(&mut tracker, tmp_location, offset)?;
+            }
+            Some(tracker)
+        } else {
+            None
+        };
+
         let consume_file_chunks = async {
             let mut bytes_received = offset;
             let mut last_progress = bytes_received;
@@ -519,7 +1154,11 @@ impl FileXferTask {
                 .await;
 
             while bytes_received < self.file.size() {
-                let chunk = stream.recv().await.ok_or(crate::Error::Canceled)?;
+                let mut chunk = stream.recv().await.ok_or(crate::Error::Canceled)?;
+
+                if let Some(decoder) = &mut decoder {
+                    chunk = decoder.decompress(&chunk, self.file.size() - bytes_received)?;
+                }
 
                 let chunk_size = chunk.len();
                 if chunk_size as u64 + bytes_received > self.file.size() {
@@ -530,6 +1169,18 @@ impl FileXferTask {
 
                 bytes_received += chunk_size as u64;
 
+                if let Some(tracker) = &mut tracker {
+                    for entry in tracker.feed(&chunk) {
+                        events
+                            .emit(crate::Event::FileDownloadArchiveEntry(
+                                self.xfer.clone(),
+                                self.file.id().clone(),
+                                entry,
+                            ))
+                            .await;
+                    }
+                }
+
                 if last_progress + REPORT_PROGRESS_THRESHOLD <= bytes_received {
                     // send progress to the caller
                     downloader.progress(bytes_received).await?;
@@ -569,15 +1220,28 @@ impl FileXferTask {
             }
         };
 
-        let dst = match self.move_tmp_to_dst(tmp_location, logger) {
-            Ok(dst) => dst,
-            Err(err) => {
-                error!(
-                    logger,
-                    "Could not rename temporary file {:?} after downloading: {err}",
-                    self.absolute_path,
-                );
-                return Err(err);
+        let dst = if self.file.is_archive() {
+            match self.extract_archive(tmp_location, logger) {
+                Ok(dst) => dst,
+                Err(err) => {
+                    error!(
+                        logger,
+                        "Could not extract archive into {:?}: {err}", self.absolute_path,
+                    );
+                    return Err(err);
+                }
+            }
+        } else {
+            match self.move_tmp_to_dst(tmp_location, logger) {
+                Ok(dst) => dst,
+                Err(err) => {
+                    error!(
+                        logger,
+                        "Could not rename temporary file {:?} after downloading: {err}",
+                        self.absolute_path,
+                    );
+                    return Err(err);
+                }
             }
         };
 
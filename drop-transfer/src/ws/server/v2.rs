@@ -17,9 +17,9 @@ use tokio::{
     sync::mpsc::{self, Sender, UnboundedSender},
     task::JoinHandle,
 };
-use warp::ws::{Message, WebSocket};
+use warp::ws::Message;
 
-use super::{handler, ServerReq};
+use super::{handler, ServerReq, WebSocket};
 use crate::{
     file::FileSubPath,
     protocol::v2,
@@ -83,7 +83,7 @@ impl<'a, const PING: bool> handler::HandlerInit for HandlerInit<'a, PING> {
 
         let req = serde_json::from_str(msg).context("Failed to deserialize transfer request")?;
 
-        Ok((req, self.peer, self.state.config.clone()))
+        Ok((req, self.peer, self.state.config()))
     }
 
     async fn on_error(&mut self, ws: &mut WebSocket, err: anyhow::Error) -> anyhow::Result<()> {
@@ -210,6 +210,7 @@ impl<const PING: bool> HandlerLoop<'_, PING> {
                         self.xfer.clone(),
                         file_id.clone(),
                         false,
+                        None,
                     ))
                     .await;
             }
@@ -230,6 +231,7 @@ impl<const PING: bool> HandlerLoop<'_, PING> {
                     transfer_id: self.xfer.id(),
                     file_id,
                     by_peer: false,
+                    reason: None,
                 })
                 .await
                 .expect("Event channel should be open");
@@ -287,6 +289,7 @@ impl<const PING: bool> HandlerLoop<'_, PING> {
                         self.xfer.clone(),
                         file.id().clone(),
                         by_peer,
+                        None,
                     ))
                     .await;
             }
@@ -334,8 +337,24 @@ impl<const PING: bool> handler::HandlerLoop for HandlerLoop<'_, PING> {
     async fn on_req(&mut self, ws: &mut WebSocket, req: ServerReq) -> anyhow::Result<()> {
         match req {
             ServerReq::Download { task } => self.issue_download(ws, *task)?,
-            ServerReq::Cancel { file } => self.issue_cancel(ws, file).await?,
-            ServerReq::Reject { file } => self.issue_reject(ws, file).await?,
+            ServerReq::Cancel { file, .. } => self.issue_cancel(ws, file).await?,
+            ServerReq::Reject { file, .. } => self.issue_reject(ws, file).await?,
+            ServerReq::RejectTransfer { files, reason } => {
+                // The protocol has no transfer level rejection
+                for file in files {
+                    self.issue_reject(ws, file).await?;
+                }
+
+                self.state
+                    .event_tx
+                    .send(crate::Event::TransferRejected(
+                        self.xfer.clone(),
+                        false,
+                        reason,
+                    ))
+                    .await
+                    .expect("Event channel should be open");
+            }
         }
 
         Ok(())
@@ -447,7 +466,7 @@ impl<const PING: bool> handler::HandlerLoop for HandlerLoop<'_, PING> {
         if PING {
             Some(
                 self.state
-                    .config
+                    .config()
                     .transfer_idle_lifetime
                     .saturating_sub(self.last_recv.elapsed()),
             )
@@ -18,9 +18,9 @@ use tokio::{
     sync::mpsc::{self, Sender, UnboundedSender},
     task::JoinHandle,
 };
-use warp::ws::{Message, WebSocket};
+use warp::ws::Message;
 
-use super::{handler, ServerReq};
+use super::{handler, ServerReq, WebSocket};
 use crate::{
     file::{self, FileKind},
     protocol::v5 as prot,
@@ -90,7 +90,7 @@ impl<'a> handler::HandlerInit for HandlerInit<'a> {
 
         let req = serde_json::from_str(msg).context("Failed to deserialize transfer request")?;
 
-        Ok((req, self.peer, self.state.config.clone()))
+        Ok((req, self.peer, self.state.config()))
     }
 
     async fn on_error(&mut self, ws: &mut WebSocket, err: anyhow::Error) -> anyhow::Result<()> {
@@ -192,7 +192,7 @@ impl<'a> handler::HandlerInit for HandlerInit<'a> {
     }
 
     fn pinger(&mut self) -> Self::Pinger {
-        tokio::time::interval(self.state.config.ping_interval())
+        tokio::time::interval(self.state.config().ping_interval())
     }
 }
 
@@ -315,6 +315,7 @@ impl HandlerLoop<'_> {
                         self.xfer.clone(),
                         file_id,
                         by_peer,
+                        None,
                     ))
                     .await;
             }
@@ -358,6 +359,7 @@ impl HandlerLoop<'_> {
                         self.xfer.clone(),
                         file_id.clone(),
                         by_peer,
+                        None,
                     ))
                     .await;
             }
@@ -383,6 +385,7 @@ impl HandlerLoop<'_> {
                 transfer_id: self.xfer.id(),
                 file_id,
                 by_peer,
+                reason: None,
             })
             .await
             .expect("Event channel should be open");
@@ -461,8 +464,24 @@ impl handler::HandlerLoop for HandlerLoop<'_> {
     async fn on_req(&mut self, ws: &mut WebSocket, req: ServerReq) -> anyhow::Result<()> {
         match req {
             ServerReq::Download { task } => self.issue_download(ws, *task)?,
-            ServerReq::Cancel { file } => self.issue_cancel(ws, file).await?,
-            ServerReq::Reject { file } => self.issue_reject(ws, file).await?,
+            ServerReq::Cancel { file, .. } => self.issue_cancel(ws, file).await?,
+            ServerReq::Reject { file, .. } => self.issue_reject(ws, file).await?,
+            ServerReq::RejectTransfer { files, reason } => {
+                // The protocol has no transfer level rejection
+                for file in files {
+                    self.issue_reject(ws, file).await?;
+                }
+
+                self.state
+                    .event_tx
+                    .send(crate::Event::TransferRejected(
+                        self.xfer.clone(),
+                        false,
+                        reason,
+                    ))
+                    .await
+                    .expect("Event channel should be open");
+            }
         }
 
         Ok(())
@@ -577,7 +596,7 @@ impl handler::HandlerLoop for HandlerLoop<'_> {
     fn recv_timeout(&mut self) -> Option<Duration> {
         Some(
             self.state
-                .config
+                .config()
                 .transfer_idle_lifetime
                 .saturating_sub(self.last_recv.elapsed()),
         )
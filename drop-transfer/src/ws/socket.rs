@@ -0,0 +1,218 @@
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{Sink, Stream};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{
+    tungstenite::{
+        self,
+        protocol::{frame::coding::CloseCode, CloseFrame},
+    },
+    WebSocketStream,
+};
+
+/// Connection of the sending side of the transfer. Usually it is dialed by us,
+/// but when the peer pulls a share the transfer is sent over the connection it
+/// opened to our server
+pub enum ClientSocket {
+    Dialed(WebSocketStream<TcpStream>),
+    Accepted(warp::ws::WebSocket),
+}
+
+/// Connection of the receiving side of the transfer. Usually it is accepted by
+/// our server, but when pulling a share the transfer is received over the
+/// connection we opened to the publisher
+pub enum ServerSocket {
+    Accepted(warp::ws::WebSocket),
+    Dialed(WebSocketStream<TcpStream>),
+}
+
+impl ClientSocket {
+    pub async fn close(&mut self, msg: Option<CloseFrame<'_>>) -> tungstenite::Result<()> {
+        match self {
+            Self::Dialed(socket) => socket.close(msg).await,
+            Self::Accepted(socket) => {
+                let msg = match msg {
+                    Some(frame) => {
+                        warp::ws::Message::close_with(frame.code, frame.reason.into_owned())
+                    }
+                    None => warp::ws::Message::close(),
+                };
+
+                futures::SinkExt::send(socket, msg).await.map_err(from_warp)
+            }
+        }
+    }
+}
+
+impl Stream for ClientSocket {
+    type Item = tungstenite::Result<tungstenite::Message>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.get_mut() {
+            Self::Dialed(socket) => Pin::new(socket).poll_next(cx),
+            Self::Accepted(socket) => Pin::new(socket)
+                .poll_next(cx)
+                .map(|msg| msg.map(|msg| msg.map(to_tungstenite).map_err(from_warp))),
+        }
+    }
+}
+
+impl Sink<tungstenite::Message> for ClientSocket {
+    type Error = tungstenite::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        match self.get_mut() {
+            Self::Dialed(socket) => Pin::new(socket).poll_ready(cx),
+            Self::Accepted(socket) => Pin::new(socket).poll_ready(cx).map_err(from_warp),
+        }
+    }
+
+    fn start_send(self: Pin<&mut Self>, msg: tungstenite::Message) -> Result<(), Self::Error> {
+        match self.get_mut() {
+            Self::Dialed(socket) => Pin::new(socket).start_send(msg),
+            Self::Accepted(socket) => match to_warp(msg) {
+                Some(msg) => Pin::new(socket).start_send(msg).map_err(from_warp),
+                None => Ok(()),
+            },
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        match self.get_mut() {
+            Self::Dialed(socket) => Pin::new(socket).poll_flush(cx),
+            Self::Accepted(socket) => Pin::new(socket).poll_flush(cx).map_err(from_warp),
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        match self.get_mut() {
+            Self::Dialed(socket) => Pin::new(socket).poll_close(cx),
+            Self::Accepted(socket) => Pin::new(socket).poll_close(cx).map_err(from_warp),
+        }
+    }
+}
+
+impl Stream for ServerSocket {
+    type Item = crate::Result<warp::ws::Message>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.get_mut() {
+            Self::Accepted(socket) => Pin::new(socket)
+                .poll_next(cx)
+                .map(|msg| msg.map(|msg| msg.map_err(Into::into))),
+            Self::Dialed(socket) => loop {
+                match Pin::new(&mut *socket).poll_next(cx) {
+                    Poll::Ready(Some(Ok(msg))) => match to_warp(msg) {
+                        Some(msg) => return Poll::Ready(Some(Ok(msg))),
+                        None => continue,
+                    },
+                    Poll::Ready(Some(Err(err))) => return Poll::Ready(Some(Err(err.into()))),
+                    Poll::Ready(None) => return Poll::Ready(None),
+                    Poll::Pending => return Poll::Pending,
+                }
+            },
+        }
+    }
+}
+
+impl Sink<warp::ws::Message> for ServerSocket {
+    type Error = crate::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        match self.get_mut() {
+            Self::Accepted(socket) => Pin::new(socket).poll_ready(cx).map_err(Into::into),
+            Self::Dialed(socket) => Pin::new(socket).poll_ready(cx).map_err(Into::into),
+        }
+    }
+
+    fn start_send(self: Pin<&mut Self>, msg: warp::ws::Message) -> Result<(), Self::Error> {
+        match self.get_mut() {
+            Self::Accepted(socket) => Pin::new(socket).start_send(msg).map_err(Into::into),
+            Self::Dialed(socket) => Pin::new(socket)
+                .start_send(to_tungstenite(msg))
+                .map_err(Into::into),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        match self.get_mut() {
+            Self::Accepted(socket) => Pin::new(socket).poll_flush(cx).map_err(Into::into),
+            Self::Dialed(socket) => Pin::new(socket).poll_flush(cx).map_err(Into::into),
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        match self.get_mut() {
+            Self::Accepted(socket) => Pin::new(socket).poll_close(cx).map_err(Into::into),
+            Self::Dialed(socket) => Pin::new(socket).poll_close(cx).map_err(Into::into),
+        }
+    }
+}
+
+fn to_tungstenite(msg: warp::ws::Message) -> tungstenite::Message {
+    if msg.is_text() {
+        tungstenite::Message::Text(msg.to_str().unwrap_or_default().to_owned())
+    } else if msg.is_ping() {
+        tungstenite::Message::Ping(msg.into_bytes())
+    } else if msg.is_pong() {
+        tungstenite::Message::Pong(msg.into_bytes())
+    } else if msg.is_close() {
+        tungstenite::Message::Close(msg.close_frame().map(|(code, reason)| CloseFrame {
+            code: CloseCode::from(code),
+            reason: reason.to_owned().into(),
+        }))
+    } else {
+        tungstenite::Message::Binary(msg.into_bytes())
+    }
+}
+
+// Raw frames are never yielded when reading messages
+fn to_warp(msg: tungstenite::Message) -> Option<warp::ws::Message> {
+    let msg = match msg {
+        tungstenite::Message::Text(text) => warp::ws::Message::text(text),
+        tungstenite::Message::Binary(data) => warp::ws::Message::binary(data),
+        tungstenite::Message::Ping(data) => warp::ws::Message::ping(data),
+        tungstenite::Message::Pong(data) => warp::ws::Message::pong(data),
+        tungstenite::Message::Close(Some(frame)) => {
+            warp::ws::Message::close_with(frame.code, frame.reason.into_owned())
+        }
+        tungstenite::Message::Close(None) => warp::ws::Message::close(),
+        tungstenite::Message::Frame(_) => return None,
+    };
+
+    Some(msg)
+}
+
+// Warp does not expose the underlying error
+fn from_warp(err: warp::Error) -> tungstenite::Error {
+    tungstenite::Error::Io(io::Error::other(err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn message_conversion() {
+        let messages = [
+            tungstenite::Message::Text("text".into()),
+            tungstenite::Message::Binary(vec![1, 2, 3]),
+            tungstenite::Message::Ping(vec![4]),
+            tungstenite::Message::Pong(vec![5]),
+            tungstenite::Message::Close(None),
+            tungstenite::Message::Close(Some(CloseFrame {
+                code: CloseCode::Normal,
+                reason: "bye".into(),
+            })),
+        ];
+
+        for msg in messages {
+            let converted = to_warp(msg.clone()).expect("Message should be converted");
+            assert_eq!(to_tungstenite(converted), msg);
+        }
+    }
+}
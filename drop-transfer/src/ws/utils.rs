@@ -10,7 +10,7 @@ pub struct Pinger<const PING: bool = true> {
 
 impl<const PING: bool> Pinger<PING> {
     pub(crate) fn new(state: &State) -> Self {
-        let interval = tokio::time::interval(state.config.ping_interval());
+        let interval = tokio::time::interval(state.config().ping_interval());
         Self { interval }
     }
 }
@@ -72,6 +72,26 @@ typedef enum norddrop_result {
    * Database error
    */
   NORDDROP_RES_DB_ERROR = 11,
+  /**
+   * The transfer is unknown or no longer active
+   */
+  NORDDROP_RES_BAD_TRANSFER = 12,
+  /**
+   * The file is not part of the transfer
+   */
+  NORDDROP_RES_BAD_FILE = 13,
+  /**
+   * The destination path is invalid
+   */
+  NORDDROP_RES_BAD_PATH = 14,
+  /**
+   * The file is already rejected
+   */
+  NORDDROP_RES_FILE_REJECTED = 15,
+  /**
+   * The setting cannot be changed while the instance is running
+   */
+  NORDDROP_RES_CONFIG_NOT_UPDATABLE = 16,
 } norddrop_result;
 
 typedef struct norddrop norddrop;
@@ -111,185 +131,1266 @@ typedef struct norddrop_logger_cb {
 } norddrop_logger_cb;
 
 /**
- * Writes the peer's public key into the buffer of length 32.
- * The peer is identifed by IP address passed as string,
- * Returns 0 on success and 1 on failure or missing key
+ * Writes the peer's public key into the buffer of length 32.
+ * The peer is identifed by IP address passed as string,
+ * Returns 0 on success and 1 on failure or missing key
+ */
+typedef int (*norddrop_pubkey_fn)(void*, const char*, char*);
+
+/**
+ * Fetch peer public key callback
+ */
+typedef struct norddrop_pubkey_cb {
+  /**
+   * Context to pass to callback.
+   * User must ensure safe access of this var from multitheaded context.
+   */
+  void *ctx;
+  /**
+   * Function to be called
+   */
+  norddrop_pubkey_fn cb;
+} norddrop_pubkey_cb;
+
+/**
+ * Kind of the typed event
+ */
+typedef enum norddrop_event_kind {
+  /**
+   * Incoming transfer, `peer` and `files` are set
+   */
+  NORDDROP_EVENT_REQUEST_RECEIVED = 0,
+  /**
+   * Outgoing transfer queued, `peer` and `files` are set
+   */
+  NORDDROP_EVENT_REQUEST_QUEUED = 1,
+  /**
+   * File transfer started
+   */
+  NORDDROP_EVENT_FILE_STARTED = 2,
+  /**
+   * File transfer progress, `bytes` is the number of bytes transferred
+   */
+  NORDDROP_EVENT_FILE_PROGRESS = 3,
+  /**
+   * File uploaded to the peer
+   */
+  NORDDROP_EVENT_FILE_UPLOADED = 4,
+  /**
+   * File downloaded, `final_path` is set
+   */
+  NORDDROP_EVENT_FILE_DOWNLOADED = 5,
+  /**
+   * File failed, `status` is set
+   */
+  NORDDROP_EVENT_FILE_FAILED = 6,
+  /**
+   * File canceled, `by_peer` is set
+   */
+  NORDDROP_EVENT_FILE_CANCELED = 7,
+  /**
+   * File rejected, `by_peer` is set
+   */
+  NORDDROP_EVENT_FILE_REJECTED = 8,
+  /**
+   * Transfer canceled, `by_peer` is set
+   */
+  NORDDROP_EVENT_TRANSFER_CANCELED = 9,
+  /**
+   * Transfer failed, `status` is set
+   */
+  NORDDROP_EVENT_TRANSFER_FAILED = 10,
+} norddrop_event_kind;
+
+/**
+ * Kind of the file in the transfer
+ */
+typedef enum norddrop_file_kind {
+  NORDDROP_FILE_KIND_FILE = 0,
+  NORDDROP_FILE_KIND_DIR = 1,
+  NORDDROP_FILE_KIND_SYMLINK = 2,
+  NORDDROP_FILE_KIND_ARCHIVE = 3,
+} norddrop_file_kind;
+
+/**
+ * File of the transfer
+ */
+typedef struct norddrop_file {
+  const char *id;
+  const char *path;
+  uint64_t size;
+  enum norddrop_file_kind kind;
+} norddrop_file;
+
+/**
+ * Typed event, the fields not relevant for the kind are zeroed
+ */
+typedef struct norddrop_event {
+  enum norddrop_event_kind kind;
+  /**
+   * Transfer ID
+   */
+  const char *transfer;
+  /**
+   * File ID, `NULL` for the transfer events
+   */
+  const char *file;
+  /**
+   * Peer address, `NULL` unless the event is a request
+   */
+  const char *peer;
+  /**
+   * Final path of the downloaded file, `NULL` otherwise
+   */
+  const char *final_path;
+  const struct norddrop_file *files;
+  uintptr_t files_len;
+  uint64_t bytes;
+  /**
+   * Status code of the failure, 0 otherwise
+   */
+  uint32_t status;
+  bool by_peer;
+} norddrop_event;
+
+typedef void (*norddrop_typed_event_fn)(void*, const struct norddrop_event*);
+
+/**
+ * Typed event callback
+ */
+typedef struct norddrop_typed_event_cb {
+  /**
+   * Context to pass to callback.
+   * User must ensure safe access of this var from multitheaded context.
+   */
+  void *ctx;
+  /**
+   * Function to be called
+   */
+  norddrop_typed_event_fn cb;
+} norddrop_typed_event_cb;
+
+/**
+ * Symlink handling inside of the directories
+ */
+typedef enum norddrop_symlink_policy {
+  NORDDROP_SYMLINKS_SKIP = 0,
+  NORDDROP_SYMLINKS_FOLLOW = 1,
+  NORDDROP_SYMLINKS_PRESERVE = 2,
+} norddrop_symlink_policy;
+
+/**
+ * Typed transfer descriptor
+ */
+typedef struct norddrop_transfer_descriptor {
+  /**
+   * Path of the file or directory
+   */
+  const char *path;
+  /**
+   * File descriptor to read the file from, negative when not used
+   */
+  int32_t fd;
+  /**
+   * One of the `norddrop_symlink_policy` values
+   */
+  int symlinks;
+  /**
+   * Send the directory as a single archive
+   */
+  bool archive;
+} norddrop_transfer_descriptor;
+
+/**
+ * State of the file in an active transfer
+ */
+typedef enum norddrop_file_state {
+  NORDDROP_FILE_PENDING = 0,
+  NORDDROP_FILE_IN_PROGRESS = 1,
+  NORDDROP_FILE_COMPLETED = 2,
+  NORDDROP_FILE_FAILED = 3,
+  NORDDROP_FILE_CANCELED = 4,
+  NORDDROP_FILE_REJECTED = 5,
+} norddrop_file_state;
+
+/**
+ * Progress of the file in an active transfer
+ */
+typedef struct norddrop_file_progress {
+  enum norddrop_file_state state;
+  /**
+   * Number of bytes transferred so far
+   */
+  uint64_t bytes;
+  /**
+   * Size of the file
+   */
+  uint64_t size;
+} norddrop_file_progress;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+extern void fortify_source(void);
+
+/**
+ * Initialize a new transfer with the provided peer and descriptors
+ *
+ * # Arguments
+ *
+ * * `dev` - A pointer to the instance.
+ * * `peer` - Peer address.
+ * * `descriptors` - JSON descriptors.
+ *
+ * # Returns
+ *
+ * A String containing the transfer ID.
+ *
+ * # Descriptors format
+ *
+ * Descriptors are provided as an array of JSON objects, with each object
+ * containing a "path" and optionally a file descriptor "fd":
+ *
+ * ```json
+ * [
+ *   {
+ *     "path": "/path/to/file",
+ *   },
+ *   {
+ *     "path": "/path/to/dir",
+ *   }
+ * ]
+ * ```
+ *
+ * # On Android, due to limitations, we must also accept a file descriptor
+ *
+ * ```json
+ * [
+ *   {
+ *    "path": "/path/to/file",
+ *    "fd": 1234
+ *   }
+ * ]
+ * ```
+ *
+ * # Symbolic links inside directories
+ *
+ * By default symlinks found inside directories are skipped. The optional
+ * "symlinks" field selects the behaviour: "skip", "follow" or "preserve".
+ * With "preserve", relative links are sent as links and recreated by the
+ * receiver only when they point inside of the destination directory. The
+ * links that are left out are reported with the `TransferPathsSkipped` event
+ *
+ * ```json
+ * [
+ *   {
+ *     "path": "/path/to/dir",
+ *     "symlinks": "preserve"
+ *   }
+ * ]
+ * ```
+ *
+ * # Filtering directory content
+ *
+ * The entries found inside directories can be filtered with glob patterns
+ * matched against the path relative to the directory, the ones without a
+ * slash match at any depth like in `.gitignore`. "include" limits the
+ * transfer to the matching entries, "exclude" leaves out the matching ones
+ * and does not descend into excluded directories. The directories left
+ * empty by the filtering are not sent.
+ * "skip_hidden" leaves out the entries which names start with a dot and
+ * "ignore_file" names `.gitignore` style files whose rules apply to the
+ * directory containing them
+ *
+ * ```json
+ * [
+ *   {
+ *     "path": "/path/to/project",
+ *     "exclude": ["node_modules", "*.log"],
+ *     "skip_hidden": true,
+ *     "ignore_file": ".gitignore"
+ *   }
+ * ]
+ * ```
+ *
+ * # Sending a directory as an archive
+ *
+ * With "archive" set the directory is sent as a single tar stream and
+ * extracted by the receiver once downloaded, which is much faster for
+ * directories with a lot of small files. The optional "compression" field
+ * selects "none" or "deflate". Progress of the individual entries is
+ * reported with the `TransferArchiveEntry` event
+ *
+ * ```json
+ * [
+ *   {
+ *     "path": "/path/to/dir",
+ *     "archive": true,
+ *     "compression": "deflate"
+ *   }
+ * ]
+ * ```
+ */
+char *norddrop_new_transfer(const struct norddrop *dev, const char *peer, const char *descriptors);
+
+/**
+ * Initialize a new transfer with the provided peer and descriptors, connecting
+ * to the peer from the given source address. Overrides the `source_addr` of
+ * the configuration for this transfer only
+ *
+ * # Arguments
+ *
+ * * `dev` - A pointer to the instance.
+ * * `peer` - Peer address.
+ * * `source_addr` - Local IP address to connect from.
+ * * `descriptors` - JSON descriptors, same as in `norddrop_new_transfer()`.
+ *
+ * # Returns
+ *
+ * The transfer ID, `NULL` on failure
+ *
+ * # Safety
+ * The pointers provided should be valid
+ */
+char *norddrop_new_transfer_with_source(const struct norddrop *dev,
+                                        const char *peer,
+                                        const char *source_addr,
+                                        const char *descriptors);
+
+/**
+ * Initialize a new transfer with the provided peer and descriptors. Unlike
+ * `norddrop_new_transfer()`, the entries that cannot be read are left out
+ * instead of failing the whole transfer
+ *
+ * # Arguments
+ *
+ * * `dev` - A pointer to the instance.
+ * * `peer` - Peer address.
+ * * `descriptors` - JSON descriptors, same as in `norddrop_new_transfer()`.
+ *
+ * # Returns
+ *
+ * A JSON string containing the transfer ID and the paths that were skipped,
+ * together with the reason. NULL when nothing is left to send after
+ * skipping.
+ *
+ * ```json
+ * {
+ *   "transfer": "2c1e0a8c-5dbd-4b89-a4a1-8ad9d34d1b8a",
+ *   "paths": [
+ *     {
+ *       "path": "/path/to/dir/secret.txt",
+ *       "reason": "unreadable",
+ *       "status": 15,
+ *       "os_error_code": 13
+ *     },
+ *     {
+ *       "path": "/path/to/dir/link",
+ *       "reason": "symlink"
+ *     }
+ *   ]
+ * }
+ * ```
+ *
+ * # Safety
+ * The pointers provided should be valid
+ */
+char *norddrop_new_transfer_tolerant(const struct norddrop *dev,
+                                     const char *peer,
+                                     const char *descriptors);
+
+/**
+ * Initialize one transfer of the same files for each of the provided peers.
+ * The files are gathered once and the checksums requested by the receivers
+ * are computed only once for the whole group
+ *
+ * # Arguments
+ *
+ * * `dev` - A pointer to the instance.
+ * * `peers` - JSON array of peer addresses.
+ * * `descriptors` - JSON descriptors, same as in `norddrop_new_transfer()`.
+ *
+ * # Returns
+ *
+ * A JSON string containing the group ID together with the transfer ID created
+ * for each of the peers. `NULL` on failure, in that case no transfer is
+ * created.
+ *
+ * ```json
+ * {
+ *   "group": "8a5c47b1-8f9a-4a4c-9f0e-9d1b7c2c4f3e",
+ *   "transfers": [
+ *     {
+ *       "peer": "172.17.0.2",
+ *       "transfer": "2c1e0a8c-5dbd-4b89-a4a1-8ad9d34d1b8a"
+ *     },
+ *     {
+ *       "peer": "172.17.0.3",
+ *       "transfer": "d5ff2b4a-4c4e-4e3a-8f8e-3e0c3b2f9a71"
+ *     }
+ *   ]
+ * }
+ * ```
+ *
+ * Each transfer runs independently and reports its own events. The
+ * `RequestQueued` events of the group carry the `group` field.
+ *
+ * # Safety
+ * The pointers provided should be valid
+ */
+char *norddrop_new_transfer_multi(const struct norddrop *dev,
+                                  const char *peers,
+                                  const char *descriptors);
+
+/**
+ * Initialize a new transfer with the provided peer and descriptors without
+ * waiting for the directories to be scanned. Meant for huge directory trees
+ *
+ * # Arguments
+ *
+ * * `dev` - A pointer to the instance.
+ * * `peer` - Peer address.
+ * * `descriptors` - JSON descriptors, same as in `norddrop_new_transfer()`.
+ *
+ * # Returns
+ *
+ * A String containing the transfer ID.
+ *
+ * # Scanning
+ *
+ * The descriptors are scanned in the background. While scanning, the
+ * `TransferScanProgress` event is reported periodically with the number of
+ * entries and bytes found so far. `RequestQueued` is emitted once the first
+ * entries are found and the entries found later are offered to the peer as
+ * the scan goes on, each batch reported with `RequestAppendQueued`. The peers
+ * which cannot take appended files get the request when the scan is done.
+ * `TransferFinished` with `TransferFailed` is emitted when the scanning fails
+ * or nothing is found.
+ *
+ * Unreadable entries are skipped and reported with `TransferPathsSkipped` at
+ * the end of the scan.
+ *
+ * ```json
+ * {
+ *   "type": "TransferScanProgress",
+ *   "data": {
+ *     "transfer": "2c1e0a8c-5dbd-4b89-a4a1-8ad9d34d1b8a",
+ *     "files": 3000,
+ *     "bytes": 1048576
+ *   }
+ * }
+ * ```
+ *
+ * The files are not opened while scanning. The mime type is detected when
+ * the upload of the file starts and unreadable files fail only then. The
+ * file descriptors need to remain valid until the transfer is finished.
+ *
+ * # Safety
+ * The pointers provided should be valid
+ */
+char *norddrop_new_transfer_async(const struct norddrop *dev,
+                                  const char *peer,
+                                  const char *descriptors);
+
+/**
+ * Offer more files within an already running outgoing transfer. The peer
+ * receives the `RequestAppendReceived` event listing the new files, while the
+ * sender receives `RequestAppendQueued` once the files are offered. Requires
+ * the peer to run a version supporting the feature
+ *
+ * # Arguments
+ *
+ * * `dev` - A pointer to the instance.
+ * * `xfid` - Transfer ID.
+ * * `descriptors` - JSON descriptors, same as in `norddrop_new_transfer()`.
+ *
+ * # Safety
+ * The pointers provided should be valid
+ */
+enum norddrop_result norddrop_append_files(const struct norddrop *dev,
+                                           const char *xfid,
+                                           const char *descriptors);
+
+/**
+ * Publish the files under a random token, so that the peers can pull them
+ * with `norddrop_pull()` instead of waiting for them to be sent. Only the
+ * authenticated peers are allowed to pull
+ *
+ * # Arguments
+ *
+ * * `dev` - A pointer to the instance.
+ * * `descriptors` - JSON descriptors, same as in `norddrop_new_transfer()`.
+ * * `lifetime_ms` - Time after which the token expires.
+ * * `max_downloads` - Number of times the files can be pulled. Zero or less
+ *   means there is no limit.
+ *
+ * # Returns
+ *
+ * A String containing the token, `NULL` on failure.
+ *
+ * Each pull results in a regular outgoing transfer to the peer, reported with
+ * the `RequestQueued` event.
+ *
+ * # Safety
+ * The pointers provided should be valid
+ */
+char *norddrop_publish(const struct norddrop *dev,
+                       const char *descriptors,
+                       long long lifetime_ms,
+                       int max_downloads);
+
+/**
+ * Remove the published files, the token cannot be pulled anymore
+ *
+ * # Arguments
+ *
+ * * `dev` - A pointer to the instance.
+ * * `token` - Token returned by `norddrop_publish()`.
+ *
+ * # Safety
+ * The pointers provided should be valid
+ */
+enum norddrop_result norddrop_unpublish(const struct norddrop *dev, const char *token);
+
+/**
+ * Ask the peer for the files it published under the token. On success the
+ * files are received over the connection opened to the peer, in a regular
+ * transfer reported with the `RequestReceived` event. The peer does not need
+ * to be able to connect back
+ *
+ * # Arguments
+ *
+ * * `dev` - A pointer to the instance.
+ * * `peer` - Address of the peer which published the files.
+ * * `token` - Token returned by `norddrop_publish()` on the peer side.
+ *
+ * # Returns
+ *
+ * A String containing the ID of the transfer, `NULL` on failure. An unknown,
+ * expired or exhausted token is a failure as well.
+ *
+ * # Safety
+ * The pointers provided should be valid
+ */
+char *norddrop_pull(const struct norddrop *dev, const char *peer, const char *token);
+
+/**
+ * Allow the peers to request files from the directory with
+ * `norddrop_request_files()`. Each request needs to be approved
+ *
+ * # Arguments
+ *
+ * * `dev` - A pointer to the instance.
+ * * `name` - Name under which the directory is exported, replaces the
+ *   previous export with the same name.
+ * * `path` - Path to the directory.
+ *
+ * # Safety
+ * The pointers provided should be valid
+ */
+enum norddrop_result norddrop_export_dir(const struct norddrop *dev,
+                                         const char *name,
+                                         const char *path);
+
+/**
+ * Stop exporting the directory. The requests already received can still be
+ * approved
+ *
+ * # Arguments
+ *
+ * * `dev` - A pointer to the instance.
+ * * `name` - Name of the export.
+ *
+ * # Safety
+ * The pointers provided should be valid
+ */
+enum norddrop_result norddrop_unexport_dir(const struct norddrop *dev, const char *name);
+
+/**
+ * Ask the peer for the files from the directory it exported. The peer
+ * receives the `FileRequestReceived` event and once it approves the request,
+ * the files are sent in a regular transfer with the ID of the request
+ *
+ * # Arguments
+ *
+ * * `dev` - A pointer to the instance.
+ * * `peer` - Peer address.
+ * * `export` - Name of the directory exported by the peer.
+ * * `paths` - JSON array of paths relative to the exported directory.
+ *
+ * # Returns
+ *
+ * A String containing the request ID, `NULL` on failure. The request fails
+ * when the export is unknown or any of the paths is outside of it, or when too
+ * many requests await the approval of the peer. The peer drops the requests
+ * not answered within an hour.
+ *
+ * ```json
+ * {
+ *   "type": "FileRequestReceived",
+ *   "data": {
+ *     "request": "2c1e0a8c-5dbd-4b89-a4a1-8ad9d34d1b8a",
+ *     "peer": "172.17.0.2",
+ *     "export": "docs",
+ *     "paths": ["reports/q3.pdf"]
+ *   }
+ * }
+ * ```
+ *
+ * # Safety
+ * The pointers provided should be valid
+ */
+char *norddrop_request_files(const struct norddrop *dev,
+                             const char *peer,
+                             const char *export,
+                             const char *paths);
+
+/**
+ * Approve the file request received from the peer. The requested files are
+ * sent in a transfer with the ID of the request
+ *
+ * # Arguments
+ *
+ * * `dev` - A pointer to the instance.
+ * * `request` - Request ID from the `FileRequestReceived` event.
+ *
+ * # Safety
+ * The pointers provided should be valid
+ */
+enum norddrop_result norddrop_approve_file_request(const struct norddrop *dev,
+                                                   const char *request);
+
+/**
+ * Deny the file request received from the peer. The peer is notified with
+ * the `FileRequestDenied` event
+ *
+ * ```json
+ * {
+ *   "type": "FileRequestDenied",
+ *   "data": {
+ *     "request": "2c1e0a8c-5dbd-4b89-a4a1-8ad9d34d1b8a",
+ *     "peer": "172.17.0.1"
+ *   }
+ * }
+ * ```
+ *
+ * # Arguments
+ *
+ * * `dev` - A pointer to the instance.
+ * * `request` - Request ID from the `FileRequestReceived` event.
+ *
+ * # Safety
+ * The pointers provided should be valid
+ */
+enum norddrop_result norddrop_deny_file_request(const struct norddrop *dev,
+                                                const char *request);
+
+/**
+ * Destroy the libdrop instance.
+ *
+ * # Arguments
+ *
+ * * `dev` - Pointer to the instance.
+ *
+ * # Safety
+ * This function creates a box with instance pointer and immediately drops it.
+ */
+void norddrop_destroy(struct norddrop *dev);
+
+/**
+ * # Download a file from the peer
+ *
+ * # Arguments
+ *
+ * * `dev` - Pointer to the instance
+ * * `xfid` - Transfer ID
+ * * `fid` - File ID
+ * * `dst` - Destination path
+ *
+ * # Returns
+ *
+ * The destination and the file are validated before returning,
+ * `NORDDROP_RES_BAD_TRANSFER`, `NORDDROP_RES_BAD_FILE`, `NORDDROP_RES_BAD_PATH`
+ * or `NORDDROP_RES_FILE_REJECTED` is returned when the download cannot start.
+ * The download itself continues in the background.
+ */
+enum norddrop_result norddrop_download(const struct norddrop *dev,
+                                       const char *xfid,
+                                       const char *fid,
+                                       const char *dst);
+
+/**
+ * # Cancel a transfer from either side
+ *
+ * # Arguments
+ *
+ * * `dev`: Pointer to the instance
+ * * `xfid`: Transfer ID
+ *
+ * # Returns
+ *
+ * `NORDDROP_RES_BAD_TRANSFER` when the transfer is unknown or already finished.
+ */
+enum norddrop_result norddrop_cancel_transfer(const struct norddrop *dev, const char *xfid);
+
+/**
+ * # Cancel a file from either side
+ *
+ * # Arguments
+ *
+ * * `dev`: Pointer to the instance
+ * * `xfid`: Transfer ID
+ * * `fid`: File ID
+ *
+ * # Returns
+ *
+ * `NORDDROP_RES_BAD_TRANSFER`, `NORDDROP_RES_BAD_FILE` or
+ * `NORDDROP_RES_FILE_REJECTED` when the file cannot be canceled.
+ */
+enum norddrop_result norddrop_cancel_file(const struct norddrop *dev,
+                                          const char *xfid,
+                                          const char *fid);
+
+/**
+ * Cancel multiple files of a transfer at once
+ *
+ * # Arguments
+ *
+ * * `dev` - A pointer to the instance.
+ * * `xfid` - Transfer ID.
+ * * `files` - JSON array of file IDs.
+ *
+ * # Returns
+ *
+ * A JSON array with the result for each of the files, in order. The `status`
+ * field is present only for the files the operation failed for. `NULL` when
+ * the input is invalid or the instance is not started.
+ *
+ * ```json
+ * [
+ *   { "file": "4OTT7EM9SU6zqxD0WqaIw0nwcHaCWEzG7dbkKgKnUPE" },
+ *   { "file": "A5N4CIrbOy3Y3nDwqb4Pr7ntoyzXIf2YUXtDlHLUz-I", "status": 34 }
+ * ]
+ * ```
+ *
+ * # Safety
+ * The pointers provided should be valid
+ */
+char *norddrop_cancel_files(const struct norddrop *dev,
+                            const char *xfid,
+                            const char *files);
+
+/**
+ * Reject multiple files of a transfer at once
+ *
+ * # Arguments
+ *
+ * * `dev` - A pointer to the instance.
+ * * `xfid` - Transfer ID.
+ * * `files` - JSON array of file IDs.
+ *
+ * # Returns
+ *
+ * A JSON array with the result for each of the files, in order. The `status`
+ * field is present only for the files the operation failed for. `NULL` when
+ * the input is invalid or the instance is not started.
+ *
+ * ```json
+ * [
+ *   { "file": "4OTT7EM9SU6zqxD0WqaIw0nwcHaCWEzG7dbkKgKnUPE" },
+ *   { "file": "A5N4CIrbOy3Y3nDwqb4Pr7ntoyzXIf2YUXtDlHLUz-I", "status": 34 }
+ * ]
+ * ```
+ *
+ * # Safety
+ * The pointers provided should be valid
+ */
+char *norddrop_reject_files(const struct norddrop *dev,
+                            const char *xfid,
+                            const char *files);
+
+/**
+ * Download multiple files of a transfer into the same directory at once
+ *
+ * # Arguments
+ *
+ * * `dev` - A pointer to the instance.
+ * * `xfid` - Transfer ID.
+ * * `files` - JSON array of file IDs.
+ * * `dst` - Destination directory.
+ *
+ * # Returns
+ *
+ * A JSON array with the result for each of the files, in order. The `status`
+ * field is present only for the files the operation failed for. `NULL` when
+ * the input is invalid or the instance is not started.
+ *
+ * ```json
+ * [
+ *   { "file": "4OTT7EM9SU6zqxD0WqaIw0nwcHaCWEzG7dbkKgKnUPE" },
+ *   { "file": "A5N4CIrbOy3Y3nDwqb4Pr7ntoyzXIf2YUXtDlHLUz-I", "status": 34 }
+ * ]
+ * ```
+ *
+ * # Safety
+ * The pointers provided should be valid
+ */
+char *norddrop_download_files(const struct norddrop *dev,
+                              const char *xfid,
+                              const char *files,
+                              const char *dst);
+
+/**
+ * Reject a file from either side
+ *
+ * # Arguments
+ *
+ * * `dev`: Pointer to the instance
+ * * `xfid`: Transfer ID
+ * * `fid`: File ID
+ *
+ * # Safety
+ * The pointers provided should be valid
+ */
+enum norddrop_result norddrop_reject_file(const struct norddrop *dev,
+                                          const char *xfid,
+                                          const char *fid);
+
+/**
+ * Cancel a file from either side, letting the peer know why. Peers using
+ * older protocol versions receive the cancel without the reason
+ *
+ * # Arguments
+ *
+ * * `dev`: Pointer to the instance
+ * * `xfid`: Transfer ID
+ * * `fid`: File ID
+ * * `code`: Application defined reason code
+ * * `reason`: Human readable reason, can be NULL
+ *
+ * # Safety
+ * The pointers provided should be valid
+ */
+enum norddrop_result norddrop_cancel_file_with_reason(const struct norddrop *dev,
+                                                      const char *xfid,
+                                                      const char *fid,
+                                                      unsigned int code,
+                                                      const char *reason);
+
+/**
+ * Reject a file from either side, letting the peer know why. Peers using
+ * older protocol versions receive the rejection without the reason
+ *
+ * # Arguments
+ *
+ * * `dev`: Pointer to the instance
+ * * `xfid`: Transfer ID
+ * * `fid`: File ID
+ * * `code`: Application defined reason code
+ * * `reason`: Human readable reason, can be NULL
+ *
+ * # Safety
+ * The pointers provided should be valid
+ */
+enum norddrop_result norddrop_reject_file_with_reason(const struct norddrop *dev,
+                                                      const char *xfid,
+                                                      const char *fid,
+                                                      unsigned int code,
+                                                      const char *reason);
+
+/**
+ * Reject all of the files in a transfer with a single message to the peer and
+ * close the connection. Finishes with the `TransferRejected` event
+ *
+ * # Arguments
+ *
+ * * `dev`: Pointer to the instance
+ * * `xfid`: Transfer ID
+ *
+ * # Safety
+ * The pointers provided should be valid
+ */
+enum norddrop_result norddrop_reject_transfer(const struct norddrop *dev, const char *xfid);
+
+/**
+ * Reject all of the files in a transfer, letting the peer know why, and close
+ * the connection. Finishes with the `TransferRejected` event
+ *
+ * # Arguments
+ *
+ * * `dev`: Pointer to the instance
+ * * `xfid`: Transfer ID
+ * * `code`: Application defined reason code
+ * * `reason`: Human readable reason, can be NULL
+ *
+ * # Safety
+ * The pointers provided should be valid
+ */
+enum norddrop_result norddrop_reject_transfer_with_reason(const struct norddrop *dev,
+                                                          const char *xfid,
+                                                          unsigned int code,
+                                                          const char *reason);
+
+/**
+ * Start libdrop
+ *
+ * # Arguments
+ *
+ * * `dev` - Pointer to the instance
+ * * `listen_addr` - Address to listen on, or a comma separated list of them,
+ *   e.g. `"100.64.0.1,192.168.1.2"`
+ * * `config` - JSON configuration
+ *
+ * # Configuration Parameters
+ *
+ * * `dir_depth_limit` - if the tree contains more levels then the error is
+ * returned.
+ *
+ * * `transfer_file_limit` - when aggregating files from the path, if this
+ * limit is reached, an error is returned. The transfers scanned in the
+ * background fail once they grow over the limit.
+ *
+ * * `req_connection_timeout_ms` - timeout value used in connecting to the
+ *   peer.
+ * The formula for retrying is: starting from 0.2 seconds we double it
+ * each time until we cap at req_connection_timeout_ms / 10. This is useful
+ * when the peer is not responding at all.
+ *
+ * * `transfer_idle_lifetime_ms` - this timeout plays a role in an already
+ * established transfer as sometimes one peer might go offline with no notice.
+ * This timeout controls the amount of time we will wait for any action from
+ * the peer and after that, we will fail the transfer.
+ *
+ * * `transfer_accept_timeout_ms` - optional, the time within which an incoming
+ * transfer needs to be acted upon. Afterwards the pending files are rejected
+ * and the transfer finishes with `TransferExpired`. Disabled when not set.
+ *
+ * * `moose_event_path` - moose database path.
+ *
+ * * `storage_path` - storage path for persistence engine.
+ *
+ * * `source_addr` - optional, local IP address the outgoing connections are
+ * made from. Chosen by the OS when not set.
+ *
+ * * `bind_device` - optional, network interface the outgoing connections are
+ * bound to, e.g. `"wlan0"`. Supported on Linux and Android only.
+ */
+enum norddrop_result norddrop_start(const struct norddrop *dev,
+                                    const char *listen_addr,
+                                    const char *config);
+
+/**
+ * Stop norddrop instance
+ *
+ * # Arguments
+ *
+ * * `dev` - Pointer to the instance
  */
-typedef int (*norddrop_pubkey_fn)(void*, const char*, char*);
+enum norddrop_result norddrop_stop(const struct norddrop *dev);
 
 /**
- * Fetch peer public key callback
+ * Refresh the network after the connectivity has changed, e.g. when
+ * switching between the Wi-Fi and cellular network or when the meshnet address
+ * changes. The server is moved to the new addresses and the outgoing transfers
+ * that are still connecting retry right away. The established outgoing
+ * transfers are dialed again over the new route, the incoming ones are kept.
+ *
+ * # Arguments
+ *
+ * * `dev` - Pointer to the instance
+ * * `listen_addr` - Address to listen on, or a comma separated list of them.
+ *   The instance stops listening on the addresses missing from the list
+ *
+ * # Returns
+ *
+ * `NORDDROP_RES_ADDR_IN_USE` when the new address cannot be bound, the
+ * instance keeps listening on the previous addresses then
+ *
+ * # Safety
+ * The pointers provided should be valid
  */
-typedef struct norddrop_pubkey_cb {
-  /**
-   * Context to pass to callback.
-   * User must ensure safe access of this var from multitheaded context.
-   */
-  void *ctx;
-  /**
-   * Function to be called
-   */
-  norddrop_pubkey_fn cb;
-} norddrop_pubkey_cb;
+enum norddrop_result norddrop_network_refresh(const struct norddrop *dev, const char *listen_addr);
 
-#ifdef __cplusplus
-extern "C" {
-#endif // __cplusplus
+/**
+ * Start accepting the transfers on one more address, e.g. on the LAN
+ * interface next to the meshnet one. Does nothing if the instance already
+ * listens on it
+ *
+ * # Arguments
+ *
+ * * `dev` - Pointer to the instance
+ * * `addr` - IP address to listen on
+ *
+ * # Returns
+ *
+ * `NORDDROP_RES_ADDR_IN_USE` when the address cannot be bound
+ *
+ * # Safety
+ * The pointers provided should be valid
+ */
+enum norddrop_result norddrop_enable_listener(const struct norddrop *dev, const char *addr);
 
-extern void fortify_source(void);
+/**
+ * Stop accepting the transfers on the address. The transfers that arrived on
+ * it are kept
+ *
+ * # Arguments
+ *
+ * * `dev` - Pointer to the instance
+ * * `addr` - IP address to stop listening on
+ *
+ * # Returns
+ *
+ * `NORDDROP_RES_BAD_INPUT` when the instance does not listen on the address
+ *
+ * # Safety
+ * The pointers provided should be valid
+ */
+enum norddrop_result norddrop_disable_listener(const struct norddrop *dev, const char *addr);
 
 /**
- * Initialize a new transfer with the provided peer and descriptors
+ * Update the configuration of the running instance without stopping it.
+ * Takes the same JSON as `norddrop_start()`. The limits and timeouts apply to
+ * the transfers and connections made afterwards, while `storage_path`,
+ * `moose_event_path` and `moose_prod` must stay the same. The listen
+ * addresses are changed with `norddrop_enable_listener()`,
+ * `norddrop_disable_listener()` and `norddrop_network_refresh()` instead.
  *
  * # Arguments
  *
- * * `dev` - A pointer to the instance.
- * * `peer` - Peer address.
- * * `descriptors` - JSON descriptors.
+ * * `dev` - Pointer to the instance
+ * * `config` - JSON configuration
  *
  * # Returns
  *
- * A String containing the transfer ID.
+ * `NORDDROP_RES_CONFIG_NOT_UPDATABLE` when one of the fixed settings differs
  *
- * # Descriptors format
+ * # Safety
+ * The pointers provided should be valid
+ */
+enum norddrop_result norddrop_set_config(const struct norddrop *dev, const char *config);
+
+/**
+ * Deliver the events through a bounded queue read with
+ * `norddrop_poll_event()` instead of the event callback. Useful for runtimes
+ * that handle calls from foreign threads poorly. Should be called before
+ * `norddrop_start()`, the callback is not called afterwards.
  *
- * Descriptors are provided as an array of JSON objects, with each object
- * containing a "path" and optionally a file descriptor "fd":
+ * When the queue is full the new events are dropped and an `EventsDropped`
+ * event with their count is queued in their place, so the order of the
+ * delivered events stays the same as the order in which they were emitted.
  *
- * ```json
- * [
- *   {
- *     "path": "/path/to/file",
- *   },
- *   {
- *     "path": "/path/to/dir",
- *   }
- * ]
- * ```
+ * # Arguments
  *
- * # On Android, due to limitations, we must also accept a file descriptor
+ * * `dev` - Pointer to the instance
+ * * `capacity` - Maximum number of events kept in the queue, must be positive
  *
  * ```json
- * [
- *   {
- *    "path": "/path/to/file",
- *    "fd": 1234
+ * {
+ *   "version": 3,
+ *   "type": "EventsDropped",
+ *   "data": {
+ *     "count": 12
  *   }
- * ]
+ * }
  * ```
  */
-char *norddrop_new_transfer(const struct norddrop *dev, const char *peer, const char *descriptors);
+enum norddrop_result norddrop_enable_event_queue(const struct norddrop *dev,
+                                                 unsigned long long capacity);
 
 /**
- * Destroy the libdrop instance.
+ * Wait for the next event in the queue enabled with
+ * `norddrop_enable_event_queue()`. Does not block other calls on the
+ * instance.
  *
  * # Arguments
  *
- * * `dev` - Pointer to the instance.
+ * * `dev` - Pointer to the instance
+ * * `timeout_ms` - Maximum time to wait for an event, 0 returns immediately
+ *
+ * # Returns
+ *
+ * The event JSON, the same as passed to the event callback, which must be
+ * released with `norddrop_free_event()`. `NULL` when no event arrived in
+ * time or the queue is not enabled.
+ */
+char *norddrop_poll_event(const struct norddrop *dev, unsigned long long timeout_ms);
+
+/**
+ * Release the event returned by `norddrop_poll_event()`
+ *
+ * # Arguments
+ *
+ * * `event` - The event to release, may be `NULL`
  *
  * # Safety
- * This function creates a box with instance pointer and immediately drops it.
+ * The pointer must be returned by `norddrop_poll_event()` and not released
+ * before
  */
-void norddrop_destroy(struct norddrop *dev);
+void norddrop_free_event(char *event);
 
 /**
- * # Download a file from the peer
+ * Opt into a specific version of the event format for compatibility. Every
+ * event carries the `version` field, by default the latest version is used.
+ * 
+ * The versions:
+ * * 1 - the initial format
+ * * 2 - `local_addr` in `RequestReceived`
+ * * 3 - `identity` in `RequestReceived` and `RequestQueued`
  *
  * # Arguments
  *
  * * `dev` - Pointer to the instance
- * * `xfid` - Transfer ID
- * * `fid` - File ID
- * * `dst` - Destination path
+ * * `version` - Event format version
+ *
+ * # Returns
+ *
+ * `NORDDROP_RES_BAD_INPUT` when the version is not supported
  */
-enum norddrop_result norddrop_download(const struct norddrop *dev,
-                                       const char *xfid,
-                                       const char *fid,
-                                       const char *dst);
+enum norddrop_result norddrop_set_event_version(const struct norddrop *dev, unsigned int version);
 
 /**
- * # Cancel a transfer from either side
+ * Generate the code for an unknown peer to pair with this instance. The user
+ * shows the code to the other user, who enters it in `norddrop_pair()`. The
+ * code allows a single attempt within its lifetime and replaces the
+ * previously generated one
  *
  * # Arguments
  *
- * * `dev`: Pointer to the instance
- * * `xfid`: Transfer ID
+ * * `dev` - A pointer to the instance.
+ * * `lifetime_ms` - Time after which the code expires.
+ *
+ * # Returns
+ *
+ * A String containing the code, `NULL` on failure.
  */
-enum norddrop_result norddrop_cancel_transfer(const struct norddrop *dev, const char *xfid);
+char *norddrop_start_pairing(const struct norddrop *dev, long long lifetime_ms);
 
 /**
- * # Cancel a file from either side
+ * Invalidate the code generated with `norddrop_start_pairing()`
  *
  * # Arguments
  *
- * * `dev`: Pointer to the instance
- * * `xfid`: Transfer ID
- * * `fid`: File ID
+ * * `dev` - A pointer to the instance.
+ *
+ * # Returns
+ *
+ * `NORDDROP_RES_BAD_INPUT` when there is no pending code
  */
-enum norddrop_result norddrop_cancel_file(const struct norddrop *dev,
-                                          const char *xfid,
-                                          const char *fid);
+enum norddrop_result norddrop_cancel_pairing(const struct norddrop *dev);
 
 /**
- * Reject a file from either side
+ * Pair with the peer using the code it generated with
+ * `norddrop_start_pairing()`. Both sides run SPAKE2 with the code and, if the
+ * codes match, exchange and persist their public keys. The paired peers are
+ * authenticated with these keys when the public key callback does not know
+ * them.
+ *
+ * The pairing runs in the background. Both sides report the progress with
+ * the `PairingStarted` (the side entering the code) or `PairingRequested`
+ * (the side which generated it) event, followed by `PairingCompleted` with
+ * the identity of the peer or `PairingFailed` with the status.
  *
  * # Arguments
  *
- * * `dev`: Pointer to the instance
- * * `xfid`: Transfer ID
- * * `fid`: File ID
+ * * `dev` - A pointer to the instance.
+ * * `peer` - Peer address.
+ * * `code` - Code generated by the peer.
+ *
+ * ```json
+ * {
+ *   "type": "PairingCompleted",
+ *   "data": {
+ *     "peer": "172.17.0.2",
+ *     "identity": "JA/Me7wRDBJ67fkmjpokpFobTLGHTv9GXlYxsjNryG0"
+ *   }
+ * }
+ * ```
  *
  * # Safety
  * The pointers provided should be valid
  */
-enum norddrop_result norddrop_reject_file(const struct norddrop *dev,
-                                          const char *xfid,
-                                          const char *fid);
+enum norddrop_result norddrop_pair(const struct norddrop *dev, const char *peer, const char *code);
 
 /**
- * Start libdrop
+ * Get the JSON Schema of the events and of the `norddrop_get_transfers_since()`
+ * output for the latest event format version
  *
- * # Arguments
+ * # Returns
  *
- * * `dev` - Pointer to the instance
- * * `listen_addr` - Address to listen on
- * * `config` - JSON configuration
+ * JSON object with the `version` of the event format, and the `event` and
+ * `transfers` schemas. `NULL` on failure.
  *
- * # Configuration Parameters
+ * ```json
+ * {
+ *   "version": 3,
+ *   "event": { "$schema": "http://json-schema.org/draft-07/schema#", ... },
+ *   "transfers": { "$schema": "http://json-schema.org/draft-07/schema#", ... }
+ * }
+ * ```
+ */
+char *norddrop_get_event_schema(void);
+
+/**
+ * Set the callback receiving the common events as `norddrop_event` structures
+ * instead of JSON. Other events are still passed to the JSON callback. When
+ * the event queue is enabled, all of the events are queued as JSON and the
+ * typed callback is not called.
  *
- * * `dir_depth_limit` - if the tree contains more levels then the error is
- * returned.
+ * # Arguments
  *
- * * `transfer_file_limit` - when aggregating files from the path, if this
- * limit is reached, an error is returned.
+ * * `dev` - Pointer to the instance
+ * * `event_cb` - Typed event callback
+ */
+enum norddrop_result norddrop_set_typed_event_cb(const struct norddrop *dev,
+                                                 struct norddrop_typed_event_cb event_cb);
+
+/**
+ * Create a new transfer from the typed descriptors
  *
- * * `req_connection_timeout_ms` - timeout value used in connecting to the
- *   peer.
- * The formula for retrying is: starting from 0.2 seconds we double it
- * each time until we cap at req_connection_timeout_ms / 10. This is useful
- * when the peer is not responding at all.
+ * # Arguments
  *
- * * `transfer_idle_lifetime_ms` - this timeout plays a role in an already
- * established transfer as sometimes one peer might go offline with no notice.
- * This timeout controls the amount of time we will wait for any action from
- * the peer and after that, we will fail the transfer.
+ * * `dev` - Pointer to the instance
+ * * `peer` - Peer address
+ * * `descriptors` - Array of the transfer descriptors
+ * * `descriptors_len` - Number of the descriptors
  *
- * * `moose_event_path` - moose database path.
+ * # Returns
  *
- * * `storage_path` - storage path for persistence engine.
+ * The transfer ID, `NULL` on failure
+ *
+ * # Safety
+ * The descriptors should point to an array of `descriptors_len` valid
+ * structures with valid string pointers
  */
-enum norddrop_result norddrop_start(const struct norddrop *dev,
-                                    const char *listen_addr,
-                                    const char *config);
+char *norddrop_new_transfer_typed(const struct norddrop *dev,
+                                  const char *peer,
+                                  const struct norddrop_transfer_descriptor *descriptors,
+                                  uintptr_t descriptors_len);
 
 /**
- * Stop norddrop instance
+ * Get the progress of a file in an active transfer
  *
  * # Arguments
  *
  * * `dev` - Pointer to the instance
+ * * `xfid` - Transfer ID
+ * * `fid` - File ID
+ * * `progress` - Filled with the progress on success
+ *
+ * # Returns
+ *
+ * `NORDDROP_RES_BAD_TRANSFER` when the transfer is not active and
+ * `NORDDROP_RES_BAD_FILE` when the file is not part of it
+ *
+ * # Safety
+ * The pointers provided should be valid
  */
-enum norddrop_result norddrop_stop(const struct norddrop *dev);
+enum norddrop_result norddrop_get_file_progress(const struct norddrop *dev,
+                                                const char *xfid,
+                                                const char *fid,
+                                                struct norddrop_file_progress *progress);
 
 /**
  * Purge transfers from the database
@@ -411,6 +1512,87 @@ enum norddrop_result norddrop_purge_transfers_until(const struct norddrop *dev,
  */
 char *norddrop_get_transfers_since(const struct norddrop *dev, long long since_timestamp);
 
+/**
+ * Get the live state of the transfers which are currently connecting or
+ * connected. Unlike `norddrop_get_transfers_since()` this does not touch the
+ * database, the state is taken from the running connections.
+ *
+ * # Arguments
+ *
+ * * `dev` - Pointer to the instance
+ *
+ * # Returns
+ *
+ * A JSON array of the active transfers. `NULL` when the instance is not
+ * started. The `version` field is present only once the connection is
+ * established. The file `state` is one of `pending`, `in_progress`,
+ * `completed`, `failed`, `canceled` or `rejected` and `bytes` is the number of
+ * bytes transferred so far.
+ *
+ * ```json
+ * [
+ *   {
+ *     "transfer": "b49fc2f8-ce2d-41ac-a081-96a4d760899e",
+ *     "peer": "172.17.0.1",
+ *     "direction": "outgoing",
+ *     "state": "connected",
+ *     "version": "v6",
+ *     "files": [
+ *       {
+ *         "id": "Unu_l4PVyu15-RsdVL9IOQvaKQdqcqUy7F9EpvP-CrY",
+ *         "path": "doggo.jpg",
+ *         "size": 29852,
+ *         "state": "in_progress",
+ *         "bytes": 8192
+ *       }
+ *     ],
+ *     "rejected": []
+ *   }
+ * ]
+ * ```
+ */
+char *norddrop_get_active_transfers(const struct norddrop *dev);
+
+/**
+ * Get the identity of the peer last seen at the given address. The identity
+ * is derived from the public key of the peer and stays the same when the peer
+ * changes its address
+ *
+ * # Arguments
+ *
+ * * `dev` - Pointer to the instance
+ * * `addr` - IP address of the peer
+ *
+ * # Returns
+ *
+ * The peer identity, `NULL` when the peer is unknown or on failure
+ *
+ * # Safety
+ * The pointers provided should be valid
+ */
+char *norddrop_get_peer_identity(const struct norddrop *dev, const char *addr);
+
+/**
+ * Get the addresses the peer with the given identity was seen at
+ *
+ * # Arguments
+ *
+ * * `dev` - Pointer to the instance
+ * * `identity` - Peer identity, as reported in the events
+ *
+ * # Returns
+ *
+ * A JSON array of the IP addresses, the most recent first. `NULL` on failure
+ *
+ * ```json
+ * ["172.17.0.2", "172.17.0.3"]
+ * ```
+ *
+ * # Safety
+ * The pointers provided should be valid
+ */
+char *norddrop_get_peer_addresses(const struct norddrop *dev, const char *identity);
+
 /**
  * Removes a single transfer file from the database. The file must be rejected
  * beforehand, otherwise the error is returned.
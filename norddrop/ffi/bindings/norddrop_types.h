@@ -72,6 +72,26 @@ typedef enum norddrop_result {
    * Database error
    */
   NORDDROP_RES_DB_ERROR = 11,
+  /**
+   * The transfer is unknown or no longer active
+   */
+  NORDDROP_RES_BAD_TRANSFER = 12,
+  /**
+   * The file is not part of the transfer
+   */
+  NORDDROP_RES_BAD_FILE = 13,
+  /**
+   * The destination path is invalid
+   */
+  NORDDROP_RES_BAD_PATH = 14,
+  /**
+   * The file is already rejected
+   */
+  NORDDROP_RES_FILE_REJECTED = 15,
+  /**
+   * The setting cannot be changed while the instance is running
+   */
+  NORDDROP_RES_CONFIG_NOT_UPDATABLE = 16,
 } norddrop_result;
 
 typedef void (*norddrop_event_fn)(void*, const char*);
@@ -62,6 +62,21 @@ struct norddrop {};
     enum norddrop_result start(const char *listen_addr, const char* config_json);
 
     enum norddrop_result stop();
+
+    enum norddrop_result network_refresh(const char *listen_addr);
+
+    enum norddrop_result enable_listener(const char *addr);
+
+    enum norddrop_result disable_listener(const char *addr);
+
+    enum norddrop_result set_config(const char* config_json);
+
+    enum norddrop_result enable_event_queue(unsigned long long capacity);
+
+    %newobject poll_event;
+    char* poll_event(unsigned long long timeout_ms);
+
+    enum norddrop_result set_event_version(unsigned int version);
         
     enum norddrop_result cancel_transfer(const char* txid);
 
@@ -69,11 +84,68 @@ struct norddrop {};
 
     enum norddrop_result reject_file(const char* txid, const char* fid);
 
+    enum norddrop_result cancel_file_with_reason(const char* txid, const char* fid, unsigned int code, const char* reason);
+
+    enum norddrop_result reject_file_with_reason(const char* txid, const char* fid, unsigned int code, const char* reason);
+
+    enum norddrop_result reject_transfer(const char* txid);
+
+    enum norddrop_result reject_transfer_with_reason(const char* txid, unsigned int code, const char* reason);
+
     enum norddrop_result download(const char* txid, const char* fid, const char* dst_path);
 
+    %newobject cancel_files;
+    char* cancel_files(const char* txid, const char* fids);
+
+    %newobject reject_files;
+    char* reject_files(const char* txid, const char* fids);
+
+    %newobject download_files;
+    char* download_files(const char* txid, const char* fids, const char* dst_path);
+
     %newobject new_transfer;
     char* new_transfer(const char* peer, const char* descriptors);
 
+    %newobject new_transfer_with_source;
+    char* new_transfer_with_source(const char* peer, const char* source_addr, const char* descriptors);
+
+    %newobject new_transfer_tolerant;
+    char* new_transfer_tolerant(const char* peer, const char* descriptors);
+
+    %newobject new_transfer_multi;
+    char* new_transfer_multi(const char* peers, const char* descriptors);
+
+    %newobject new_transfer_async;
+    char* new_transfer_async(const char* peer, const char* descriptors);
+
+    enum norddrop_result append_files(const char* txid, const char* descriptors);
+
+    %newobject publish;
+    char* publish(const char* descriptors, long long lifetime_ms, int max_downloads);
+
+    enum norddrop_result unpublish(const char* token);
+
+    %newobject pull;
+    char* pull(const char* peer, const char* token);
+
+    enum norddrop_result export_dir(const char* name, const char* path);
+
+    enum norddrop_result unexport_dir(const char* name);
+
+    %newobject request_files;
+    char* request_files(const char* peer, const char* export, const char* paths);
+
+    enum norddrop_result approve_file_request(const char* request);
+
+    enum norddrop_result deny_file_request(const char* request);
+
+    %newobject start_pairing;
+    char* start_pairing(long long lifetime_ms);
+
+    enum norddrop_result cancel_pairing();
+
+    enum norddrop_result pair(const char* peer, const char* code);
+
     enum norddrop_result purge_transfers(const char *txids);
 
     enum norddrop_result purge_transfers_until(long long until_timestamp);
@@ -83,6 +155,18 @@ struct norddrop {};
     %newobject get_transfers_since;
     char *get_transfers_since(long long since_timestamp);
 
+    %newobject get_active_transfers;
+    char *get_active_transfers();
+
+    %newobject get_peer_identity;
+    char *get_peer_identity(const char *addr);
+
+    %newobject get_peer_addresses;
+    char *get_peer_addresses(const char *identity);
+
+    %newobject get_event_schema;
+    static char* get_event_schema();
+
     static char* version();
 };
 
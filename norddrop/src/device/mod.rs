@@ -1,17 +1,27 @@
+mod queue;
 pub mod types;
 
 use std::{
-    net::{IpAddr, ToSocketAddrs},
-    sync::Arc,
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, RwLock,
+    },
+    time::Duration,
 };
 
 use drop_auth::{PublicKey, SecretKey, PUBLIC_KEY_LENGTH};
 use drop_config::{Config, DropConfig};
-use drop_transfer::{auth, utils::Hidden, File, Service, Transfer};
+use drop_transfer::{
+    auth,
+    file::{GatherOpts, SkipReason, Skipped},
+    utils::Hidden,
+    File, Service, Transfer,
+};
 use slog::{debug, error, trace, warn, Logger};
 use tokio::sync::{mpsc, Mutex};
 
-use self::types::TransferDescriptor;
+use self::{queue::EventQueue, types::TransferDescriptor};
 use crate::{device::types::FinishEvent, ffi, ffi::types as ffi_types};
 
 pub type Result<T = ()> = std::result::Result<T, ffi::types::norddrop_result>;
@@ -19,6 +29,12 @@ pub type Result<T = ()> = std::result::Result<T, ffi::types::norddrop_result>;
 const SQLITE_TIMESTAMP_MIN: i64 = -210866760000;
 const SQLITE_TIMESTAMP_MAX: i64 = 253402300799;
 
+/// Number of entries found between the scanning progress events, also the
+/// size of the batches sent while scanning
+const SCAN_PROGRESS_STEP: usize = 1000;
+/// Number of batches found ahead of the transfer
+const SCAN_FEED_DEPTH: usize = 4;
+
 pub(super) struct NordDropFFI {
     rt: tokio::runtime::Runtime,
     pub logger: Logger,
@@ -30,14 +46,28 @@ pub(super) struct NordDropFFI {
 
 struct EventDispatcher {
     cb: ffi_types::norddrop_event_cb,
+    // When set the events are queued for polling instead of calling the callback
+    queue: RwLock<Option<Arc<EventQueue>>>,
+    // Event format version the app opted into
+    version: AtomicU32,
+    // Receives the common events instead of the JSON callback when set
+    typed_cb: RwLock<Option<ffi::typed::norddrop_typed_event_cb>>,
     logger: Logger,
 }
 
 impl EventDispatcher {
     pub fn dispatch(&self, e: types::Event) {
-        let res = std::ffi::CString::new(
-            serde_json::to_string(&e).unwrap_or_else(|_| String::from("event_to_json error")),
-        );
+        let version = self.version.load(Ordering::Relaxed);
+        let json = e
+            .to_versioned_json(version)
+            .unwrap_or_else(|_| String::from("event_to_json error"));
+
+        if let Some(queue) = self.queue() {
+            queue.push(json, version);
+            return;
+        }
+
+        let res = std::ffi::CString::new(json);
 
         match res {
             Ok(s) => unsafe {
@@ -49,6 +79,34 @@ impl EventDispatcher {
             Err(e) => warn!(self.logger, "Failed to create CString: {}", e),
         }
     }
+
+    /// Returns `false` if the event should be delivered as JSON instead
+    fn dispatch_typed(&self, e: &drop_transfer::Event) -> bool {
+        // The queued events must not reach the app from the library threads
+        if self.queue().is_some() {
+            return false;
+        }
+
+        let cb = match *self.typed_cb.read().expect("Typed callback lock poisoned") {
+            Some(cb) => cb,
+            None => return false,
+        };
+
+        match ffi::typed::typed_event(e) {
+            Some(event) => {
+                unsafe { (cb.cb)(cb.ctx, event.as_ptr()) };
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn queue(&self) -> Option<Arc<EventQueue>> {
+        self.queue
+            .read()
+            .expect("Event queue lock poisoned")
+            .clone()
+    }
 }
 
 impl NordDropFFI {
@@ -69,6 +127,9 @@ impl NordDropFFI {
             rt: tokio::runtime::Runtime::new().map_err(|_| ffi::types::NORDDROP_RES_ERROR)?,
             event_dispatcher: Arc::new(EventDispatcher {
                 cb: event_cb,
+                queue: RwLock::default(),
+                version: AtomicU32::new(types::EVENT_VERSION),
+                typed_cb: RwLock::default(),
                 logger: logger.clone(),
             }),
             config: Config::default(),
@@ -76,6 +137,71 @@ impl NordDropFFI {
         })
     }
 
+    /// Switch to queueing the events for `norddrop_poll_event()` instead of
+    /// calling the event callback
+    pub(super) fn enable_event_queue(&self, capacity: usize) -> Result<()> {
+        trace!(
+            self.logger,
+            "norddrop_enable_event_queue() capacity: {capacity}"
+        );
+
+        if capacity == 0 {
+            error!(self.logger, "Event queue capacity cannot be zero");
+            return Err(ffi::types::NORDDROP_RES_BAD_INPUT);
+        }
+
+        let mut queue = self
+            .event_dispatcher
+            .queue
+            .write()
+            .expect("Event queue lock poisoned");
+
+        if queue.is_some() {
+            error!(self.logger, "Event queue is already enabled");
+            return Err(ffi::types::NORDDROP_RES_BAD_INPUT);
+        }
+
+        *queue = Some(Arc::new(EventQueue::new(capacity)));
+        Ok(())
+    }
+
+    pub(super) fn set_event_version(&self, version: u32) -> Result<()> {
+        trace!(
+            self.logger,
+            "norddrop_set_event_version() version: {version}"
+        );
+
+        if !(types::EVENT_VERSION_MIN..=types::EVENT_VERSION).contains(&version) {
+            error!(
+                self.logger,
+                "Unsupported event version {version}, supported versions are {} to {}",
+                types::EVENT_VERSION_MIN,
+                types::EVENT_VERSION
+            );
+            return Err(ffi::types::NORDDROP_RES_BAD_INPUT);
+        }
+
+        self.event_dispatcher
+            .version
+            .store(version, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub(super) fn set_typed_event_cb(&self, cb: ffi::typed::norddrop_typed_event_cb) {
+        trace!(self.logger, "norddrop_set_typed_event_cb()");
+
+        *self
+            .event_dispatcher
+            .typed_cb
+            .write()
+            .expect("Typed callback lock poisoned") = Some(cb);
+    }
+
+    /// The event queue, waiting on it must not block the instance
+    pub(super) fn event_queue(&self) -> Option<Arc<EventQueue>> {
+        self.event_dispatcher.queue()
+    }
+
     pub(super) fn start(&mut self, listen_addr: &str, config_json: &str) -> Result<()> {
         let logger = self.logger.clone();
 
@@ -118,13 +244,7 @@ impl NordDropFFI {
             }
         };
 
-        let addr: IpAddr = match listen_addr.parse() {
-            Ok(addr) => addr,
-            Err(err) => {
-                error!(logger, "Failed to parse IP address: {err}");
-                return Err(ffi::types::NORDDROP_RES_BAD_INPUT);
-            }
-        };
+        let addrs = self.parse_listen_addrs(listen_addr)?;
 
         let (tx, mut rx) = mpsc::channel::<drop_transfer::Event>(16);
 
@@ -155,13 +275,17 @@ impl NordDropFFI {
                 // The events where dispatched in different order than where emitted.
                 // To fix that we need to process the events sequentially.
                 // Also the callback may block the executor - we need to be resistant to that.
-                tokio::task::block_in_place(|| ed.dispatch(e.into()));
+                tokio::task::block_in_place(|| {
+                    if !ed.dispatch_typed(&e) {
+                        ed.dispatch(e.into())
+                    }
+                });
             }
         });
 
         self.rt.block_on(async {
             let service = match Service::start(
-                addr,
+                &addrs,
                 storage,
                 tx,
                 self.logger.clone(),
@@ -204,6 +328,127 @@ impl NordDropFFI {
         })
     }
 
+    pub(super) fn network_refresh(&mut self, listen_addr: &str) -> Result<()> {
+        trace!(
+            self.logger,
+            "norddrop_network_refresh() listen address: {:?}",
+            listen_addr
+        );
+
+        let addrs = self.parse_listen_addrs(listen_addr)?;
+
+        self.rt.block_on(async {
+            let mut inst = self.instance.lock().await;
+            let inst = inst.as_mut().ok_or(ffi::types::NORDDROP_RES_NOT_STARTED)?;
+
+            inst.rebind(&addrs).await.map_err(|err| {
+                error!(self.logger, "Failed to rebind the service: {err}");
+                ffi::types::norddrop_result::from(&err)
+            })
+        })
+    }
+
+    pub(super) fn enable_listener(&mut self, addr: &str) -> Result<()> {
+        trace!(
+            self.logger,
+            "norddrop_enable_listener() address: {:?}",
+            addr
+        );
+
+        let addr = self.parse_ip(addr)?;
+
+        self.rt.block_on(async {
+            let mut inst = self.instance.lock().await;
+            let inst = inst.as_mut().ok_or(ffi::types::NORDDROP_RES_NOT_STARTED)?;
+
+            inst.enable_listener(addr).map_err(|err| {
+                error!(self.logger, "Failed to listen on {addr}: {err}");
+                ffi::types::norddrop_result::from(&err)
+            })
+        })
+    }
+
+    pub(super) fn disable_listener(&mut self, addr: &str) -> Result<()> {
+        trace!(
+            self.logger,
+            "norddrop_disable_listener() address: {:?}",
+            addr
+        );
+
+        let addr = self.parse_ip(addr)?;
+
+        self.rt.block_on(async {
+            let mut inst = self.instance.lock().await;
+            let inst = inst.as_mut().ok_or(ffi::types::NORDDROP_RES_NOT_STARTED)?;
+
+            inst.disable_listener(addr).await.map_err(|err| {
+                error!(self.logger, "Failed to stop listening on {addr}: {err}");
+                ffi::types::NORDDROP_RES_BAD_INPUT
+            })
+        })
+    }
+
+    fn parse_ip(&self, addr: &str) -> Result<IpAddr> {
+        addr.trim().parse().map_err(|err| {
+            error!(self.logger, "Failed to parse IP address {addr:?}: {err}");
+            ffi::types::NORDDROP_RES_BAD_INPUT
+        })
+    }
+
+    // Comma separated list of the addresses to listen on
+    fn parse_listen_addrs(&self, listen_addr: &str) -> Result<Vec<IpAddr>> {
+        listen_addr
+            .split(',')
+            .map(|addr| self.parse_ip(addr))
+            .collect()
+    }
+
+    pub(super) fn set_config(&mut self, config_json: &str) -> Result<()> {
+        trace!(self.logger, "norddrop_set_config()");
+
+        let config: types::Config = serde_json::from_str(config_json).map_err(|err| {
+            error!(self.logger, "Failed to parse config: {}", err);
+            ffi::types::NORDDROP_RES_JSON_PARSE
+        })?;
+        let config: Config = config.into();
+
+        // Settings the instance is started with. The listen addresses, keys and
+        // the storage itself are not a part of the config, they're passed to
+        // `norddrop_new()` and `norddrop_start()` or have dedicated calls
+        let fixed = [
+            (
+                "storage_path",
+                config.drop.storage_path != self.config.drop.storage_path,
+            ),
+            (
+                "moose_event_path",
+                config.moose.event_path != self.config.moose.event_path,
+            ),
+            ("moose_prod", config.moose.prod != self.config.moose.prod),
+        ];
+
+        if let Some((name, _)) = fixed.iter().find(|(_, changed)| *changed) {
+            error!(
+                self.logger,
+                "Config setting {name} cannot be changed at runtime"
+            );
+            return Err(ffi::types::NORDDROP_RES_CONFIG_NOT_UPDATABLE);
+        }
+
+        self.rt.block_on(async {
+            let inst = self.instance.lock().await;
+            let inst = inst.as_ref().ok_or(ffi::types::NORDDROP_RES_NOT_STARTED)?;
+
+            inst.update_config(config.drop.clone()).map_err(|err| {
+                error!(self.logger, "Failed to update config: {err}");
+                ffi::types::norddrop_result::from(&err)
+            })
+        })?;
+
+        self.config = config;
+        Ok(())
+    }
+
     pub(super) fn purge_transfers(&mut self, transfer_ids: &str) -> Result<()> {
         trace!(
             self.logger,
@@ -335,209 +580,999 @@ impl NordDropFFI {
             descriptors
         );
 
-        let descriptors: Vec<TransferDescriptor> = match serde_json::from_str(descriptors) {
-            Ok(descriptors) => descriptors,
-            Err(e) => {
-                error!(
-                    self.logger,
-                    "Failed to parse new_transfer() descriptors: {}", e
-                );
-                return Err(ffi::types::NORDDROP_RES_JSON_PARSE);
-            }
-        };
-
-        let peer = (peer, drop_config::PORT)
-            .to_socket_addrs()
-            .map_err(|err| {
-                error!(self.logger, "Failed to perform lookup of address: {err}");
-                ffi::types::NORDDROP_RES_BAD_INPUT
-            })?
-            .next()
-            .ok_or(ffi::types::NORDDROP_RES_BAD_INPUT)?;
-
-        let xfer = {
-            let files = prepare_transfer_files(&self.logger, &descriptors, &self.config.drop)?;
-            Transfer::new(peer.ip(), files, &self.config.drop).map_err(|e| {
-                error!(
-                    self.logger,
-                    "Could not create transfer ({:?}): {}", descriptors, e
-                );
-
-                ffi::types::NORDDROP_RES_TRANSFER_CREATE
-            })?
-        };
+        let (xfid, _) = self.create_transfer(peer, descriptors, None, false)?;
+        Ok(xfid)
+    }
 
-        debug!(
+    pub(super) fn new_transfer_with_source(
+        &mut self,
+        peer: &str,
+        source_addr: &str,
+        descriptors: &str,
+    ) -> Result<uuid::Uuid> {
+        trace!(
             self.logger,
-            "Created transfer with files:\n{:#?}",
-            xfer.files().values()
+            "norddrop_new_transfer_with_source() to peer {:?} from {:?}: {:?}",
+            peer,
+            source_addr,
+            descriptors
         );
 
-        let xfid = xfer.id();
+        let source = self.parse_ip(source_addr)?;
 
-        self.rt.block_on(async {
-            self.instance
-                .lock()
-                .await
-                .as_mut()
-                .ok_or(ffi::types::NORDDROP_RES_NOT_STARTED)?
-                .send_request(xfer)
-                .await;
+        let (xfid, _) = self.create_transfer(peer, descriptors, Some(source), false)?;
+        Ok(xfid)
+    }
 
-            Result::Ok(())
-        })?;
+    pub(super) fn new_transfer_typed(
+        &mut self,
+        peer: &str,
+        descriptors: Vec<TransferDescriptor>,
+    ) -> Result<uuid::Uuid> {
+        trace!(
+            self.logger,
+            "norddrop_new_transfer_typed() to peer {:?}: {:?}",
+            peer,
+            descriptors
+        );
 
+        let (xfid, _) = self.create_transfer_from(peer, descriptors, None, false)?;
         Ok(xfid)
     }
 
-    pub(super) fn download(
+    pub(super) fn new_transfer_async(
         &mut self,
-        xfid: uuid::Uuid,
-        file_id: String,
-        dst: String,
-    ) -> Result<()> {
-        let instance = self.instance.clone();
-        let logger = self.logger.clone();
-        let ed = self.event_dispatcher.clone();
-
+        peer: &str,
+        descriptors: &str,
+    ) -> Result<uuid::Uuid> {
         trace!(
-            logger,
-            "norddrop_download() for transfer {:?}, file {:?}, to {:?}",
-            xfid,
-            file_id,
-            dst
+            self.logger,
+            "norddrop_new_transfer_async() to peer {:?}: {:?}",
+            peer,
+            descriptors
         );
 
-        self.rt.spawn(async move {
-            let mut locked_inst = instance.lock().await;
-            let inst = locked_inst.as_mut().expect("Instance not initialized");
-
-            if let Err(e) = inst
-                .download(xfid, &file_id.clone().into(), dst.as_ref())
-                .await
-            {
-                error!(
-                    logger,
-                    "Failed to download a file with xfid: {}, file: {:?}, dst: {:?}, error: {:?}",
-                    xfid,
-                    Hidden(&file_id),
-                    Hidden(&dst),
-                    e
-                );
+        let descriptors = self.parse_descriptors(descriptors)?;
+        let peer = self.resolve_peer(peer)?;
 
-                ed.dispatch(types::Event::TransferFinished {
-                    transfer: xfid.to_string(),
-                    data: FinishEvent::FileFailed {
-                        file: file_id,
-                        status: From::from(&e),
-                    },
-                });
-            }
-        });
+        if self.instance.blocking_lock().is_none() {
+            return Err(ffi::types::NORDDROP_RES_NOT_STARTED);
+        }
 
-        Ok(())
-    }
+        let xfid = uuid::Uuid::new_v4();
 
-    pub(super) fn cancel_transfer(&mut self, xfid: uuid::Uuid) -> Result<()> {
         let instance = self.instance.clone();
         let logger = self.logger.clone();
         let ed = self.event_dispatcher.clone();
-
-        trace!(logger, "norddrop_cancel_transfer() for {:?}", xfid);
+        let config = self.config.drop.clone();
 
         self.rt.spawn(async move {
-            let mut locked_inst = instance.lock().await;
-            let inst = locked_inst.as_mut().expect("Instance not initialized");
-
-            if let Err(e) = inst.cancel_all(xfid).await {
-                error!(
-                    logger,
-                    "Failed to cancel a transfer with xfid: {:?}, error: {:?}", xfid, e
-                );
-
-                ed.dispatch(types::Event::TransferFinished {
-                    transfer: xfid.to_string(),
-                    data: FinishEvent::TransferFailed {
-                        status: From::from(&e),
+            let scan_logger = logger.clone();
+            let scan_ed = ed.clone();
+            let scan_config = config.clone();
+
+            // The files are sent in batches while the scan goes on
+            let (feed_tx, mut feed) = mpsc::channel(SCAN_FEED_DEPTH);
+
+            let scan = tokio::task::spawn_blocking(move || {
+                let opts = background_gather_opts();
+
+                let mut files = 0;
+                let mut bytes = 0;
+                let mut batch = Vec::new();
+
+                let report = |files, bytes| {
+                    scan_ed.dispatch(types::Event::TransferScanProgress(
+                        types::ScanProgressEvent {
+                            transfer: xfid.to_string(),
+                            files,
+                            bytes,
+                        },
+                    ))
+                };
+
+                let res = scan_transfer_files(
+                    &scan_logger,
+                    &descriptors,
+                    &scan_config,
+                    &opts,
+                    &mut |file| {
+                        files += 1;
+                        bytes += file.size();
+
+                        if files % SCAN_PROGRESS_STEP == 0 {
+                            report(files, bytes);
+                        }
                     },
-                })
-            }
-        });
+                    &mut |file| {
+                        batch.push(file);
+
+                        if batch.len() == SCAN_PROGRESS_STEP {
+                            // The feed is closed once the transfer is over
+                            feed_tx
+                                .blocking_send(Ok(std::mem::take(&mut batch)))
+                                .map_err(|_| drop_transfer::Error::Canceled)?;
+                        }
+                        Ok(())
+                    },
+                );
 
-        Ok(())
-    }
+                report(files, bytes);
+
+                match res {
+                    Ok(skipped) => {
+                        if !skipped.is_empty() {
+                            scan_ed.dispatch(types::Event::TransferPathsSkipped(
+                                types::SkippedEvent {
+                                    transfer: xfid.to_string(),
+                                    paths: skipped.iter().map(From::from).collect(),
+                                },
+                            ));
+                        }
+
+                        if !batch.is_empty() {
+                            let _ = feed_tx.blocking_send(Ok(batch));
+                        }
+                    }
+                    Err(err) => {
+                        let _ = feed_tx.blocking_send(Err(err));
+                    }
+                }
+            });
 
-    pub(super) fn cancel_file(&mut self, xfid: uuid::Uuid, file: String) {
-        let instance = self.instance.clone();
-        let logger = self.logger.clone();
-        let ed = self.event_dispatcher.clone();
+            let first = match feed.recv().await {
+                Some(res) => res,
+                None => {
+                    if let Err(err) = scan.await {
+                        error!(logger, "Transfer {xfid} scanning task failed: {err}");
+                        return;
+                    }
 
-        trace!(
-            logger,
-            "norddrop_cancel_file() for transfer {:?}, file {:?}",
-            xfid,
-            file
-        );
+                    error!(logger, "Nothing left to send in transfer {xfid}");
+                    Err(drop_transfer::Error::InvalidArgument)
+                }
+            };
 
-        self.rt.spawn(async move {
-            let mut locked_inst = instance.lock().await;
-            let inst = locked_inst.as_mut().expect("Instance not initialized");
+            let xfer = match first
+                .and_then(|files| Transfer::new_with_uuid(peer.ip(), files, xfid, &config))
+            {
+                Ok(xfer) => xfer,
+                Err(err) => {
+                    error!(logger, "Could not create transfer {xfid}: {err}");
+
+                    ed.dispatch(types::Event::TransferFinished {
+                        transfer: xfid.to_string(),
+                        data: FinishEvent::TransferFailed {
+                            status: From::from(&err),
+                        },
+                    });
+                    return;
+                }
+            };
 
-            if let Err(e) = inst.cancel(xfid, file.clone().into()).await {
-                error!(
-                    logger,
-                    "Failed to cancel a file with xfid: {}, file: {:?}, error: {:?}",
-                    xfid,
-                    Hidden(&file),
-                    e
-                );
+            debug!(
+                logger,
+                "Created transfer with files:\n{:#?}",
+                xfer.files().values()
+            );
 
-                ed.dispatch(types::Event::TransferFinished {
-                    transfer: xfid.to_string(),
-                    data: FinishEvent::FileFailed {
-                        file,
-                        status: From::from(&e),
-                    },
-                })
+            match instance.lock().await.as_mut() {
+                Some(inst) => inst.send_request_streamed(xfer, feed).await,
+                None => {
+                    error!(logger, "Transfer {xfid} created after the instance stopped");
+
+                    ed.dispatch(types::Event::TransferFinished {
+                        transfer: xfid.to_string(),
+                        data: FinishEvent::TransferFailed {
+                            status: From::from(&drop_transfer::Error::ServiceStop),
+                        },
+                    });
+                }
             }
         });
+
+        Ok(xfid)
     }
 
-    pub(super) fn reject_file(&self, xfid: uuid::Uuid, file: String) -> Result<()> {
+    pub(super) fn new_transfer_tolerant(
+        &mut self,
+        peer: &str,
+        descriptors: &str,
+    ) -> Result<String> {
         trace!(
             self.logger,
-            "norddrop_reject_file() for transfer {xfid}, file {file}",
+            "norddrop_new_transfer_tolerant() to peer {:?}: {:?}",
+            peer,
+            descriptors
         );
 
-        let logger = self.logger.clone();
-        let evdisp = self.event_dispatcher.clone();
+        let (xfid, skipped) = self.create_transfer(peer, descriptors, None, true)?;
 
-        let inst = self.instance.clone().blocking_lock_owned();
+        let report = types::SkippedEvent {
+            transfer: xfid.to_string(),
+            paths: skipped.iter().map(From::from).collect(),
+        };
 
-        if inst.is_none() {
-            return Err(ffi::types::NORDDROP_RES_NOT_STARTED);
-        }
+        serde_json::to_string(&report).map_err(|_| ffi::types::NORDDROP_RES_JSON_PARSE)
+    }
 
-        self.rt.spawn(async move {
-            let inst = inst.as_ref().expect("Instance not initialized");
+    fn create_transfer(
+        &mut self,
+        peer: &str,
+        descriptors: &str,
+        source: Option<IpAddr>,
+        skip_unreadable: bool,
+    ) -> Result<(uuid::Uuid, Vec<Skipped>)> {
+        let descriptors = self.parse_descriptors(descriptors)?;
+        self.create_transfer_from(peer, descriptors, source, skip_unreadable)
+    }
+
+    fn create_transfer_from(
+        &mut self,
+        peer: &str,
+        descriptors: Vec<TransferDescriptor>,
+        source: Option<IpAddr>,
+        skip_unreadable: bool,
+    ) -> Result<(uuid::Uuid, Vec<Skipped>)> {
+        let peer = self.resolve_peer(peer)?;
+
+        let (xfer, skipped) = {
+            let opts = GatherOpts {
+                skip_unreadable,
+                ..Default::default()
+            };
 
-            if let Err(err) = inst.reject(xfid, file.clone().into()).await {
+            let (files, skipped) =
+                prepare_transfer_files(&self.logger, &descriptors, &self.config.drop, &opts)
+                    .map_err(|e| match e {
+                        drop_transfer::Error::InvalidArgument => ffi::types::NORDDROP_RES_BAD_INPUT,
+                        _ => ffi::types::NORDDROP_RES_TRANSFER_CREATE,
+                    })?;
+
+            if files.is_empty() {
                 error!(
-                    logger,
-                    "Failed to reject a file with xfid: {xfid}, file: {file}, error: {err:?}"
+                    self.logger,
+                    "Nothing left to send after skipping {} paths",
+                    skipped.len()
                 );
-
-                evdisp.dispatch(types::Event::TransferFinished {
-                    transfer: xfid.to_string(),
-                    data: FinishEvent::FileFailed {
-                        file,
-                        status: From::from(&err),
-                    },
-                });
+                return Err(ffi::types::NORDDROP_RES_BAD_INPUT);
             }
-        });
 
-        Ok(())
+            let mut xfer = Transfer::new(peer.ip(), files, &self.config.drop).map_err(|e| {
+                error!(
+                    self.logger,
+                    "Could not create transfer ({:?}): {}", descriptors, e
+                );
+
+                ffi::types::NORDDROP_RES_TRANSFER_CREATE
+            })?;
+
+            if let Some(source) = source {
+                xfer.set_source_addr(source);
+            }
+
+            (xfer, skipped)
+        };
+
+        debug!(
+            self.logger,
+            "Created transfer with files:\n{:#?}",
+            xfer.files().values()
+        );
+
+        let xfid = xfer.id();
+
+        if !skipped.is_empty() {
+            self.event_dispatcher
+                .dispatch(types::Event::TransferPathsSkipped(types::SkippedEvent {
+                    transfer: xfid.to_string(),
+                    paths: skipped.iter().map(From::from).collect(),
+                }));
+        }
+
+        self.rt.block_on(async {
+            self.instance
+                .lock()
+                .await
+                .as_mut()
+                .ok_or(ffi::types::NORDDROP_RES_NOT_STARTED)?
+                .send_request(xfer)
+                .await;
+
+            Result::Ok(())
+        })?;
+
+        Ok((xfid, skipped))
+    }
+
+    pub(super) fn new_transfer_multi(&mut self, peers: &str, descriptors: &str) -> Result<String> {
+        trace!(
+            self.logger,
+            "norddrop_new_transfer_multi() to peers {:?}: {:?}",
+            peers,
+            descriptors
+        );
+
+        let peers: Vec<String> = serde_json::from_str(peers).map_err(|e| {
+            error!(self.logger, "Failed to parse peers: {e}");
+            ffi::types::NORDDROP_RES_JSON_PARSE
+        })?;
+        let descriptors = self.parse_descriptors(descriptors)?;
+
+        let mut addrs = Vec::with_capacity(peers.len());
+        for peer in &peers {
+            let ip = self.resolve_peer(peer)?.ip();
+            if addrs.contains(&ip) {
+                error!(self.logger, "Peer {peer:?} is listed more than once");
+                return Err(ffi::types::NORDDROP_RES_BAD_INPUT);
+            }
+            addrs.push(ip);
+        }
+
+        let (files, _) = prepare_transfer_files(
+            &self.logger,
+            &descriptors,
+            &self.config.drop,
+            &GatherOpts::default(),
+        )
+        .map_err(|e| match e {
+            drop_transfer::Error::InvalidArgument => ffi::types::NORDDROP_RES_BAD_INPUT,
+            _ => ffi::types::NORDDROP_RES_TRANSFER_CREATE,
+        })?;
+
+        let xfers = Transfer::new_group(&addrs, files, &self.config.drop).map_err(|e| {
+            error!(
+                self.logger,
+                "Could not create transfers ({:?}): {}", descriptors, e
+            );
+
+            match e {
+                drop_transfer::Error::BadTransfer => ffi::types::NORDDROP_RES_BAD_INPUT,
+                _ => ffi::types::NORDDROP_RES_TRANSFER_CREATE,
+            }
+        })?;
+
+        let group = types::TransferGroup {
+            group: xfers
+                .first()
+                .and_then(Transfer::group)
+                .map(|group| group.to_string())
+                .unwrap_or_default(),
+            transfers: peers
+                .into_iter()
+                .zip(&xfers)
+                .map(|(peer, xfer)| types::GroupMember {
+                    peer,
+                    transfer: xfer.id().to_string(),
+                })
+                .collect(),
+        };
+
+        self.rt.block_on(async {
+            let mut inst = self.instance.lock().await;
+            let inst = inst.as_mut().ok_or(ffi::types::NORDDROP_RES_NOT_STARTED)?;
+
+            for xfer in xfers {
+                inst.send_request(xfer).await;
+            }
+
+            Result::Ok(())
+        })?;
+
+        serde_json::to_string(&group).map_err(|_| ffi::types::NORDDROP_RES_JSON_PARSE)
+    }
+
+    pub(super) fn append_files(&self, xfid: uuid::Uuid, descriptors: &str) -> Result<()> {
+        trace!(
+            self.logger,
+            "norddrop_append_files() for transfer {xfid}: {descriptors:?}",
+        );
+
+        let descriptors = self.parse_descriptors(descriptors)?;
+
+        let (files, _) = prepare_transfer_files(
+            &self.logger,
+            &descriptors,
+            &self.config.drop,
+            &GatherOpts::default(),
+        )
+        .map_err(|e| match e {
+            drop_transfer::Error::InvalidArgument => ffi::types::NORDDROP_RES_BAD_INPUT,
+            _ => ffi::types::NORDDROP_RES_TRANSFER_CREATE,
+        })?;
+
+        self.rt.block_on(async {
+            let inst = self.instance.lock().await;
+            let inst = inst.as_ref().ok_or(ffi::types::NORDDROP_RES_NOT_STARTED)?;
+
+            inst.append_files(xfid, files).await.map_err(|err| {
+                error!(
+                    self.logger,
+                    "Failed to append files to transfer {xfid}: {err}"
+                );
+
+                match err {
+                    drop_transfer::Error::BadTransfer
+                    | drop_transfer::Error::BadTransferState(_)
+                    | drop_transfer::Error::TransferLimitsExceeded => {
+                        ffi::types::NORDDROP_RES_BAD_INPUT
+                    }
+                    _ => ffi::types::NORDDROP_RES_ERROR,
+                }
+            })
+        })
+    }
+
+    pub(super) fn publish(
+        &self,
+        descriptors: &str,
+        lifetime_ms: i64,
+        max_downloads: i32,
+    ) -> Result<String> {
+        trace!(
+            self.logger,
+            "norddrop_publish() for {lifetime_ms} ms, {max_downloads} downloads: {descriptors:?}",
+        );
+
+        if lifetime_ms <= 0 {
+            return Err(ffi::types::NORDDROP_RES_BAD_INPUT);
+        }
+
+        let descriptors = self.parse_descriptors(descriptors)?;
+
+        let (files, _) = prepare_transfer_files(
+            &self.logger,
+            &descriptors,
+            &self.config.drop,
+            &GatherOpts::default(),
+        )
+        .map_err(|e| match e {
+            drop_transfer::Error::InvalidArgument => ffi::types::NORDDROP_RES_BAD_INPUT,
+            _ => ffi::types::NORDDROP_RES_TRANSFER_CREATE,
+        })?;
+
+        let lifetime = Duration::from_millis(lifetime_ms as _);
+        let max_downloads = u32::try_from(max_downloads).ok().filter(|&max| max > 0);
+
+        self.rt.block_on(async {
+            let inst = self.instance.lock().await;
+            let inst = inst.as_ref().ok_or(ffi::types::NORDDROP_RES_NOT_STARTED)?;
+
+            inst.publish(files, lifetime, max_downloads)
+                .await
+                .map_err(|err| {
+                    error!(self.logger, "Failed to publish files: {err}");
+                    ffi::types::NORDDROP_RES_BAD_INPUT
+                })
+        })
+    }
+
+    pub(super) fn unpublish(&self, token: &str) -> Result<()> {
+        trace!(self.logger, "norddrop_unpublish() for {token:?}");
+
+        self.rt.block_on(async {
+            let inst = self.instance.lock().await;
+            let inst = inst.as_ref().ok_or(ffi::types::NORDDROP_RES_NOT_STARTED)?;
+
+            inst.unpublish(token).await.map_err(|err| {
+                error!(self.logger, "Failed to unpublish {token:?}: {err}");
+                ffi::types::NORDDROP_RES_BAD_INPUT
+            })
+        })
+    }
+
+    pub(super) fn pull(&self, peer: &str, token: &str) -> Result<uuid::Uuid> {
+        trace!(self.logger, "norddrop_pull() from peer {peer:?}: {token:?}");
+
+        let peer = self.resolve_peer(peer)?;
+
+        self.rt.block_on(async {
+            let inst = self.instance.lock().await;
+            let inst = inst.as_ref().ok_or(ffi::types::NORDDROP_RES_NOT_STARTED)?;
+
+            inst.pull(peer.ip(), token).await.map_err(|err| {
+                error!(self.logger, "Failed to pull {token:?} from {peer}: {err}");
+
+                match err {
+                    drop_transfer::Error::ShareUnavailable => ffi::types::NORDDROP_RES_BAD_INPUT,
+                    _ => ffi::types::NORDDROP_RES_ERROR,
+                }
+            })
+        })
+    }
+
+    pub(super) fn export_dir(&self, name: &str, path: &str) -> Result<()> {
+        trace!(self.logger, "norddrop_export_dir() {name:?}: {path:?}");
+
+        self.rt.block_on(async {
+            let inst = self.instance.lock().await;
+            let inst = inst.as_ref().ok_or(ffi::types::NORDDROP_RES_NOT_STARTED)?;
+
+            inst.export_dir(name.to_string(), path.as_ref())
+                .await
+                .map_err(|err| {
+                    error!(self.logger, "Failed to export {path:?}: {err}");
+                    ffi::types::NORDDROP_RES_BAD_INPUT
+                })
+        })
+    }
+
+    pub(super) fn unexport_dir(&self, name: &str) -> Result<()> {
+        trace!(self.logger, "norddrop_unexport_dir() {name:?}");
+
+        self.rt.block_on(async {
+            let inst = self.instance.lock().await;
+            let inst = inst.as_ref().ok_or(ffi::types::NORDDROP_RES_NOT_STARTED)?;
+
+            inst.unexport_dir(name).await.map_err(|err| {
+                error!(self.logger, "Failed to unexport {name:?}: {err}");
+                ffi::types::NORDDROP_RES_BAD_INPUT
+            })
+        })
+    }
+
+    pub(super) fn request_files(
+        &self,
+        peer: &str,
+        export: &str,
+        paths: &str,
+    ) -> Result<uuid::Uuid> {
+        trace!(
+            self.logger,
+            "norddrop_request_files() from peer {peer:?}, {export:?}: {paths:?}"
+        );
+
+        let paths: Vec<String> = serde_json::from_str(paths).map_err(|e| {
+            error!(self.logger, "Failed to parse paths: {e}");
+            ffi::types::NORDDROP_RES_JSON_PARSE
+        })?;
+        let peer = self.resolve_peer(peer)?;
+
+        self.rt.block_on(async {
+            let inst = self.instance.lock().await;
+            let inst = inst.as_ref().ok_or(ffi::types::NORDDROP_RES_NOT_STARTED)?;
+
+            inst.request_files(peer.ip(), export.to_string(), paths)
+                .await
+                .map_err(|err| {
+                    error!(self.logger, "Failed to request files from {peer}: {err}");
+
+                    match err {
+                        drop_transfer::Error::BadPath(_) => ffi::types::NORDDROP_RES_BAD_INPUT,
+                        _ => ffi::types::NORDDROP_RES_ERROR,
+                    }
+                })
+        })
+    }
+
+    pub(super) fn approve_file_request(&self, request_id: uuid::Uuid) -> Result<()> {
+        trace!(self.logger, "norddrop_approve_file_request() {request_id}");
+
+        self.rt.block_on(async {
+            let inst = self.instance.lock().await;
+            let inst = inst.as_ref().ok_or(ffi::types::NORDDROP_RES_NOT_STARTED)?;
+
+            inst.approve_file_request(request_id).await.map_err(|err| {
+                error!(
+                    self.logger,
+                    "Failed to approve file request {request_id}: {err}"
+                );
+
+                match err {
+                    drop_transfer::Error::InvalidArgument => ffi::types::NORDDROP_RES_BAD_INPUT,
+                    _ => ffi::types::NORDDROP_RES_TRANSFER_CREATE,
+                }
+            })
+        })
+    }
+
+    pub(super) fn deny_file_request(&self, request_id: uuid::Uuid) -> Result<()> {
+        trace!(self.logger, "norddrop_deny_file_request() {request_id}");
+
+        self.rt.block_on(async {
+            let inst = self.instance.lock().await;
+            let inst = inst.as_ref().ok_or(ffi::types::NORDDROP_RES_NOT_STARTED)?;
+
+            inst.deny_file_request(request_id).await.map_err(|err| {
+                error!(
+                    self.logger,
+                    "Failed to deny file request {request_id}: {err}"
+                );
+                ffi::types::NORDDROP_RES_BAD_INPUT
+            })
+        })
+    }
+
+    pub(super) fn start_pairing(&self, lifetime_ms: i64) -> Result<String> {
+        trace!(self.logger, "norddrop_start_pairing() for {lifetime_ms} ms");
+
+        if lifetime_ms <= 0 {
+            return Err(ffi::types::NORDDROP_RES_BAD_INPUT);
+        }
+
+        self.rt.block_on(async {
+            let inst = self.instance.lock().await;
+            let inst = inst.as_ref().ok_or(ffi::types::NORDDROP_RES_NOT_STARTED)?;
+
+            Ok(inst
+                .start_pairing(Duration::from_millis(lifetime_ms as _))
+                .await)
+        })
+    }
+
+    pub(super) fn cancel_pairing(&self) -> Result<()> {
+        trace!(self.logger, "norddrop_cancel_pairing()");
+
+        self.rt.block_on(async {
+            let inst = self.instance.lock().await;
+            let inst = inst.as_ref().ok_or(ffi::types::NORDDROP_RES_NOT_STARTED)?;
+
+            inst.cancel_pairing().await.map_err(|err| {
+                error!(self.logger, "Failed to cancel pairing: {err}");
+                ffi::types::NORDDROP_RES_BAD_INPUT
+            })
+        })
+    }
+
+    pub(super) fn pair(&self, peer: &str, code: &str) -> Result<()> {
+        trace!(self.logger, "norddrop_pair() with peer {peer:?}");
+
+        if code.trim().is_empty() {
+            return Err(ffi::types::NORDDROP_RES_BAD_INPUT);
+        }
+
+        let peer = self.resolve_peer(peer)?;
+
+        self.rt.block_on(async {
+            let inst = self.instance.lock().await;
+            let inst = inst.as_ref().ok_or(ffi::types::NORDDROP_RES_NOT_STARTED)?;
+
+            inst.pair(peer.ip(), code.to_string());
+            Ok(())
+        })
+    }
+
+    pub(super) fn reject_transfer(
+        &self,
+        xfid: uuid::Uuid,
+        reason: Option<drop_transfer::Reason>,
+    ) -> Result<()> {
+        trace!(self.logger, "norddrop_reject_transfer() {xfid}");
+
+        self.rt.block_on(async {
+            let inst = self.instance.lock().await;
+            let inst = inst.as_ref().ok_or(ffi::types::NORDDROP_RES_NOT_STARTED)?;
+
+            inst.reject_transfer(xfid, reason).await.map_err(|err| {
+                error!(self.logger, "Failed to reject transfer {xfid}: {err}");
+                ffi::types::NORDDROP_RES_BAD_INPUT
+            })
+        })
+    }
+
+    pub(super) fn cancel_files(&self, xfid: uuid::Uuid, files: &str) -> Result<String> {
+        let files = self.parse_file_ids(files)?;
+        trace!(
+            self.logger,
+            "norddrop_cancel_files() for transfer {xfid}, {} files",
+            files.len()
+        );
+
+        let results = self.rt.block_on(async {
+            let mut inst = self.instance.lock().await;
+            let inst = inst.as_mut().ok_or(ffi::types::NORDDROP_RES_NOT_STARTED)?;
+
+            let ids = files.iter().map(|file| file.as_str().into()).collect();
+            Result::Ok(inst.cancel_files(xfid, ids).await)
+        })?;
+
+        self.file_results("cancel", xfid, files, results)
+    }
+
+    pub(super) fn reject_files(&self, xfid: uuid::Uuid, files: &str) -> Result<String> {
+        let files = self.parse_file_ids(files)?;
+        trace!(
+            self.logger,
+            "norddrop_reject_files() for transfer {xfid}, {} files",
+            files.len()
+        );
+
+        let results = self.rt.block_on(async {
+            let inst = self.instance.lock().await;
+            let inst = inst.as_ref().ok_or(ffi::types::NORDDROP_RES_NOT_STARTED)?;
+
+            let ids = files.iter().map(|file| file.as_str().into()).collect();
+            Result::Ok(inst.reject_files(xfid, ids).await)
+        })?;
+
+        self.file_results("reject", xfid, files, results)
+    }
+
+    pub(super) fn download_files(
+        &self,
+        xfid: uuid::Uuid,
+        files: &str,
+        dst: &str,
+    ) -> Result<String> {
+        let files = self.parse_file_ids(files)?;
+        trace!(
+            self.logger,
+            "norddrop_download_files() for transfer {xfid}, {} files, to {:?}",
+            files.len(),
+            Hidden(dst)
+        );
+
+        let results = self.rt.block_on(async {
+            let mut inst = self.instance.lock().await;
+            let inst = inst.as_mut().ok_or(ffi::types::NORDDROP_RES_NOT_STARTED)?;
+
+            let ids: Vec<_> = files.iter().map(|file| file.as_str().into()).collect();
+            Result::Ok(inst.download_files(xfid, &ids, dst.as_ref()).await)
+        })?;
+
+        self.file_results("download", xfid, files, results)
+    }
+
+    pub(super) fn file_progress(
+        &self,
+        xfid: uuid::Uuid,
+        file: &str,
+    ) -> Result<ffi::typed::norddrop_file_progress> {
+        trace!(
+            self.logger,
+            "norddrop_get_file_progress() for transfer {xfid}, file {file}"
+        );
+
+        let transfers = self.rt.block_on(async {
+            let inst = self.instance.lock().await;
+            let inst = inst.as_ref().ok_or(ffi::types::NORDDROP_RES_NOT_STARTED)?;
+
+            Result::Ok(inst.active_transfers().await)
+        })?;
+
+        let active = transfers
+            .into_iter()
+            .find(|active| active.transfer.id() == xfid)
+            .ok_or(ffi::types::NORDDROP_RES_BAD_TRANSFER)?;
+
+        let file_id = drop_transfer::FileId::from(file);
+        let size = active
+            .transfer
+            .files()
+            .get(&file_id)
+            .ok_or(ffi::types::NORDDROP_RES_BAD_FILE)?
+            .size();
+        let progress = active
+            .files
+            .get(&file_id)
+            .ok_or(ffi::types::NORDDROP_RES_BAD_FILE)?;
+
+        Ok(ffi::typed::norddrop_file_progress {
+            state: progress.state.into(),
+            bytes: progress.bytes,
+            size,
+        })
+    }
+
+    pub(super) fn get_active_transfers(&self) -> Result<String> {
+        trace!(self.logger, "norddrop_get_active_transfers()");
+
+        let transfers = self.rt.block_on(async {
+            let inst = self.instance.lock().await;
+            let inst = inst.as_ref().ok_or(ffi::types::NORDDROP_RES_NOT_STARTED)?;
+
+            Result::Ok(inst.active_transfers().await)
+        })?;
+
+        let transfers: Vec<types::ActiveTransfer> = transfers.into_iter().map(From::from).collect();
+
+        serde_json::to_string(&transfers).map_err(|_| ffi::types::NORDDROP_RES_JSON_PARSE)
+    }
+
+    pub(super) fn peer_identity(&self, addr: &str) -> Result<Option<String>> {
+        trace!(
+            self.logger,
+            "norddrop_get_peer_identity() address: {:?}",
+            addr
+        );
+
+        let addr = self.parse_ip(addr)?;
+
+        self.rt.block_on(async {
+            let inst = self.instance.lock().await;
+            let inst = inst.as_ref().ok_or(ffi::types::NORDDROP_RES_NOT_STARTED)?;
+
+            inst.peer_identity(addr)
+                .map_err(|_| ffi::types::NORDDROP_RES_DB_ERROR)
+        })
+    }
+
+    pub(super) fn peer_addresses(&self, identity: &str) -> Result<String> {
+        trace!(
+            self.logger,
+            "norddrop_get_peer_addresses() identity: {:?}",
+            identity
+        );
+
+        let addresses = self.rt.block_on(async {
+            let inst = self.instance.lock().await;
+            let inst = inst.as_ref().ok_or(ffi::types::NORDDROP_RES_NOT_STARTED)?;
+
+            inst.peer_addresses(identity)
+                .map_err(|_| ffi::types::NORDDROP_RES_DB_ERROR)
+        })?;
+
+        serde_json::to_string(&addresses).map_err(|_| ffi::types::NORDDROP_RES_JSON_PARSE)
+    }
+
+    fn parse_file_ids(&self, files: &str) -> Result<Vec<String>> {
+        serde_json::from_str(files).map_err(|e| {
+            error!(self.logger, "Failed to parse file IDs: {e}");
+            ffi::types::NORDDROP_RES_JSON_PARSE
+        })
+    }
+
+    fn file_results(
+        &self,
+        op: &str,
+        xfid: uuid::Uuid,
+        files: Vec<String>,
+        results: Vec<drop_transfer::Result<()>>,
+    ) -> Result<String> {
+        let results: Vec<_> = files
+            .into_iter()
+            .zip(results)
+            .map(|(file, res)| {
+                let status = res.err().map(|err| {
+                    error!(
+                        self.logger,
+                        "Failed to {op} a file with xfid: {xfid}, file: {:?}, error: {err:?}",
+                        Hidden(&file)
+                    );
+                    types::Status::from(&err)
+                });
+
+                types::FileResult { file, status }
+            })
+            .collect();
+
+        serde_json::to_string(&results).map_err(|_| ffi::types::NORDDROP_RES_JSON_PARSE)
+    }
+
+    fn parse_descriptors(&self, descriptors: &str) -> Result<Vec<TransferDescriptor>> {
+        serde_json::from_str(descriptors).map_err(|e| {
+            error!(
+                self.logger,
+                "Failed to parse new_transfer() descriptors: {}", e
+            );
+            ffi::types::NORDDROP_RES_JSON_PARSE
+        })
+    }
+
+    fn resolve_peer(&self, peer: &str) -> Result<SocketAddr> {
+        (peer, drop_config::PORT)
+            .to_socket_addrs()
+            .map_err(|err| {
+                error!(self.logger, "Failed to perform lookup of address: {err}");
+                ffi::types::NORDDROP_RES_BAD_INPUT
+            })?
+            .next()
+            .ok_or(ffi::types::NORDDROP_RES_BAD_INPUT)
+    }
+
+    pub(super) fn download(
+        &mut self,
+        xfid: uuid::Uuid,
+        file_id: String,
+        dst: String,
+    ) -> Result<()> {
+        trace!(
+            self.logger,
+            "norddrop_download() for transfer {:?}, file {:?}, to {:?}",
+            xfid,
+            file_id,
+            dst
+        );
+
+        self.rt.block_on(async {
+            let mut inst = self.instance.lock().await;
+            let inst = inst.as_mut().ok_or(ffi::types::NORDDROP_RES_NOT_STARTED)?;
+
+            inst.download(xfid, &file_id.clone().into(), dst.as_ref())
+                .await
+                .map_err(|e| {
+                    error!(
+                        self.logger,
+                        "Failed to download a file with xfid: {}, file: {:?}, dst: {:?}, error: \
+                         {:?}",
+                        xfid,
+                        Hidden(&file_id),
+                        Hidden(&dst),
+                        e
+                    );
+
+                    ffi::types::norddrop_result::from(&e)
+                })
+        })
+    }
+
+    pub(super) fn cancel_transfer(&mut self, xfid: uuid::Uuid) -> Result<()> {
+        trace!(self.logger, "norddrop_cancel_transfer() for {:?}", xfid);
+
+        self.rt.block_on(async {
+            let mut inst = self.instance.lock().await;
+            let inst = inst.as_mut().ok_or(ffi::types::NORDDROP_RES_NOT_STARTED)?;
+
+            inst.cancel_all(xfid).await.map_err(|e| {
+                error!(
+                    self.logger,
+                    "Failed to cancel a transfer with xfid: {:?}, error: {:?}", xfid, e
+                );
+
+                ffi::types::norddrop_result::from(&e)
+            })
+        })
+    }
+
+    pub(super) fn cancel_file(
+        &mut self,
+        xfid: uuid::Uuid,
+        file: String,
+        reason: Option<drop_transfer::Reason>,
+    ) -> Result<()> {
+        trace!(
+            self.logger,
+            "norddrop_cancel_file() for transfer {:?}, file {:?}",
+            xfid,
+            file
+        );
+
+        self.rt.block_on(async {
+            let mut inst = self.instance.lock().await;
+            let inst = inst.as_mut().ok_or(ffi::types::NORDDROP_RES_NOT_STARTED)?;
+
+            inst.cancel(xfid, file.clone().into(), reason)
+                .await
+                .map_err(|e| {
+                    error!(
+                        self.logger,
+                        "Failed to cancel a file with xfid: {}, file: {:?}, error: {:?}",
+                        xfid,
+                        Hidden(&file),
+                        e
+                    );
+
+                    ffi::types::norddrop_result::from(&e)
+                })
+        })
+    }
+
+    pub(super) fn reject_file(
+        &self,
+        xfid: uuid::Uuid,
+        file: String,
+        reason: Option<drop_transfer::Reason>,
+    ) -> Result<()> {
+        trace!(
+            self.logger,
+            "norddrop_reject_file() for transfer {xfid}, file {file}",
+        );
+
+        self.rt.block_on(async {
+            let inst = self.instance.lock().await;
+            let inst = inst.as_ref().ok_or(ffi::types::NORDDROP_RES_NOT_STARTED)?;
+
+            inst.reject(xfid, file.clone().into(), reason)
+                .await
+                .map_err(|err| {
+                    error!(
+                        self.logger,
+                        "Failed to reject a file with xfid: {xfid}, file: {file}, error: {err:?}"
+                    );
+
+                    ffi::types::norddrop_result::from(&err)
+                })
+        })
     }
 }
 
@@ -568,15 +1603,62 @@ fn crate_key_context(
     auth::Context::new(privkey, public)
 }
 
+/// The transfers created in the background skip what cannot be read, the app
+/// learns about it from the skipped paths event
+fn background_gather_opts() -> GatherOpts {
+    GatherOpts {
+        skip_unreadable: true,
+        defer_mime: true,
+        ..Default::default()
+    }
+}
+
 fn prepare_transfer_files(
     logger: &slog::Logger,
     descriptors: &[TransferDescriptor],
     config: &DropConfig,
-) -> Result<Vec<File>> {
+    base_opts: &GatherOpts,
+) -> drop_transfer::Result<(Vec<File>, Vec<Skipped>)> {
     let mut files = Vec::new();
 
+    let skipped = scan_transfer_files(
+        logger,
+        descriptors,
+        config,
+        base_opts,
+        &mut |_| (),
+        &mut |file| {
+            if files.len() >= config.transfer_file_limit {
+                return Err(drop_transfer::Error::TransferLimitsExceeded);
+            }
+
+            files.push(file);
+            Ok(())
+        },
+    )?;
+
+    Ok((files, skipped))
+}
+
+/// Pass the files to the sink as they are found. The number of files is not
+/// limited here
+fn scan_transfer_files(
+    logger: &slog::Logger,
+    descriptors: &[TransferDescriptor],
+    config: &DropConfig,
+    base_opts: &GatherOpts,
+    progress: &mut dyn FnMut(&File),
+    sink: &mut dyn FnMut(File) -> drop_transfer::Result<()>,
+) -> drop_transfer::Result<Vec<Skipped>> {
+    let mut skipped = Vec::new();
+
     #[allow(unused_variables)]
     for (i, desc) in descriptors.iter().enumerate() {
+        if desc.archive && desc.fd.is_some() {
+            error!(logger, "Only directories can be sent as archives: {desc:?}");
+            return Err(drop_transfer::Error::InvalidArgument);
+        }
+
         if let Some(fd) = desc.fd {
             #[cfg(target_os = "windows")]
             {
@@ -584,35 +1666,75 @@ fn prepare_transfer_files(
                     logger,
                     "Specifying file descriptors in transfers is not supported under Windows"
                 );
-                return Err(ffi::types::NORDDROP_RES_BAD_INPUT);
+                return Err(drop_transfer::Error::InvalidArgument);
             }
 
             #[cfg(not(target_os = "windows"))]
             {
-                let file = File::from_fd(&desc.path.0, fd, i).map_err(|e| {
-                    error!(
-                        logger,
-                        "Could not open file {desc:?} for transfer ({descriptors:?}): {e}",
-                    );
-                    ffi::types::NORDDROP_RES_TRANSFER_CREATE
-                })?;
+                match File::from_fd(&desc.path.0, fd, i) {
+                    Ok(file) => {
+                        progress(&file);
+                        sink(file)?;
+                    }
+                    Err(e) if base_opts.skip_unreadable => {
+                        warn!(logger, "Skipping file {desc:?}: {e}");
 
-                files.push(file);
+                        skipped.push(Skipped {
+                            path: Hidden(desc.path.0.clone().into()),
+                            reason: SkipReason::Unreadable(e),
+                        });
+                    }
+                    Err(e) => {
+                        error!(
+                            logger,
+                            "Could not open file {desc:?} for transfer ({descriptors:?}): {e}",
+                        );
+                        return Err(e);
+                    }
+                }
             }
         } else {
-            let batch = File::from_path(&desc.path.0, config).map_err(|e| {
+            let opts = GatherOpts {
+                symlinks: desc.symlinks.into(),
+                include: desc.include.clone(),
+                exclude: desc.exclude.clone(),
+                skip_hidden: desc.skip_hidden,
+                ignore_file: desc.ignore_file.clone(),
+                ..base_opts.clone()
+            };
+
+            let res = if desc.archive {
+                File::archive_from_path(
+                    &desc.path.0,
+                    config,
+                    &opts,
+                    desc.compression.into(),
+                    progress,
+                )
+                .and_then(|batch| {
+                    batch.files.into_iter().try_for_each(&mut *sink)?;
+                    Ok(batch.skipped)
+                })
+            } else {
+                File::gather_each(&desc.path.0, config, &opts, &mut |file| {
+                    progress(&file);
+                    sink(file)
+                })
+            };
+
+            let batch_skipped = res.map_err(|e| {
                 error!(
                     logger,
                     "Could not open file {desc:?} for transfer ({descriptors:?}): {e}",
                 );
-                ffi::types::NORDDROP_RES_TRANSFER_CREATE
+                e
             })?;
 
-            files.extend(batch);
+            skipped.extend(batch_skipped);
         }
     }
 
-    Ok(files)
+    Ok(skipped)
 }
 
 fn open_database(
@@ -685,3 +1807,43 @@ fn open_database(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn background_scan_skips_unreadable() {
+        let dir = tempfile::tempdir().expect("Failed to create tmp dir");
+        std::fs::write(dir.path().join("a.txt"), b"a").expect("Failed to create file");
+        std::os::unix::fs::symlink(dir.path().join("missing"), dir.path().join("dangling"))
+            .expect("Failed to create symlink");
+
+        let descriptors: Vec<TransferDescriptor> = serde_json::from_value(serde_json::json!([{
+            "path": dir.path(),
+            "symlinks": "follow",
+        }]))
+        .expect("Failed to parse descriptors");
+
+        let logger = slog::Logger::root(slog::Discard, slog::o!());
+        let mut found = Vec::new();
+
+        let skipped = scan_transfer_files(
+            &logger,
+            &descriptors,
+            &DropConfig::default(),
+            &background_gather_opts(),
+            &mut |_| (),
+            &mut |file| {
+                found.push(file);
+                Ok(())
+            },
+        )
+        .expect("The dangling link should be skipped");
+
+        assert_eq!(found.len(), 1);
+        assert_eq!(skipped.len(), 1);
+        assert!(matches!(skipped[0].reason, SkipReason::Unreadable(_)));
+    }
+}
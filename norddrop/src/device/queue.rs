@@ -0,0 +1,155 @@
+use std::{
+    collections::VecDeque,
+    sync::{Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+use super::types;
+
+enum Entry {
+    Event(String),
+    // Number of events dropped at this position because the queue was full
+    Overflow { count: u64, version: u32 },
+}
+
+/// Bounded queue of serialized events, used instead of the callback when the
+/// host app polls for the events. The events are kept in the emission order
+pub(crate) struct EventQueue {
+    entries: Mutex<VecDeque<Entry>>,
+    available: Condvar,
+    capacity: usize,
+}
+
+impl EventQueue {
+    pub(super) fn new(capacity: usize) -> Self {
+        Self {
+            entries: Mutex::new(VecDeque::with_capacity(capacity)),
+            available: Condvar::new(),
+            capacity,
+        }
+    }
+
+    pub(super) fn push(&self, event: String, version: u32) {
+        let mut entries = self.lock();
+
+        if entries.len() >= self.capacity {
+            // The marker may exceed the capacity by one so that the app learns
+            // where the events were lost
+            match entries.back_mut() {
+                Some(Entry::Overflow { count, .. }) => *count += 1,
+                _ => entries.push_back(Entry::Overflow { count: 1, version }),
+            }
+        } else {
+            entries.push_back(Entry::Event(event));
+        }
+
+        drop(entries);
+        self.available.notify_one();
+    }
+
+    /// Wait for the next event up to the given timeout. Returns `None` if no
+    /// event arrived in time. A timeout too large to be represented waits
+    /// forever
+    pub(crate) fn pop(&self, timeout: Duration) -> Option<String> {
+        let deadline = Instant::now().checked_add(timeout);
+        let mut entries = self.lock();
+
+        loop {
+            if let Some(entry) = entries.pop_front() {
+                return Some(match entry {
+                    Entry::Event(event) => event,
+                    Entry::Overflow { count, version } => serialize_overflow(count, version),
+                });
+            }
+
+            entries = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return None;
+                    }
+
+                    self.available
+                        .wait_timeout(entries, deadline - now)
+                        .expect("Event queue lock poisoned")
+                        .0
+                }
+                None => self
+                    .available
+                    .wait(entries)
+                    .expect("Event queue lock poisoned"),
+            };
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, VecDeque<Entry>> {
+        self.entries.lock().expect("Event queue lock poisoned")
+    }
+}
+
+fn serialize_overflow(count: u64, version: u32) -> String {
+    types::Event::EventsDropped(types::EventsDroppedEvent { count })
+        .to_versioned_json(version)
+        .unwrap_or_else(|_| String::from("event_to_json error"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overflow_keeps_order() {
+        let queue = EventQueue::new(2);
+
+        for i in 0..5 {
+            queue.push(i.to_string(), types::EVENT_VERSION);
+        }
+
+        let timeout = Duration::ZERO;
+        assert_eq!(queue.pop(timeout).as_deref(), Some("0"));
+        assert_eq!(queue.pop(timeout).as_deref(), Some("1"));
+
+        queue.push("5".into(), types::EVENT_VERSION);
+
+        let overflow: serde_json::Value =
+            serde_json::from_str(&queue.pop(timeout).unwrap()).unwrap();
+        assert_eq!(
+            overflow,
+            serde_json::json!({
+                "version": types::EVENT_VERSION,
+                "type": "EventsDropped",
+                "data": { "count": 3 },
+            })
+        );
+
+        assert_eq!(queue.pop(timeout).as_deref(), Some("5"));
+        assert_eq!(queue.pop(timeout), None);
+    }
+
+    #[test]
+    fn pop_waits_for_event() {
+        let queue = std::sync::Arc::new(EventQueue::new(4));
+
+        let handle = {
+            let queue = queue.clone();
+            std::thread::spawn(move || queue.pop(Duration::from_secs(10)))
+        };
+
+        queue.push("event".into(), types::EVENT_VERSION);
+        assert_eq!(handle.join().unwrap().as_deref(), Some("event"));
+    }
+
+    #[test]
+    fn pop_waits_forever_on_huge_timeout() {
+        let queue = std::sync::Arc::new(EventQueue::new(4));
+
+        let handle = {
+            let queue = queue.clone();
+            std::thread::spawn(move || queue.pop(Duration::MAX))
+        };
+
+        std::thread::sleep(Duration::from_millis(50));
+        queue.push("event".into(), types::EVENT_VERSION);
+        assert_eq!(handle.join().unwrap().as_deref(), Some("event"));
+    }
+}
@@ -1,66 +1,284 @@
-use std::time::Duration;
+use std::{net::IpAddr, time::Duration};
 
 use drop_transfer::utils::Hidden;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 #[derive(Deserialize, Debug)]
 pub struct TransferDescriptor {
     pub path: Hidden<String>,
     pub fd: Option<i32>,
+    #[serde(default)]
+    pub symlinks: SymlinkPolicy,
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    #[serde(default)]
+    pub skip_hidden: bool,
+    pub ignore_file: Option<String>,
+    #[serde(default)]
+    pub archive: bool,
+    #[serde(default)]
+    pub compression: Compression,
 }
 
-#[derive(Serialize)]
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SymlinkPolicy {
+    #[default]
+    Skip,
+    Follow,
+    Preserve,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    #[default]
+    None,
+    Deflate,
+}
+
+#[derive(Serialize, JsonSchema)]
 pub struct EventTransferRequest {
     peer: String,
+    // Identity derived from the public key of the authenticated peer
+    #[serde(skip_serializing_if = "Option::is_none")]
+    identity: Option<String>,
     transfer: String,
     files: Vec<File>,
+    // Listen address the request arrived on
+    local_addr: String,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 pub struct EventRequestQueued {
+    transfer: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    identity: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    group: Option<String>,
+    files: Vec<File>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct EventRequestAppend {
     transfer: String,
     files: Vec<File>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 pub struct EventTransfer {
     transfer: String,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum FileKind {
+    File,
+    Dir,
+    Symlink,
+    Archive,
+}
+
+#[derive(Serialize, JsonSchema)]
 struct File {
     id: String,
     path: String,
     size: u64,
+    #[serde(skip_serializing_if = "FileKind::is_file")]
+    kind: FileKind,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 pub struct StartEvent {
     transfer: String,
     file: String,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 pub struct CancelEvent {
     transfer: String,
     files: Vec<String>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 pub struct ProgressEvent {
     transfer: String,
     file: String,
     transfered: u64,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
+pub struct ArchiveEntryEvent {
+    transfer: String,
+    file: String,
+    path: String,
+    size: u64,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum SkipReason {
+    Symlink,
+    SymlinkLoop,
+    AbsoluteSymlink,
+    Unreadable {
+        #[serde(flatten)]
+        status: Status,
+    },
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct SkippedPath {
+    path: String,
+    #[serde(flatten)]
+    reason: SkipReason,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct SkippedEvent {
+    pub transfer: String,
+    pub paths: Vec<SkippedPath>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct TransferGroup {
+    pub group: String,
+    pub transfers: Vec<GroupMember>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct GroupMember {
+    pub peer: String,
+    pub transfer: String,
+}
+
+/// Outcome of a bulk file operation, the status is missing on success
+#[derive(Serialize, JsonSchema)]
+pub struct FileResult {
+    pub file: String,
+    #[serde(flatten)]
+    pub status: Option<Status>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct ActiveTransfer {
+    transfer: String,
+    peer: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    identity: Option<String>,
+    direction: Direction,
+    state: ConnectionState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+    files: Vec<ActiveFile>,
+    rejected: Vec<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+enum Direction {
+    Incoming,
+    Outgoing,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+enum ConnectionState {
+    Connecting,
+    Connected,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct ActiveFile {
+    #[serde(flatten)]
+    file: File,
+    state: FileState,
+    bytes: u64,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+enum FileState {
+    Pending,
+    InProgress,
+    Completed,
+    Failed,
+    Canceled,
+    Rejected,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct FileRequestEvent {
+    pub request: String,
+    pub peer: String,
+    pub export: String,
+    pub paths: Vec<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct FileRequestDeniedEvent {
+    pub request: String,
+    pub peer: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct PairingEvent {
+    pub peer: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct PairingCompletedEvent {
+    pub peer: String,
+    pub identity: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct PairingFailedEvent {
+    pub peer: String,
+    #[serde(flatten)]
+    pub status: Status,
+}
+
+/// Reported in the event queue in place of the events dropped because the
+/// queue was full
+#[derive(Serialize, JsonSchema)]
+pub struct EventsDroppedEvent {
+    pub count: u64,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct ScanProgressEvent {
+    pub transfer: String,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+#[derive(Serialize, JsonSchema)]
 pub struct Status {
     status: u32,
     #[serde(skip_serializing_if = "Option::is_none")]
     os_error_code: Option<i32>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
+pub struct Reason {
+    reason_code: u32,
+    reason: String,
+}
+
+impl From<drop_transfer::Reason> for Reason {
+    fn from(value: drop_transfer::Reason) -> Self {
+        Self {
+            reason_code: value.code,
+            reason: value.message,
+        }
+    }
+}
+
+#[derive(Serialize, JsonSchema)]
 #[serde(tag = "reason", content = "data")]
 pub enum FinishEvent {
     TransferCanceled {
@@ -76,6 +294,8 @@ pub enum FinishEvent {
     FileCanceled {
         file: String,
         by_peer: bool,
+        #[serde(flatten)]
+        reason: Option<Reason>,
     },
     FileFailed {
         file: String,
@@ -89,24 +309,117 @@ pub enum FinishEvent {
     FileRejected {
         file: String,
         by_peer: bool,
+        #[serde(flatten)]
+        reason: Option<Reason>,
+    },
+    TransferExpired,
+    TransferRejected {
+        by_peer: bool,
+        #[serde(flatten)]
+        reason: Option<Reason>,
     },
 }
 
-#[derive(serde::Serialize)]
+#[derive(Serialize, JsonSchema)]
 #[serde(tag = "type", content = "data")]
 pub enum Event {
     RequestReceived(EventTransferRequest),
     RequestQueued(EventRequestQueued),
+    RequestAppendReceived(EventRequestAppend),
+    RequestAppendQueued(EventRequestAppend),
     TransferStarted(StartEvent),
     TransferProgress(ProgressEvent),
+    TransferArchiveEntry(ArchiveEntryEvent),
     TransferFinished {
         transfer: String,
         #[serde(flatten)]
         data: FinishEvent,
     },
     RuntimeError {
+        #[schemars(with = "u32")]
         status: drop_core::Status,
     },
+    TransferPathsSkipped(SkippedEvent),
+    TransferScanProgress(ScanProgressEvent),
+    FileRequestReceived(FileRequestEvent),
+    FileRequestDenied(FileRequestDeniedEvent),
+    PairingStarted(PairingEvent),
+    PairingRequested(PairingEvent),
+    PairingCompleted(PairingCompletedEvent),
+    PairingFailed(PairingFailedEvent),
+    EventsDropped(EventsDroppedEvent),
+}
+
+/// Version of the event format, bumped on every change of the shape
+pub const EVENT_VERSION: u32 = 3;
+/// The oldest event format version the apps can opt into
+pub const EVENT_VERSION_MIN: u32 = 1;
+
+/// Conversions of the serialized event into the previous format version. The
+/// first one converts `EVENT_VERSION_MIN + 1` into `EVENT_VERSION_MIN`, each
+/// version bump appends a new one
+const DOWNGRADES: &[fn(&mut serde_json::Value)] = &[remove_local_addr, remove_identity];
+
+const _: () = assert!(DOWNGRADES.len() == (EVENT_VERSION - EVENT_VERSION_MIN) as usize);
+
+#[derive(Serialize, JsonSchema)]
+pub struct VersionedEvent<'a> {
+    version: u32,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+impl Event {
+    /// Serialize the event in the format of the given version
+    pub fn to_versioned_json(&self, version: u32) -> serde_json::Result<String> {
+        debug_assert!((EVENT_VERSION_MIN..=EVENT_VERSION).contains(&version));
+
+        let event = VersionedEvent {
+            version,
+            event: self,
+        };
+
+        if version >= EVENT_VERSION {
+            return serde_json::to_string(&event);
+        }
+
+        // Walk back from the current format one version at a time
+        let mut value = serde_json::to_value(&event)?;
+        let skip = version.saturating_sub(EVENT_VERSION_MIN) as usize;
+        for downgrade in DOWNGRADES.iter().skip(skip).rev() {
+            downgrade(&mut value);
+        }
+
+        serde_json::to_string(&value)
+    }
+}
+
+/// Version 2 added the listen address the request arrived on
+fn remove_local_addr(event: &mut serde_json::Value) {
+    if event["type"] == "RequestReceived" {
+        if let Some(data) = event["data"].as_object_mut() {
+            data.remove("local_addr");
+        }
+    }
+}
+
+/// Version 3 added the identity of the authenticated peer
+fn remove_identity(event: &mut serde_json::Value) {
+    if event["type"] == "RequestReceived" || event["type"] == "RequestQueued" {
+        if let Some(data) = event["data"].as_object_mut() {
+            data.remove("identity");
+        }
+    }
+}
+
+/// JSON Schema of the events and of the `norddrop_get_transfers_since()`
+/// output, generated from the types above
+pub fn schema() -> serde_json::Value {
+    serde_json::json!({
+        "version": EVENT_VERSION,
+        "event": schemars::schema_for!(VersionedEvent<'static>),
+        "transfers": schemars::schema_for!(Vec<drop_storage::types::Transfer>),
+    })
 }
 
 #[derive(Deserialize, Clone, Debug)]
@@ -117,9 +430,15 @@ pub struct Config {
     #[serde(default = "default_connection_max_retry_interval_ms")]
     pub connection_max_retry_interval_ms: u64,
     pub transfer_idle_lifetime_ms: u64,
+    #[serde(default)]
+    pub transfer_accept_timeout_ms: Option<u64>,
     pub moose_event_path: String,
     pub moose_prod: bool,
     pub storage_path: String,
+    #[serde(default)]
+    pub source_addr: Option<IpAddr>,
+    #[serde(default)]
+    pub bind_device: Option<String>,
 }
 
 const fn default_connection_max_retry_interval_ms() -> u64 {
@@ -138,8 +457,16 @@ impl From<&drop_transfer::Error> for Status {
 impl From<drop_transfer::Event> for Event {
     fn from(e: drop_transfer::Event) -> Self {
         match e {
-            drop_transfer::Event::RequestReceived(tx) => Event::RequestReceived(tx.into()),
+            drop_transfer::Event::RequestReceived(tx, local_addr) => {
+                Event::RequestReceived(EventTransferRequest::new(&tx, local_addr))
+            }
             drop_transfer::Event::RequestQueued(tx) => Event::RequestQueued(tx.into()),
+            drop_transfer::Event::RequestAppendReceived(tx, files) => {
+                Event::RequestAppendReceived(EventRequestAppend::new(&tx, &files))
+            }
+            drop_transfer::Event::RequestAppendQueued(tx, files) => {
+                Event::RequestAppendQueued(EventRequestAppend::new(&tx, &files))
+            }
             drop_transfer::Event::FileUploadStarted(tx, fid) => {
                 Event::TransferStarted(StartEvent {
                     transfer: tx.id().to_string(),
@@ -166,6 +493,15 @@ impl From<drop_transfer::Event> for Event {
                     transfered: progress,
                 })
             }
+            drop_transfer::Event::FileUploadArchiveEntry(tx, fid, entry)
+            | drop_transfer::Event::FileDownloadArchiveEntry(tx, fid, entry) => {
+                Event::TransferArchiveEntry(ArchiveEntryEvent {
+                    transfer: tx.id().to_string(),
+                    file: fid.to_string(),
+                    path: entry.path.to_string(),
+                    size: entry.size,
+                })
+            }
             drop_transfer::Event::FileUploadSuccess(tx, fid) => Event::TransferFinished {
                 transfer: tx.id().to_string(),
                 data: FinishEvent::FileUploaded {
@@ -179,21 +515,23 @@ impl From<drop_transfer::Event> for Event {
                     final_path: info.final_path.0.to_string_lossy().to_string(),
                 },
             },
-            drop_transfer::Event::FileUploadCancelled(tx, fid, by_peer) => {
+            drop_transfer::Event::FileUploadCancelled(tx, fid, by_peer, reason) => {
                 Event::TransferFinished {
                     transfer: tx.id().to_string(),
                     data: FinishEvent::FileCanceled {
                         file: fid.to_string(),
                         by_peer,
+                        reason: reason.map(From::from),
                     },
                 }
             }
-            drop_transfer::Event::FileDownloadCancelled(tx, fid, by_peer) => {
+            drop_transfer::Event::FileDownloadCancelled(tx, fid, by_peer, reason) => {
                 Event::TransferFinished {
                     transfer: tx.id().to_string(),
                     data: FinishEvent::FileCanceled {
                         file: fid.to_string(),
                         by_peer,
+                        reason: reason.map(From::from),
                     },
                 }
             }
@@ -225,24 +563,119 @@ impl From<drop_transfer::Event> for Event {
                 transfer_id,
                 file_id,
                 by_peer,
+                reason,
             } => Event::TransferFinished {
                 transfer: transfer_id.to_string(),
                 data: FinishEvent::FileRejected {
                     file: file_id.to_string(),
                     by_peer,
+                    reason: reason.map(From::from),
                 },
             },
             drop_transfer::Event::FileUploadRejected {
                 transfer_id,
                 file_id,
                 by_peer,
+                reason,
             } => Event::TransferFinished {
                 transfer: transfer_id.to_string(),
                 data: FinishEvent::FileRejected {
                     file: file_id.to_string(),
                     by_peer,
+                    reason: reason.map(From::from),
                 },
             },
+            drop_transfer::Event::TransferRejected(tx, by_peer, reason) => {
+                Event::TransferFinished {
+                    transfer: tx.id().to_string(),
+                    data: FinishEvent::TransferRejected {
+                        by_peer,
+                        reason: reason.map(From::from),
+                    },
+                }
+            }
+            drop_transfer::Event::TransferExpired(tx) => Event::TransferFinished {
+                transfer: tx.id().to_string(),
+                data: FinishEvent::TransferExpired,
+            },
+            drop_transfer::Event::FileRequestReceived {
+                request_id,
+                peer,
+                export,
+                paths,
+            } => Event::FileRequestReceived(FileRequestEvent {
+                request: request_id.to_string(),
+                peer: peer.to_string(),
+                export,
+                paths,
+            }),
+            drop_transfer::Event::FileRequestDenied { request_id, peer } => {
+                Event::FileRequestDenied(FileRequestDeniedEvent {
+                    request: request_id.to_string(),
+                    peer: peer.to_string(),
+                })
+            }
+            drop_transfer::Event::PairingStarted(peer) => Event::PairingStarted(PairingEvent {
+                peer: peer.to_string(),
+            }),
+            drop_transfer::Event::PairingRequested(peer) => Event::PairingRequested(PairingEvent {
+                peer: peer.to_string(),
+            }),
+            drop_transfer::Event::PairingCompleted { peer, identity } => {
+                Event::PairingCompleted(PairingCompletedEvent {
+                    peer: peer.to_string(),
+                    identity,
+                })
+            }
+            drop_transfer::Event::PairingFailed(peer, err) => {
+                Event::PairingFailed(PairingFailedEvent {
+                    peer: peer.to_string(),
+                    status: From::from(&err),
+                })
+            }
+        }
+    }
+}
+
+impl From<&drop_transfer::file::SkipReason> for SkipReason {
+    fn from(value: &drop_transfer::file::SkipReason) -> Self {
+        use drop_transfer::file::SkipReason as R;
+
+        match value {
+            R::Symlink => Self::Symlink,
+            R::SymlinkLoop => Self::SymlinkLoop,
+            R::AbsoluteSymlink => Self::AbsoluteSymlink,
+            R::Unreadable(err) => Self::Unreadable {
+                status: From::from(err),
+            },
+        }
+    }
+}
+
+impl From<&drop_transfer::file::Skipped> for SkippedPath {
+    fn from(value: &drop_transfer::file::Skipped) -> Self {
+        Self {
+            path: value.path.to_string_lossy().to_string(),
+            reason: From::from(&value.reason),
+        }
+    }
+}
+
+impl From<SymlinkPolicy> for drop_transfer::file::SymlinkPolicy {
+    fn from(value: SymlinkPolicy) -> Self {
+        match value {
+            SymlinkPolicy::Skip => Self::Skip,
+            SymlinkPolicy::Follow => Self::Follow,
+            SymlinkPolicy::Preserve => Self::Preserve,
+        }
+    }
+}
+
+impl From<Compression> for drop_transfer::file::Compression {
+    fn from(value: Compression) -> Self {
+        match value {
+            Compression::None => Self::None,
+            Compression::Deflate => Self::Deflate,
         }
     }
 }
@@ -255,12 +688,14 @@ impl From<drop_transfer::Transfer> for EventTransfer {
     }
 }
 
-impl From<drop_transfer::Transfer> for EventTransferRequest {
-    fn from(t: drop_transfer::Transfer) -> EventTransferRequest {
-        EventTransferRequest {
+impl EventTransferRequest {
+    fn new(t: &drop_transfer::Transfer, local_addr: IpAddr) -> Self {
+        Self {
             peer: t.peer().to_string(),
+            identity: t.identity().map(ToString::to_string),
             transfer: t.id().to_string(),
-            files: extract_transfer_files(&t),
+            files: extract_transfer_files(t),
+            local_addr: local_addr.to_string(),
         }
     }
 }
@@ -269,20 +704,113 @@ impl From<drop_transfer::Transfer> for EventRequestQueued {
     fn from(t: drop_transfer::Transfer) -> EventRequestQueued {
         EventRequestQueued {
             transfer: t.id().to_string(),
+            identity: t.identity().map(ToString::to_string),
+            group: t.group().map(|group| group.to_string()),
             files: extract_transfer_files(&t),
         }
     }
 }
 
+impl EventRequestAppend {
+    fn new(t: &drop_transfer::Transfer, files: &[drop_transfer::FileId]) -> Self {
+        Self {
+            transfer: t.id().to_string(),
+            files: files
+                .iter()
+                .filter_map(|id| t.files().get(id))
+                .map(From::from)
+                .collect(),
+        }
+    }
+}
+
 fn extract_transfer_files(t: &drop_transfer::Transfer) -> Vec<File> {
-    t.files()
-        .values()
-        .map(|f| File {
+    t.files().values().map(From::from).collect()
+}
+
+impl From<&drop_transfer::File> for File {
+    fn from(f: &drop_transfer::File) -> Self {
+        File {
             id: f.id().to_string(),
             path: f.subpath().to_string(),
             size: f.size(),
-        })
-        .collect()
+            kind: f.into(),
+        }
+    }
+}
+
+impl From<&drop_transfer::File> for FileKind {
+    fn from(f: &drop_transfer::File) -> Self {
+        if f.is_dir() {
+            FileKind::Dir
+        } else if f.symlink_target().is_some() {
+            FileKind::Symlink
+        } else if f.is_archive() {
+            FileKind::Archive
+        } else {
+            FileKind::File
+        }
+    }
+}
+
+impl From<drop_transfer::progress::ActiveTransfer> for ActiveTransfer {
+    fn from(active: drop_transfer::progress::ActiveTransfer) -> Self {
+        use drop_transfer::progress::{self, FileState as State};
+
+        let drop_transfer::progress::ActiveTransfer {
+            transfer,
+            outgoing,
+            connection,
+            version,
+            mut files,
+            rejected,
+        } = active;
+
+        let files = transfer
+            .files()
+            .values()
+            .map(|file| {
+                let progress = files.remove(file.id());
+
+                ActiveFile {
+                    file: file.into(),
+                    state: match progress.as_ref().map(|p| p.state) {
+                        None | Some(State::Pending) => FileState::Pending,
+                        Some(State::InProgress) => FileState::InProgress,
+                        Some(State::Completed) => FileState::Completed,
+                        Some(State::Failed) => FileState::Failed,
+                        Some(State::Canceled) => FileState::Canceled,
+                        Some(State::Rejected) => FileState::Rejected,
+                    },
+                    bytes: progress.map_or(0, |p| p.bytes),
+                }
+            })
+            .collect();
+
+        ActiveTransfer {
+            transfer: transfer.id().to_string(),
+            peer: transfer.peer().to_string(),
+            identity: transfer.identity().map(ToString::to_string),
+            direction: if outgoing {
+                Direction::Outgoing
+            } else {
+                Direction::Incoming
+            },
+            state: match connection {
+                progress::ConnectionState::Connecting => ConnectionState::Connecting,
+                progress::ConnectionState::Connected => ConnectionState::Connected,
+            },
+            version,
+            files,
+            rejected: rejected.iter().map(ToString::to_string).collect(),
+        }
+    }
+}
+
+impl FileKind {
+    fn is_file(&self) -> bool {
+        *self == FileKind::File
+    }
 }
 
 impl From<Config> for drop_config::Config {
@@ -293,9 +821,12 @@ impl From<Config> for drop_config::Config {
             req_connection_timeout_ms,
             connection_max_retry_interval_ms,
             transfer_idle_lifetime_ms,
+            transfer_accept_timeout_ms,
             moose_event_path,
             moose_prod,
             storage_path,
+            source_addr,
+            bind_device,
         } = val;
 
         drop_config::Config {
@@ -307,7 +838,10 @@ impl From<Config> for drop_config::Config {
                     connection_max_retry_interval_ms,
                 ),
                 transfer_idle_lifetime: Duration::from_millis(transfer_idle_lifetime_ms),
+                transfer_accept_timeout: transfer_accept_timeout_ms.map(Duration::from_millis),
                 storage_path,
+                source_addr,
+                bind_device,
             },
             moose: drop_config::MooseConfig {
                 event_path: moose_event_path,
@@ -338,6 +872,9 @@ mod tests {
 
         let cfg: Config = serde_json::from_str(json).expect("Failed to deserialize config");
         assert_eq!(cfg.connection_max_retry_interval_ms, 10000);
+        assert_eq!(cfg.transfer_accept_timeout_ms, None);
+        assert_eq!(cfg.source_addr, None);
+        assert_eq!(cfg.bind_device, None);
 
         let json = r#"
         {
@@ -345,10 +882,13 @@ mod tests {
           "transfer_file_limit": 100,
           "req_connection_timeout_ms": 1000,
           "transfer_idle_lifetime_ms": 2000,
+          "transfer_accept_timeout_ms": 3000,
           "connection_max_retry_interval_ms": 500,
           "moose_event_path": "test/path",
           "moose_prod": true,
-          "storage_path": ":memory:"
+          "storage_path": ":memory:",
+          "source_addr": "100.64.0.2",
+          "bind_device": "nordlynx"
         }
         "#;
 
@@ -361,8 +901,11 @@ mod tests {
                     transfer_file_limit,
                     req_connection_timeout,
                     transfer_idle_lifetime,
+                    transfer_accept_timeout,
                     connection_max_retry_interval,
                     storage_path,
+                    source_addr,
+                    bind_device,
                 },
             moose: drop_config::MooseConfig { event_path, prod },
         } = cfg.into();
@@ -372,8 +915,86 @@ mod tests {
         assert_eq!(req_connection_timeout, Duration::from_millis(1000));
         assert_eq!(connection_max_retry_interval, Duration::from_millis(500));
         assert_eq!(transfer_idle_lifetime, Duration::from_millis(2000));
+        assert_eq!(transfer_accept_timeout, Some(Duration::from_millis(3000)));
         assert_eq!(event_path, "test/path");
         assert_eq!(storage_path, ":memory:");
+        assert_eq!(source_addr, Some(IpAddr::from([100, 64, 0, 2])));
+        assert_eq!(bind_device.as_deref(), Some("nordlynx"));
         assert!(prod);
     }
+
+    #[test]
+    fn serialize_file_results() {
+        let results = [
+            FileResult {
+                file: "ID1".into(),
+                status: None,
+            },
+            FileResult {
+                file: "ID2".into(),
+                status: Some(Status::from(&drop_transfer::Error::Rejected)),
+            },
+        ];
+
+        assert_eq!(
+            serde_json::to_value(results).unwrap(),
+            serde_json::json!([
+                { "file": "ID1" },
+                { "file": "ID2", "status": 34 },
+            ])
+        );
+    }
+
+    #[test]
+    fn versioned_event() {
+        let event = Event::EventsDropped(EventsDroppedEvent { count: 2 });
+        let json = event.to_versioned_json(EVENT_VERSION).unwrap();
+
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&json).unwrap(),
+            serde_json::json!({
+                "version": EVENT_VERSION,
+                "type": "EventsDropped",
+                "data": { "count": 2 },
+            })
+        );
+
+        let event = Event::RequestReceived(EventTransferRequest {
+            peer: "1.2.3.4".into(),
+            identity: Some("KEY".into()),
+            transfer: "ID".into(),
+            files: Vec::new(),
+            local_addr: "5.6.7.8".into(),
+        });
+
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&event.to_versioned_json(2).unwrap())
+                .unwrap(),
+            serde_json::json!({
+                "version": 2,
+                "type": "RequestReceived",
+                "data": {
+                    "peer": "1.2.3.4",
+                    "transfer": "ID",
+                    "files": [],
+                    "local_addr": "5.6.7.8",
+                },
+            })
+        );
+
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&event.to_versioned_json(1).unwrap())
+                .unwrap(),
+            serde_json::json!({
+                "version": 1,
+                "type": "RequestReceived",
+                "data": { "peer": "1.2.3.4", "transfer": "ID", "files": [] },
+            })
+        );
+
+        let schema = schema();
+        assert_eq!(schema["version"], EVENT_VERSION);
+        assert!(schema["event"]["definitions"]["EventTransferRequest"].is_object());
+        assert!(schema["transfers"]["definitions"]["IncomingPath"].is_object());
+    }
 }
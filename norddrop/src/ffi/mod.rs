@@ -1,3 +1,4 @@
+pub mod typed;
 pub mod types;
 mod version;
 
@@ -6,13 +7,18 @@ use std::{
     ffi::{CStr, CString},
     fmt, panic,
     sync::{Mutex, Once},
+    time::Duration,
 };
 
 use libc::c_char;
 use slog::{error, o, warn, Drain, Logger, KV};
 
-use self::types::{
-    norddrop_event_cb, norddrop_log_level, norddrop_logger_cb, norddrop_pubkey_cb, norddrop_result,
+use self::{
+    typed::{norddrop_file_progress, norddrop_transfer_descriptor, norddrop_typed_event_cb},
+    types::{
+        norddrop_event_cb, norddrop_log_level, norddrop_logger_cb, norddrop_pubkey_cb,
+        norddrop_result,
+    },
 };
 use crate::{
     device::{NordDropFFI, Result as DevResult},
@@ -78,6 +84,64 @@ pub struct norddrop(Mutex<NordDropFFI>);
 ///   }
 /// ]
 /// ```
+///
+/// # Symbolic links inside directories
+///
+/// By default symlinks found inside directories are skipped. The optional
+/// "symlinks" field selects the behaviour: "skip", "follow" or "preserve".
+/// With "preserve", relative links are sent as links and recreated by the
+/// receiver only when they point inside of the destination directory. The
+/// links that are left out are reported with the `TransferPathsSkipped` event
+///
+/// ```json
+/// [
+///   {
+///     "path": "/path/to/dir",
+///     "symlinks": "preserve"
+///   }
+/// ]
+/// ```
+///
+/// # Filtering directory content
+///
+/// The entries found inside directories can be filtered with glob patterns
+/// matched against the path relative to the directory, the ones without a
+/// slash match at any depth like in `.gitignore`. "include" limits the
+/// transfer to the matching entries, "exclude" leaves out the matching ones
+/// and does not descend into excluded directories. The directories left
+/// empty by the filtering are not sent.
+/// "skip_hidden" leaves out the entries which names start with a dot and
+/// "ignore_file" names `.gitignore` style files whose rules apply to the
+/// directory containing them
+///
+/// ```json
+/// [
+///   {
+///     "path": "/path/to/project",
+///     "exclude": ["node_modules", "*.log"],
+///     "skip_hidden": true,
+///     "ignore_file": ".gitignore"
+///   }
+/// ]
+/// ```
+///
+/// # Sending a directory as an archive
+///
+/// With "archive" set the directory is sent as a single tar stream and
+/// extracted by the receiver once downloaded, which is much faster for
+/// directories with a lot of small files. The optional "compression" field
+/// selects "none" or "deflate". Progress of the individual entries is
+/// reported with the `TransferArchiveEntry` event
+///
+/// ```json
+/// [
+///   {
+///     "path": "/path/to/dir",
+///     "archive": true,
+///     "compression": "deflate"
+///   }
+/// ]
+/// ```
 #[no_mangle]
 pub extern "C" fn norddrop_new_transfer(
     dev: &norddrop,
@@ -111,212 +175,1827 @@ pub extern "C" fn norddrop_new_transfer(
     }
 }
 
-/// Destroy the libdrop instance.
+/// Initialize a new transfer with the provided peer and descriptors, connecting
+/// to the peer from the given source address. Overrides the `source_addr` of
+/// the configuration for this transfer only
 ///
 /// # Arguments
 ///
-/// * `dev` - Pointer to the instance.
+/// * `dev` - A pointer to the instance.
+/// * `peer` - Peer address.
+/// * `source_addr` - Local IP address to connect from.
+/// * `descriptors` - JSON descriptors, same as in `norddrop_new_transfer()`.
+///
+/// # Returns
+///
+/// The transfer ID, `NULL` on failure
 ///
 /// # Safety
-/// This function creates a box with instance pointer and immediately drops it.
+/// The pointers provided should be valid
 #[no_mangle]
-pub unsafe extern "C" fn norddrop_destroy(dev: *mut norddrop) {
-    if !dev.is_null() {
-        let _ = Box::from_raw(dev);
+pub unsafe extern "C" fn norddrop_new_transfer_with_source(
+    dev: &norddrop,
+    peer: *const c_char,
+    source_addr: *const c_char,
+    descriptors: *const c_char,
+) -> *mut c_char {
+    let res = panic::catch_unwind(|| {
+        let mut dev = dev.0.lock().expect("lock instance");
+
+        if peer.is_null() || source_addr.is_null() || descriptors.is_null() {
+            return Err(norddrop_result::NORDDROP_RES_INVALID_STRING);
+        }
+
+        let peer = unsafe { CStr::from_ptr(peer) }.to_str()?;
+        let source_addr = unsafe { CStr::from_ptr(source_addr) }.to_str()?;
+        let descriptors = unsafe { CStr::from_ptr(descriptors) }.to_str()?;
+
+        let xfid = dev.new_transfer_with_source(peer, source_addr, descriptors)?;
+
+        Ok(xfid.to_string().into_bytes())
+    });
+
+    match res {
+        Ok(Ok(xfid)) => new_unmanaged_str(&xfid),
+        _ => std::ptr::null_mut(),
     }
 }
 
-/// # Download a file from the peer
+/// Initialize a new transfer with the provided peer and descriptors. Unlike
+/// `norddrop_new_transfer()`, the entries that cannot be read are left out
+/// instead of failing the whole transfer
 ///
 /// # Arguments
 ///
-/// * `dev` - Pointer to the instance
-/// * `xfid` - Transfer ID
-/// * `fid` - File ID
-/// * `dst` - Destination path
+/// * `dev` - A pointer to the instance.
+/// * `peer` - Peer address.
+/// * `descriptors` - JSON descriptors, same as in `norddrop_new_transfer()`.
+///
+/// # Returns
+///
+/// A JSON string containing the transfer ID and the paths that were skipped,
+/// together with the reason. NULL when nothing is left to send after
+/// skipping.
+///
+/// ```json
+/// {
+///   "transfer": "2c1e0a8c-5dbd-4b89-a4a1-8ad9d34d1b8a",
+///   "paths": [
+///     {
+///       "path": "/path/to/dir/secret.txt",
+///       "reason": "unreadable",
+///       "status": 15,
+///       "os_error_code": 13
+///     },
+///     {
+///       "path": "/path/to/dir/link",
+///       "reason": "symlink"
+///     }
+///   ]
+/// }
+/// ```
+///
+/// # Safety
+/// The pointers provided should be valid
 #[no_mangle]
-pub extern "C" fn norddrop_download(
+pub unsafe extern "C" fn norddrop_new_transfer_tolerant(
     dev: &norddrop,
-    xfid: *const c_char,
-    fid: *const c_char,
-    dst: *const c_char,
-) -> norddrop_result {
-    let result = panic::catch_unwind(move || {
-        let mut dev = ffi_try!(dev
-            .0
-            .lock()
-            .map_err(|_| norddrop_result::NORDDROP_RES_ERROR));
+    peer: *const c_char,
+    descriptors: *const c_char,
+) -> *mut c_char {
+    let res = panic::catch_unwind(|| {
+        let mut dev = dev.0.lock().expect("lock instance");
 
-        let str_xfid = {
-            if xfid.is_null() {
-                return norddrop_result::NORDDROP_RES_INVALID_STRING;
-            }
-            let cstr_xfid = unsafe { CStr::from_ptr(xfid) };
-            ffi_try!(cstr_xfid.to_str())
-        };
+        if peer.is_null() {
+            return Err(norddrop_result::NORDDROP_RES_INVALID_STRING);
+        }
 
-        let str_fid = {
-            if fid.is_null() {
-                return norddrop_result::NORDDROP_RES_INVALID_STRING;
-            }
-            let cstr_fid = unsafe { CStr::from_ptr(fid) };
-            ffi_try!(cstr_fid.to_str())
-        };
+        let peer = unsafe { CStr::from_ptr(peer) }.to_str()?;
 
-        let str_dst = {
-            if dst.is_null() {
-                return norddrop_result::NORDDROP_RES_INVALID_STRING;
-            }
-            let cstr_dst = unsafe { CStr::from_ptr(dst) };
-            ffi_try!(cstr_dst.to_str())
-        };
+        if descriptors.is_null() {
+            return Err(norddrop_result::NORDDROP_RES_INVALID_STRING);
+        }
 
-        dev.download(
-            ffi_try!(str_xfid
-                .to_string()
-                .parse()
-                .map_err(|_| norddrop_result::NORDDROP_RES_BAD_INPUT)),
-            ffi_try!(str_fid
-                .to_string()
-                .parse()
-                .map_err(|_| norddrop_result::NORDDROP_RES_BAD_INPUT)),
-            str_dst.to_string(),
-        )
-        .norddrop_log_result(&dev.logger, "norddrop_download")
+        let descriptors = unsafe { CStr::from_ptr(descriptors) }.to_str()?;
+
+        let report = dev.new_transfer_tolerant(peer, descriptors)?;
+
+        Ok(report.into_bytes())
     });
 
-    result.unwrap_or(norddrop_result::NORDDROP_RES_ERROR)
+    // catch_unwind catches panics as errors and everything else goes to Ok
+    match res {
+        Ok(Ok(report)) => new_unmanaged_str(&report),
+        _ => std::ptr::null_mut(),
+    }
 }
 
-/// # Cancel a transfer from either side
+/// Initialize one transfer of the same files for each of the provided peers.
+/// The files are gathered once and the checksums requested by the receivers
+/// are computed only once for the whole group
 ///
 /// # Arguments
 ///
-/// * `dev`: Pointer to the instance
-/// * `xfid`: Transfer ID
+/// * `dev` - A pointer to the instance.
+/// * `peers` - JSON array of peer addresses.
+/// * `descriptors` - JSON descriptors, same as in `norddrop_new_transfer()`.
+///
+/// # Returns
+///
+/// A JSON string containing the group ID together with the transfer ID created
+/// for each of the peers. `NULL` on failure, in that case no transfer is
+/// created.
+///
+/// ```json
+/// {
+///   "group": "8a5c47b1-8f9a-4a4c-9f0e-9d1b7c2c4f3e",
+///   "transfers": [
+///     {
+///       "peer": "172.17.0.2",
+///       "transfer": "2c1e0a8c-5dbd-4b89-a4a1-8ad9d34d1b8a"
+///     },
+///     {
+///       "peer": "172.17.0.3",
+///       "transfer": "d5ff2b4a-4c4e-4e3a-8f8e-3e0c3b2f9a71"
+///     }
+///   ]
+/// }
+/// ```
+///
+/// Each transfer runs independently and reports its own events. The
+/// `RequestQueued` events of the group carry the `group` field.
+///
+/// # Safety
+/// The pointers provided should be valid
 #[no_mangle]
-pub extern "C" fn norddrop_cancel_transfer(dev: &norddrop, xfid: *const c_char) -> norddrop_result {
-    let result = panic::catch_unwind(move || {
-        let str_xfid = {
-            if xfid.is_null() {
-                return norddrop_result::NORDDROP_RES_INVALID_STRING;
-            }
-            let cstr_xfid = unsafe { CStr::from_ptr(xfid) };
-            ffi_try!(cstr_xfid.to_str())
-        };
+pub unsafe extern "C" fn norddrop_new_transfer_multi(
+    dev: &norddrop,
+    peers: *const c_char,
+    descriptors: *const c_char,
+) -> *mut c_char {
+    let res = panic::catch_unwind(|| {
+        let mut dev = dev.0.lock().expect("lock instance");
 
-        let mut dev = ffi_try!(dev
-            .0
-            .lock()
-            .map_err(|_| norddrop_result::NORDDROP_RES_ERROR));
+        if peers.is_null() {
+            return Err(norddrop_result::NORDDROP_RES_INVALID_STRING);
+        }
 
-        dev.cancel_transfer(ffi_try!(str_xfid
-            .to_string()
-            .parse()
-            .map_err(|_| norddrop_result::NORDDROP_RES_BAD_INPUT)))
-            .norddrop_log_result(
-                &dev.logger,
-                &format!("norddrop_cancel_transfer, xfid: {xfid:?}"),
-            )
+        let peers = unsafe { CStr::from_ptr(peers) }.to_str()?;
+
+        if descriptors.is_null() {
+            return Err(norddrop_result::NORDDROP_RES_INVALID_STRING);
+        }
+
+        let descriptors = unsafe { CStr::from_ptr(descriptors) }.to_str()?;
+
+        let group = dev.new_transfer_multi(peers, descriptors)?;
+
+        Ok(group.into_bytes())
     });
 
-    result.unwrap_or(norddrop_result::NORDDROP_RES_ERROR)
+    // catch_unwind catches panics as errors and everything else goes to Ok
+    match res {
+        Ok(Ok(group)) => new_unmanaged_str(&group),
+        _ => std::ptr::null_mut(),
+    }
 }
 
-/// # Cancel a file from either side
+/// Initialize a new transfer with the provided peer and descriptors without
+/// waiting for the directories to be scanned. Meant for huge directory trees
 ///
 /// # Arguments
 ///
-/// * `dev`: Pointer to the instance
-/// * `xfid`: Transfer ID
-/// * `fid`: File ID
+/// * `dev` - A pointer to the instance.
+/// * `peer` - Peer address.
+/// * `descriptors` - JSON descriptors, same as in `norddrop_new_transfer()`.
+///
+/// # Returns
+///
+/// A String containing the transfer ID.
+///
+/// # Scanning
+///
+/// The descriptors are scanned in the background. While scanning, the
+/// `TransferScanProgress` event is reported periodically with the number of
+/// entries and bytes found so far. `RequestQueued` is emitted once the first
+/// entries are found and the entries found later are offered to the peer as
+/// the scan goes on, each batch reported with `RequestAppendQueued`. The peers
+/// which cannot take appended files get the request when the scan is done.
+/// `TransferFinished` with `TransferFailed` is emitted when the scanning fails
+/// or nothing is found.
+///
+/// Unreadable entries are skipped and reported with `TransferPathsSkipped` at
+/// the end of the scan.
+///
+/// ```json
+/// {
+///   "type": "TransferScanProgress",
+///   "data": {
+///     "transfer": "2c1e0a8c-5dbd-4b89-a4a1-8ad9d34d1b8a",
+///     "files": 3000,
+///     "bytes": 1048576
+///   }
+/// }
+/// ```
+///
+/// The files are not opened while scanning. The mime type is detected when
+/// the upload of the file starts and unreadable files fail only then. The
+/// file descriptors need to remain valid until the transfer is finished.
+///
+/// # Safety
+/// The pointers provided should be valid
 #[no_mangle]
-pub extern "C" fn norddrop_cancel_file(
+pub unsafe extern "C" fn norddrop_new_transfer_async(
     dev: &norddrop,
-    xfid: *const c_char,
-    fid: *const c_char,
-) -> norddrop_result {
-    let result = panic::catch_unwind(move || {
-        let str_xfid = {
-            if xfid.is_null() {
-                return norddrop_result::NORDDROP_RES_INVALID_STRING;
-            }
-            let cstr_xfid = unsafe { CStr::from_ptr(xfid) };
-            ffi_try!(cstr_xfid.to_str())
-        };
-
-        let str_fid = {
-            if fid.is_null() {
-                return norddrop_result::NORDDROP_RES_INVALID_STRING;
-            }
+    peer: *const c_char,
+    descriptors: *const c_char,
+) -> *mut c_char {
+    let res = panic::catch_unwind(|| {
+        let mut dev = dev.0.lock().expect("lock instance");
 
-            let cstr_fid = unsafe { CStr::from_ptr(fid) };
-            ffi_try!(cstr_fid.to_str())
-        };
+        if peer.is_null() {
+            return Err(norddrop_result::NORDDROP_RES_INVALID_STRING);
+        }
 
-        let mut dev = ffi_try!(dev
-            .0
-            .lock()
-            .map_err(|_| norddrop_result::NORDDROP_RES_ERROR));
+        let peer = unsafe { CStr::from_ptr(peer) }.to_str()?;
 
-        dev.cancel_file(
+        if descriptors.is_null() {
+            return Err(norddrop_result::NORDDROP_RES_INVALID_STRING);
+        }
+
+        let descriptors = unsafe { CStr::from_ptr(descriptors) }.to_str()?;
+
+        let xfid = dev.new_transfer_async(peer, descriptors)?;
+
+        Ok(xfid.to_string().into_bytes())
+    });
+
+    // catch_unwind catches panics as errors and everything else goes to Ok
+    match res {
+        Ok(Ok(xfid)) => new_unmanaged_str(&xfid),
+        _ => std::ptr::null_mut(),
+    }
+}
+
+/// Offer more files within an already running outgoing transfer. The peer
+/// receives the `RequestAppendReceived` event listing the new files, while the
+/// sender receives `RequestAppendQueued` once the files are offered. Requires
+/// the peer to run a version supporting the feature
+///
+/// # Arguments
+///
+/// * `dev` - A pointer to the instance.
+/// * `xfid` - Transfer ID.
+/// * `descriptors` - JSON descriptors, same as in `norddrop_new_transfer()`.
+///
+/// # Safety
+/// The pointers provided should be valid
+#[no_mangle]
+pub unsafe extern "C" fn norddrop_append_files(
+    dev: &norddrop,
+    xfid: *const c_char,
+    descriptors: *const c_char,
+) -> norddrop_result {
+    let result = panic::catch_unwind(|| {
+        let xfid = {
+            if xfid.is_null() {
+                return Err(norddrop_result::NORDDROP_RES_INVALID_STRING);
+            }
+
+            CStr::from_ptr(xfid)
+                .to_str()?
+                .parse()
+                .map_err(|_| norddrop_result::NORDDROP_RES_BAD_INPUT)?
+        };
+
+        if descriptors.is_null() {
+            return Err(norddrop_result::NORDDROP_RES_INVALID_STRING);
+        }
+
+        let descriptors = CStr::from_ptr(descriptors).to_str()?;
+
+        let dev = dev
+            .0
+            .lock()
+            .map_err(|_| norddrop_result::NORDDROP_RES_ERROR)?;
+
+        dev.append_files(xfid, descriptors)?;
+
+        Ok(())
+    });
+
+    match result {
+        Ok(Ok(())) => norddrop_result::NORDDROP_RES_OK,
+        Ok(Err(err)) => err,
+        Err(_) => norddrop_result::NORDDROP_RES_ERROR,
+    }
+}
+
+/// Publish the files under a random token, so that the peers can pull them
+/// with `norddrop_pull()` instead of waiting for them to be sent. Only the
+/// authenticated peers are allowed to pull
+///
+/// # Arguments
+///
+/// * `dev` - A pointer to the instance.
+/// * `descriptors` - JSON descriptors, same as in `norddrop_new_transfer()`.
+/// * `lifetime_ms` - Time after which the token expires.
+/// * `max_downloads` - Number of times the files can be pulled. Zero or less
+///   means there is no limit.
+///
+/// # Returns
+///
+/// A String containing the token, `NULL` on failure.
+///
+/// Each pull results in a regular outgoing transfer to the peer, reported with
+/// the `RequestQueued` event.
+///
+/// # Safety
+/// The pointers provided should be valid
+#[no_mangle]
+pub unsafe extern "C" fn norddrop_publish(
+    dev: &norddrop,
+    descriptors: *const c_char,
+    lifetime_ms: std::ffi::c_longlong,
+    max_downloads: std::ffi::c_int,
+) -> *mut c_char {
+    let res = panic::catch_unwind(|| {
+        let dev = dev.0.lock().expect("lock instance");
+
+        if descriptors.is_null() {
+            return Err(norddrop_result::NORDDROP_RES_INVALID_STRING);
+        }
+
+        let descriptors = unsafe { CStr::from_ptr(descriptors) }.to_str()?;
+
+        let token = dev.publish(descriptors, lifetime_ms, max_downloads)?;
+
+        Ok(token.into_bytes())
+    });
+
+    // catch_unwind catches panics as errors and everything else goes to Ok
+    match res {
+        Ok(Ok(token)) => new_unmanaged_str(&token),
+        _ => std::ptr::null_mut(),
+    }
+}
+
+/// Remove the published files, the token cannot be pulled anymore
+///
+/// # Arguments
+///
+/// * `dev` - A pointer to the instance.
+/// * `token` - Token returned by `norddrop_publish()`.
+///
+/// # Safety
+/// The pointers provided should be valid
+#[no_mangle]
+pub unsafe extern "C" fn norddrop_unpublish(
+    dev: &norddrop,
+    token: *const c_char,
+) -> norddrop_result {
+    let result = panic::catch_unwind(|| {
+        if token.is_null() {
+            return Err(norddrop_result::NORDDROP_RES_INVALID_STRING);
+        }
+
+        let token = CStr::from_ptr(token).to_str()?;
+
+        let dev = dev
+            .0
+            .lock()
+            .map_err(|_| norddrop_result::NORDDROP_RES_ERROR)?;
+
+        dev.unpublish(token)?;
+
+        Ok(())
+    });
+
+    match result {
+        Ok(Ok(())) => norddrop_result::NORDDROP_RES_OK,
+        Ok(Err(err)) => err,
+        Err(_) => norddrop_result::NORDDROP_RES_ERROR,
+    }
+}
+
+/// Ask the peer for the files it published under the token. On success the
+/// files are received over the connection opened to the peer, in a regular
+/// transfer reported with the `RequestReceived` event. The peer does not need
+/// to be able to connect back
+///
+/// # Arguments
+///
+/// * `dev` - A pointer to the instance.
+/// * `peer` - Address of the peer which published the files.
+/// * `token` - Token returned by `norddrop_publish()` on the peer side.
+///
+/// # Returns
+///
+/// A String containing the ID of the transfer, `NULL` on failure. An unknown,
+/// expired or exhausted token is a failure as well.
+///
+/// # Safety
+/// The pointers provided should be valid
+#[no_mangle]
+pub unsafe extern "C" fn norddrop_pull(
+    dev: &norddrop,
+    peer: *const c_char,
+    token: *const c_char,
+) -> *mut c_char {
+    let res = panic::catch_unwind(|| {
+        let dev = dev.0.lock().expect("lock instance");
+
+        if peer.is_null() {
+            return Err(norddrop_result::NORDDROP_RES_INVALID_STRING);
+        }
+
+        let peer = unsafe { CStr::from_ptr(peer) }.to_str()?;
+
+        if token.is_null() {
+            return Err(norddrop_result::NORDDROP_RES_INVALID_STRING);
+        }
+
+        let token = unsafe { CStr::from_ptr(token) }.to_str()?;
+
+        let xfid = dev.pull(peer, token)?;
+
+        Ok(xfid.to_string().into_bytes())
+    });
+
+    // catch_unwind catches panics as errors and everything else goes to Ok
+    match res {
+        Ok(Ok(xfid)) => new_unmanaged_str(&xfid),
+        _ => std::ptr::null_mut(),
+    }
+}
+
+/// Allow the peers to request files from the directory with
+/// `norddrop_request_files()`. Each request needs to be approved
+///
+/// # Arguments
+///
+/// * `dev` - A pointer to the instance.
+/// * `name` - Name under which the directory is exported, replaces the previous
+///   export with the same name.
+/// * `path` - Path to the directory.
+///
+/// # Safety
+/// The pointers provided should be valid
+#[no_mangle]
+pub unsafe extern "C" fn norddrop_export_dir(
+    dev: &norddrop,
+    name: *const c_char,
+    path: *const c_char,
+) -> norddrop_result {
+    let result = panic::catch_unwind(|| {
+        if name.is_null() {
+            return Err(norddrop_result::NORDDROP_RES_INVALID_STRING);
+        }
+
+        let name = CStr::from_ptr(name).to_str()?;
+
+        if path.is_null() {
+            return Err(norddrop_result::NORDDROP_RES_INVALID_STRING);
+        }
+
+        let path = CStr::from_ptr(path).to_str()?;
+
+        let dev = dev
+            .0
+            .lock()
+            .map_err(|_| norddrop_result::NORDDROP_RES_ERROR)?;
+
+        dev.export_dir(name, path)?;
+
+        Ok(())
+    });
+
+    match result {
+        Ok(Ok(())) => norddrop_result::NORDDROP_RES_OK,
+        Ok(Err(err)) => err,
+        Err(_) => norddrop_result::NORDDROP_RES_ERROR,
+    }
+}
+
+/// Stop exporting the directory. The requests already received can still be
+/// approved
+///
+/// # Arguments
+///
+/// * `dev` - A pointer to the instance.
+/// * `name` - Name of the export.
+///
+/// # Safety
+/// The pointers provided should be valid
+#[no_mangle]
+pub unsafe extern "C" fn norddrop_unexport_dir(
+    dev: &norddrop,
+    name: *const c_char,
+) -> norddrop_result {
+    let result = panic::catch_unwind(|| {
+        if name.is_null() {
+            return Err(norddrop_result::NORDDROP_RES_INVALID_STRING);
+        }
+
+        let name = CStr::from_ptr(name).to_str()?;
+
+        let dev = dev
+            .0
+            .lock()
+            .map_err(|_| norddrop_result::NORDDROP_RES_ERROR)?;
+
+        dev.unexport_dir(name)?;
+
+        Ok(())
+    });
+
+    match result {
+        Ok(Ok(())) => norddrop_result::NORDDROP_RES_OK,
+        Ok(Err(err)) => err,
+        Err(_) => norddrop_result::NORDDROP_RES_ERROR,
+    }
+}
+
+/// Ask the peer for the files from the directory it exported. The peer
+/// receives the `FileRequestReceived` event and once it approves the request,
+/// the files are sent in a regular transfer with the ID of the request
+///
+/// # Arguments
+///
+/// * `dev` - A pointer to the instance.
+/// * `peer` - Peer address.
+/// * `export` - Name of the directory exported by the peer.
+/// * `paths` - JSON array of paths relative to the exported directory.
+///
+/// # Returns
+///
+/// A String containing the request ID, `NULL` on failure. The request fails
+/// when the export is unknown or any of the paths is outside of it, or when too
+/// many requests await the approval of the peer. The peer drops the requests
+/// not answered within an hour.
+///
+/// ```json
+/// {
+///   "type": "FileRequestReceived",
+///   "data": {
+///     "request": "2c1e0a8c-5dbd-4b89-a4a1-8ad9d34d1b8a",
+///     "peer": "172.17.0.2",
+///     "export": "docs",
+///     "paths": ["reports/q3.pdf"]
+///   }
+/// }
+/// ```
+///
+/// # Safety
+/// The pointers provided should be valid
+#[no_mangle]
+pub unsafe extern "C" fn norddrop_request_files(
+    dev: &norddrop,
+    peer: *const c_char,
+    export: *const c_char,
+    paths: *const c_char,
+) -> *mut c_char {
+    let res = panic::catch_unwind(|| {
+        let dev = dev.0.lock().expect("lock instance");
+
+        if peer.is_null() {
+            return Err(norddrop_result::NORDDROP_RES_INVALID_STRING);
+        }
+
+        let peer = unsafe { CStr::from_ptr(peer) }.to_str()?;
+
+        if export.is_null() {
+            return Err(norddrop_result::NORDDROP_RES_INVALID_STRING);
+        }
+
+        let export = unsafe { CStr::from_ptr(export) }.to_str()?;
+
+        if paths.is_null() {
+            return Err(norddrop_result::NORDDROP_RES_INVALID_STRING);
+        }
+
+        let paths = unsafe { CStr::from_ptr(paths) }.to_str()?;
+
+        let request = dev.request_files(peer, export, paths)?;
+
+        Ok(request.to_string().into_bytes())
+    });
+
+    // catch_unwind catches panics as errors and everything else goes to Ok
+    match res {
+        Ok(Ok(request)) => new_unmanaged_str(&request),
+        _ => std::ptr::null_mut(),
+    }
+}
+
+/// Approve the file request received from the peer. The requested files are
+/// sent in a transfer with the ID of the request
+///
+/// # Arguments
+///
+/// * `dev` - A pointer to the instance.
+/// * `request` - Request ID from the `FileRequestReceived` event.
+///
+/// # Safety
+/// The pointers provided should be valid
+#[no_mangle]
+pub unsafe extern "C" fn norddrop_approve_file_request(
+    dev: &norddrop,
+    request: *const c_char,
+) -> norddrop_result {
+    let result = panic::catch_unwind(|| {
+        let request = {
+            if request.is_null() {
+                return Err(norddrop_result::NORDDROP_RES_INVALID_STRING);
+            }
+
+            CStr::from_ptr(request)
+                .to_str()?
+                .parse()
+                .map_err(|_| norddrop_result::NORDDROP_RES_BAD_INPUT)?
+        };
+
+        let dev = dev
+            .0
+            .lock()
+            .map_err(|_| norddrop_result::NORDDROP_RES_ERROR)?;
+
+        dev.approve_file_request(request)?;
+
+        Ok(())
+    });
+
+    match result {
+        Ok(Ok(())) => norddrop_result::NORDDROP_RES_OK,
+        Ok(Err(err)) => err,
+        Err(_) => norddrop_result::NORDDROP_RES_ERROR,
+    }
+}
+
+/// Deny the file request received from the peer. The peer is notified with
+/// the `FileRequestDenied` event
+///
+/// ```json
+/// {
+///   "type": "FileRequestDenied",
+///   "data": {
+///     "request": "2c1e0a8c-5dbd-4b89-a4a1-8ad9d34d1b8a",
+///     "peer": "172.17.0.1"
+///   }
+/// }
+/// ```
+///
+/// # Arguments
+///
+/// * `dev` - A pointer to the instance.
+/// * `request` - Request ID from the `FileRequestReceived` event.
+///
+/// # Safety
+/// The pointers provided should be valid
+#[no_mangle]
+pub unsafe extern "C" fn norddrop_deny_file_request(
+    dev: &norddrop,
+    request: *const c_char,
+) -> norddrop_result {
+    let result = panic::catch_unwind(|| {
+        let request = {
+            if request.is_null() {
+                return Err(norddrop_result::NORDDROP_RES_INVALID_STRING);
+            }
+
+            CStr::from_ptr(request)
+                .to_str()?
+                .parse()
+                .map_err(|_| norddrop_result::NORDDROP_RES_BAD_INPUT)?
+        };
+
+        let dev = dev
+            .0
+            .lock()
+            .map_err(|_| norddrop_result::NORDDROP_RES_ERROR)?;
+
+        dev.deny_file_request(request)?;
+
+        Ok(())
+    });
+
+    match result {
+        Ok(Ok(())) => norddrop_result::NORDDROP_RES_OK,
+        Ok(Err(err)) => err,
+        Err(_) => norddrop_result::NORDDROP_RES_ERROR,
+    }
+}
+
+/// Destroy the libdrop instance.
+///
+/// # Arguments
+///
+/// * `dev` - Pointer to the instance.
+///
+/// # Safety
+/// This function creates a box with instance pointer and immediately drops it.
+#[no_mangle]
+pub unsafe extern "C" fn norddrop_destroy(dev: *mut norddrop) {
+    if !dev.is_null() {
+        let _ = Box::from_raw(dev);
+    }
+}
+
+/// # Download a file from the peer
+///
+/// # Arguments
+///
+/// * `dev` - Pointer to the instance
+/// * `xfid` - Transfer ID
+/// * `fid` - File ID
+/// * `dst` - Destination path
+///
+/// # Returns
+///
+/// The destination and the file are validated before returning,
+/// `NORDDROP_RES_BAD_TRANSFER`, `NORDDROP_RES_BAD_FILE`,
+/// `NORDDROP_RES_BAD_PATH` or `NORDDROP_RES_FILE_REJECTED` is returned when the
+/// download cannot start. The download itself continues in the background.
+#[no_mangle]
+pub extern "C" fn norddrop_download(
+    dev: &norddrop,
+    xfid: *const c_char,
+    fid: *const c_char,
+    dst: *const c_char,
+) -> norddrop_result {
+    let result = panic::catch_unwind(move || {
+        let mut dev = ffi_try!(dev
+            .0
+            .lock()
+            .map_err(|_| norddrop_result::NORDDROP_RES_ERROR));
+
+        let str_xfid = {
+            if xfid.is_null() {
+                return norddrop_result::NORDDROP_RES_INVALID_STRING;
+            }
+            let cstr_xfid = unsafe { CStr::from_ptr(xfid) };
+            ffi_try!(cstr_xfid.to_str())
+        };
+
+        let str_fid = {
+            if fid.is_null() {
+                return norddrop_result::NORDDROP_RES_INVALID_STRING;
+            }
+            let cstr_fid = unsafe { CStr::from_ptr(fid) };
+            ffi_try!(cstr_fid.to_str())
+        };
+
+        let str_dst = {
+            if dst.is_null() {
+                return norddrop_result::NORDDROP_RES_INVALID_STRING;
+            }
+            let cstr_dst = unsafe { CStr::from_ptr(dst) };
+            ffi_try!(cstr_dst.to_str())
+        };
+
+        dev.download(
+            ffi_try!(str_xfid
+                .to_string()
+                .parse()
+                .map_err(|_| norddrop_result::NORDDROP_RES_BAD_INPUT)),
+            ffi_try!(str_fid
+                .to_string()
+                .parse()
+                .map_err(|_| norddrop_result::NORDDROP_RES_BAD_INPUT)),
+            str_dst.to_string(),
+        )
+        .norddrop_log_result(&dev.logger, "norddrop_download")
+    });
+
+    result.unwrap_or(norddrop_result::NORDDROP_RES_ERROR)
+}
+
+/// # Cancel a transfer from either side
+///
+/// # Arguments
+///
+/// * `dev`: Pointer to the instance
+/// * `xfid`: Transfer ID
+///
+/// # Returns
+///
+/// `NORDDROP_RES_BAD_TRANSFER` when the transfer is unknown or already
+/// finished.
+#[no_mangle]
+pub extern "C" fn norddrop_cancel_transfer(dev: &norddrop, xfid: *const c_char) -> norddrop_result {
+    let result = panic::catch_unwind(move || {
+        let str_xfid = {
+            if xfid.is_null() {
+                return norddrop_result::NORDDROP_RES_INVALID_STRING;
+            }
+            let cstr_xfid = unsafe { CStr::from_ptr(xfid) };
+            ffi_try!(cstr_xfid.to_str())
+        };
+
+        let mut dev = ffi_try!(dev
+            .0
+            .lock()
+            .map_err(|_| norddrop_result::NORDDROP_RES_ERROR));
+
+        dev.cancel_transfer(ffi_try!(str_xfid
+            .to_string()
+            .parse()
+            .map_err(|_| norddrop_result::NORDDROP_RES_BAD_INPUT)))
+            .norddrop_log_result(
+                &dev.logger,
+                &format!("norddrop_cancel_transfer, xfid: {xfid:?}"),
+            )
+    });
+
+    result.unwrap_or(norddrop_result::NORDDROP_RES_ERROR)
+}
+
+/// # Cancel a file from either side
+///
+/// # Arguments
+///
+/// * `dev`: Pointer to the instance
+/// * `xfid`: Transfer ID
+/// * `fid`: File ID
+///
+/// # Returns
+///
+/// `NORDDROP_RES_BAD_TRANSFER`, `NORDDROP_RES_BAD_FILE` or
+/// `NORDDROP_RES_FILE_REJECTED` when the file cannot be canceled.
+#[no_mangle]
+pub extern "C" fn norddrop_cancel_file(
+    dev: &norddrop,
+    xfid: *const c_char,
+    fid: *const c_char,
+) -> norddrop_result {
+    let result = panic::catch_unwind(move || {
+        let str_xfid = {
+            if xfid.is_null() {
+                return norddrop_result::NORDDROP_RES_INVALID_STRING;
+            }
+            let cstr_xfid = unsafe { CStr::from_ptr(xfid) };
+            ffi_try!(cstr_xfid.to_str())
+        };
+
+        let str_fid = {
+            if fid.is_null() {
+                return norddrop_result::NORDDROP_RES_INVALID_STRING;
+            }
+
+            let cstr_fid = unsafe { CStr::from_ptr(fid) };
+            ffi_try!(cstr_fid.to_str())
+        };
+
+        let mut dev = ffi_try!(dev
+            .0
+            .lock()
+            .map_err(|_| norddrop_result::NORDDROP_RES_ERROR));
+
+        dev.cancel_file(
             ffi_try!(str_xfid
                 .to_string()
                 .parse()
                 .map_err(|_| norddrop_result::NORDDROP_RES_BAD_INPUT)),
             ffi_try!(str_fid
                 .parse()
-                .map_err(|_| norddrop_result::NORDDROP_RES_BAD_INPUT)),
+                .map_err(|_| norddrop_result::NORDDROP_RES_BAD_INPUT)),
+            None,
+        )
+        .norddrop_log_result(&dev.logger, "norddrop_cancel_file")
+    });
+
+    result.unwrap_or(norddrop_result::NORDDROP_RES_ERROR)
+}
+
+/// Cancel multiple files of a transfer at once
+///
+/// # Arguments
+///
+/// * `dev` - A pointer to the instance.
+/// * `xfid` - Transfer ID.
+/// * `files` - JSON array of file IDs.
+///
+/// # Returns
+///
+/// A JSON array with the result for each of the files, in order. The `status`
+/// field is present only for the files the operation failed for. `NULL` when
+/// the input is invalid or the instance is not started.
+///
+/// ```json
+/// [
+///   { "file": "4OTT7EM9SU6zqxD0WqaIw0nwcHaCWEzG7dbkKgKnUPE" },
+///   { "file": "A5N4CIrbOy3Y3nDwqb4Pr7ntoyzXIf2YUXtDlHLUz-I", "status": 34 }
+/// ]
+/// ```
+///
+/// # Safety
+/// The pointers provided should be valid
+#[no_mangle]
+pub unsafe extern "C" fn norddrop_cancel_files(
+    dev: &norddrop,
+    xfid: *const c_char,
+    files: *const c_char,
+) -> *mut c_char {
+    let res = panic::catch_unwind(|| {
+        let dev = dev.0.lock().expect("lock instance");
+
+        if xfid.is_null() {
+            return Err(norddrop_result::NORDDROP_RES_INVALID_STRING);
+        }
+
+        let xfid = unsafe { CStr::from_ptr(xfid) }.to_str()?;
+
+        if files.is_null() {
+            return Err(norddrop_result::NORDDROP_RES_INVALID_STRING);
+        }
+
+        let files = unsafe { CStr::from_ptr(files) }.to_str()?;
+
+        let xfid = xfid
+            .parse()
+            .map_err(|_| norddrop_result::NORDDROP_RES_BAD_INPUT)?;
+
+        let results = dev.cancel_files(xfid, files)?;
+
+        Ok(results.into_bytes())
+    });
+
+    // catch_unwind catches panics as errors and everything else goes to Ok
+    match res {
+        Ok(Ok(results)) => new_unmanaged_str(&results),
+        _ => std::ptr::null_mut(),
+    }
+}
+
+/// Reject multiple files of a transfer at once
+///
+/// # Arguments
+///
+/// * `dev` - A pointer to the instance.
+/// * `xfid` - Transfer ID.
+/// * `files` - JSON array of file IDs.
+///
+/// # Returns
+///
+/// A JSON array with the result for each of the files, in order. The `status`
+/// field is present only for the files the operation failed for. `NULL` when
+/// the input is invalid or the instance is not started.
+///
+/// ```json
+/// [
+///   { "file": "4OTT7EM9SU6zqxD0WqaIw0nwcHaCWEzG7dbkKgKnUPE" },
+///   { "file": "A5N4CIrbOy3Y3nDwqb4Pr7ntoyzXIf2YUXtDlHLUz-I", "status": 34 }
+/// ]
+/// ```
+///
+/// # Safety
+/// The pointers provided should be valid
+#[no_mangle]
+pub unsafe extern "C" fn norddrop_reject_files(
+    dev: &norddrop,
+    xfid: *const c_char,
+    files: *const c_char,
+) -> *mut c_char {
+    let res = panic::catch_unwind(|| {
+        let dev = dev.0.lock().expect("lock instance");
+
+        if xfid.is_null() {
+            return Err(norddrop_result::NORDDROP_RES_INVALID_STRING);
+        }
+
+        let xfid = unsafe { CStr::from_ptr(xfid) }.to_str()?;
+
+        if files.is_null() {
+            return Err(norddrop_result::NORDDROP_RES_INVALID_STRING);
+        }
+
+        let files = unsafe { CStr::from_ptr(files) }.to_str()?;
+
+        let xfid = xfid
+            .parse()
+            .map_err(|_| norddrop_result::NORDDROP_RES_BAD_INPUT)?;
+
+        let results = dev.reject_files(xfid, files)?;
+
+        Ok(results.into_bytes())
+    });
+
+    // catch_unwind catches panics as errors and everything else goes to Ok
+    match res {
+        Ok(Ok(results)) => new_unmanaged_str(&results),
+        _ => std::ptr::null_mut(),
+    }
+}
+
+/// Download multiple files of a transfer into the same directory at once
+///
+/// # Arguments
+///
+/// * `dev` - A pointer to the instance.
+/// * `xfid` - Transfer ID.
+/// * `files` - JSON array of file IDs.
+/// * `dst` - Destination directory.
+///
+/// # Returns
+///
+/// A JSON array with the result for each of the files, in order. The `status`
+/// field is present only for the files the operation failed for. `NULL` when
+/// the input is invalid or the instance is not started.
+///
+/// ```json
+/// [
+///   { "file": "4OTT7EM9SU6zqxD0WqaIw0nwcHaCWEzG7dbkKgKnUPE" },
+///   { "file": "A5N4CIrbOy3Y3nDwqb4Pr7ntoyzXIf2YUXtDlHLUz-I", "status": 34 }
+/// ]
+/// ```
+///
+/// # Safety
+/// The pointers provided should be valid
+#[no_mangle]
+pub unsafe extern "C" fn norddrop_download_files(
+    dev: &norddrop,
+    xfid: *const c_char,
+    files: *const c_char,
+    dst: *const c_char,
+) -> *mut c_char {
+    let res = panic::catch_unwind(|| {
+        let dev = dev.0.lock().expect("lock instance");
+
+        if xfid.is_null() {
+            return Err(norddrop_result::NORDDROP_RES_INVALID_STRING);
+        }
+
+        let xfid = unsafe { CStr::from_ptr(xfid) }.to_str()?;
+
+        if files.is_null() {
+            return Err(norddrop_result::NORDDROP_RES_INVALID_STRING);
+        }
+
+        let files = unsafe { CStr::from_ptr(files) }.to_str()?;
+
+        if dst.is_null() {
+            return Err(norddrop_result::NORDDROP_RES_INVALID_STRING);
+        }
+
+        let dst = unsafe { CStr::from_ptr(dst) }.to_str()?;
+
+        let xfid = xfid
+            .parse()
+            .map_err(|_| norddrop_result::NORDDROP_RES_BAD_INPUT)?;
+
+        let results = dev.download_files(xfid, files, dst)?;
+
+        Ok(results.into_bytes())
+    });
+
+    // catch_unwind catches panics as errors and everything else goes to Ok
+    match res {
+        Ok(Ok(results)) => new_unmanaged_str(&results),
+        _ => std::ptr::null_mut(),
+    }
+}
+
+/// Reject a file from either side
+///
+/// # Arguments
+///
+/// * `dev`: Pointer to the instance
+/// * `xfid`: Transfer ID
+/// * `fid`: File ID
+///
+/// # Safety
+/// The pointers provided should be valid
+#[no_mangle]
+pub unsafe extern "C" fn norddrop_reject_file(
+    dev: &norddrop,
+    xfid: *const c_char,
+    fid: *const c_char,
+) -> norddrop_result {
+    let result = panic::catch_unwind(|| {
+        let xfid = {
+            if xfid.is_null() {
+                return Err(norddrop_result::NORDDROP_RES_INVALID_STRING);
+            }
+
+            CStr::from_ptr(xfid)
+                .to_str()?
+                .parse()
+                .map_err(|_| norddrop_result::NORDDROP_RES_BAD_INPUT)?
+        };
+
+        let fid = {
+            if fid.is_null() {
+                return Err(norddrop_result::NORDDROP_RES_INVALID_STRING);
+            }
+
+            CStr::from_ptr(fid).to_str()?.to_owned()
+        };
+
+        let dev = dev
+            .0
+            .lock()
+            .map_err(|_| norddrop_result::NORDDROP_RES_ERROR)?;
+
+        dev.reject_file(xfid, fid, None)?;
+
+        Ok(())
+    });
+
+    match result {
+        Ok(Ok(())) => norddrop_result::NORDDROP_RES_OK,
+        Ok(Err(err)) => err,
+        Err(_) => norddrop_result::NORDDROP_RES_ERROR,
+    }
+}
+
+/// Cancel a file from either side, letting the peer know why. Peers using
+/// older protocol versions receive the cancel without the reason
+///
+/// # Arguments
+///
+/// * `dev`: Pointer to the instance
+/// * `xfid`: Transfer ID
+/// * `fid`: File ID
+/// * `code`: Application defined reason code
+/// * `reason`: Human readable reason, can be NULL
+///
+/// # Safety
+/// The pointers provided should be valid
+#[no_mangle]
+pub unsafe extern "C" fn norddrop_cancel_file_with_reason(
+    dev: &norddrop,
+    xfid: *const c_char,
+    fid: *const c_char,
+    code: std::ffi::c_uint,
+    reason: *const c_char,
+) -> norddrop_result {
+    let result = panic::catch_unwind(|| {
+        let (xfid, fid, reason) = file_reason_args(xfid, fid, code, reason)?;
+
+        let mut dev = dev
+            .0
+            .lock()
+            .map_err(|_| norddrop_result::NORDDROP_RES_ERROR)?;
+
+        dev.cancel_file(xfid, fid, Some(reason))
+    });
+
+    match result {
+        Ok(Ok(())) => norddrop_result::NORDDROP_RES_OK,
+        Ok(Err(err)) => err,
+        Err(_) => norddrop_result::NORDDROP_RES_ERROR,
+    }
+}
+
+/// Reject a file from either side, letting the peer know why. Peers using
+/// older protocol versions receive the rejection without the reason
+///
+/// # Arguments
+///
+/// * `dev`: Pointer to the instance
+/// * `xfid`: Transfer ID
+/// * `fid`: File ID
+/// * `code`: Application defined reason code
+/// * `reason`: Human readable reason, can be NULL
+///
+/// # Safety
+/// The pointers provided should be valid
+#[no_mangle]
+pub unsafe extern "C" fn norddrop_reject_file_with_reason(
+    dev: &norddrop,
+    xfid: *const c_char,
+    fid: *const c_char,
+    code: std::ffi::c_uint,
+    reason: *const c_char,
+) -> norddrop_result {
+    let result = panic::catch_unwind(|| {
+        let (xfid, fid, reason) = file_reason_args(xfid, fid, code, reason)?;
+
+        let dev = dev
+            .0
+            .lock()
+            .map_err(|_| norddrop_result::NORDDROP_RES_ERROR)?;
+
+        dev.reject_file(xfid, fid, Some(reason))?;
+
+        Ok(())
+    });
+
+    match result {
+        Ok(Ok(())) => norddrop_result::NORDDROP_RES_OK,
+        Ok(Err(err)) => err,
+        Err(_) => norddrop_result::NORDDROP_RES_ERROR,
+    }
+}
+
+/// Reject all of the files in a transfer with a single message to the peer and
+/// close the connection. Finishes with the `TransferRejected` event
+///
+/// # Arguments
+///
+/// * `dev`: Pointer to the instance
+/// * `xfid`: Transfer ID
+///
+/// # Safety
+/// The pointers provided should be valid
+#[no_mangle]
+pub unsafe extern "C" fn norddrop_reject_transfer(
+    dev: &norddrop,
+    xfid: *const c_char,
+) -> norddrop_result {
+    let result = panic::catch_unwind(|| {
+        let xfid = parse_xfid(xfid)?;
+
+        let dev = dev
+            .0
+            .lock()
+            .map_err(|_| norddrop_result::NORDDROP_RES_ERROR)?;
+
+        dev.reject_transfer(xfid, None)
+    });
+
+    match result {
+        Ok(Ok(())) => norddrop_result::NORDDROP_RES_OK,
+        Ok(Err(err)) => err,
+        Err(_) => norddrop_result::NORDDROP_RES_ERROR,
+    }
+}
+
+/// Reject all of the files in a transfer, letting the peer know why, and close
+/// the connection. Finishes with the `TransferRejected` event
+///
+/// # Arguments
+///
+/// * `dev`: Pointer to the instance
+/// * `xfid`: Transfer ID
+/// * `code`: Application defined reason code
+/// * `reason`: Human readable reason, can be NULL
+///
+/// # Safety
+/// The pointers provided should be valid
+#[no_mangle]
+pub unsafe extern "C" fn norddrop_reject_transfer_with_reason(
+    dev: &norddrop,
+    xfid: *const c_char,
+    code: std::ffi::c_uint,
+    reason: *const c_char,
+) -> norddrop_result {
+    let result = panic::catch_unwind(|| {
+        let xfid = parse_xfid(xfid)?;
+        let reason = parse_reason(code, reason)?;
+
+        let dev = dev
+            .0
+            .lock()
+            .map_err(|_| norddrop_result::NORDDROP_RES_ERROR)?;
+
+        dev.reject_transfer(xfid, Some(reason))
+    });
+
+    match result {
+        Ok(Ok(())) => norddrop_result::NORDDROP_RES_OK,
+        Ok(Err(err)) => err,
+        Err(_) => norddrop_result::NORDDROP_RES_ERROR,
+    }
+}
+
+unsafe fn file_reason_args(
+    xfid: *const c_char,
+    fid: *const c_char,
+    code: std::ffi::c_uint,
+    reason: *const c_char,
+) -> std::result::Result<(uuid::Uuid, String, drop_transfer::Reason), norddrop_result> {
+    let xfid = parse_xfid(xfid)?;
+
+    if fid.is_null() {
+        return Err(norddrop_result::NORDDROP_RES_INVALID_STRING);
+    }
+    let fid = CStr::from_ptr(fid).to_str()?.to_owned();
+
+    Ok((xfid, fid, parse_reason(code, reason)?))
+}
+
+unsafe fn parse_xfid(xfid: *const c_char) -> std::result::Result<uuid::Uuid, norddrop_result> {
+    if xfid.is_null() {
+        return Err(norddrop_result::NORDDROP_RES_INVALID_STRING);
+    }
+
+    CStr::from_ptr(xfid)
+        .to_str()?
+        .parse()
+        .map_err(|_| norddrop_result::NORDDROP_RES_BAD_INPUT)
+}
+
+unsafe fn parse_reason(
+    code: std::ffi::c_uint,
+    reason: *const c_char,
+) -> std::result::Result<drop_transfer::Reason, norddrop_result> {
+    let message = if reason.is_null() {
+        String::new()
+    } else {
+        CStr::from_ptr(reason).to_str()?.to_owned()
+    };
+
+    Ok(drop_transfer::Reason { code, message })
+}
+
+/// Start libdrop
+///
+/// # Arguments
+///
+/// * `dev` - Pointer to the instance
+/// * `listen_addr` - Address to listen on, or a comma separated list of them,
+///   e.g. `"100.64.0.1,192.168.1.2"`
+/// * `config` - JSON configuration
+///
+/// # Configuration Parameters
+///
+/// * `dir_depth_limit` - if the tree contains more levels then the error is
+/// returned.
+///
+/// * `transfer_file_limit` - when aggregating files from the path, if this
+/// limit is reached, an error is returned. The transfers scanned in the
+/// background fail once they grow over the limit.
+///
+/// * `req_connection_timeout_ms` - timeout value used in connecting to the
+///   peer.
+/// The formula for retrying is: starting from 0.2 seconds we double it
+/// each time until we cap at req_connection_timeout_ms / 10. This is useful
+/// when the peer is not responding at all.
+///
+/// * `transfer_idle_lifetime_ms` - this timeout plays a role in an already
+/// established transfer as sometimes one peer might go offline with no notice.
+/// This timeout controls the amount of time we will wait for any action from
+/// the peer and after that, we will fail the transfer.
+///
+/// * `transfer_accept_timeout_ms` - optional, the time within which an incoming
+/// transfer needs to be acted upon. Afterwards the pending files are rejected
+/// and the transfer finishes with `TransferExpired`. Disabled when not set.
+///
+/// * `moose_event_path` - moose database path.
+///
+/// * `storage_path` - storage path for persistence engine.
+///
+/// * `source_addr` - optional, local IP address the outgoing connections are
+/// made from. Chosen by the OS when not set.
+///
+/// * `bind_device` - optional, network interface the outgoing connections are
+/// bound to, e.g. `"wlan0"`. Supported on Linux and Android only.
+#[no_mangle]
+pub extern "C" fn norddrop_start(
+    dev: &norddrop,
+    listen_addr: *const c_char,
+    config: *const c_char,
+) -> norddrop_result {
+    let result = panic::catch_unwind(move || {
+        let addr = {
+            if listen_addr.is_null() {
+                return norddrop_result::NORDDROP_RES_INVALID_STRING;
+            }
+
+            ffi_try!(unsafe { CStr::from_ptr(listen_addr) }.to_str())
+        };
+
+        let config = {
+            if config.is_null() {
+                return norddrop_result::NORDDROP_RES_INVALID_STRING;
+            }
+
+            ffi_try!(unsafe { CStr::from_ptr(config) }.to_str())
+        };
+
+        let mut dev = ffi_try!(dev
+            .0
+            .lock()
+            .map_err(|_| norddrop_result::NORDDROP_RES_ERROR));
+
+        dev.start(addr, config)
+            .norddrop_log_result(&dev.logger, "norddrop_start")
+    });
+
+    result.unwrap_or(norddrop_result::NORDDROP_RES_ERROR)
+}
+
+/// Stop norddrop instance
+///
+/// # Arguments
+///
+/// * `dev` - Pointer to the instance
+#[no_mangle]
+pub extern "C" fn norddrop_stop(dev: &norddrop) -> norddrop_result {
+    let result = panic::catch_unwind(move || {
+        let mut dev = match dev.0.lock() {
+            Ok(inst) => inst,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        dev.stop().norddrop_log_result(&dev.logger, "norddrop_stop")
+    });
+
+    result.unwrap_or(norddrop_result::NORDDROP_RES_ERROR)
+}
+
+/// Refresh the network after the connectivity has changed, e.g. when
+/// switching between the Wi-Fi and cellular network or when the meshnet address
+/// changes. The server is moved to the new addresses and the outgoing transfers
+/// that are still connecting retry right away. The established outgoing
+/// transfers are dialed again over the new route, the incoming ones are kept.
+///
+/// # Arguments
+///
+/// * `dev` - Pointer to the instance
+/// * `listen_addr` - Address to listen on, or a comma separated list of them.
+///   The instance stops listening on the addresses missing from the list
+///
+/// # Returns
+///
+/// `NORDDROP_RES_ADDR_IN_USE` when the new address cannot be bound, the
+/// instance keeps listening on the previous addresses then
+///
+/// # Safety
+/// The pointers provided should be valid
+#[no_mangle]
+pub unsafe extern "C" fn norddrop_network_refresh(
+    dev: &norddrop,
+    listen_addr: *const c_char,
+) -> norddrop_result {
+    let result = panic::catch_unwind(move || {
+        let addr = {
+            if listen_addr.is_null() {
+                return norddrop_result::NORDDROP_RES_INVALID_STRING;
+            }
+
+            ffi_try!(unsafe { CStr::from_ptr(listen_addr) }.to_str())
+        };
+
+        let mut dev = ffi_try!(dev
+            .0
+            .lock()
+            .map_err(|_| norddrop_result::NORDDROP_RES_ERROR));
+
+        dev.network_refresh(addr)
+            .norddrop_log_result(&dev.logger, "norddrop_network_refresh")
+    });
+
+    result.unwrap_or(norddrop_result::NORDDROP_RES_ERROR)
+}
+
+/// Start accepting the transfers on one more address, e.g. on the LAN
+/// interface next to the meshnet one. Does nothing if the instance already
+/// listens on it
+///
+/// # Arguments
+///
+/// * `dev` - Pointer to the instance
+/// * `addr` - IP address to listen on
+///
+/// # Returns
+///
+/// `NORDDROP_RES_ADDR_IN_USE` when the address cannot be bound
+///
+/// # Safety
+/// The pointers provided should be valid
+#[no_mangle]
+pub unsafe extern "C" fn norddrop_enable_listener(
+    dev: &norddrop,
+    addr: *const c_char,
+) -> norddrop_result {
+    let result = panic::catch_unwind(move || {
+        let addr = {
+            if addr.is_null() {
+                return norddrop_result::NORDDROP_RES_INVALID_STRING;
+            }
+
+            ffi_try!(unsafe { CStr::from_ptr(addr) }.to_str())
+        };
+
+        let mut dev = ffi_try!(dev
+            .0
+            .lock()
+            .map_err(|_| norddrop_result::NORDDROP_RES_ERROR));
+
+        dev.enable_listener(addr)
+            .norddrop_log_result(&dev.logger, "norddrop_enable_listener")
+    });
+
+    result.unwrap_or(norddrop_result::NORDDROP_RES_ERROR)
+}
+
+/// Stop accepting the transfers on the address. The transfers that arrived on
+/// it are kept
+///
+/// # Arguments
+///
+/// * `dev` - Pointer to the instance
+/// * `addr` - IP address to stop listening on
+///
+/// # Returns
+///
+/// `NORDDROP_RES_BAD_INPUT` when the instance does not listen on the address
+///
+/// # Safety
+/// The pointers provided should be valid
+#[no_mangle]
+pub unsafe extern "C" fn norddrop_disable_listener(
+    dev: &norddrop,
+    addr: *const c_char,
+) -> norddrop_result {
+    let result = panic::catch_unwind(move || {
+        let addr = {
+            if addr.is_null() {
+                return norddrop_result::NORDDROP_RES_INVALID_STRING;
+            }
+
+            ffi_try!(unsafe { CStr::from_ptr(addr) }.to_str())
+        };
+
+        let mut dev = ffi_try!(dev
+            .0
+            .lock()
+            .map_err(|_| norddrop_result::NORDDROP_RES_ERROR));
+
+        dev.disable_listener(addr)
+            .norddrop_log_result(&dev.logger, "norddrop_disable_listener")
+    });
+
+    result.unwrap_or(norddrop_result::NORDDROP_RES_ERROR)
+}
+
+/// Update the configuration of the running instance without stopping it.
+/// Takes the same JSON as `norddrop_start()`. The limits and timeouts apply to
+/// the transfers and connections made afterwards, while `storage_path`,
+/// `moose_event_path` and `moose_prod` must stay the same. The listen
+/// addresses are changed with `norddrop_enable_listener()`,
+/// `norddrop_disable_listener()` and `norddrop_network_refresh()` instead.
+///
+/// # Arguments
+///
+/// * `dev` - Pointer to the instance
+/// * `config` - JSON configuration
+///
+/// # Returns
+///
+/// `NORDDROP_RES_CONFIG_NOT_UPDATABLE` when one of the fixed settings differs
+///
+/// # Safety
+/// The pointers provided should be valid
+#[no_mangle]
+pub unsafe extern "C" fn norddrop_set_config(
+    dev: &norddrop,
+    config: *const c_char,
+) -> norddrop_result {
+    let result = panic::catch_unwind(move || {
+        let config = {
+            if config.is_null() {
+                return norddrop_result::NORDDROP_RES_INVALID_STRING;
+            }
+
+            ffi_try!(unsafe { CStr::from_ptr(config) }.to_str())
+        };
+
+        let mut dev = ffi_try!(dev
+            .0
+            .lock()
+            .map_err(|_| norddrop_result::NORDDROP_RES_ERROR));
+
+        dev.set_config(config)
+            .norddrop_log_result(&dev.logger, "norddrop_set_config")
+    });
+
+    result.unwrap_or(norddrop_result::NORDDROP_RES_ERROR)
+}
+
+/// Deliver the events through a bounded queue read with
+/// `norddrop_poll_event()` instead of the event callback. Useful for runtimes
+/// that handle calls from foreign threads poorly. Should be called before
+/// `norddrop_start()`, the callback is not called afterwards.
+///
+/// When the queue is full the new events are dropped and an `EventsDropped`
+/// event with their count is queued in their place, so the order of the
+/// delivered events stays the same as the order in which they were emitted.
+///
+/// # Arguments
+///
+/// * `dev` - Pointer to the instance
+/// * `capacity` - Maximum number of events kept in the queue, must be positive
+///
+/// ```json
+/// {
+///   "version": 3,
+///   "type": "EventsDropped",
+///   "data": {
+///     "count": 12
+///   }
+/// }
+/// ```
+#[no_mangle]
+pub extern "C" fn norddrop_enable_event_queue(
+    dev: &norddrop,
+    capacity: std::ffi::c_ulonglong,
+) -> norddrop_result {
+    let result = panic::catch_unwind(move || {
+        let dev = ffi_try!(dev
+            .0
+            .lock()
+            .map_err(|_| norddrop_result::NORDDROP_RES_ERROR));
+
+        let capacity = ffi_try!(
+            usize::try_from(capacity).map_err(|_| norddrop_result::NORDDROP_RES_BAD_INPUT)
         );
 
-        norddrop_result::NORDDROP_RES_OK
+        dev.enable_event_queue(capacity)
+            .norddrop_log_result(&dev.logger, "norddrop_enable_event_queue")
+    });
+
+    result.unwrap_or(norddrop_result::NORDDROP_RES_ERROR)
+}
+
+/// Wait for the next event in the queue enabled with
+/// `norddrop_enable_event_queue()`. Does not block other calls on the
+/// instance.
+///
+/// # Arguments
+///
+/// * `dev` - Pointer to the instance
+/// * `timeout_ms` - Maximum time to wait for an event, 0 returns immediately
+///
+/// # Returns
+///
+/// The event JSON, the same as passed to the event callback, which must be
+/// released with `norddrop_free_event()`. `NULL` when no event arrived in
+/// time or the queue is not enabled.
+#[no_mangle]
+pub extern "C" fn norddrop_poll_event(
+    dev: &norddrop,
+    timeout_ms: std::ffi::c_ulonglong,
+) -> *mut c_char {
+    let res = panic::catch_unwind(move || {
+        // Release the instance before waiting
+        let queue = dev.0.lock().expect("lock instance").event_queue()?;
+        queue.pop(Duration::from_millis(timeout_ms))
+    });
+
+    match res {
+        Ok(Some(event)) => new_unmanaged_str(event.as_bytes()),
+        _ => std::ptr::null_mut(),
+    }
+}
+
+/// Release the event returned by `norddrop_poll_event()`
+///
+/// # Arguments
+///
+/// * `event` - The event to release, may be `NULL`
+///
+/// # Safety
+/// The pointer must be returned by `norddrop_poll_event()` and not released
+/// before
+#[no_mangle]
+pub unsafe extern "C" fn norddrop_free_event(event: *mut c_char) {
+    if !event.is_null() {
+        libc::free(event as *mut _);
+    }
+}
+
+/// Opt into a specific version of the event format for compatibility. Every
+/// event carries the `version` field, by default the latest version is used.
+///
+/// The versions:
+/// * 1 - the initial format
+/// * 2 - `local_addr` in `RequestReceived`
+/// * 3 - `identity` in `RequestReceived` and `RequestQueued`
+///
+/// # Arguments
+///
+/// * `dev` - Pointer to the instance
+/// * `version` - Event format version
+///
+/// # Returns
+///
+/// `NORDDROP_RES_BAD_INPUT` when the version is not supported
+#[no_mangle]
+pub extern "C" fn norddrop_set_event_version(
+    dev: &norddrop,
+    version: std::ffi::c_uint,
+) -> norddrop_result {
+    let result = panic::catch_unwind(move || {
+        let dev = ffi_try!(dev
+            .0
+            .lock()
+            .map_err(|_| norddrop_result::NORDDROP_RES_ERROR));
+
+        dev.set_event_version(version)
+            .norddrop_log_result(&dev.logger, "norddrop_set_event_version")
+    });
+
+    result.unwrap_or(norddrop_result::NORDDROP_RES_ERROR)
+}
+
+/// Generate the code for an unknown peer to pair with this instance. The user
+/// shows the code to the other user, who enters it in `norddrop_pair()`. The
+/// code allows a single attempt within its lifetime and replaces the
+/// previously generated one
+///
+/// # Arguments
+///
+/// * `dev` - A pointer to the instance.
+/// * `lifetime_ms` - Time after which the code expires.
+///
+/// # Returns
+///
+/// A String containing the code, `NULL` on failure.
+#[no_mangle]
+pub extern "C" fn norddrop_start_pairing(
+    dev: &norddrop,
+    lifetime_ms: std::ffi::c_longlong,
+) -> *mut c_char {
+    let res = panic::catch_unwind(|| {
+        let dev = dev.0.lock().expect("lock instance");
+
+        let code = dev.start_pairing(lifetime_ms)?;
+
+        Ok::<Vec<u8>, norddrop_result>(code.into_bytes())
+    });
+
+    match res {
+        Ok(Ok(code)) => new_unmanaged_str(&code),
+        _ => std::ptr::null_mut(),
+    }
+}
+
+/// Invalidate the code generated with `norddrop_start_pairing()`
+///
+/// # Arguments
+///
+/// * `dev` - A pointer to the instance.
+///
+/// # Returns
+///
+/// `NORDDROP_RES_BAD_INPUT` when there is no pending code
+#[no_mangle]
+pub extern "C" fn norddrop_cancel_pairing(dev: &norddrop) -> norddrop_result {
+    let result = panic::catch_unwind(move || {
+        let dev = ffi_try!(dev
+            .0
+            .lock()
+            .map_err(|_| norddrop_result::NORDDROP_RES_ERROR));
+
+        dev.cancel_pairing()
+            .norddrop_log_result(&dev.logger, "norddrop_cancel_pairing")
     });
 
     result.unwrap_or(norddrop_result::NORDDROP_RES_ERROR)
 }
 
-/// Reject a file from either side
+/// Pair with the peer using the code it generated with
+/// `norddrop_start_pairing()`. Both sides run SPAKE2 with the code and, if the
+/// codes match, exchange and persist their public keys. The paired peers are
+/// authenticated with these keys when the public key callback does not know
+/// them.
+///
+/// The pairing runs in the background. Both sides report the progress with
+/// the `PairingStarted` (the side entering the code) or `PairingRequested`
+/// (the side which generated it) event, followed by `PairingCompleted` with
+/// the identity of the peer or `PairingFailed` with the status.
 ///
 /// # Arguments
 ///
-/// * `dev`: Pointer to the instance
-/// * `xfid`: Transfer ID
-/// * `fid`: File ID
+/// * `dev` - A pointer to the instance.
+/// * `peer` - Peer address.
+/// * `code` - Code generated by the peer.
+///
+/// ```json
+/// {
+///   "type": "PairingCompleted",
+///   "data": {
+///     "peer": "172.17.0.2",
+///     "identity": "JA/Me7wRDBJ67fkmjpokpFobTLGHTv9GXlYxsjNryG0"
+///   }
+/// }
+/// ```
 ///
 /// # Safety
 /// The pointers provided should be valid
 #[no_mangle]
-pub unsafe extern "C" fn norddrop_reject_file(
+pub unsafe extern "C" fn norddrop_pair(
     dev: &norddrop,
-    xfid: *const c_char,
-    fid: *const c_char,
+    peer: *const c_char,
+    code: *const c_char,
 ) -> norddrop_result {
     let result = panic::catch_unwind(|| {
-        let xfid = {
-            if xfid.is_null() {
-                return Err(norddrop_result::NORDDROP_RES_INVALID_STRING);
-            }
-
-            CStr::from_ptr(xfid)
-                .to_str()?
-                .parse()
-                .map_err(|_| norddrop_result::NORDDROP_RES_BAD_INPUT)?
-        };
-
-        let fid = {
-            if fid.is_null() {
-                return Err(norddrop_result::NORDDROP_RES_INVALID_STRING);
-            }
+        if peer.is_null() || code.is_null() {
+            return Err(norddrop_result::NORDDROP_RES_INVALID_STRING);
+        }
 
-            CStr::from_ptr(fid).to_str()?.to_owned()
-        };
+        let peer = CStr::from_ptr(peer).to_str()?;
+        let code = CStr::from_ptr(code).to_str()?;
 
         let dev = dev
             .0
             .lock()
             .map_err(|_| norddrop_result::NORDDROP_RES_ERROR)?;
 
-        dev.reject_file(xfid, fid)?;
+        dev.pair(peer, code)?;
 
         Ok(())
     });
@@ -328,88 +2007,165 @@ pub unsafe extern "C" fn norddrop_reject_file(
     }
 }
 
-/// Start libdrop
+/// Get the JSON Schema of the events and of the
+/// `norddrop_get_transfers_since()` output for the latest event format version
 ///
-/// # Arguments
+/// # Returns
 ///
-/// * `dev` - Pointer to the instance
-/// * `listen_addr` - Address to listen on
-/// * `config` - JSON configuration
+/// JSON object with the `version` of the event format, and the `event` and
+/// `transfers` schemas. `NULL` on failure.
 ///
-/// # Configuration Parameters
+/// ```json
+/// {
+///   "version": 3,
+///   "event": { "$schema": "http://json-schema.org/draft-07/schema#", ... },
+///   "transfers": { "$schema": "http://json-schema.org/draft-07/schema#", ... }
+/// }
+/// ```
+#[no_mangle]
+pub extern "C" fn norddrop_get_event_schema() -> *mut c_char {
+    let res = panic::catch_unwind(|| serde_json::to_vec(&crate::device::types::schema()));
+
+    match res {
+        Ok(Ok(schema)) => new_unmanaged_str(&schema),
+        _ => std::ptr::null_mut(),
+    }
+}
+
+/// Set the callback receiving the common events as `norddrop_event` structures
+/// instead of JSON. Other events are still passed to the JSON callback. When
+/// the event queue is enabled, all of the events are queued as JSON and the
+/// typed callback is not called.
 ///
-/// * `dir_depth_limit` - if the tree contains more levels then the error is
-/// returned.
+/// # Arguments
 ///
-/// * `transfer_file_limit` - when aggregating files from the path, if this
-/// limit is reached, an error is returned.
+/// * `dev` - Pointer to the instance
+/// * `event_cb` - Typed event callback
+#[no_mangle]
+pub extern "C" fn norddrop_set_typed_event_cb(
+    dev: &norddrop,
+    event_cb: norddrop_typed_event_cb,
+) -> norddrop_result {
+    let result = panic::catch_unwind(move || {
+        let dev = ffi_try!(dev
+            .0
+            .lock()
+            .map_err(|_| norddrop_result::NORDDROP_RES_ERROR));
+
+        dev.set_typed_event_cb(event_cb);
+        norddrop_result::NORDDROP_RES_OK
+    });
+
+    result.unwrap_or(norddrop_result::NORDDROP_RES_ERROR)
+}
+
+/// Create a new transfer from the typed descriptors
 ///
-/// * `req_connection_timeout_ms` - timeout value used in connecting to the
-///   peer.
-/// The formula for retrying is: starting from 0.2 seconds we double it
-/// each time until we cap at req_connection_timeout_ms / 10. This is useful
-/// when the peer is not responding at all.
+/// # Arguments
 ///
-/// * `transfer_idle_lifetime_ms` - this timeout plays a role in an already
-/// established transfer as sometimes one peer might go offline with no notice.
-/// This timeout controls the amount of time we will wait for any action from
-/// the peer and after that, we will fail the transfer.
+/// * `dev` - Pointer to the instance
+/// * `peer` - Peer address
+/// * `descriptors` - Array of the transfer descriptors
+/// * `descriptors_len` - Number of the descriptors
 ///
-/// * `moose_event_path` - moose database path.
+/// # Returns
 ///
-/// * `storage_path` - storage path for persistence engine.
+/// The transfer ID, `NULL` on failure
+///
+/// # Safety
+/// The descriptors should point to an array of `descriptors_len` valid
+/// structures with valid string pointers
 #[no_mangle]
-pub extern "C" fn norddrop_start(
+pub unsafe extern "C" fn norddrop_new_transfer_typed(
     dev: &norddrop,
-    listen_addr: *const c_char,
-    config: *const c_char,
-) -> norddrop_result {
-    let result = panic::catch_unwind(move || {
-        let addr = {
-            if listen_addr.is_null() {
-                return norddrop_result::NORDDROP_RES_INVALID_STRING;
-            }
+    peer: *const c_char,
+    descriptors: *const norddrop_transfer_descriptor,
+    descriptors_len: usize,
+) -> *mut c_char {
+    let res = panic::catch_unwind(|| {
+        if peer.is_null() {
+            return Err(norddrop_result::NORDDROP_RES_INVALID_STRING);
+        }
 
-            ffi_try!(unsafe { CStr::from_ptr(listen_addr) }.to_str())
-        };
+        let peer = CStr::from_ptr(peer).to_str()?;
 
-        let config = {
-            if config.is_null() {
-                return norddrop_result::NORDDROP_RES_INVALID_STRING;
-            }
+        if descriptors.is_null() || descriptors_len == 0 {
+            return Err(norddrop_result::NORDDROP_RES_BAD_INPUT);
+        }
 
-            ffi_try!(unsafe { CStr::from_ptr(config) }.to_str())
-        };
+        let descriptors = std::slice::from_raw_parts(descriptors, descriptors_len)
+            .iter()
+            .map(|desc| {
+                if desc.path.is_null() {
+                    return Err(norddrop_result::NORDDROP_RES_INVALID_STRING);
+                }
 
-        let mut dev = ffi_try!(dev
-            .0
-            .lock()
-            .map_err(|_| norddrop_result::NORDDROP_RES_ERROR));
+                desc.try_into()
+            })
+            .collect::<Result<Vec<_>, norddrop_result>>()?;
 
-        dev.start(addr, config)
-            .norddrop_log_result(&dev.logger, "norddrop_start")
+        let mut dev = dev.0.lock().expect("lock instance");
+        let xfid = dev.new_transfer_typed(peer, descriptors)?;
+
+        Ok(xfid.to_string().into_bytes())
     });
 
-    result.unwrap_or(norddrop_result::NORDDROP_RES_ERROR)
+    match res {
+        Ok(Ok(xfid)) => new_unmanaged_str(&xfid),
+        _ => std::ptr::null_mut(),
+    }
 }
 
-/// Stop norddrop instance
+/// Get the progress of a file in an active transfer
 ///
 /// # Arguments
 ///
 /// * `dev` - Pointer to the instance
+/// * `xfid` - Transfer ID
+/// * `fid` - File ID
+/// * `progress` - Filled with the progress on success
+///
+/// # Returns
+///
+/// `NORDDROP_RES_BAD_TRANSFER` when the transfer is not active and
+/// `NORDDROP_RES_BAD_FILE` when the file is not part of it
+///
+/// # Safety
+/// The pointers provided should be valid
 #[no_mangle]
-pub extern "C" fn norddrop_stop(dev: &norddrop) -> norddrop_result {
-    let result = panic::catch_unwind(move || {
-        let mut dev = match dev.0.lock() {
-            Ok(inst) => inst,
-            Err(poisoned) => poisoned.into_inner(),
-        };
+pub unsafe extern "C" fn norddrop_get_file_progress(
+    dev: &norddrop,
+    xfid: *const c_char,
+    fid: *const c_char,
+    progress: *mut norddrop_file_progress,
+) -> norddrop_result {
+    let result = panic::catch_unwind(|| {
+        if progress.is_null() {
+            return Err(norddrop_result::NORDDROP_RES_BAD_INPUT);
+        }
 
-        dev.stop().norddrop_log_result(&dev.logger, "norddrop_stop")
+        let xfid = parse_xfid(xfid)?;
+
+        if fid.is_null() {
+            return Err(norddrop_result::NORDDROP_RES_INVALID_STRING);
+        }
+
+        let fid = CStr::from_ptr(fid).to_str()?;
+
+        let dev = dev
+            .0
+            .lock()
+            .map_err(|_| norddrop_result::NORDDROP_RES_ERROR)?;
+
+        *progress = dev.file_progress(xfid, fid)?;
+        Ok(())
     });
 
-    result.unwrap_or(norddrop_result::NORDDROP_RES_ERROR)
+    match result {
+        Ok(Ok(())) => norddrop_result::NORDDROP_RES_OK,
+        Ok(Err(err)) => err,
+        Err(_) => norddrop_result::NORDDROP_RES_ERROR,
+    }
 }
 
 /// Purge transfers from the database
@@ -585,6 +2341,139 @@ pub extern "C" fn norddrop_get_transfers_since(
     }
 }
 
+/// Get the live state of the transfers which are currently connecting or
+/// connected. Unlike `norddrop_get_transfers_since()` this does not touch the
+/// database, the state is taken from the running connections.
+///
+/// # Arguments
+///
+/// * `dev` - Pointer to the instance
+///
+/// # Returns
+///
+/// A JSON array of the active transfers. `NULL` when the instance is not
+/// started. The `version` field is present only once the connection is
+/// established. The file `state` is one of `pending`, `in_progress`,
+/// `completed`, `failed`, `canceled` or `rejected` and `bytes` is the number of
+/// bytes transferred so far.
+///
+/// ```json
+/// [
+///   {
+///     "transfer": "b49fc2f8-ce2d-41ac-a081-96a4d760899e",
+///     "peer": "172.17.0.1",
+///     "direction": "outgoing",
+///     "state": "connected",
+///     "version": "v6",
+///     "files": [
+///       {
+///         "id": "Unu_l4PVyu15-RsdVL9IOQvaKQdqcqUy7F9EpvP-CrY",
+///         "path": "doggo.jpg",
+///         "size": 29852,
+///         "state": "in_progress",
+///         "bytes": 8192
+///       }
+///     ],
+///     "rejected": []
+///   }
+/// ]
+/// ```
+#[no_mangle]
+pub extern "C" fn norddrop_get_active_transfers(dev: &norddrop) -> *mut c_char {
+    let res = panic::catch_unwind(move || {
+        let dev = dev.0.lock().expect("lock instance");
+
+        let transfers = dev.get_active_transfers()?;
+
+        Ok::<Vec<u8>, norddrop_result>(transfers.into_bytes())
+    });
+
+    match res {
+        Ok(Ok(transfers)) => new_unmanaged_str(&transfers),
+        _ => std::ptr::null_mut(),
+    }
+}
+
+/// Get the identity of the peer last seen at the given address. The identity
+/// is derived from the public key of the peer and stays the same when the peer
+/// changes its address
+///
+/// # Arguments
+///
+/// * `dev` - Pointer to the instance
+/// * `addr` - IP address of the peer
+///
+/// # Returns
+///
+/// The peer identity, `NULL` when the peer is unknown or on failure
+///
+/// # Safety
+/// The pointers provided should be valid
+#[no_mangle]
+pub unsafe extern "C" fn norddrop_get_peer_identity(
+    dev: &norddrop,
+    addr: *const c_char,
+) -> *mut c_char {
+    let res = panic::catch_unwind(move || {
+        let dev = dev.0.lock().expect("lock instance");
+
+        if addr.is_null() {
+            return Err(norddrop_result::NORDDROP_RES_INVALID_STRING);
+        }
+
+        let addr = unsafe { CStr::from_ptr(addr) }.to_str()?;
+
+        dev.peer_identity(addr)
+    });
+
+    match res {
+        Ok(Ok(Some(identity))) => new_unmanaged_str(identity.as_bytes()),
+        _ => std::ptr::null_mut(),
+    }
+}
+
+/// Get the addresses the peer with the given identity was seen at
+///
+/// # Arguments
+///
+/// * `dev` - Pointer to the instance
+/// * `identity` - Peer identity, as reported in the events
+///
+/// # Returns
+///
+/// A JSON array of the IP addresses, the most recent first. `NULL` on failure
+///
+/// ```json
+/// ["172.17.0.2", "172.17.0.3"]
+/// ```
+///
+/// # Safety
+/// The pointers provided should be valid
+#[no_mangle]
+pub unsafe extern "C" fn norddrop_get_peer_addresses(
+    dev: &norddrop,
+    identity: *const c_char,
+) -> *mut c_char {
+    let res = panic::catch_unwind(move || {
+        let dev = dev.0.lock().expect("lock instance");
+
+        if identity.is_null() {
+            return Err(norddrop_result::NORDDROP_RES_INVALID_STRING);
+        }
+
+        let identity = unsafe { CStr::from_ptr(identity) }.to_str()?;
+
+        let addresses = dev.peer_addresses(identity)?;
+
+        Ok(addresses.into_bytes())
+    });
+
+    match res {
+        Ok(Ok(addresses)) => new_unmanaged_str(&addresses),
+        _ => std::ptr::null_mut(),
+    }
+}
+
 /// Removes a single transfer file from the database. The file must be rejected
 /// beforehand, otherwise the error is returned.
 ///
@@ -0,0 +1,439 @@
+//! Typed counterparts of the JSON API for the most common calls and events.
+//! The pointers inside of the structures passed to the app are valid only for
+//! the duration of the callback
+
+use std::{
+    ffi::{c_int, c_void, CStr, CString},
+    ptr,
+};
+
+use libc::c_char;
+
+use super::types::norddrop_result;
+use crate::device::types::{FileKind, SymlinkPolicy, TransferDescriptor};
+
+#[allow(non_camel_case_types)]
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+/// Kind of the typed event
+pub enum norddrop_event_kind {
+    /// Incoming transfer, `peer` and `files` are set
+    NORDDROP_EVENT_REQUEST_RECEIVED = 0,
+    /// Outgoing transfer queued, `peer` and `files` are set
+    NORDDROP_EVENT_REQUEST_QUEUED = 1,
+    /// File transfer started
+    NORDDROP_EVENT_FILE_STARTED = 2,
+    /// File transfer progress, `bytes` is the number of bytes transferred
+    NORDDROP_EVENT_FILE_PROGRESS = 3,
+    /// File uploaded to the peer
+    NORDDROP_EVENT_FILE_UPLOADED = 4,
+    /// File downloaded, `final_path` is set
+    NORDDROP_EVENT_FILE_DOWNLOADED = 5,
+    /// File failed, `status` is set
+    NORDDROP_EVENT_FILE_FAILED = 6,
+    /// File canceled, `by_peer` is set
+    NORDDROP_EVENT_FILE_CANCELED = 7,
+    /// File rejected, `by_peer` is set
+    NORDDROP_EVENT_FILE_REJECTED = 8,
+    /// Transfer canceled, `by_peer` is set
+    NORDDROP_EVENT_TRANSFER_CANCELED = 9,
+    /// Transfer failed, `status` is set
+    NORDDROP_EVENT_TRANSFER_FAILED = 10,
+}
+
+#[allow(non_camel_case_types)]
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+/// Kind of the file in the transfer
+pub enum norddrop_file_kind {
+    NORDDROP_FILE_KIND_FILE = 0,
+    NORDDROP_FILE_KIND_DIR = 1,
+    NORDDROP_FILE_KIND_SYMLINK = 2,
+    NORDDROP_FILE_KIND_ARCHIVE = 3,
+}
+
+#[allow(non_camel_case_types)]
+#[repr(C)]
+/// File of the transfer
+pub struct norddrop_file {
+    pub id: *const c_char,
+    pub path: *const c_char,
+    pub size: u64,
+    pub kind: norddrop_file_kind,
+}
+
+#[allow(non_camel_case_types)]
+#[repr(C)]
+/// Typed event, the fields not relevant for the kind are zeroed
+pub struct norddrop_event {
+    pub kind: norddrop_event_kind,
+    /// Transfer ID
+    pub transfer: *const c_char,
+    /// File ID, `NULL` for the transfer events
+    pub file: *const c_char,
+    /// Peer address, `NULL` unless the event is a request
+    pub peer: *const c_char,
+    /// Final path of the downloaded file, `NULL` otherwise
+    pub final_path: *const c_char,
+    pub files: *const norddrop_file,
+    pub files_len: usize,
+    pub bytes: u64,
+    /// Status code of the failure, 0 otherwise
+    pub status: u32,
+    pub by_peer: bool,
+}
+
+#[allow(non_camel_case_types)]
+pub type norddrop_typed_event_fn = unsafe extern "C" fn(*mut c_void, *const norddrop_event);
+
+#[allow(non_camel_case_types)]
+#[repr(C)]
+#[derive(Copy, Clone)]
+/// Typed event callback
+pub struct norddrop_typed_event_cb {
+    /// Context to pass to callback.
+    /// User must ensure safe access of this var from multitheaded context.
+    pub ctx: *mut c_void,
+    /// Function to be called
+    pub cb: norddrop_typed_event_fn,
+}
+
+unsafe impl Sync for norddrop_typed_event_cb {}
+unsafe impl Send for norddrop_typed_event_cb {}
+
+#[allow(non_camel_case_types)]
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+/// Symlink handling inside of the directories
+pub enum norddrop_symlink_policy {
+    NORDDROP_SYMLINKS_SKIP = 0,
+    NORDDROP_SYMLINKS_FOLLOW = 1,
+    NORDDROP_SYMLINKS_PRESERVE = 2,
+}
+
+#[allow(non_camel_case_types)]
+#[repr(C)]
+/// Typed transfer descriptor
+pub struct norddrop_transfer_descriptor {
+    /// Path of the file or directory
+    pub path: *const c_char,
+    /// File descriptor to read the file from, negative when not used
+    pub fd: i32,
+    /// One of the `norddrop_symlink_policy` values
+    pub symlinks: c_int,
+    /// Send the directory as a single archive
+    pub archive: bool,
+}
+
+#[allow(non_camel_case_types)]
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+/// State of the file in an active transfer
+pub enum norddrop_file_state {
+    NORDDROP_FILE_PENDING = 0,
+    NORDDROP_FILE_IN_PROGRESS = 1,
+    NORDDROP_FILE_COMPLETED = 2,
+    NORDDROP_FILE_FAILED = 3,
+    NORDDROP_FILE_CANCELED = 4,
+    NORDDROP_FILE_REJECTED = 5,
+}
+
+#[allow(non_camel_case_types)]
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+/// Progress of the file in an active transfer
+pub struct norddrop_file_progress {
+    pub state: norddrop_file_state,
+    /// Number of bytes transferred so far
+    pub bytes: u64,
+    /// Size of the file
+    pub size: u64,
+}
+
+/// Typed event together with the memory its pointers refer to
+pub(crate) struct TypedEvent {
+    raw: norddrop_event,
+    _strings: Vec<CString>,
+    _files: Vec<norddrop_file>,
+}
+
+impl TypedEvent {
+    pub(crate) fn as_ptr(&self) -> *const norddrop_event {
+        &self.raw
+    }
+}
+
+#[derive(Default)]
+struct Builder {
+    strings: Vec<CString>,
+}
+
+impl Builder {
+    fn str(&mut self, s: impl Into<Vec<u8>>) -> *const c_char {
+        match CString::new(s) {
+            Ok(s) => {
+                // The heap buffer does not move together with the CString
+                let ptr = s.as_ptr();
+                self.strings.push(s);
+                ptr
+            }
+            Err(_) => ptr::null(),
+        }
+    }
+}
+
+/// Converts the common events, the rest is delivered only through the JSON
+/// callback
+pub(crate) fn typed_event(event: &drop_transfer::Event) -> Option<TypedEvent> {
+    use drop_transfer::Event;
+
+    use self::norddrop_event_kind::*;
+
+    let mut b = Builder::default();
+    let mut files = Vec::new();
+
+    let mut raw = norddrop_event {
+        kind: NORDDROP_EVENT_REQUEST_RECEIVED,
+        transfer: ptr::null(),
+        file: ptr::null(),
+        peer: ptr::null(),
+        final_path: ptr::null(),
+        files: ptr::null(),
+        files_len: 0,
+        bytes: 0,
+        status: 0,
+        by_peer: false,
+    };
+
+    let mut request =
+        |raw: &mut norddrop_event, b: &mut Builder, xfer: &drop_transfer::Transfer| {
+            raw.transfer = b.str(xfer.id().to_string());
+            raw.peer = b.str(xfer.peer().to_string());
+
+            files = xfer
+                .files()
+                .values()
+                .map(|file| norddrop_file {
+                    id: b.str(file.id().to_string()),
+                    path: b.str(file.subpath().to_string()),
+                    size: file.size(),
+                    kind: FileKind::from(file).into(),
+                })
+                .collect();
+        };
+
+    match event {
+        Event::RequestReceived(xfer, _) => {
+            raw.kind = NORDDROP_EVENT_REQUEST_RECEIVED;
+            request(&mut raw, &mut b, xfer);
+        }
+        Event::RequestQueued(xfer) => {
+            raw.kind = NORDDROP_EVENT_REQUEST_QUEUED;
+            request(&mut raw, &mut b, xfer);
+        }
+        Event::FileUploadStarted(xfer, file) | Event::FileDownloadStarted(xfer, file, _) => {
+            raw.kind = NORDDROP_EVENT_FILE_STARTED;
+            raw.transfer = b.str(xfer.id().to_string());
+            raw.file = b.str(file.to_string());
+        }
+        Event::FileUploadProgress(xfer, file, bytes)
+        | Event::FileDownloadProgress(xfer, file, bytes) => {
+            raw.kind = NORDDROP_EVENT_FILE_PROGRESS;
+            raw.transfer = b.str(xfer.id().to_string());
+            raw.file = b.str(file.to_string());
+            raw.bytes = *bytes;
+        }
+        Event::FileUploadSuccess(xfer, file) => {
+            raw.kind = NORDDROP_EVENT_FILE_UPLOADED;
+            raw.transfer = b.str(xfer.id().to_string());
+            raw.file = b.str(file.to_string());
+        }
+        Event::FileDownloadSuccess(xfer, success) => {
+            raw.kind = NORDDROP_EVENT_FILE_DOWNLOADED;
+            raw.transfer = b.str(xfer.id().to_string());
+            raw.file = b.str(success.id.to_string());
+            raw.final_path = b.str(success.final_path.0.to_string_lossy().into_owned());
+        }
+        Event::FileUploadFailed(xfer, file, err) | Event::FileDownloadFailed(xfer, file, err) => {
+            raw.kind = NORDDROP_EVENT_FILE_FAILED;
+            raw.transfer = b.str(xfer.id().to_string());
+            raw.file = b.str(file.to_string());
+            raw.status = err.into();
+        }
+        Event::FileUploadCancelled(xfer, file, by_peer, _)
+        | Event::FileDownloadCancelled(xfer, file, by_peer, _) => {
+            raw.kind = NORDDROP_EVENT_FILE_CANCELED;
+            raw.transfer = b.str(xfer.id().to_string());
+            raw.file = b.str(file.to_string());
+            raw.by_peer = *by_peer;
+        }
+        Event::FileUploadRejected {
+            transfer_id,
+            file_id,
+            by_peer,
+            ..
+        }
+        | Event::FileDownloadRejected {
+            transfer_id,
+            file_id,
+            by_peer,
+            ..
+        } => {
+            raw.kind = NORDDROP_EVENT_FILE_REJECTED;
+            raw.transfer = b.str(transfer_id.to_string());
+            raw.file = b.str(file_id.to_string());
+            raw.by_peer = *by_peer;
+        }
+        Event::TransferCanceled(xfer, _, by_peer) => {
+            raw.kind = NORDDROP_EVENT_TRANSFER_CANCELED;
+            raw.transfer = b.str(xfer.id().to_string());
+            raw.by_peer = *by_peer;
+        }
+        Event::TransferFailed(xfer, err, _) => {
+            raw.kind = NORDDROP_EVENT_TRANSFER_FAILED;
+            raw.transfer = b.str(xfer.id().to_string());
+            raw.status = err.into();
+        }
+        _ => return None,
+    }
+
+    raw.files = if files.is_empty() {
+        ptr::null()
+    } else {
+        files.as_ptr()
+    };
+    raw.files_len = files.len();
+
+    Some(TypedEvent {
+        raw,
+        _strings: b.strings,
+        _files: files,
+    })
+}
+
+impl TryFrom<c_int> for norddrop_symlink_policy {
+    type Error = norddrop_result;
+
+    fn try_from(value: c_int) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::NORDDROP_SYMLINKS_SKIP),
+            1 => Ok(Self::NORDDROP_SYMLINKS_FOLLOW),
+            2 => Ok(Self::NORDDROP_SYMLINKS_PRESERVE),
+            _ => Err(norddrop_result::NORDDROP_RES_BAD_INPUT),
+        }
+    }
+}
+
+impl TryFrom<&norddrop_transfer_descriptor> for TransferDescriptor {
+    type Error = norddrop_result;
+
+    fn try_from(desc: &norddrop_transfer_descriptor) -> Result<Self, Self::Error> {
+        let path = unsafe { CStr::from_ptr(desc.path) }.to_str()?.to_string();
+
+        Ok(TransferDescriptor {
+            path: drop_transfer::utils::Hidden(path),
+            fd: if desc.fd < 0 { None } else { Some(desc.fd) },
+            // Any value may come from C, so it's not read as the enum directly
+            symlinks: match norddrop_symlink_policy::try_from(desc.symlinks)? {
+                norddrop_symlink_policy::NORDDROP_SYMLINKS_SKIP => SymlinkPolicy::Skip,
+                norddrop_symlink_policy::NORDDROP_SYMLINKS_FOLLOW => SymlinkPolicy::Follow,
+                norddrop_symlink_policy::NORDDROP_SYMLINKS_PRESERVE => SymlinkPolicy::Preserve,
+            },
+            include: Vec::new(),
+            exclude: Vec::new(),
+            skip_hidden: false,
+            ignore_file: None,
+            archive: desc.archive,
+            compression: Default::default(),
+        })
+    }
+}
+
+impl From<FileKind> for norddrop_file_kind {
+    fn from(kind: FileKind) -> Self {
+        match kind {
+            FileKind::File => Self::NORDDROP_FILE_KIND_FILE,
+            FileKind::Dir => Self::NORDDROP_FILE_KIND_DIR,
+            FileKind::Symlink => Self::NORDDROP_FILE_KIND_SYMLINK,
+            FileKind::Archive => Self::NORDDROP_FILE_KIND_ARCHIVE,
+        }
+    }
+}
+
+impl From<drop_transfer::progress::FileState> for norddrop_file_state {
+    fn from(state: drop_transfer::progress::FileState) -> Self {
+        use drop_transfer::progress::FileState;
+
+        match state {
+            FileState::Pending => Self::NORDDROP_FILE_PENDING,
+            FileState::InProgress => Self::NORDDROP_FILE_IN_PROGRESS,
+            FileState::Completed => Self::NORDDROP_FILE_COMPLETED,
+            FileState::Failed => Self::NORDDROP_FILE_FAILED,
+            FileState::Canceled => Self::NORDDROP_FILE_CANCELED,
+            FileState::Rejected => Self::NORDDROP_FILE_REJECTED,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    #[test]
+    fn request_event() {
+        let dir = tempfile::tempdir().unwrap();
+        let tmp = dir.path().join("norddrop-typed-event");
+        std::fs::write(&tmp, b"abc").unwrap();
+
+        let config = drop_config::DropConfig::default();
+        let files = drop_transfer::File::from_path(&tmp, &config).unwrap();
+        let xfer =
+            drop_transfer::Transfer::new(Ipv4Addr::LOCALHOST.into(), files, &config).unwrap();
+
+        let event = typed_event(&drop_transfer::Event::RequestQueued(xfer.clone())).unwrap();
+        let raw = unsafe { &*event.as_ptr() };
+
+        assert_eq!(raw.kind, norddrop_event_kind::NORDDROP_EVENT_REQUEST_QUEUED);
+        assert_eq!(
+            unsafe { CStr::from_ptr(raw.transfer) }.to_str().unwrap(),
+            xfer.id().to_string()
+        );
+        assert_eq!(
+            unsafe { CStr::from_ptr(raw.peer) }.to_str().unwrap(),
+            "127.0.0.1"
+        );
+        assert_eq!(raw.files_len, 1);
+
+        let file = unsafe { &*raw.files };
+        assert_eq!(file.size, 3);
+        assert_eq!(file.kind, norddrop_file_kind::NORDDROP_FILE_KIND_FILE);
+        assert_eq!(
+            unsafe { CStr::from_ptr(file.path) }.to_str().unwrap(),
+            "norddrop-typed-event"
+        );
+
+        assert!(typed_event(&drop_transfer::Event::TransferExpired(xfer)).is_none());
+    }
+
+    #[test]
+    fn descriptor_symlink_policy() {
+        let path = CString::new("/tmp").unwrap();
+        let mut desc = norddrop_transfer_descriptor {
+            path: path.as_ptr(),
+            fd: -1,
+            symlinks: norddrop_symlink_policy::NORDDROP_SYMLINKS_PRESERVE as _,
+            archive: false,
+        };
+
+        let parsed = TransferDescriptor::try_from(&desc).unwrap();
+        assert!(matches!(parsed.symlinks, SymlinkPolicy::Preserve));
+
+        desc.symlinks = 3;
+        assert!(matches!(
+            TransferDescriptor::try_from(&desc),
+            Err(norddrop_result::NORDDROP_RES_BAD_INPUT)
+        ));
+    }
+}
@@ -50,6 +50,21 @@ pub enum norddrop_result {
 
     /// Database error
     NORDDROP_RES_DB_ERROR = 11,
+
+    /// The transfer is unknown or no longer active
+    NORDDROP_RES_BAD_TRANSFER = 12,
+
+    /// The file is not part of the transfer
+    NORDDROP_RES_BAD_FILE = 13,
+
+    /// The destination path is invalid
+    NORDDROP_RES_BAD_PATH = 14,
+
+    /// The file is already rejected
+    NORDDROP_RES_FILE_REJECTED = 15,
+
+    /// The setting cannot be changed while the instance is running
+    NORDDROP_RES_CONFIG_NOT_UPDATABLE = 16,
 }
 
 pub use norddrop_result::*;
@@ -144,6 +159,23 @@ impl From<std::str::Utf8Error> for norddrop_result {
     }
 }
 
+impl From<&drop_transfer::Error> for norddrop_result {
+    fn from(err: &drop_transfer::Error) -> Self {
+        use drop_transfer::Error;
+
+        match err {
+            Error::BadTransfer => NORDDROP_RES_BAD_TRANSFER,
+            Error::BadFileId => NORDDROP_RES_BAD_FILE,
+            Error::BadPath(_) => NORDDROP_RES_BAD_PATH,
+            Error::Rejected => NORDDROP_RES_FILE_REJECTED,
+            Error::StorageError => NORDDROP_RES_DB_ERROR,
+            Error::AddrInUse => NORDDROP_RES_ADDR_IN_USE,
+            Error::ConfigNotUpdatable(_) => NORDDROP_RES_CONFIG_NOT_UPDATABLE,
+            _ => NORDDROP_RES_ERROR,
+        }
+    }
+}
+
 impl From<DevResult> for norddrop_result {
     fn from(res: DevResult) -> Self {
         use norddrop_result::*;
@@ -169,6 +169,32 @@ class Download(Action):
         return f"DownloadFile({print_uuid(self._uuid_slot)}, {self._fid}, {self._dst})"
 
 
+class DownloadFails(Action):
+    def __init__(self, uuid_slot: int, fid, dst, result: ffi.LibResult):
+        self._uuid_slot = uuid_slot
+        self._fid = fid
+        self._dst = dst
+        self._result = result
+
+    async def run(self, drop: ffi.Drop):
+        UUIDS_LOCK.acquire()
+        try:
+            drop.download(UUIDS[self._uuid_slot], self._fid, self._dst)
+        except ffi.LibResultError as err:
+            if err.result != self._result:
+                raise Exception(
+                    f"Download failed with {err.result.name}, expected {self._result.name}"
+                )
+            return
+        finally:
+            UUIDS_LOCK.release()
+
+        raise Exception("DownloadFails did not fail")
+
+    def __str__(self):
+        return f"DownloadFails({print_uuid(self._uuid_slot)}, {self._fid}, {self._dst}, {self._result.name})"
+
+
 class CancelTransferRequest(Action):
     def __init__(self, uuid_slot: int):
         self._uuid_slot = uuid_slot
@@ -370,6 +396,17 @@ class Stop(Action):
         return "Stop"
 
 
+class NetworkRefresh(Action):
+    def __init__(self, addr: str):
+        self._addr = addr
+
+    async def run(self, drop: ffi.Drop):
+        drop.network_refresh(self._addr)
+
+    def __str__(self):
+        return f"NetworkRefresh({self._addr})"
+
+
 class ModifyFile(Action):
     def __init__(self, file: str):
         self._file = file
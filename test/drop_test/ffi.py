@@ -26,6 +26,51 @@ class LibResult(IntEnum):
     # Bad JSON input
     NORDDROP_RES_BAD_CONFIG = (3,)
 
+    # Failed to parse JSON argument
+    NORDDROP_RES_JSON_PARSE = (4,)
+
+    # Failed to create transfer based on arguments provided
+    NORDDROP_RES_TRANSFER_CREATE = (5,)
+
+    # The libdrop instance is not started yet
+    NORDDROP_RES_NOT_STARTED = (6,)
+
+    # Address already in use
+    NORDDROP_RES_ADDR_IN_USE = (7,)
+
+    # Failed to start the libdrop instance
+    NORDDROP_RES_INSTANCE_START = (8,)
+
+    # Failed to stop the libdrop instance
+    NORDDROP_RES_INSTANCE_STOP = (9,)
+
+    # Invalid private key provided
+    NORDDROP_RES_INVALID_PRIVKEY = (10,)
+
+    # Database error
+    NORDDROP_RES_DB_ERROR = (11,)
+
+    # The transfer is unknown or no longer active
+    NORDDROP_RES_BAD_TRANSFER = (12,)
+
+    # The file is not part of the transfer
+    NORDDROP_RES_BAD_FILE = (13,)
+
+    # The destination path is invalid
+    NORDDROP_RES_BAD_PATH = (14,)
+
+    # The file is already rejected
+    NORDDROP_RES_FILE_REJECTED = (15,)
+
+    # The setting cannot be changed while the instance is running
+    NORDDROP_RES_CONFIG_NOT_UPDATABLE = (16,)
+
+
+class LibResultError(Exception):
+    def __init__(self, func: str, result: LibResult):
+        super().__init__(f"{func} has failed with code: {int(result)}({result.name})")
+        self.result = result
+
 
 class LogLevel(IntEnum):
     Critical = 1
@@ -308,10 +353,7 @@ class Drop:
         )
 
         if err != 0:
-            err_type = LibResult(err).name
-            raise Exception(
-                f"norddrop_download has failed with code: {err}({err_type})"
-            )
+            raise LibResultError("norddrop_download", LibResult(err))
 
     def cancel_transfer_request(self, uuid: str):
         err = self._lib.norddrop_cancel_transfer(
@@ -421,6 +463,14 @@ class Drop:
             err_type = LibResult(err).name
             raise Exception(f"norddrop_stop has failed with code: {err}({err_type})")
 
+    def network_refresh(self, addr: str):
+        err = self._lib.norddrop_network_refresh(
+            self._instance,
+            ctypes.create_string_buffer(bytes(addr, "utf-8")),
+        )
+        if err != 0:
+            raise LibResultError("norddrop_network_refresh", LibResult(err))
+
     @property
     def version(self) -> str:
         version = self._lib.norddrop_version(self._instance)
@@ -1,6 +1,7 @@
 from drop_test import action, event
 from drop_test.scenario import Scenario, ActionList
 from drop_test.error import Error
+from drop_test.ffi import LibResult
 from drop_test.config import FILES
 
 from pathlib import Path
@@ -2622,17 +2623,11 @@ scenarios = [
                             },
                         )
                     ),
-                    action.Download(
+                    action.DownloadFails(
                         0,
                         FILES["testfile-small"].id,
                         "/tmp/received/symtest-dir",
-                    ),
-                    action.Wait(
-                        event.FinishFileFailed(
-                            0,
-                            FILES["testfile-small"].id,
-                            Error.BAD_PATH,
-                        )
+                        LibResult.NORDDROP_RES_BAD_PATH,
                     ),
                     action.CheckFileDoesNotExist(
                         [
@@ -2696,17 +2691,11 @@ scenarios = [
                             },
                         )
                     ),
-                    action.Download(
+                    action.DownloadFails(
                         0,
                         "testfile-small-xd",
                         "/tmp/received",
-                    ),
-                    action.Wait(
-                        event.FinishFileFailed(
-                            0,
-                            "testfile-small-xd",
-                            Error.BAD_FILE_ID,
-                        )
+                        LibResult.NORDDROP_RES_BAD_FILE,
                     ),
                     action.Download(
                         0,
@@ -4867,13 +4856,11 @@ scenarios = [
                     action.Wait(
                         event.FinishFileRejected(0, FILES["testfile-small"].id, True)
                     ),
-                    action.Download(
-                        0, FILES["testfile-small"].id, "/tmp/received/27-5"
-                    ),
-                    action.Wait(
-                        event.FinishFileFailed(
-                            0, FILES["testfile-small"].id, Error.FILE_REJECTED
-                        )
+                    action.DownloadFails(
+                        0,
+                        FILES["testfile-small"].id,
+                        "/tmp/received/27-5",
+                        LibResult.NORDDROP_RES_FILE_REJECTED,
                     ),
                     action.CancelTransferRequest(0),
                     action.ExpectCancel([0], False),
@@ -4930,13 +4917,11 @@ scenarios = [
                     action.Wait(
                         event.FinishFileRejected(0, FILES["testfile-small"].id, False)
                     ),
-                    action.Download(
-                        0, FILES["testfile-small"].id, "/tmp/received/27-6"
-                    ),
-                    action.Wait(
-                        event.FinishFileFailed(
-                            0, FILES["testfile-small"].id, Error.FILE_REJECTED
-                        )
+                    action.DownloadFails(
+                        0,
+                        FILES["testfile-small"].id,
+                        "/tmp/received/27-6",
+                        LibResult.NORDDROP_RES_FILE_REJECTED,
                     ),
                     action.CancelTransferRequest(0),
                     action.ExpectCancel([0], False),
@@ -5351,4 +5336,70 @@ scenarios = [
             ),
         },
     ),
+    Scenario(
+        "scenario30",
+        "Refresh the network on the receiver before the transfer, expect the transfer to go through",
+        {
+            "ren": ActionList(
+                [
+                    action.WaitForAnotherPeer(),
+                    action.NewTransfer("172.20.0.15", ["/tmp/testfile-small"]),
+                    action.Wait(
+                        event.Queued(
+                            0,
+                            {
+                                event.File(
+                                    FILES["testfile-small"].id,
+                                    "testfile-small",
+                                    1048576,
+                                ),
+                            },
+                        )
+                    ),
+                    action.Wait(event.Start(0, FILES["testfile-small"].id)),
+                    action.Wait(
+                        event.FinishFileUploaded(0, FILES["testfile-small"].id)
+                    ),
+                    action.ExpectCancel([0], True),
+                    action.NoEvent(),
+                    action.Stop(),
+                ]
+            ),
+            "stimpy": ActionList(
+                [
+                    action.NetworkRefresh("172.20.0.15"),
+                    action.Wait(
+                        event.Receive(
+                            0,
+                            "172.20.0.5",
+                            {
+                                event.File(
+                                    FILES["testfile-small"].id,
+                                    "testfile-small",
+                                    1048576,
+                                ),
+                            },
+                        )
+                    ),
+                    action.Download(
+                        0,
+                        FILES["testfile-small"].id,
+                        "/tmp/received/30",
+                    ),
+                    action.Wait(event.Start(0, FILES["testfile-small"].id)),
+                    action.Wait(
+                        event.FinishFileDownloaded(
+                            0,
+                            FILES["testfile-small"].id,
+                            "/tmp/received/30/testfile-small",
+                        )
+                    ),
+                    action.CancelTransferRequest(0),
+                    action.ExpectCancel([0], False),
+                    action.NoEvent(),
+                    action.Stop(),
+                ]
+            ),
+        },
+    ),
 ]